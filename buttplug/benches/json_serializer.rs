@@ -0,0 +1,126 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Throughput of the server JSON serializer on batches of device commands, at
+//! each validation level, compared against validating and parsing separately.
+//!
+//! Run with `cargo bench --bench json_serializer`. To check a change for
+//! regressions, run with `-- --save-baseline before` on the old tree, then
+//! `-- --baseline before` on the new one.
+
+use buttplug::{
+  core::messages::{
+    serializer::{
+      ButtplugMessageSerializer, ButtplugSerializedMessage, ButtplugServerJSONSerializer,
+      MessageValidationLevel,
+    },
+    ButtplugMessage, ButtplugSpecV3ClientMessage, LinearCmd, VectorSubcommand, VibrateCmd,
+    VibrateSubcommand,
+  },
+  util::json::JSONValidator,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+static MESSAGE_JSON_SCHEMA: &str = include_str!("../buttplug-schema/schema/buttplug-schema.json");
+
+const BATCH_SIZE: u32 = 20;
+
+fn vibrate_batch() -> String {
+  let msgs: Vec<ButtplugSpecV3ClientMessage> = (1..=BATCH_SIZE)
+    .map(|id| {
+      let mut msg = VibrateCmd::new(
+        0,
+        vec![
+          VibrateSubcommand::new(0, id as f64 / BATCH_SIZE as f64),
+          VibrateSubcommand::new(1, 0.5),
+        ],
+      );
+      msg.set_id(id);
+      msg.into()
+    })
+    .collect();
+  serde_json::to_string(&msgs).unwrap()
+}
+
+fn linear_batch() -> String {
+  let msgs: Vec<ButtplugSpecV3ClientMessage> = (1..=BATCH_SIZE)
+    .map(|id| {
+      let mut msg = LinearCmd::new(
+        0,
+        vec![VectorSubcommand::new(0, 500, id as f64 / BATCH_SIZE as f64)],
+      );
+      msg.set_id(id);
+      msg.into()
+    })
+    .collect();
+  serde_json::to_string(&msgs).unwrap()
+}
+
+fn server_serializer(level: MessageValidationLevel) -> ButtplugServerJSONSerializer {
+  let mut serializer = ButtplugServerJSONSerializer::default();
+  serializer.set_validation_level(level);
+  serializer
+    .deserialize(ButtplugSerializedMessage::Text(
+      r#"[{"RequestServerInfo":{"Id":1,"ClientName":"Bench","MessageVersion":3}}]"#.to_owned(),
+    ))
+    .unwrap();
+  serializer
+}
+
+fn bench_batch(c: &mut Criterion, name: &str, batch: &str) {
+  let mut group = c.benchmark_group(name);
+  group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+  // Validating the string, then parsing it again with serde, the way the
+  // serializer worked before it reused the parsed value.
+  let validator = JSONValidator::new(MESSAGE_JSON_SCHEMA);
+  group.bench_function("validate + parse", |b| {
+    b.iter(|| {
+      // Copy like the serializer gets, to keep the comparison fair.
+      let batch = batch.to_owned();
+      validator.validate(&batch).unwrap();
+      let msgs: Vec<ButtplugSpecV3ClientMessage> = serde_json::from_str(&batch).unwrap();
+      assert_eq!(msgs.len(), BATCH_SIZE as usize);
+    })
+  });
+
+  for (level_name, level) in [
+    ("schema", MessageValidationLevel::Schema),
+    ("strict", MessageValidationLevel::Strict),
+    ("off", MessageValidationLevel::Off),
+  ] {
+    let serializer = server_serializer(level);
+    group.bench_function(BenchmarkId::new("serializer", level_name), |b| {
+      b.iter(|| {
+        let msgs = serializer
+          .deserialize(ButtplugSerializedMessage::Text(batch.to_owned()))
+          .unwrap();
+        assert_eq!(msgs.len(), BATCH_SIZE as usize);
+      })
+    });
+  }
+  group.finish();
+}
+
+fn bench_connection_setup(c: &mut Criterion) {
+  let mut group = c.benchmark_group("Serializer setup");
+  // Each serializer used to compile its own copy of the schema.
+  group.bench_function("compile schema", |b| {
+    b.iter(|| JSONValidator::new(MESSAGE_JSON_SCHEMA))
+  });
+  group.bench_function("cached schema", |b| {
+    b.iter(|| server_serializer(MessageValidationLevel::Schema))
+  });
+  group.finish();
+}
+
+fn bench_batches(c: &mut Criterion) {
+  bench_batch(c, "VibrateCmd", &vibrate_batch());
+  bench_batch(c, "LinearCmd", &linear_batch());
+}
+
+criterion_group!(benches, bench_connection_setup, bench_batches);
+criterion_main!(benches);
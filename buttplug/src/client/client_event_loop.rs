@@ -202,6 +202,15 @@ where
       return;
     }
     trace!("Message future not found, assuming server event.");
+    // Log messages are forwarded without being logged again, otherwise we'd
+    // create a feedback loop when client and server share a log layer.
+    if let ButtplugCurrentSpecServerMessage::Log(log) = msg {
+      self.send_client_event(ButtplugClientEvent::Log(
+        log.log_level(),
+        log.log_message().clone(),
+      ));
+      return;
+    }
     info!("{:?}", msg);
     match msg {
       ButtplugCurrentSpecServerMessage::DeviceAdded(dev) => {
@@ -230,6 +239,65 @@ where
           self.send_client_event(ButtplugClientEvent::Error(ButtplugDeviceError::DeviceConnectionError("Device removal requested for a device the client does not know about. Server may be in a weird state.".to_owned()).into()));
         }
       }
+      ButtplugCurrentSpecServerMessage::DeviceReconnecting(dev) => {
+        if let Some(device) = self.device_map.get(&dev.device_index()).map(|d| d.value().clone()) {
+          device.set_device_connected(false);
+          device.queue_event(ButtplugClientDeviceEvent::DeviceReconnecting);
+          self.send_client_event(ButtplugClientEvent::DeviceReconnecting(device));
+        } else {
+          error!("Received DeviceReconnecting for non-existent device index");
+        }
+      }
+      ButtplugCurrentSpecServerMessage::DeviceReconnected(dev) => {
+        if let Some(device) = self.device_map.get(&dev.device_index()).map(|d| d.value().clone()) {
+          device.set_device_connected(true);
+          device.queue_event(ButtplugClientDeviceEvent::DeviceReconnected);
+          self.send_client_event(ButtplugClientEvent::DeviceReconnected(device));
+        } else {
+          error!("Received DeviceReconnected for non-existent device index");
+        }
+      }
+      ButtplugCurrentSpecServerMessage::DeviceStopped(dev) => {
+        if let Some(device) = self.device_map.get(&dev.device_index()).map(|d| d.value().clone()) {
+          device.queue_event(ButtplugClientDeviceEvent::DeviceStopped(dev.reason()));
+          self.send_client_event(ButtplugClientEvent::DeviceStopped(device, dev.reason()));
+        } else {
+          error!("Received DeviceStopped for non-existent device index");
+        }
+      }
+      ButtplugCurrentSpecServerMessage::BatteryLevelLow(msg) => {
+        if let Some(device) = self.device_map.get(&msg.device_index()).map(|d| d.value().clone()) {
+          device.queue_event(ButtplugClientDeviceEvent::BatteryLevelLow(
+            msg.battery_level(),
+            msg.threshold(),
+          ));
+          self.send_client_event(ButtplugClientEvent::BatteryLevelLow(
+            device,
+            msg.battery_level(),
+            msg.threshold(),
+          ));
+        } else {
+          error!("Received BatteryLevelLow for non-existent device index");
+        }
+      }
+      ButtplugCurrentSpecServerMessage::SensorReading(msg) => {
+        if let Some(device) = self.device_map.get(&msg.device_index()) {
+          device
+            .value()
+            .queue_event(ButtplugClientDeviceEvent::SensorReading(
+              msg.sensor_index(),
+              msg.sensor_type(),
+              msg.data().clone(),
+            ));
+        }
+      }
+      ButtplugCurrentSpecServerMessage::DeviceConnectionFailed(msg) => {
+        self.send_client_event(ButtplugClientEvent::DeviceConnectionFailed(
+          msg.device_name().clone(),
+          msg.stage(),
+          msg.error_message().clone(),
+        ));
+      }
       ButtplugCurrentSpecServerMessage::ScanningFinished(_) => {
         trace!("Scanning finished event received, forwarding to client.");
         self.send_client_event(ButtplugClientEvent::ScanningFinished);
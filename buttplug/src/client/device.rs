@@ -16,15 +16,17 @@ use crate::{
     messages::{
       BatteryLevelCmd, ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecDeviceMessageType,
       ButtplugCurrentSpecServerMessage, ButtplugMessage, DeviceMessageAttributes,
-      DeviceMessageAttributesMap, DeviceMessageInfo, LinearCmd, RSSILevelCmd, RawReadCmd,
-      RawSubscribeCmd, RawUnsubscribeCmd, RawWriteCmd, RotateCmd, RotationSubcommand,
-      StopDeviceCmd, VectorSubcommand, VibrateCmd, VibrateSubcommand,
+      DeviceMessageAttributesMap, DeviceMessageInfo, DeviceStopReason, LinearCmd, PatternCmd,
+      PatternSubcommand, RSSILevelCmd, RawReadCmd, RawSubscribeCmd, RawUnsubscribeCmd, RawWriteCmd,
+      ReleaseDeviceLease, RequestDeviceLease, RotateCmd, RotationSubcommand, ScalarCmd,
+      ScalarSubcommand, SensorSubscribeCmd, SensorType, SensorUnsubscribeCmd, StopDeviceCmd,
+      VectorSubcommand, VibrateCmd, VibrateSubcommand,
     },
   },
   device::Endpoint,
   util::stream::convert_broadcast_receiver_to_stream,
 };
-use futures::{future, Stream};
+use futures::{future, Stream, StreamExt};
 use std::{
   collections::HashMap,
   convert::TryFrom,
@@ -42,6 +44,18 @@ use tracing_futures::Instrument;
 pub enum ButtplugClientDeviceEvent {
   /// Device has disconnected from server.
   DeviceRemoved,
+  /// Device dropped its connection, server is trying to reconnect.
+  DeviceReconnecting,
+  /// Device is connected again after reconnecting.
+  DeviceReconnected,
+  /// Server stopped the device on its own, for the given reason.
+  DeviceStopped(DeviceStopReason),
+  /// Device battery level dropped to or below a low battery threshold. Holds
+  /// the battery level and the threshold.
+  BatteryLevelLow(f64, f64),
+  /// Reading from a sensor the client subscribed to. Holds the sensor index,
+  /// sensor type and values.
+  SensorReading(u32, SensorType, Vec<i32>),
   /// Client has disconnected from server.
   ClientDisconnect,
   /// Message was received from server for that specific device.
@@ -101,6 +115,21 @@ pub enum LinearCommand {
   LinearMap(HashMap<u32, (u32, f64)>),
 }
 
+/// Convenience enum for forming [ScalarCmd] commands.
+///
+/// Works like [VibrateCommand], but for every actuator listed under ScalarCmd,
+/// whatever its type. Actuator types are filled in from the device's message
+/// attributes.
+pub enum ScalarCommand {
+  /// Sets all scalar features of a device to the same value.
+  Scalar(f64),
+  /// Sets scalar features to values based on the index of the value in the
+  /// vec.
+  ScalarVec(Vec<f64>),
+  /// Sets scalar features indicated by index to the requested value.
+  ScalarMap(HashMap<u32, f64>),
+}
+
 // Using a macro here so we can encabe the return statement. Otherwise we'd have
 // to do validity checks on every call since we return futures, not results.
 macro_rules! check_message_support {
@@ -138,6 +167,8 @@ fn convert_to_client_device_map(
 pub struct ButtplugClientDevice {
   /// Name of the device
   pub name: String,
+  /// Name the user has given the device on the server, if any.
+  pub display_name: Option<String>,
   /// Index of the device, matching the index in the
   /// [ButtplugServer][crate::server::ButtplugServer]'s
   /// [DeviceManager][crate::server::device_manager::DeviceManager].
@@ -193,6 +224,7 @@ impl ButtplugClientDevice {
 
     Self {
       name: name.to_owned(),
+      display_name: None,
       index,
       allowed_messages,
       event_loop_sender: message_sender,
@@ -206,12 +238,14 @@ impl ButtplugClientDevice {
     info: &DeviceMessageInfo,
     sender: broadcast::Sender<ButtplugClientRequest>,
   ) -> Self {
-    ButtplugClientDevice::new(
+    let mut device = ButtplugClientDevice::new(
       &*info.device_name,
       info.device_index,
       convert_to_client_device_map(&info.device_messages),
       sender,
-    )
+    );
+    device.display_name = info.device_display_name.clone();
+    device
   }
 
   pub fn connected(&self) -> bool {
@@ -272,7 +306,7 @@ impl ButtplugClientDevice {
 
   fn create_boxed_future_client_error<T>(&self, err: ButtplugError) -> ButtplugClientResultFuture<T>
   where
-    T: 'static + Send + Sync,
+    T: 'static + Send,
   {
     Box::pin(future::ready(Err(ButtplugClientError::ButtplugError(err))))
   }
@@ -457,6 +491,71 @@ impl ButtplugClientDevice {
     self.send_message_expect_ok(msg)
   }
 
+  /// Sets scalar actuators (vibrators, air pumps, etc), assuming the device
+  /// has them.
+  pub fn scalar(&self, scalar_cmd: ScalarCommand) -> ButtplugClientResultFuture {
+    check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::ScalarCmd);
+    let actuator_types = self
+      .allowed_messages
+      .get(&ButtplugCurrentSpecDeviceMessageType::ScalarCmd)
+      .and_then(|features| features.actuator_type.clone())
+      .unwrap_or_default();
+    let scalar_count = actuator_types.len() as u32;
+    let scalars: Vec<(u32, f64)> = match scalar_cmd {
+      ScalarCommand::Scalar(scalar) => (0..scalar_count).map(|i| (i, scalar)).collect(),
+      ScalarCommand::ScalarMap(map) => {
+        if map.len() as u32 > scalar_count {
+          return self.create_boxed_future_client_error(
+            ButtplugDeviceError::DeviceFeatureCountMismatch(scalar_count, map.len() as u32).into(),
+          );
+        }
+        if let Some(idx) = map.keys().find(|idx| **idx >= scalar_count) {
+          return self.create_boxed_future_client_error(
+            ButtplugDeviceError::DeviceFeatureIndexError(scalar_count, *idx).into(),
+          );
+        }
+        map.into_iter().collect()
+      }
+      ScalarCommand::ScalarVec(vec) => {
+        if vec.len() as u32 > scalar_count {
+          return self.create_boxed_future_client_error(
+            ButtplugDeviceError::DeviceFeatureCountMismatch(scalar_count, vec.len() as u32).into(),
+          );
+        }
+        vec
+          .into_iter()
+          .enumerate()
+          .map(|(i, scalar)| (i as u32, scalar))
+          .collect()
+      }
+    };
+    let scalar_vec = scalars
+      .into_iter()
+      .map(|(idx, scalar)| ScalarSubcommand::new(idx, scalar, actuator_types[idx as usize]))
+      .collect();
+    let msg = ScalarCmd::new(self.index, scalar_vec).into();
+    self.send_message_expect_ok(msg)
+  }
+
+  /// Starts one of the firmware patterns listed in the PatternCmd Patterns
+  /// attribute on a feature, or stops the feature's pattern if `pattern` is
+  /// None.
+  pub fn pattern(&self, index: u32, pattern: Option<&str>) -> ButtplugClientResultFuture {
+    check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::PatternCmd);
+    let pattern_count = self
+      .allowed_messages
+      .get(&ButtplugCurrentSpecDeviceMessageType::PatternCmd)
+      .and_then(|features| features.patterns.as_ref())
+      .map_or(0, |patterns| patterns.len() as u32);
+    if index >= pattern_count {
+      return self.create_boxed_future_client_error(
+        ButtplugDeviceError::DeviceFeatureIndexError(pattern_count, index).into(),
+      );
+    }
+    let msg = PatternCmd::new(self.index, vec![PatternSubcommand::new(index, pattern)]).into();
+    self.send_message_expect_ok(msg)
+  }
+
   pub fn battery_level(&self) -> ButtplugClientResultFuture<f64> {
     check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::BatteryLevelCmd);
     let msg = ButtplugCurrentSpecClientMessage::BatteryLevelCmd(BatteryLevelCmd::new(self.index));
@@ -560,6 +659,61 @@ impl ButtplugClientDevice {
     self.send_message_expect_ok(msg)
   }
 
+  /// Subscribes to a device sensor, returning a stream of its readings.
+  ///
+  /// Sensors are indexed in the order of the SensorSubscribeCmd attributes,
+  /// and the type has to match the sensor's SensorType. The stream ends when
+  /// the device or client disconnects.
+  pub fn subscribe_sensor(
+    &self,
+    index: u32,
+    sensor_type: SensorType,
+  ) -> ButtplugClientResultFuture<Box<dyn Stream<Item = Vec<i32>> + Send + Unpin>> {
+    check_message_support!(
+      self,
+      ButtplugCurrentSpecDeviceMessageType::SensorSubscribeCmd
+    );
+    // Listen before subscribing, so we can't miss the first readings.
+    let events = self.event_stream();
+    let subscribe_fut =
+      self.send_message_expect_ok(SensorSubscribeCmd::new(self.index, index, sensor_type).into());
+    Box::pin(async move {
+      subscribe_fut.await?;
+      let readings = events
+        .take_while(|event| {
+          future::ready(!matches!(
+            event,
+            ButtplugClientDeviceEvent::DeviceRemoved | ButtplugClientDeviceEvent::ClientDisconnect
+          ))
+        })
+        .filter_map(move |event| {
+          future::ready(match event {
+            ButtplugClientDeviceEvent::SensorReading(reading_index, reading_type, data)
+              if reading_index == index && reading_type == sensor_type =>
+            {
+              Some(data)
+            }
+            _ => None,
+          })
+        });
+      Ok(Box::new(Box::pin(readings)) as Box<dyn Stream<Item = Vec<i32>> + Send + Unpin>)
+    })
+  }
+
+  /// Stops readings from a sensor subscribed to with
+  /// [ButtplugClientDevice::subscribe_sensor].
+  pub fn unsubscribe_sensor(
+    &self,
+    index: u32,
+    sensor_type: SensorType,
+  ) -> ButtplugClientResultFuture {
+    check_message_support!(
+      self,
+      ButtplugCurrentSpecDeviceMessageType::SensorSubscribeCmd
+    );
+    self.send_message_expect_ok(SensorUnsubscribeCmd::new(self.index, index, sensor_type).into())
+  }
+
   /// Commands device to stop all movement.
   pub fn stop(&self) -> ButtplugClientResultFuture {
     // Everything *should* support StopDeviceCmd but let's just make sure.
@@ -568,6 +722,21 @@ impl ButtplugClientDevice {
     self.send_message_expect_ok(StopDeviceCmd::new(self.index).into())
   }
 
+  /// Asks the server for a lease on the device.
+  ///
+  /// Only matters when other clients are connected to the same server. An
+  /// exclusive lease keeps other clients from commanding the device, a shared
+  /// lease lets other clients with shared leases command it too. Fails if
+  /// another client already holds a conflicting lease.
+  pub fn request_lease(&self, exclusive: bool) -> ButtplugClientResultFuture {
+    self.send_message_expect_ok(RequestDeviceLease::new(self.index, exclusive).into())
+  }
+
+  /// Gives up a lease taken with [ButtplugClientDevice::request_lease].
+  pub fn release_lease(&self) -> ButtplugClientResultFuture {
+    self.send_message_expect_ok(ReleaseDeviceLease::new(self.index).into())
+  }
+
   pub fn index(&self) -> u32 {
     self.index
   }
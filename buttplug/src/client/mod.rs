@@ -15,9 +15,9 @@ use crate::{
   core::{
     errors::{ButtplugError, ButtplugHandshakeError},
     messages::{
-      ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage,
-      ButtplugMessageSpecVersion, Ping, RequestDeviceList, RequestServerInfo, StartScanning,
-      StopAllDevices, StopScanning,
+      ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage, DeviceConnectionStage,
+      DeviceStopReason, LogLevel, Ping, RequestDeviceList, RequestLog, RequestServerInfo,
+      StartScanning, StopAllDevices, StopScanning, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
   util::{
@@ -32,7 +32,7 @@ use client_event_loop::{ButtplugClientEventLoop, ButtplugClientRequest};
 use dashmap::DashMap;
 pub use device::{
   ButtplugClientDevice, ButtplugClientDeviceEvent, ButtplugClientDeviceMessageType, LinearCommand,
-  RotateCommand, VibrateCommand,
+  RotateCommand, ScalarCommand, VibrateCommand,
 };
 use futures::{
   future::{self, BoxFuture},
@@ -130,6 +130,26 @@ pub enum ButtplugClientEvent {
   /// Emitted when a device has been removed from the server. Includes a
   /// [ButtplugClientDevice] object representing the device.
   DeviceRemoved(Arc<ButtplugClientDevice>),
+  /// Emitted when a device has dropped its connection and the server is
+  /// trying to reconnect to it. The device can't be commanded until
+  /// [ButtplugClientEvent::DeviceReconnected] is received for it. If the
+  /// server gives up, [ButtplugClientEvent::DeviceRemoved] is emitted instead.
+  DeviceReconnecting(Arc<ButtplugClientDevice>),
+  /// Emitted when a device that was reconnecting is available again. The
+  /// server restores the last actuator state it had before disconnecting.
+  DeviceReconnected(Arc<ButtplugClientDevice>),
+  /// Emitted when the server stops a device without being asked to, i.e. when
+  /// nothing has commanded a running device within the server's inactivity
+  /// timeout.
+  DeviceStopped(Arc<ButtplugClientDevice>, DeviceStopReason),
+  /// Emitted when the server's battery monitor sees a device's battery level
+  /// drop to or below one of its low battery thresholds. Includes the battery
+  /// level and the threshold.
+  BatteryLevelLow(Arc<ButtplugClientDevice>, f64, f64),
+  /// Emitted when the server found a device but failed to connect to or
+  /// initialize it. Includes the device name, and the stage and error it
+  /// failed with.
+  DeviceConnectionFailed(String, DeviceConnectionStage, String),
   /// Emitted when a client has not pinged the server in a sufficient amount of
   /// time.
   PingTimeout,
@@ -140,6 +160,10 @@ pub enum ButtplugClientEvent {
   /// Emitted when an error that cannot be matched to a request is received from
   /// the server.
   Error(ButtplugError),
+  /// Emitted when the server sends a log message, after log forwarding has
+  /// been turned on with [ButtplugClient::request_log]. Includes the level
+  /// and text of the message.
+  Log(LogLevel, String),
 }
 
 impl Unpin for ButtplugClientEvent {}
@@ -360,7 +384,7 @@ impl ButtplugClient {
     info!("Running handshake with server.");
     let msg = self
       .send_message_ignore_connect_status(
-        RequestServerInfo::new(&self.client_name, ButtplugMessageSpecVersion::Version2).into(),
+        RequestServerInfo::new(&self.client_name, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
       )
       .await?;
 
@@ -446,6 +470,17 @@ impl ButtplugClient {
     self.send_message_expect_ok(StopAllDevices::default().into())
   }
 
+  /// Tells server to send log messages at or above the requested level.
+  ///
+  /// Messages will show up as [ButtplugClientEvent::Log] events. Sending
+  /// [LogLevel::Off] stops log forwarding.
+  ///
+  /// Returns Err([ButtplugClientError]) if request fails due to disconnection,
+  /// etc.
+  pub fn request_log(&self, level: LogLevel) -> ButtplugClientResultFuture {
+    self.send_message_expect_ok(RequestLog::new(level).into())
+  }
+
   pub fn event_stream(&self) -> impl Stream<Item = ButtplugClientEvent> {
     let stream = convert_broadcast_receiver_to_stream(self.event_stream.subscribe());
     // We can either Box::pin here or force the user to pin_mut!() on their
@@ -87,10 +87,16 @@ pub use remote_connector::{
 #[cfg(feature = "websockets")]
 pub use transport::ButtplugWebsocketClientTransport;
 #[cfg(feature = "websockets")]
-pub use transport::{ButtplugWebsocketServerTransport, ButtplugWebsocketServerTransportBuilder};
+pub use transport::{
+  ButtplugWebsocketServerListener, ButtplugWebsocketServerTransport,
+  ButtplugWebsocketServerTransportBuilder,
+};
 
 use crate::{
-  core::messages::{serializer::ButtplugSerializedMessage, ButtplugMessage},
+  core::messages::{
+    serializer::{ButtplugSerializedMessage, MessageValidationLevel},
+    ButtplugMessage,
+  },
   util::future::{ButtplugFuture, ButtplugFutureStateShared},
 };
 use displaydoc::Display;
@@ -187,4 +193,7 @@ where
   /// If the connector is not currently connected, or an error happens during
   /// the send operation, this will return a [ButtplugConnectorError]
   fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture;
+  /// Sets how strictly incoming messages are checked, for connectors that
+  /// deserialize them. Only takes effect if called before connecting.
+  fn set_message_validation_level(&mut self, _level: MessageValidationLevel) {}
 }
@@ -13,6 +13,7 @@ use crate::{
   core::messages::{
     serializer::{
       ButtplugClientJSONSerializer, ButtplugMessageSerializer, ButtplugSerializedMessage,
+      MessageValidationLevel,
     },
     ButtplugClientMessage, ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage,
     ButtplugMessage, ButtplugServerMessage,
@@ -55,6 +56,7 @@ async fn remote_connector_event_loop<
   transport_outgoing_sender: Sender<ButtplugSerializedMessage>,
   // Takes data coming in from the transport.
   mut transport_incoming_recv: Receiver<ButtplugTransportIncomingMessage>,
+  validation_level: MessageValidationLevel,
 ) where
   TransportType: ButtplugConnectorTransport + 'static,
   SerializerType: ButtplugMessageSerializer<Inbound = InboundMessageType, Outbound = OutboundMessageType>
@@ -63,7 +65,8 @@ async fn remote_connector_event_loop<
   InboundMessageType: ButtplugMessage + 'static,
 {
   // Message sorter that receives messages that come in from the client.
-  let serializer = SerializerType::default();
+  let mut serializer = SerializerType::default();
+  serializer.set_validation_level(validation_level);
   loop {
     // We use two Options instead of an enum because we may never get anything.
     //
@@ -196,6 +199,8 @@ pub struct ButtplugRemoteConnector<
   transport: Option<TransportType>,
   /// Sender for forwarding outgoing messages to the connector event loop.
   event_loop_sender: Option<Sender<ButtplugRemoteConnectorMessage<OutboundMessageType>>>,
+  /// Checks the serializer runs on incoming messages.
+  validation_level: MessageValidationLevel,
   dummy_serializer: PhantomData<SerializerType>,
 }
 
@@ -212,6 +217,7 @@ where
     Self {
       transport: Some(transport),
       event_loop_sender: None,
+      validation_level: MessageValidationLevel::default(),
       dummy_serializer: PhantomData::default(),
     }
   }
@@ -239,6 +245,7 @@ where
     if self.transport.is_some() {
       // We can unwrap this because we just proved we had it.
       let transport = self.transport.take().unwrap();
+      let validation_level = self.validation_level;
       let (connector_outgoing_sender, connector_outgoing_receiver) = channel(256);
       self.event_loop_sender = Some(connector_outgoing_sender);
       Box::pin(async move {
@@ -263,6 +270,7 @@ where
                 transport,
                 transport_outgoing_sender,
                 transport_incoming_receiver,
+                validation_level,
               )
               .await
             })
@@ -291,6 +299,10 @@ where
     }
   }
 
+  fn set_message_validation_level(&mut self, level: MessageValidationLevel) {
+    self.validation_level = level;
+  }
+
   fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture {
     if let Some(ref sender) = self.event_loop_sender {
       let sender_clone = sender.clone();
@@ -6,7 +6,7 @@ use crate::connector::{
 use futures::future::BoxFuture;
 use tokio::sync::mpsc::{Receiver, Sender};
 #[cfg(feature = "websockets")]
-pub use websocket::{ButtplugWebsocketClientTransport, TungsteniteError, ButtplugWebsocketServerTransport, ButtplugWebsocketServerTransportBuilder};
+pub use websocket::{ButtplugWebsocketClientTransport, TungsteniteError, ButtplugWebsocketServerListener, ButtplugWebsocketServerTransport, ButtplugWebsocketServerTransportBuilder};
 
 use thiserror::Error;
 
@@ -15,7 +15,7 @@ use std::{
   sync::Arc,
   time::Duration
 };
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{
   mpsc::{Receiver, Sender},
   Mutex, Notify,
@@ -54,8 +54,64 @@ impl ButtplugWebsocketServerTransportBuilder {
       port: self.port,
       listen_on_all_interfaces: self.listen_on_all_interfaces,
       disconnect_notifier: Arc::new(Notify::new()),
+      accepted_stream: Arc::new(Mutex::new(None)),
     }
   }
+
+  /// Binds the port and returns a listener that can accept any number of
+  /// connections, instead of the single connection a transport from
+  /// [ButtplugWebsocketServerTransportBuilder::finish] accepts.
+  pub async fn listen(&self) -> Result<ButtplugWebsocketServerListener, ButtplugConnectorError> {
+    let addr = format!("{}:{}", base_address(self.listen_on_all_interfaces), self.port);
+    debug!("Websocket Insecure: Trying to listen on {}", addr);
+    let listener = TcpListener::bind(&addr).await.map_err(|e| {
+      ButtplugConnectorError::TransportSpecificError(
+        ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+      )
+    })?;
+    debug!("Websocket Insecure: Listening on: {}", addr);
+    Ok(ButtplugWebsocketServerListener {
+      port: self.port,
+      listen_on_all_interfaces: self.listen_on_all_interfaces,
+      listener,
+    })
+  }
+}
+
+fn base_address(listen_on_all_interfaces: bool) -> &'static str {
+  if listen_on_all_interfaces {
+    "0.0.0.0"
+  } else {
+    "127.0.0.1"
+  }
+}
+
+/// Listens for websocket connections, handing back a transport for each
+/// connection. Used for servers that handle multiple clients, like
+/// [ButtplugMultiClientServer][crate::server::ButtplugMultiClientServer].
+pub struct ButtplugWebsocketServerListener {
+  port: u16,
+  listen_on_all_interfaces: bool,
+  listener: TcpListener,
+}
+
+impl ButtplugWebsocketServerListener {
+  /// Waits for the next TCP connection. The websocket handshake happens when
+  /// the returned transport is connected.
+  pub async fn accept(&self) -> Result<ButtplugWebsocketServerTransport, ButtplugConnectorError> {
+    let (stream, addr) = self.listener.accept().await.map_err(|e| {
+      ButtplugConnectorError::TransportSpecificError(
+        ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+      )
+    })?;
+    info!("Websocket Insecure: Got connection from {}", addr);
+    Ok(ButtplugWebsocketServerTransport {
+      port: self.port,
+      listen_on_all_interfaces: self.listen_on_all_interfaces,
+      disconnect_notifier: Arc::new(Notify::new()),
+      accepted_stream: Arc::new(Mutex::new(Some(stream))),
+    })
+  }
 }
 
 async fn run_connection_loop<S>(
@@ -180,6 +236,9 @@ pub struct ButtplugWebsocketServerTransport {
   port: u16,
   listen_on_all_interfaces: bool,
   disconnect_notifier: Arc<Notify>,
+  /// Connection already accepted by a [ButtplugWebsocketServerListener]. If
+  /// this is None, we'll listen for a connection ourselves on connect.
+  accepted_stream: Arc<Mutex<Option<TcpStream>>>,
 }
 
 impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
@@ -190,30 +249,32 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
   ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
     let disconnect_notifier = self.disconnect_notifier.clone();
 
-    let base_addr = if self.listen_on_all_interfaces {
-      "0.0.0.0"
-    } else {
-      "127.0.0.1"
-    };
+    let base_addr = base_address(self.listen_on_all_interfaces);
 
     let request_receiver = Arc::new(Mutex::new(Some(outgoing_receiver)));
 
     let addr = format!("{}:{}", base_addr, self.port);
-    debug!("Websocket Insecure: Trying to listen on {}", addr);
     let request_receiver_clone = request_receiver;
     let response_sender_clone = incoming_sender;
     let disconnect_notifier_clone = disconnect_notifier;
+    let accepted_stream = self.accepted_stream.clone();
     let fut = async move {
-      // Create the event loop and TCP listener we'll accept connections on.
-      let try_socket = TcpListener::bind(&addr).await;
-      debug!("Websocket Insecure: Socket bound.");
-      let listener = try_socket.map_err(|e| {
-        ButtplugConnectorError::TransportSpecificError(
-          ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
-        )
-      })?;
-      debug!("Websocket Insecure: Listening on: {}", addr);
-      if let Ok((stream, _)) = listener.accept().await {
+      let stream = if let Some(stream) = accepted_stream.lock().await.take() {
+        Some(stream)
+      } else {
+        debug!("Websocket Insecure: Trying to listen on {}", addr);
+        // Create the event loop and TCP listener we'll accept connections on.
+        let try_socket = TcpListener::bind(&addr).await;
+        debug!("Websocket Insecure: Socket bound.");
+        let listener = try_socket.map_err(|e| {
+          ButtplugConnectorError::TransportSpecificError(
+            ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+          )
+        })?;
+        debug!("Websocket Insecure: Listening on: {}", addr);
+        listener.accept().await.ok().map(|(stream, _)| stream)
+      };
+      if let Some(stream) = stream {
         info!("Websocket Insecure: Got connection");
         let ws_fut = async_tungstenite::tokio::accept_async(stream);
         let ws_stream = ws_fut.await.map_err(|err| {
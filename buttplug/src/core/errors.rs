@@ -131,6 +131,8 @@ pub enum ButtplugDeviceError {
   DeviceConnectionError(String),
   /// Device communication error: {0}
   DeviceCommunicationError(String),
+  /// Device connection timed out: {0}
+  DeviceConnectionTimeout(String),
   /// Device does not have endpoint {0}
   InvalidEndpoint(Endpoint),
   /// Device does not handle command type: {0}
@@ -148,6 +150,10 @@ pub enum ButtplugDeviceError {
   DeviceScanningAlreadyStarted,
   /// Device scanning already stopped.
   DeviceScanningAlreadyStopped,
+  /// Device {0} is leased by another client.
+  DeviceLeasedByOtherClient(u32),
+  /// Client does not hold a lease on device {0}.
+  DeviceLeaseNotHeld(u32),
   /// Device permission error: {0}
   DevicePermissionError(String),
   /// {0}
@@ -162,6 +168,8 @@ pub enum ButtplugDeviceError {
   UntypedDeserializedError(String),
   /// Device Configuration File Error: {0}
   DeviceConfigurationFileError(String),
+  /// Device store error: {0}
+  DeviceStoreError(String),
 }
 
 /// Unknown errors occur in exceptional circumstances where no other error type
@@ -180,6 +188,10 @@ where
 pub enum ButtplugUnknownError {
   /// Cannot start scanning, no device communication managers available to use for scanning.
   NoDeviceCommManagers,
+  /// Device communication manager {0} does not exist.
+  DeviceCommManagerNotFound(String),
+  /// Device manager is no longer running.
+  DeviceManagerNotRunning,
   /// Got unexpected enum type: {0}
   UnexpectedType(String),
   /// Untyped Deserialized Error: {0}
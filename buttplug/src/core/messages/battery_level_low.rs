@@ -0,0 +1,63 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Sent by the server's battery monitor when a device's battery drops to or
+/// below one of the server's low battery thresholds.
+#[derive(Debug, Default, ButtplugMessage, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct BatteryLevelLow {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "BatteryLevel"))]
+  battery_level: f64,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Threshold"))]
+  threshold: f64,
+}
+
+impl BatteryLevelLow {
+  pub fn new(device_index: u32, battery_level: f64, threshold: f64) -> Self {
+    Self {
+      id: 0,
+      device_index,
+      battery_level,
+      threshold,
+    }
+  }
+
+  pub fn device_index(&self) -> u32 {
+    self.device_index
+  }
+
+  pub fn battery_level(&self) -> f64 {
+    self.battery_level
+  }
+
+  /// Lowest threshold the battery level is at or below.
+  pub fn threshold(&self) -> f64 {
+    self.threshold
+  }
+}
+
+impl ButtplugMessageValidator for BatteryLevelLow {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)?;
+    self.is_in_command_range(
+      self.battery_level,
+      "BatteryLevelLow battery level must be between 0.0 and 1.0".to_string(),
+    )?;
+    self.is_in_command_range(
+      self.threshold,
+      "BatteryLevelLow threshold must be between 0.0 and 1.0".to_string(),
+    )
+  }
+}
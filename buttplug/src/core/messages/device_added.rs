@@ -5,7 +5,7 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::device_message_info::{DeviceMessageInfoV0, DeviceMessageInfoV1};
+use super::device_message_info::{v2_device_messages, DeviceMessageInfoV0, DeviceMessageInfoV1};
 use super::*;
 
 #[cfg(feature = "serialize-json")]
@@ -20,6 +20,15 @@ pub struct DeviceAdded {
   device_index: u32,
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
   device_name: String,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceDisplayName",
+      skip_serializing_if = "Option::is_none",
+      default
+    )
+  )]
+  device_display_name: Option<String>,
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceMessages"))]
   device_messages: DeviceMessageAttributesMap,
 }
@@ -34,6 +43,7 @@ impl DeviceAdded {
       id: 0,
       device_index,
       device_name: device_name.to_string(),
+      device_display_name: None,
       device_messages: device_messages.clone(),
     }
   }
@@ -46,6 +56,15 @@ impl DeviceAdded {
     &self.device_name
   }
 
+  /// Name the user has given the device, if any.
+  pub fn device_display_name(&self) -> &Option<String> {
+    &self.device_display_name
+  }
+
+  pub fn set_device_display_name(&mut self, display_name: Option<String>) {
+    self.device_display_name = display_name;
+  }
+
   pub fn device_messages(&self) -> &DeviceMessageAttributesMap {
     &self.device_messages
   }
@@ -57,6 +76,46 @@ impl ButtplugMessageValidator for DeviceAdded {
   }
 }
 
+#[derive(Default, ButtplugMessage, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceAddedV2 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
+  device_name: String,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceDisplayName",
+      skip_serializing_if = "Option::is_none",
+      default
+    )
+  )]
+  device_display_name: Option<String>,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceMessages"))]
+  device_messages: DeviceMessageAttributesMap,
+}
+
+impl From<DeviceAdded> for DeviceAddedV2 {
+  fn from(msg: DeviceAdded) -> Self {
+    Self {
+      id: msg.id,
+      device_index: msg.device_index,
+      device_messages: v2_device_messages(&msg.device_messages),
+      device_name: msg.device_name,
+      device_display_name: msg.device_display_name,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for DeviceAddedV2 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
+
 #[derive(Default, ButtplugMessage, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceAddedV1 {
@@ -0,0 +1,78 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Where in the connection process a device failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum DeviceConnectionStage {
+  /// Connecting to the device and setting up its endpoints.
+  Connect,
+  /// Running the protocol's initialization, i.e. asking a Lovense toy what it
+  /// is.
+  Initialize,
+}
+
+/// Sent when a device matched a protocol but couldn't be brought up, so
+/// applications can tell users about devices that were found but never got
+/// added.
+#[derive(Debug, ButtplugMessage, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceConnectionFailed {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
+  device_name: String,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceAddress"))]
+  device_address: String,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Stage"))]
+  stage: DeviceConnectionStage,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "ErrorMessage"))]
+  error_message: String,
+}
+
+impl DeviceConnectionFailed {
+  pub fn new(
+    device_name: &str,
+    device_address: &str,
+    stage: DeviceConnectionStage,
+    error_message: &str,
+  ) -> Self {
+    Self {
+      id: 0,
+      device_name: device_name.to_owned(),
+      device_address: device_address.to_owned(),
+      stage,
+      error_message: error_message.to_owned(),
+    }
+  }
+
+  pub fn device_name(&self) -> &String {
+    &self.device_name
+  }
+
+  pub fn device_address(&self) -> &String {
+    &self.device_address
+  }
+
+  pub fn stage(&self) -> DeviceConnectionStage {
+    self.stage
+  }
+
+  pub fn error_message(&self) -> &String {
+    &self.error_message
+  }
+}
+
+impl ButtplugMessageValidator for DeviceConnectionFailed {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
@@ -5,7 +5,7 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::device_message_info::{DeviceMessageInfoV0, DeviceMessageInfoV1};
+use super::device_message_info::{DeviceMessageInfoV0, DeviceMessageInfoV1, DeviceMessageInfoV2};
 use super::*;
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
@@ -35,6 +35,34 @@ impl ButtplugMessageValidator for DeviceList {
   }
 }
 
+#[derive(Default, Clone, Debug, PartialEq, ButtplugMessage)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceListV2 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Devices"))]
+  devices: Vec<DeviceMessageInfoV2>,
+}
+
+impl From<DeviceList> for DeviceListV2 {
+  fn from(msg: DeviceList) -> Self {
+    Self {
+      id: msg.id,
+      devices: msg
+        .devices
+        .into_iter()
+        .map(DeviceMessageInfoV2::from)
+        .collect(),
+    }
+  }
+}
+
+impl ButtplugMessageValidator for DeviceListV2 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
+
 #[derive(Default, Clone, Debug, PartialEq, ButtplugMessage)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceListV1 {
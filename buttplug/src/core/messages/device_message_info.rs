@@ -27,6 +27,16 @@ pub struct DeviceMessageInfo {
   pub device_index: u32,
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
   pub device_name: String,
+  /// Name the user has given the device, if any.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceDisplayName",
+      skip_serializing_if = "Option::is_none",
+      default
+    )
+  )]
+  pub device_display_name: Option<String>,
   #[cfg_attr(
     feature = "serialize-json",
     serde(rename = "DeviceMessages", serialize_with = "ordered_map")
@@ -48,6 +58,7 @@ impl DeviceMessageInfo {
     Self {
       device_index,
       device_name: device_name.to_owned(),
+      device_display_name: None,
       device_messages: device_messages.to_owned(),
       original_device_messages: device_messages,
     }
@@ -59,12 +70,76 @@ impl From<DeviceAdded> for DeviceMessageInfo {
     Self {
       device_index: device_added.device_index(),
       device_name: device_added.device_name().clone(),
+      device_display_name: device_added.device_display_name().clone(),
       device_messages: device_added.device_messages().clone(),
       original_device_messages: device_added.device_messages().clone(),
     }
   }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceMessageInfoV2 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  pub device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
+  pub device_name: String,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceDisplayName",
+      skip_serializing_if = "Option::is_none",
+      default
+    )
+  )]
+  pub device_display_name: Option<String>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "DeviceMessages", serialize_with = "ordered_map")
+  )]
+  pub device_messages: DeviceMessageAttributesMap,
+}
+
+/// Removes messages and attributes that were added in v3. Devices that can
+/// vibrate already list VibrateCmd next to ScalarCmd, so v2 clients can still
+/// use them.
+pub(super) fn v2_device_messages(
+  device_messages: &DeviceMessageAttributesMap,
+) -> DeviceMessageAttributesMap {
+  device_messages
+    .iter()
+    .filter(|(message_type, _)| {
+      !matches!(
+        message_type,
+        ButtplugDeviceMessageType::ScalarCmd
+          | ButtplugDeviceMessageType::PatternCmd
+          | ButtplugDeviceMessageType::SensorSubscribeCmd
+      )
+    })
+    .map(|(message_type, attributes)| {
+      let attributes = DeviceMessageAttributes {
+        actuator_type: None,
+        patterns: None,
+        sensor_type: None,
+        sensor_range: None,
+        ..attributes.clone()
+      };
+      (*message_type, attributes)
+    })
+    .collect()
+}
+
+impl From<DeviceMessageInfo> for DeviceMessageInfoV2 {
+  fn from(device_message_info: DeviceMessageInfo) -> Self {
+    Self {
+      device_index: device_message_info.device_index,
+      device_name: device_message_info.device_name,
+      device_display_name: device_message_info.device_display_name,
+      device_messages: v2_device_messages(&device_message_info.original_device_messages),
+    }
+  }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceMessageInfoV1 {
@@ -102,6 +177,9 @@ impl From<DeviceMessageInfo> for DeviceMessageInfoV1 {
       ButtplugDeviceMessageType::RawUnsubscribeCmd,
       ButtplugDeviceMessageType::BatteryLevelCmd,
       ButtplugDeviceMessageType::RSSILevelCmd,
+      ButtplugDeviceMessageType::ScalarCmd,
+      ButtplugDeviceMessageType::PatternCmd,
+      ButtplugDeviceMessageType::SensorSubscribeCmd,
     ];
     for t in &v2_message_types {
       dmi_v1.device_messages.remove(t);
@@ -0,0 +1,63 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Why the server stopped a device on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum DeviceStopReason {
+  /// The device was running but hadn't received a command within the server's
+  /// inactivity timeout.
+  InactivityTimeout,
+}
+
+// Not derived, as that needs `#[default]`, which our minimum rust version
+// doesn't have.
+#[allow(clippy::derivable_impls)]
+impl Default for DeviceStopReason {
+  fn default() -> Self {
+    DeviceStopReason::InactivityTimeout
+  }
+}
+
+#[derive(Debug, Default, ButtplugMessage, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceStopped {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Reason"))]
+  reason: DeviceStopReason,
+}
+
+impl DeviceStopped {
+  pub fn new(device_index: u32, reason: DeviceStopReason) -> Self {
+    Self {
+      id: 0,
+      device_index,
+      reason,
+    }
+  }
+
+  pub fn device_index(&self) -> u32 {
+    self.device_index
+  }
+
+  pub fn reason(&self) -> DeviceStopReason {
+    self.reason
+  }
+}
+
+impl ButtplugMessageValidator for DeviceStopped {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
@@ -10,7 +10,7 @@ use serde::{Deserialize, Serialize};
 use std::cmp::Ord;
 use tracing::Level;
 
-#[derive(Debug, PartialEq, Clone, Ord, PartialOrd, Eq)]
+#[derive(Debug, PartialEq, Clone, Copy, Ord, PartialOrd, Eq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum LogLevel {
   Off = 0,
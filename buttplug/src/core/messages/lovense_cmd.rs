@@ -9,9 +9,8 @@ use super::*;
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
-// As this message is considered deprecated and is not actually implemented for
-// Lovense devices even on spec v1 connections, we can put a null validator on
-// it.
+// This message is deprecated. The command string is checked when it's
+// translated into generic commands, so there's nothing to validate here.
 #[derive(Debug, ButtplugDeviceMessage, PartialEq, Clone)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct LovenseCmd {
@@ -31,6 +30,10 @@ impl LovenseCmd {
       command: command.to_owned(),
     }
   }
+
+  pub fn command(&self) -> &str {
+    &self.command
+  }
 }
 
 impl ButtplugMessageValidator for LovenseCmd {
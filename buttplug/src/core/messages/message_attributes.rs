@@ -30,17 +30,111 @@ pub struct DeviceMessageAttributes {
   #[serde(rename = "MaxDuration")]
   #[serde(skip_serializing_if = "Option::is_none")]
   pub max_duration: Option<Vec<u32>>,
-  /*
-  // Unimplemented attributes
-  #[serde(rename = "Patterns")]
+  // Output limits, set through the user device configuration. Speeds and
+  // positions sent by clients are scaled into these before being converted to
+  // device steps.
+  #[serde(rename = "MaxSpeed")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_speed: Option<Vec<f64>>,
+  #[serde(rename = "PositionRange")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub position_range: Option<Vec<(f64, f64)>>,
+  #[serde(rename = "ResponseCurve")]
   #[serde(skip_serializing_if = "Option::is_none")]
-  patterns: Option<Vec<Vec<String>>>,
+  pub response_curve: Option<ResponseCurve>,
+  // Type of each actuator, for ScalarCmd.
   #[serde(rename = "ActuatorType")]
   #[serde(skip_serializing_if = "Option::is_none")]
-  actuator_type: Option<Vec<String>>,
-  */
+  pub actuator_type: Option<Vec<ActuatorType>>,
+  // Names of the firmware patterns each feature can play, for PatternCmd.
+  #[serde(rename = "Patterns")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub patterns: Option<Vec<Vec<String>>>,
+  // Kind of each sensor, for SensorSubscribeCmd. Sensor N reports through
+  // endpoint N of the Endpoints attribute.
+  #[serde(rename = "SensorType")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sensor_type: Option<Vec<SensorType>>,
+  // Minimum and maximum value each sensor reports.
+  #[serde(rename = "SensorRange")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sensor_range: Option<Vec<(i32, i32)>>,
   // Never serialize this, its for internal use only
   #[serde(rename = "FeatureOrder")]
   #[serde(skip)]
   pub feature_order: Option<Vec<u32>>,
 }
+
+/// What an actuator does with the value it's sent in a ScalarCmd.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum ActuatorType {
+  Vibrate,
+  Rotate,
+  Oscillate,
+  Constrict,
+  Inflate,
+  Position,
+  Electrostimulate,
+}
+
+/// What a sensor measures, for SensorSubscribeCmd and SensorReading.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum SensorType {
+  Pressure,
+  Accelerometer,
+  Touch,
+  Button,
+}
+
+/// Maps speeds sent by clients to the speeds sent to the device, for devices
+/// whose output doesn't feel linear.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ResponseCurve {
+  /// Output is the input raised to this power.
+  Gamma(f64),
+  /// Output is interpolated between evenly spaced points, where the first
+  /// point is the output for 0.0 and the last is the output for 1.0.
+  Lookup(Vec<f64>),
+}
+
+impl ResponseCurve {
+  pub fn apply(&self, value: f64) -> f64 {
+    let value = value.clamp(0.0, 1.0);
+    let output = match self {
+      ResponseCurve::Gamma(gamma) => value.powf(*gamma),
+      ResponseCurve::Lookup(points) => match points.len() {
+        0 => value,
+        1 => points[0],
+        len => {
+          let position = value * (len - 1) as f64;
+          let lower = position.floor() as usize;
+          let upper = (lower + 1).min(len - 1);
+          let fraction = position - lower as f64;
+          points[lower] + (points[upper] - points[lower]) * fraction
+        }
+      },
+    };
+    output.clamp(0.0, 1.0)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ResponseCurve;
+
+  #[test]
+  fn test_response_curve_lookup_interpolates() {
+    let curve = ResponseCurve::Lookup(vec![0.0, 0.2, 1.0]);
+    assert!((curve.apply(0.0) - 0.0).abs() < f64::EPSILON);
+    assert!((curve.apply(0.25) - 0.1).abs() < 1e-9);
+    assert!((curve.apply(0.75) - 0.6).abs() < 1e-9);
+    assert!((curve.apply(1.0) - 1.0).abs() < f64::EPSILON);
+  }
+
+  #[test]
+  fn test_response_curve_gamma() {
+    let curve = ResponseCurve::Gamma(2.0);
+    assert!((curve.apply(0.5) - 0.25).abs() < f64::EPSILON);
+    assert!((curve.apply(1.5) - 1.0).abs() < f64::EPSILON);
+  }
+}
@@ -15,11 +15,16 @@
 //! instance, messages that only should be sent by a client or server.
 
 mod battery_level_cmd;
+mod battery_level_low;
 mod battery_level_reading;
 mod device_added;
+mod device_connection_failed;
 mod device_list;
 mod device_message_info;
+mod device_reconnected;
+mod device_reconnecting;
 mod device_removed;
+mod device_stopped;
 mod error;
 mod fleshlight_launch_fw12_cmd;
 mod kiiroo_cmd;
@@ -29,19 +34,26 @@ mod log_level;
 mod lovense_cmd;
 mod message_attributes;
 mod ok;
+mod pattern_cmd;
 mod ping;
 mod raw_read_cmd;
 mod raw_reading;
 mod raw_subscribe_cmd;
 mod raw_unsubscribe_cmd;
 mod raw_write_cmd;
+mod release_device_lease;
+mod request_device_lease;
 mod request_device_list;
 mod request_log;
 mod request_server_info;
 mod rotate_cmd;
 mod rssi_level_cmd;
 mod rssi_level_reading;
+mod scalar_cmd;
 mod scanning_finished;
+mod sensor_reading;
+mod sensor_subscribe_cmd;
+mod sensor_unsubscribe_cmd;
 pub mod serializer;
 mod server_info;
 mod single_motor_vibrate_cmd;
@@ -55,32 +67,44 @@ mod vorze_a10_cyclone_cmd;
 
 pub use self::log::Log;
 pub use battery_level_cmd::BatteryLevelCmd;
+pub use battery_level_low::BatteryLevelLow;
 pub use battery_level_reading::BatteryLevelReading;
-pub use device_added::{DeviceAdded, DeviceAddedV0, DeviceAddedV1};
-pub use device_list::{DeviceList, DeviceListV0, DeviceListV1};
+pub use device_added::{DeviceAdded, DeviceAddedV0, DeviceAddedV1, DeviceAddedV2};
+pub use device_connection_failed::{DeviceConnectionFailed, DeviceConnectionStage};
+pub use device_list::{DeviceList, DeviceListV0, DeviceListV1, DeviceListV2};
 pub use device_message_info::{DeviceMessageAttributesMap, DeviceMessageInfo};
+pub use device_reconnected::DeviceReconnected;
+pub use device_reconnecting::DeviceReconnecting;
 pub use device_removed::DeviceRemoved;
+pub use device_stopped::{DeviceStopReason, DeviceStopped};
 pub use error::{Error, ErrorCode, ErrorV0};
 pub use fleshlight_launch_fw12_cmd::FleshlightLaunchFW12Cmd;
 pub use kiiroo_cmd::KiirooCmd;
 pub use linear_cmd::{LinearCmd, VectorSubcommand};
 pub use log_level::LogLevel;
 pub use lovense_cmd::LovenseCmd;
-pub use message_attributes::DeviceMessageAttributes;
+pub use message_attributes::{ActuatorType, DeviceMessageAttributes, ResponseCurve, SensorType};
 pub use ok::Ok;
+pub use pattern_cmd::{PatternCmd, PatternSubcommand};
 pub use ping::Ping;
 pub use raw_read_cmd::RawReadCmd;
 pub use raw_reading::RawReading;
 pub use raw_subscribe_cmd::RawSubscribeCmd;
 pub use raw_unsubscribe_cmd::RawUnsubscribeCmd;
 pub use raw_write_cmd::RawWriteCmd;
+pub use release_device_lease::ReleaseDeviceLease;
+pub use request_device_lease::RequestDeviceLease;
 pub use request_device_list::RequestDeviceList;
 pub use request_log::RequestLog;
 pub use request_server_info::RequestServerInfo;
 pub use rotate_cmd::{RotateCmd, RotationSubcommand};
 pub use rssi_level_cmd::RSSILevelCmd;
 pub use rssi_level_reading::RSSILevelReading;
-pub use scanning_finished::ScanningFinished;
+pub use scalar_cmd::{ScalarCmd, ScalarSubcommand};
+pub use scanning_finished::{ScanningFinished, ScanningFinishedReason};
+pub use sensor_reading::SensorReading;
+pub use sensor_subscribe_cmd::SensorSubscribeCmd;
+pub use sensor_unsubscribe_cmd::SensorUnsubscribeCmd;
 pub use server_info::{ServerInfo, ServerInfoV0};
 pub use single_motor_vibrate_cmd::SingleMotorVibrateCmd;
 pub use start_scanning::StartScanning;
@@ -107,6 +131,7 @@ pub enum ButtplugMessageSpecVersion {
   Version0 = 0,
   Version1 = 1,
   Version2 = 2,
+  Version3 = 3,
 }
 
 /// Message Id for events sent from the server, which are not in response to a
@@ -115,7 +140,7 @@ pub const BUTTPLUG_SERVER_EVENT_ID: u32 = 0;
 
 /// The current latest version of the spec implemented by the library.
 pub const BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION: ButtplugMessageSpecVersion =
-  ButtplugMessageSpecVersion::Version2;
+  ButtplugMessageSpecVersion::Version3;
 
 /// Base trait for all Buttplug Protocol Message Structs. Handles management of
 /// message ids, as well as implementing conveinence functions for converting
@@ -187,6 +212,8 @@ pub enum ButtplugDeviceMessageType {
   VibrateCmd,
   LinearCmd,
   RotateCmd,
+  ScalarCmd,
+  PatternCmd,
   StopDeviceCmd,
   RawWriteCmd,
   RawReadCmd,
@@ -194,6 +221,7 @@ pub enum ButtplugDeviceMessageType {
   RawUnsubscribeCmd,
   BatteryLevelCmd,
   RSSILevelCmd,
+  SensorSubscribeCmd,
   // Deprecated generic commands
   SingleMotorVibrateCmd,
   // Deprecated device specific commands
@@ -229,6 +257,8 @@ pub enum ButtplugCurrentSpecDeviceMessageType {
   VibrateCmd,
   LinearCmd,
   RotateCmd,
+  ScalarCmd,
+  PatternCmd,
   StopDeviceCmd,
   RawWriteCmd,
   RawReadCmd,
@@ -236,6 +266,7 @@ pub enum ButtplugCurrentSpecDeviceMessageType {
   RawUnsubscribeCmd,
   BatteryLevelCmd,
   RSSILevelCmd,
+  SensorSubscribeCmd,
 }
 
 // Ordering for ButtplugCurrentDeviceMessageType should be lexicographic, for
@@ -259,6 +290,8 @@ impl TryFrom<ButtplugDeviceMessageType> for ButtplugCurrentSpecDeviceMessageType
       ButtplugDeviceMessageType::VibrateCmd => Ok(ButtplugCurrentSpecDeviceMessageType::VibrateCmd),
       ButtplugDeviceMessageType::LinearCmd => Ok(ButtplugCurrentSpecDeviceMessageType::LinearCmd),
       ButtplugDeviceMessageType::RotateCmd => Ok(ButtplugCurrentSpecDeviceMessageType::RotateCmd),
+      ButtplugDeviceMessageType::ScalarCmd => Ok(ButtplugCurrentSpecDeviceMessageType::ScalarCmd),
+      ButtplugDeviceMessageType::PatternCmd => Ok(ButtplugCurrentSpecDeviceMessageType::PatternCmd),
       ButtplugDeviceMessageType::StopDeviceCmd => {
         Ok(ButtplugCurrentSpecDeviceMessageType::StopDeviceCmd)
       }
@@ -278,6 +311,9 @@ impl TryFrom<ButtplugDeviceMessageType> for ButtplugCurrentSpecDeviceMessageType
       ButtplugDeviceMessageType::RSSILevelCmd => {
         Ok(ButtplugCurrentSpecDeviceMessageType::RSSILevelCmd)
       }
+      ButtplugDeviceMessageType::SensorSubscribeCmd => {
+        Ok(ButtplugCurrentSpecDeviceMessageType::SensorSubscribeCmd)
+      }
       _ => Err(ButtplugMessageError::MessageConversionError(
         "Device message deprecated, does not exist in current version of protocol.".to_owned(),
       )),
@@ -291,6 +327,8 @@ impl From<ButtplugCurrentSpecDeviceMessageType> for ButtplugDeviceMessageType {
       ButtplugCurrentSpecDeviceMessageType::VibrateCmd => ButtplugDeviceMessageType::VibrateCmd,
       ButtplugCurrentSpecDeviceMessageType::LinearCmd => ButtplugDeviceMessageType::LinearCmd,
       ButtplugCurrentSpecDeviceMessageType::RotateCmd => ButtplugDeviceMessageType::RotateCmd,
+      ButtplugCurrentSpecDeviceMessageType::ScalarCmd => ButtplugDeviceMessageType::ScalarCmd,
+      ButtplugCurrentSpecDeviceMessageType::PatternCmd => ButtplugDeviceMessageType::PatternCmd,
       ButtplugCurrentSpecDeviceMessageType::StopDeviceCmd => {
         ButtplugDeviceMessageType::StopDeviceCmd
       }
@@ -306,6 +344,9 @@ impl From<ButtplugCurrentSpecDeviceMessageType> for ButtplugDeviceMessageType {
         ButtplugDeviceMessageType::BatteryLevelCmd
       }
       ButtplugCurrentSpecDeviceMessageType::RSSILevelCmd => ButtplugDeviceMessageType::RSSILevelCmd,
+      ButtplugCurrentSpecDeviceMessageType::SensorSubscribeCmd => {
+        ButtplugDeviceMessageType::SensorSubscribeCmd
+      }
     }
   }
 }
@@ -331,11 +372,16 @@ pub enum ButtplugClientMessage {
   StartScanning(StartScanning),
   StopScanning(StopScanning),
   RequestDeviceList(RequestDeviceList),
+  // Device lease messages
+  RequestDeviceLease(RequestDeviceLease),
+  ReleaseDeviceLease(ReleaseDeviceLease),
   // Generic commands
   StopAllDevices(StopAllDevices),
   VibrateCmd(VibrateCmd),
   LinearCmd(LinearCmd),
   RotateCmd(RotateCmd),
+  ScalarCmd(ScalarCmd),
+  PatternCmd(PatternCmd),
   RawWriteCmd(RawWriteCmd),
   RawReadCmd(RawReadCmd),
   StopDeviceCmd(StopDeviceCmd),
@@ -344,6 +390,8 @@ pub enum ButtplugClientMessage {
   // Sensor commands
   BatteryLevelCmd(BatteryLevelCmd),
   RSSILevelCmd(RSSILevelCmd),
+  SensorSubscribeCmd(SensorSubscribeCmd),
+  SensorUnsubscribeCmd(SensorUnsubscribeCmd),
   // Deprecated generic commands
   SingleMotorVibrateCmd(SingleMotorVibrateCmd),
   // Deprecated device specific commands
@@ -352,7 +400,6 @@ pub enum ButtplugClientMessage {
   KiirooCmd(KiirooCmd),
   VorzeA10CycloneCmd(VorzeA10CycloneCmd),
   // To Add:
-  // PatternCmd
   // ShockCmd?
   // ToneEmitterCmd?
 }
@@ -381,20 +428,26 @@ pub enum ButtplugServerMessage {
   DeviceList(DeviceList),
   DeviceAdded(DeviceAdded),
   DeviceRemoved(DeviceRemoved),
+  DeviceReconnecting(DeviceReconnecting),
+  DeviceReconnected(DeviceReconnected),
+  DeviceStopped(DeviceStopped),
+  DeviceConnectionFailed(DeviceConnectionFailed),
   ScanningFinished(ScanningFinished),
   // Generic commands
   RawReading(RawReading),
   // Sensor Reading Messages
   BatteryLevelReading(BatteryLevelReading),
+  BatteryLevelLow(BatteryLevelLow),
   RSSILevelReading(RSSILevelReading),
+  SensorReading(SensorReading),
 }
 
 /// Type alias for the latest version of client-to-server messages.
-pub type ButtplugCurrentSpecClientMessage = ButtplugSpecV2ClientMessage;
+pub type ButtplugCurrentSpecClientMessage = ButtplugSpecV3ClientMessage;
 /// Type alias for the latest version of server-to-client messages.
-pub type ButtplugCurrentSpecServerMessage = ButtplugSpecV2ServerMessage;
+pub type ButtplugCurrentSpecServerMessage = ButtplugSpecV3ServerMessage;
 
-/// Represents all client-to-server messages in v2 of the Buttplug Spec
+/// Represents all client-to-server messages in v3 of the Buttplug Spec
 #[derive(
   Debug,
   Clone,
@@ -406,19 +459,25 @@ pub type ButtplugCurrentSpecServerMessage = ButtplugSpecV2ServerMessage;
   TryFromButtplugClientMessage,
 )]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
-pub enum ButtplugSpecV2ClientMessage {
+pub enum ButtplugSpecV3ClientMessage {
   // Handshake messages
   RequestServerInfo(RequestServerInfo),
   Ping(Ping),
+  RequestLog(RequestLog),
   // Device enumeration messages
   StartScanning(StartScanning),
   StopScanning(StopScanning),
   RequestDeviceList(RequestDeviceList),
+  // Device lease messages
+  RequestDeviceLease(RequestDeviceLease),
+  ReleaseDeviceLease(ReleaseDeviceLease),
   // Generic commands
   StopAllDevices(StopAllDevices),
   VibrateCmd(VibrateCmd),
   LinearCmd(LinearCmd),
   RotateCmd(RotateCmd),
+  ScalarCmd(ScalarCmd),
+  PatternCmd(PatternCmd),
   RawWriteCmd(RawWriteCmd),
   RawReadCmd(RawReadCmd),
   StopDeviceCmd(StopDeviceCmd),
@@ -427,9 +486,11 @@ pub enum ButtplugSpecV2ClientMessage {
   // Sensor commands
   BatteryLevelCmd(BatteryLevelCmd),
   RSSILevelCmd(RSSILevelCmd),
+  SensorSubscribeCmd(SensorSubscribeCmd),
+  SensorUnsubscribeCmd(SensorUnsubscribeCmd),
 }
 
-/// Represents all server-to-client messages in v2 of the Buttplug Spec
+/// Represents all server-to-client messages in v3 of the Buttplug Spec
 #[derive(
   Debug,
   Clone,
@@ -441,22 +502,134 @@ pub enum ButtplugSpecV2ClientMessage {
   TryFromButtplugServerMessage,
 )]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
-pub enum ButtplugSpecV2ServerMessage {
+pub enum ButtplugSpecV3ServerMessage {
   // Status messages
   Ok(Ok),
   Error(Error),
+  Log(Log),
   // Handshake messages
   ServerInfo(ServerInfo),
   // Device enumeration messages
   DeviceList(DeviceList),
   DeviceAdded(DeviceAdded),
   DeviceRemoved(DeviceRemoved),
+  DeviceReconnecting(DeviceReconnecting),
+  DeviceReconnected(DeviceReconnected),
+  DeviceStopped(DeviceStopped),
+  DeviceConnectionFailed(DeviceConnectionFailed),
   ScanningFinished(ScanningFinished),
   // Generic commands
   RawReading(RawReading),
   // Sensor commands
   BatteryLevelReading(BatteryLevelReading),
+  BatteryLevelLow(BatteryLevelLow),
   RSSILevelReading(RSSILevelReading),
+  SensorReading(SensorReading),
+}
+
+/// Represents all client-to-server messages in v2 of the Buttplug Spec
+#[derive(
+  Debug,
+  Clone,
+  PartialEq,
+  ButtplugMessage,
+  ButtplugMessageValidator,
+  ButtplugClientMessageType,
+  FromSpecificButtplugMessage,
+  TryFromButtplugClientMessage,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum ButtplugSpecV2ClientMessage {
+  // Handshake messages
+  RequestServerInfo(RequestServerInfo),
+  Ping(Ping),
+  RequestLog(RequestLog),
+  // Device enumeration messages
+  StartScanning(StartScanning),
+  StopScanning(StopScanning),
+  RequestDeviceList(RequestDeviceList),
+  // Generic commands
+  StopAllDevices(StopAllDevices),
+  VibrateCmd(VibrateCmd),
+  LinearCmd(LinearCmd),
+  RotateCmd(RotateCmd),
+  RawWriteCmd(RawWriteCmd),
+  RawReadCmd(RawReadCmd),
+  StopDeviceCmd(StopDeviceCmd),
+  RawSubscribeCmd(RawSubscribeCmd),
+  RawUnsubscribeCmd(RawUnsubscribeCmd),
+  // Sensor commands
+  BatteryLevelCmd(BatteryLevelCmd),
+  RSSILevelCmd(RSSILevelCmd),
+}
+
+/// Represents all server-to-client messages in v2 of the Buttplug Spec
+#[derive(
+  Debug,
+  Clone,
+  PartialEq,
+  ButtplugMessage,
+  ButtplugMessageValidator,
+  ButtplugServerMessageType,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum ButtplugSpecV2ServerMessage {
+  // Status messages
+  Ok(Ok),
+  Error(Error),
+  Log(Log),
+  // Handshake messages
+  ServerInfo(ServerInfo),
+  // Device enumeration messages
+  DeviceList(DeviceListV2),
+  DeviceAdded(DeviceAddedV2),
+  DeviceRemoved(DeviceRemoved),
+  ScanningFinished(ScanningFinished),
+  // Generic commands
+  RawReading(RawReading),
+  // Sensor commands
+  BatteryLevelReading(BatteryLevelReading),
+  RSSILevelReading(RSSILevelReading),
+}
+
+// Written out instead of derived, so ScanningFinished can drop its reason.
+impl TryFrom<ButtplugServerMessage> for ButtplugSpecV2ServerMessage {
+  type Error = ButtplugMessageError;
+  fn try_from(msg: ButtplugServerMessage) -> Result<Self, ButtplugMessageError> {
+    match msg {
+      ButtplugServerMessage::Ok(msg) => Ok(ButtplugSpecV2ServerMessage::Ok(msg)),
+      ButtplugServerMessage::Error(msg) => Ok(ButtplugSpecV2ServerMessage::Error(msg)),
+      ButtplugServerMessage::Log(msg) => Ok(ButtplugSpecV2ServerMessage::Log(msg)),
+      ButtplugServerMessage::ServerInfo(msg) => Ok(ButtplugSpecV2ServerMessage::ServerInfo(msg)),
+      ButtplugServerMessage::DeviceList(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceList(msg.into()))
+      }
+      ButtplugServerMessage::DeviceAdded(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceAdded(msg.into()))
+      }
+      ButtplugServerMessage::DeviceRemoved(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceRemoved(msg))
+      }
+      // Finish reasons were added in v3.
+      ButtplugServerMessage::ScanningFinished(msg) => {
+        let mut v2_msg = ScanningFinished::default();
+        v2_msg.set_id(msg.id());
+        Ok(ButtplugSpecV2ServerMessage::ScanningFinished(v2_msg))
+      }
+      ButtplugServerMessage::RawReading(msg) => Ok(ButtplugSpecV2ServerMessage::RawReading(msg)),
+      ButtplugServerMessage::BatteryLevelReading(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::BatteryLevelReading(msg))
+      }
+      ButtplugServerMessage::RSSILevelReading(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::RSSILevelReading(msg))
+      }
+      _ => Err(ButtplugMessageError::VersionError(
+        "ButtplugServerMessage".to_owned(),
+        format!("{:?}", msg),
+        "ButtplugSpecV2ServerMessage".to_owned(),
+      )),
+    }
+  }
 }
 
 /// Represents all client-to-server messages in v1 of the Buttplug Spec
@@ -534,9 +707,10 @@ impl TryFrom<ButtplugServerMessage> for ButtplugSpecV1ServerMessage {
       ButtplugServerMessage::DeviceRemoved(msg) => {
         Ok(ButtplugSpecV1ServerMessage::DeviceRemoved(msg))
       }
-      ButtplugServerMessage::ScanningFinished(msg) => {
-        Ok(ButtplugSpecV1ServerMessage::ScanningFinished(msg))
-      }
+      // Older specs don't know about finish reasons.
+      ButtplugServerMessage::ScanningFinished(_) => Ok(
+        ButtplugSpecV1ServerMessage::ScanningFinished(ScanningFinished::default()),
+      ),
       _ => Err(ButtplugMessageError::VersionError(
         "ButtplugServerMessage".to_owned(),
         format!("{:?}", msg),
@@ -619,9 +793,9 @@ impl TryFrom<ButtplugServerMessage> for ButtplugSpecV0ServerMessage {
       ButtplugServerMessage::DeviceRemoved(msg) => {
         Ok(ButtplugSpecV0ServerMessage::DeviceRemoved(msg))
       }
-      ButtplugServerMessage::ScanningFinished(msg) => {
-        Ok(ButtplugSpecV0ServerMessage::ScanningFinished(msg))
-      }
+      ButtplugServerMessage::ScanningFinished(_) => Ok(
+        ButtplugSpecV0ServerMessage::ScanningFinished(ScanningFinished::default()),
+      ),
       _ => Err(ButtplugMessageError::VersionError(
         "ButtplugServerMessage".to_owned(),
         format!("{:?}", msg),
@@ -666,10 +840,12 @@ pub enum ButtplugDeviceCommandMessageUnion {
   SingleMotorVibrateCmd(SingleMotorVibrateCmd),
   VorzeA10CycloneCmd(VorzeA10CycloneCmd),
   KiirooCmd(KiirooCmd),
-  // No LovenseCmd, it was never implemented anywhere.
+  LovenseCmd(LovenseCmd),
   VibrateCmd(VibrateCmd),
   LinearCmd(LinearCmd),
   RotateCmd(RotateCmd),
+  ScalarCmd(ScalarCmd),
+  PatternCmd(PatternCmd),
   RawWriteCmd(RawWriteCmd),
   RawReadCmd(RawReadCmd),
   StopDeviceCmd(StopDeviceCmd),
@@ -677,4 +853,6 @@ pub enum ButtplugDeviceCommandMessageUnion {
   RawUnsubscribeCmd(RawUnsubscribeCmd),
   BatteryLevelCmd(BatteryLevelCmd),
   RSSILevelCmd(RSSILevelCmd),
+  SensorSubscribeCmd(SensorSubscribeCmd),
+  SensorUnsubscribeCmd(SensorUnsubscribeCmd),
 }
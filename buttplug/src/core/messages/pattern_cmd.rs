@@ -0,0 +1,70 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct PatternSubcommand {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Index"))]
+  index: u32,
+  // None stops whatever pattern the feature is playing.
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Pattern"))]
+  pattern: Option<String>,
+}
+
+impl PatternSubcommand {
+  pub fn new(index: u32, pattern: Option<&str>) -> Self {
+    Self {
+      index,
+      pattern: pattern.map(|pattern| pattern.to_owned()),
+    }
+  }
+
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+
+  pub fn pattern(&self) -> Option<&str> {
+    self.pattern.as_deref()
+  }
+}
+
+/// Starts or stops patterns built into the device firmware. Pattern names for
+/// each feature are listed in the Patterns attribute. Added in spec v3.
+#[derive(Debug, Default, ButtplugDeviceMessage, PartialEq, Clone)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct PatternCmd {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Patterns"))]
+  patterns: Vec<PatternSubcommand>,
+}
+
+impl PatternCmd {
+  pub fn new(device_index: u32, patterns: Vec<PatternSubcommand>) -> Self {
+    Self {
+      id: 1,
+      device_index,
+      patterns,
+    }
+  }
+
+  pub fn patterns(&self) -> &Vec<PatternSubcommand> {
+    &self.patterns
+  }
+}
+
+impl ButtplugMessageValidator for PatternCmd {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
@@ -0,0 +1,46 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Asks the server for a lease on a device, when multiple clients are
+/// connected to the same server.
+///
+/// Exclusive leases mean only the lease holder can send commands to the
+/// device. Shared leases can be held by multiple clients at once.
+#[derive(Debug, ButtplugDeviceMessage, PartialEq, Clone)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct RequestDeviceLease {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Exclusive"))]
+  exclusive: bool,
+}
+
+impl RequestDeviceLease {
+  pub fn new(device_index: u32, exclusive: bool) -> Self {
+    Self {
+      id: 1,
+      device_index,
+      exclusive,
+    }
+  }
+
+  pub fn exclusive(&self) -> bool {
+    self.exclusive
+  }
+}
+
+impl ButtplugMessageValidator for RequestDeviceLease {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
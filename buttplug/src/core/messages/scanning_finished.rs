@@ -9,11 +9,41 @@ use super::*;
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
+/// Why a scanning session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum ScanningFinishedReason {
+  /// All comm managers finished scanning on their own.
+  Completed,
+  /// The scan duration given in StartScanning passed.
+  Timeout,
+  /// Scanning was stopped via StopScanning.
+  Stopped,
+}
+
 #[derive(Debug, Default, ButtplugMessage, Clone, PartialEq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct ScanningFinished {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
   id: u32,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "Reason", default, skip_serializing_if = "Option::is_none")
+  )]
+  reason: Option<ScanningFinishedReason>,
+}
+
+impl ScanningFinished {
+  pub fn new(reason: ScanningFinishedReason) -> Self {
+    Self {
+      id: 0,
+      reason: Some(reason),
+    }
+  }
+
+  pub fn reason(&self) -> Option<ScanningFinishedReason> {
+    self.reason
+  }
 }
 
 impl ButtplugMessageValidator for ScanningFinished {
@@ -0,0 +1,86 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Values reported by a sensor the client subscribed to with
+/// SensorSubscribeCmd. Most sensors report a single value, accelerometers
+/// report one per axis. Added in spec v3.
+#[derive(Debug, ButtplugDeviceMessage, PartialEq, Clone)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct SensorReading {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SensorIndex"))]
+  sensor_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SensorType"))]
+  sensor_type: SensorType,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Data"))]
+  data: Vec<i32>,
+}
+
+impl SensorReading {
+  pub fn new(
+    device_index: u32,
+    sensor_index: u32,
+    sensor_type: SensorType,
+    data: Vec<i32>,
+  ) -> Self {
+    Self {
+      id: 0,
+      device_index,
+      sensor_index,
+      sensor_type,
+      data,
+    }
+  }
+
+  pub fn sensor_index(&self) -> u32 {
+    self.sensor_index
+  }
+
+  pub fn sensor_type(&self) -> SensorType {
+    self.sensor_type
+  }
+
+  pub fn data(&self) -> &Vec<i32> {
+    &self.data
+  }
+}
+
+impl ButtplugMessageValidator for SensorReading {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
+
+#[cfg(feature = "serialize-json")]
+#[cfg(test)]
+mod test {
+  use crate::core::messages::{ButtplugCurrentSpecServerMessage, SensorReading, SensorType};
+
+  #[test]
+  fn test_sensor_reading_serialize() {
+    let union = ButtplugCurrentSpecServerMessage::SensorReading(SensorReading::new(
+      0,
+      1,
+      SensorType::Pressure,
+      vec![512],
+    ));
+    let js = serde_json::to_string(&union).unwrap();
+    let reading_str = "{\"SensorReading\":{\"Id\":0,\"DeviceIndex\":0,\"SensorIndex\":1,\"SensorType\":\"Pressure\",\"Data\":[512]}}";
+    assert_eq!(js, reading_str);
+    assert_eq!(
+      serde_json::from_str::<ButtplugCurrentSpecServerMessage>(reading_str).unwrap(),
+      union
+    );
+  }
+}
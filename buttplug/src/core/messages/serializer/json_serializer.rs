@@ -1,18 +1,23 @@
-use super::{ButtplugMessageSerializer, ButtplugSerializedMessage, ButtplugSerializerError};
+use super::{
+  ButtplugMessageSerializer, ButtplugSerializedMessage, ButtplugSerializerError,
+  MessageValidationLevel,
+};
 use crate::{
   core::{
     errors::{ButtplugError, ButtplugHandshakeError},
     messages::{
       self, ButtplugClientMessage, ButtplugCurrentSpecClientMessage,
       ButtplugCurrentSpecServerMessage, ButtplugMessage, ButtplugMessageSpecVersion,
-      ButtplugServerMessage, ButtplugSpecV0ClientMessage, ButtplugSpecV0ServerMessage,
-      ButtplugSpecV1ClientMessage, ButtplugSpecV1ServerMessage, ButtplugSpecV2ClientMessage,
-      ButtplugSpecV2ServerMessage,
+      ButtplugMessageValidator, ButtplugServerMessage, ButtplugSpecV0ClientMessage,
+      ButtplugSpecV0ServerMessage, ButtplugSpecV1ClientMessage, ButtplugSpecV1ServerMessage,
+      ButtplugSpecV2ClientMessage, ButtplugSpecV2ServerMessage, ButtplugSpecV3ClientMessage,
+      ButtplugSpecV3ServerMessage, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
   util::json::JSONValidator,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::cell::RefCell;
 use std::convert::TryFrom;
 
@@ -23,16 +28,24 @@ static MESSAGE_JSON_SCHEMA: &str =
 pub fn create_message_validator() -> JSONValidator {
   JSONValidator::new(MESSAGE_JSON_SCHEMA)
 }
+
+thread_local! {
+  // Compiling the schema takes far longer than validating a message, so it's
+  // only done once per thread and shared by every serializer on that thread.
+  // Valico's compiled schemas aren't Sync, so they can't be shared further.
+  static MESSAGE_VALIDATOR: JSONValidator = create_message_validator();
+}
+
 pub struct ButtplugServerJSONSerializer {
   pub(super) message_version: RefCell<Option<messages::ButtplugMessageSpecVersion>>,
-  validator: JSONValidator,
+  validation_level: MessageValidationLevel,
 }
 
 impl Default for ButtplugServerJSONSerializer {
   fn default() -> Self {
     Self {
       message_version: RefCell::new(None),
-      validator: create_message_validator(),
+      validation_level: MessageValidationLevel::default(),
     }
   }
 }
@@ -53,18 +66,51 @@ where
 }
 
 fn deserialize_to_message<T>(
-  validator: &JSONValidator,
+  validation_level: MessageValidationLevel,
   msg: String,
 ) -> Result<Vec<T>, ButtplugSerializerError>
 where
-  T: serde::de::DeserializeOwned + Clone,
+  T: serde::de::DeserializeOwned + Clone + ButtplugMessageValidator,
 {
   // We have to pass back a string formatted error, as SerdeJson's error type
   // isn't clonable.
-  validator.validate(&msg).and_then(|_| {
-    serde_json::from_str::<Vec<T>>(&msg)
-      .map_err(|e| ButtplugSerializerError::JsonSerializerError(format!("{:?}", e)))
-  })
+  let to_serializer_error =
+    |e: serde_json::Error| ButtplugSerializerError::JsonSerializerError(format!("{:?}", e));
+  let msgs = match validation_level {
+    MessageValidationLevel::Schema => {
+      // Parse once, and deserialize from the value we validated.
+      let value: Value = serde_json::from_str(&msg).map_err(to_serializer_error)?;
+      MESSAGE_VALIDATOR.with(|validator| validator.validate_value(&value))?;
+      serde_json::from_value::<Vec<T>>(value).map_err(to_serializer_error)?
+    }
+    MessageValidationLevel::Strict => {
+      // Serde skips fields it doesn't know about, so keep track of them and
+      // reject the message if there were any.
+      let mut unknown_fields = vec![];
+      let mut deserializer = serde_json::Deserializer::from_str(&msg);
+      let msgs: Vec<T> = serde_ignored::deserialize(&mut deserializer, |path| {
+        unknown_fields.push(path.to_string())
+      })
+      .map_err(to_serializer_error)?;
+      deserializer.end().map_err(to_serializer_error)?;
+      if !unknown_fields.is_empty() {
+        return Err(ButtplugSerializerError::MessageValidatorError(format!(
+          "Unknown message fields: {}",
+          unknown_fields.join(", ")
+        )));
+      }
+      for msg in &msgs {
+        msg
+          .is_valid()
+          .map_err(|e| ButtplugSerializerError::MessageValidatorError(e.to_string()))?;
+      }
+      msgs
+    }
+    MessageValidationLevel::Off => {
+      serde_json::from_str::<Vec<T>>(&msg).map_err(to_serializer_error)?
+    }
+  };
+  Ok(msgs)
 }
 
 fn serialize_to_version(
@@ -109,6 +155,17 @@ fn serialize_to_version(
         .collect();
       vec_to_protocol_json(msg_vec)
     }
+    ButtplugMessageSpecVersion::Version3 => {
+      let msg_vec: Vec<ButtplugSpecV3ServerMessage> = msgs
+        .iter()
+        .cloned()
+        .map(|msg| match ButtplugSpecV3ServerMessage::try_from(msg) {
+          Ok(msgv3) => msgv3,
+          Err(err) => ButtplugSpecV3ServerMessage::Error(ButtplugError::from(err).into()),
+        })
+        .collect();
+      vec_to_protocol_json(msg_vec)
+    }
   })
 }
 
@@ -135,23 +192,26 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
     if let Some(version) = *self.message_version.borrow() {
       return Ok(match version {
         ButtplugMessageSpecVersion::Version0 => {
-          deserialize_to_message::<ButtplugSpecV0ClientMessage>(&self.validator, msg)?
-            .iter()
-            .cloned()
+          deserialize_to_message::<ButtplugSpecV0ClientMessage>(self.validation_level, msg)?
+            .into_iter()
             .map(|m| m.into())
             .collect()
         }
         ButtplugMessageSpecVersion::Version1 => {
-          deserialize_to_message::<ButtplugSpecV1ClientMessage>(&self.validator, msg)?
-            .iter()
-            .cloned()
+          deserialize_to_message::<ButtplugSpecV1ClientMessage>(self.validation_level, msg)?
+            .into_iter()
             .map(|m| m.into())
             .collect()
         }
         ButtplugMessageSpecVersion::Version2 => {
-          deserialize_to_message::<ButtplugSpecV2ClientMessage>(&self.validator, msg)?
-            .iter()
-            .cloned()
+          deserialize_to_message::<ButtplugSpecV2ClientMessage>(self.validation_level, msg)?
+            .into_iter()
+            .map(|m| m.into())
+            .collect()
+        }
+        ButtplugMessageSpecVersion::Version3 => {
+          deserialize_to_message::<ButtplugSpecV3ClientMessage>(self.validation_level, msg)?
+            .into_iter()
             .map(|m| m.into())
             .collect()
         }
@@ -159,12 +219,13 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
     }
     // instead of using if/else here, return in the if, which drops the borrow.
     // so we can possibly mutate it now.
-    let msg_union = deserialize_to_message::<ButtplugSpecV2ClientMessage>(&self.validator, msg)?;
+    let msg_union =
+      deserialize_to_message::<ButtplugSpecV3ClientMessage>(self.validation_level, msg)?;
     // If the message is malformed, just return an spec version not received error.
     if msg_union.is_empty() {
       return Err(ButtplugSerializerError::MessageSpecVersionNotReceived);
     }
-    if let ButtplugSpecV2ClientMessage::RequestServerInfo(rsi) = &msg_union[0] {
+    if let ButtplugSpecV3ClientMessage::RequestServerInfo(rsi) = &msg_union[0] {
       info!(
         "Setting JSON Wrapper message version to {}",
         rsi.message_version()
@@ -173,7 +234,11 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
     } else {
       return Err(ButtplugSerializerError::MessageSpecVersionNotReceived);
     }
-    Ok(msg_union.iter().cloned().map(|m| m.into()).collect())
+    Ok(msg_union.into_iter().map(|m| m.into()).collect())
+  }
+
+  fn set_validation_level(&mut self, level: MessageValidationLevel) {
+    self.validation_level = level;
   }
 
   fn serialize(&self, msgs: Vec<ButtplugServerMessage>) -> ButtplugSerializedMessage {
@@ -184,7 +249,7 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
       // RequestServerInfo message (so we can't set up our known spec
       // version), just encode to the latest and return.
       if let ButtplugServerMessage::Error(_) = &msgs[0] {
-        serialize_to_version(ButtplugMessageSpecVersion::Version2, msgs)
+        serialize_to_version(BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION, msgs)
       } else {
         // If we don't even have enough info to know which message
         // version to convert to, consider this a handshake error.
@@ -198,21 +263,11 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
   }
 }
 
+#[derive(Default)]
 pub struct ButtplugClientJSONSerializer {
-  validator: JSONValidator,
+  validation_level: MessageValidationLevel,
 }
 
-impl Default for ButtplugClientJSONSerializer {
-  fn default() -> Self {
-    Self {
-      validator: create_message_validator(),
-    }
-  }
-}
-
-unsafe impl Sync for ButtplugClientJSONSerializer {}
-unsafe impl Send for ButtplugClientJSONSerializer {}
-
 impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
   type Inbound = ButtplugCurrentSpecServerMessage;
   type Outbound = ButtplugCurrentSpecClientMessage;
@@ -222,7 +277,7 @@ impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
     msg: ButtplugSerializedMessage,
   ) -> Result<Vec<ButtplugCurrentSpecServerMessage>, ButtplugSerializerError> {
     if let ButtplugSerializedMessage::Text(text_msg) = msg {
-      deserialize_to_message::<Self::Inbound>(&self.validator, text_msg)
+      deserialize_to_message::<Self::Inbound>(self.validation_level, text_msg)
     } else {
       Err(ButtplugSerializerError::BinaryDeserializationError)
     }
@@ -231,12 +286,19 @@ impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
   fn serialize(&self, msg: Vec<ButtplugCurrentSpecClientMessage>) -> ButtplugSerializedMessage {
     ButtplugSerializedMessage::Text(vec_to_protocol_json(msg))
   }
+
+  fn set_validation_level(&mut self, level: MessageValidationLevel) {
+    self.validation_level = level;
+  }
 }
 
 #[cfg(test)]
 mod test {
   use super::*;
-  use crate::core::messages::{RequestServerInfo, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION};
+  use crate::core::messages::{
+    ActuatorType, ButtplugDeviceMessageType, DeviceAdded, DeviceMessageAttributes,
+    DeviceMessageAttributesMap, RequestServerInfo, ScanningFinished, ScanningFinishedReason,
+  };
 
   #[test]
   fn test_correct_message_version() {
@@ -308,4 +370,86 @@ mod test {
       }
     }
   }
+
+  #[test]
+  fn test_scanning_finished_reason_dropped_for_v2() {
+    let msg: ButtplugServerMessage = ScanningFinished::new(ScanningFinishedReason::Timeout).into();
+    assert_eq!(
+      serialize_to_version(ButtplugMessageSpecVersion::Version3, vec![msg.clone()]),
+      ButtplugSerializedMessage::Text(
+        r#"[{"ScanningFinished":{"Id":0,"Reason":"Timeout"}}]"#.to_owned()
+      )
+    );
+    assert_eq!(
+      serialize_to_version(ButtplugMessageSpecVersion::Version2, vec![msg]),
+      ButtplugSerializedMessage::Text(r#"[{"ScanningFinished":{"Id":0}}]"#.to_owned())
+    );
+  }
+
+  #[test]
+  fn test_validation_levels() {
+    let extra_field = r#"[{"Ping":{"Id":1,"NotAField":1}}]"#;
+    let system_id = r#"[{"Ping":{"Id":0}}]"#;
+    let wrong_type = r#"[{"Ping":{"Id":"one"}}]"#;
+    let deserialize = |level, msg: &str| {
+      let mut serializer = ButtplugServerJSONSerializer::default();
+      serializer.set_validation_level(level);
+      *serializer.message_version.borrow_mut() = Some(ButtplugMessageSpecVersion::Version3);
+      serializer.deserialize(ButtplugSerializedMessage::Text(msg.to_owned()))
+    };
+    assert!(deserialize(MessageValidationLevel::Schema, extra_field).is_err());
+    assert!(matches!(
+      deserialize(MessageValidationLevel::Strict, extra_field),
+      Err(ButtplugSerializerError::MessageValidatorError(_))
+    ));
+    assert!(deserialize(MessageValidationLevel::Off, extra_field).is_ok());
+    assert!(matches!(
+      deserialize(MessageValidationLevel::Strict, system_id),
+      Err(ButtplugSerializerError::MessageValidatorError(_))
+    ));
+    assert!(deserialize(MessageValidationLevel::Off, system_id).is_ok());
+    for level in [
+      MessageValidationLevel::Schema,
+      MessageValidationLevel::Strict,
+      MessageValidationLevel::Off,
+    ] {
+      assert!(deserialize(level, wrong_type).is_err());
+    }
+  }
+
+  #[test]
+  fn test_scalar_cmd_downgrade() {
+    let mut attributes = DeviceMessageAttributesMap::new();
+    attributes.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        step_count: Some(vec![20]),
+        ..Default::default()
+      },
+    );
+    attributes.insert(
+      ButtplugDeviceMessageType::ScalarCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        step_count: Some(vec![20]),
+        actuator_type: Some(vec![ActuatorType::Vibrate]),
+        ..Default::default()
+      },
+    );
+    let msgs = vec![DeviceAdded::new(0, "Test Device", &attributes).into()];
+    let v3 = match serialize_to_version(ButtplugMessageSpecVersion::Version3, msgs.clone()) {
+      ButtplugSerializedMessage::Text(text) => text,
+      _ => panic!("Expected text"),
+    };
+    assert!(v3.contains("\"ScalarCmd\""));
+    assert!(v3.contains("\"ActuatorType\":[\"Vibrate\"]"));
+    let v2 = match serialize_to_version(ButtplugMessageSpecVersion::Version2, msgs) {
+      ButtplugSerializedMessage::Text(text) => text,
+      _ => panic!("Expected text"),
+    };
+    assert!(v2.contains("\"VibrateCmd\""));
+    assert!(!v2.contains("ScalarCmd"));
+    assert!(!v2.contains("ActuatorType"));
+  }
 }
@@ -13,6 +13,9 @@ pub enum ButtplugSerializerError {
   // turn it into a big string and pass that back.
   #[error("JSON Schema Validation Error: {0}")]
   JsonValidatorError(String),
+  /// Message failed its own validity checks (i.e. using a system id).
+  #[error("Message Validation Error: {0}")]
+  MessageValidatorError(String),
   /// Serialization error.
   #[error("Cannot serialize to JSON: {0}")]
   JsonSerializerError(String),
@@ -24,6 +27,30 @@ pub enum ButtplugSerializerError {
   MessageSpecVersionNotReceived,
 }
 
+/// How much checking incoming messages get while being deserialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageValidationLevel {
+  /// Validate against the full message schema before deserializing. Catches
+  /// unknown fields and out of range values.
+  Schema,
+  /// Skip the schema. Messages still have to deserialize into the spec's
+  /// message types without unknown fields, and are then checked by their own
+  /// validators (i.e. for system ids).
+  Strict,
+  /// Only deserialize. For trusted clients, like applications embedding the
+  /// server in the same process.
+  Off,
+}
+
+// Deriving this needs `#[default]` on the variant, which is newer than the
+// compilers we support.
+#[allow(clippy::derivable_impls)]
+impl Default for MessageValidationLevel {
+  fn default() -> Self {
+    MessageValidationLevel::Schema
+  }
+}
+
 #[derive(Debug, Display, Clone, PartialEq)]
 pub enum ButtplugSerializedMessage {
   Text(String),
@@ -50,4 +77,7 @@ pub trait ButtplugMessageSerializer: Default + Sync + Send {
     msg: ButtplugSerializedMessage,
   ) -> ButtplugSerializerResult<Vec<Self::Inbound>>;
   fn serialize(&self, msg: Vec<Self::Outbound>) -> ButtplugSerializedMessage;
+  /// Sets how strictly incoming messages are checked. Serializers that don't
+  /// validate can ignore this.
+  fn set_validation_level(&mut self, _level: MessageValidationLevel) {}
 }
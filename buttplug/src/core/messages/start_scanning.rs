@@ -9,21 +9,81 @@ use super::*;
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
+/// Starts scanning for devices. With no options set, scanning runs on every
+/// comm manager until they all report that they're done.
 #[derive(Debug, ButtplugMessage, Clone, PartialEq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct StartScanning {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
   id: u32,
+  /// Scan duration in milliseconds. Scanning is stopped once this passes, even
+  /// if comm managers are still scanning.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "Duration", default, skip_serializing_if = "Option::is_none")
+  )]
+  duration: Option<u32>,
+  /// Names of the comm managers to scan with. All comm managers are used if
+  /// this isn't set.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "CommManagers", default, skip_serializing_if = "Option::is_none")
+  )]
+  comm_managers: Option<Vec<String>>,
+  /// If true, scans are restarted whenever they finish, until StopScanning is
+  /// sent or the duration passes.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "Continuous", default, skip_serializing_if = "std::ops::Not::not")
+  )]
+  continuous: bool,
 }
 
 impl Default for StartScanning {
   fn default() -> Self {
-    Self { id: 1 }
+    Self {
+      id: 1,
+      duration: None,
+      comm_managers: None,
+      continuous: false,
+    }
+  }
+}
+
+impl StartScanning {
+  pub fn duration(&self) -> Option<u32> {
+    self.duration
+  }
+
+  pub fn set_duration(&mut self, duration: Option<u32>) {
+    self.duration = duration;
+  }
+
+  pub fn comm_managers(&self) -> &Option<Vec<String>> {
+    &self.comm_managers
+  }
+
+  pub fn set_comm_managers(&mut self, comm_managers: Option<Vec<String>>) {
+    self.comm_managers = comm_managers;
+  }
+
+  pub fn continuous(&self) -> bool {
+    self.continuous
+  }
+
+  pub fn set_continuous(&mut self, continuous: bool) {
+    self.continuous = continuous;
   }
 }
 
 impl ButtplugMessageValidator for StartScanning {
   fn is_valid(&self) -> Result<(), ButtplugMessageError> {
-    self.is_not_system_id(self.id)
+    self.is_not_system_id(self.id)?;
+    if self.duration == Some(0) {
+      return Err(ButtplugMessageError::InvalidMessageContents(
+        "StartScanning duration must be greater than 0.".to_owned(),
+      ));
+    }
+    Ok(())
   }
 }
@@ -0,0 +1,290 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Latest state of each of a device's actuators.
+//!
+//! Clients can set the same actuator with different messages (a vibrator can
+//! get VibrateCmd, ScalarCmd or SingleMotorVibrateCmd), so state is kept per
+//! actuator instead of per message type. That way a ScalarCmd stopping a motor
+//! replaces the VibrateCmd that started it, instead of both being kept around
+//! and resent in whatever order on reconnect.
+
+use super::command_scheduler::is_running_command;
+use crate::core::messages::{
+  ActuatorType, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage,
+  ButtplugDeviceMessageType, DeviceMessageAttributesMap, LinearCmd, PatternCmd, PatternSubcommand,
+  RotateCmd, RotationSubcommand, ScalarCmd, ScalarSubcommand, VectorSubcommand, VibrateCmd,
+  VibrateSubcommand,
+};
+use std::{
+  collections::HashMap,
+  mem::{self, Discriminant},
+};
+
+/// Identifies an actuator, whichever message was used to set it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ActuatorKey {
+  /// The nth actuator of a type, counting the same way VibrateCmd and
+  /// RotateCmd indexes do, so ScalarCmd index 2 may be vibrator 1.
+  Actuator(ActuatorType, u32),
+  Linear(u32),
+  Pattern(u32),
+  /// Commands that set the whole device at once, like VorzeA10CycloneCmd.
+  Device(Discriminant<ButtplugDeviceCommandMessageUnion>),
+}
+
+/// The last thing an actuator was told to do.
+#[derive(Debug, Clone)]
+enum ActuatorCommand {
+  Vibrate(VibrateSubcommand),
+  Rotate(RotationSubcommand),
+  Scalar(ScalarSubcommand),
+  Linear(VectorSubcommand),
+  Pattern(PatternSubcommand),
+  Device(ButtplugDeviceCommandMessageUnion),
+}
+
+impl ActuatorCommand {
+  fn is_running(&self) -> bool {
+    match self {
+      ActuatorCommand::Vibrate(cmd) => cmd.speed() > 0.0,
+      ActuatorCommand::Rotate(cmd) => cmd.speed() > 0.0,
+      ActuatorCommand::Scalar(cmd) => cmd.scalar() > 0.0,
+      ActuatorCommand::Linear(_) => false,
+      ActuatorCommand::Pattern(cmd) => cmd.pattern().is_some(),
+      ActuatorCommand::Device(msg) => is_running_command(msg),
+    }
+  }
+}
+
+#[derive(Default)]
+pub(super) struct ActuatorState {
+  device_index: u32,
+  actuators: HashMap<ActuatorKey, ActuatorCommand>,
+}
+
+impl ActuatorState {
+  /// Records an actuator command. `attributes` are the message attributes
+  /// clients see, used to work out which actuators a command refers to.
+  pub fn update(
+    &mut self,
+    message: ButtplugDeviceCommandMessageUnion,
+    attributes: &DeviceMessageAttributesMap,
+  ) {
+    self.device_index = message.device_index();
+    match message {
+      ButtplugDeviceCommandMessageUnion::VibrateCmd(msg) => {
+        for cmd in msg.speeds() {
+          self.actuators.insert(
+            ActuatorKey::Actuator(ActuatorType::Vibrate, cmd.index()),
+            ActuatorCommand::Vibrate(cmd.clone()),
+          );
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::SingleMotorVibrateCmd(msg) => {
+        let vibrator_count = attributes
+          .get(&ButtplugDeviceMessageType::VibrateCmd)
+          .and_then(|attrs| attrs.feature_count)
+          .unwrap_or(0);
+        for index in 0..vibrator_count {
+          self.actuators.insert(
+            ActuatorKey::Actuator(ActuatorType::Vibrate, index),
+            ActuatorCommand::Vibrate(VibrateSubcommand::new(index, msg.speed())),
+          );
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+        for cmd in &msg.rotations {
+          self.actuators.insert(
+            ActuatorKey::Actuator(ActuatorType::Rotate, cmd.index()),
+            ActuatorCommand::Rotate(cmd.clone()),
+          );
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+        let actuator_types = attributes
+          .get(&ButtplugDeviceMessageType::ScalarCmd)
+          .and_then(|attrs| attrs.actuator_type.clone())
+          .unwrap_or_default();
+        for cmd in msg.scalars() {
+          // Count the actuators of the same type before this one.
+          let nth = actuator_types
+            .iter()
+            .take(cmd.index() as usize)
+            .filter(|actuator_type| **actuator_type == cmd.actuator_type())
+            .count() as u32;
+          self.actuators.insert(
+            ActuatorKey::Actuator(cmd.actuator_type(), nth),
+            ActuatorCommand::Scalar(cmd.clone()),
+          );
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => {
+        for cmd in msg.vectors() {
+          self.actuators.insert(
+            ActuatorKey::Linear(cmd.index()),
+            ActuatorCommand::Linear(cmd.clone()),
+          );
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::PatternCmd(msg) => {
+        for cmd in msg.patterns() {
+          self.actuators.insert(
+            ActuatorKey::Pattern(cmd.index()),
+            ActuatorCommand::Pattern(cmd.clone()),
+          );
+        }
+      }
+      msg => {
+        self.actuators.insert(
+          ActuatorKey::Device(mem::discriminant(&msg)),
+          ActuatorCommand::Device(msg),
+        );
+      }
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.actuators.clear();
+  }
+
+  /// True if any actuator was last told to move.
+  pub fn is_running(&self) -> bool {
+    self.actuators.values().any(ActuatorCommand::is_running)
+  }
+
+  /// Commands that put a device back in this state, with each actuator set by
+  /// the type of message it was last sent.
+  pub fn commands(&self) -> Vec<ButtplugDeviceCommandMessageUnion> {
+    let mut vibrations = vec![];
+    let mut rotations = vec![];
+    let mut scalars = vec![];
+    let mut vectors = vec![];
+    let mut patterns = vec![];
+    let mut commands = vec![];
+    for command in self.actuators.values() {
+      match command {
+        ActuatorCommand::Vibrate(cmd) => vibrations.push(cmd.clone()),
+        ActuatorCommand::Rotate(cmd) => rotations.push(cmd.clone()),
+        ActuatorCommand::Scalar(cmd) => scalars.push(cmd.clone()),
+        ActuatorCommand::Linear(cmd) => vectors.push(cmd.clone()),
+        ActuatorCommand::Pattern(cmd) => patterns.push(cmd.clone()),
+        ActuatorCommand::Device(msg) => commands.push(msg.clone()),
+      }
+    }
+    vibrations.sort_by_key(|cmd| cmd.index());
+    rotations.sort_by_key(|cmd| cmd.index());
+    scalars.sort_by_key(|cmd| cmd.index());
+    vectors.sort_by_key(|cmd| cmd.index());
+    patterns.sort_by_key(|cmd| cmd.index());
+    if !vibrations.is_empty() {
+      commands.push(VibrateCmd::new(self.device_index, vibrations).into());
+    }
+    if !rotations.is_empty() {
+      commands.push(RotateCmd::new(self.device_index, rotations).into());
+    }
+    if !scalars.is_empty() {
+      commands.push(ScalarCmd::new(self.device_index, scalars).into());
+    }
+    if !vectors.is_empty() {
+      commands.push(LinearCmd::new(self.device_index, vectors).into());
+    }
+    if !patterns.is_empty() {
+      commands.push(PatternCmd::new(self.device_index, patterns).into());
+    }
+    commands
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::core::messages::DeviceMessageAttributes;
+
+  fn scalar_attributes(actuator_types: Vec<ActuatorType>) -> DeviceMessageAttributesMap {
+    let mut attributes = DeviceMessageAttributesMap::new();
+    attributes.insert(
+      ButtplugDeviceMessageType::ScalarCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(actuator_types.len() as u32),
+        actuator_type: Some(actuator_types),
+        ..Default::default()
+      },
+    );
+    attributes
+  }
+
+  #[test]
+  fn test_scalar_replaces_vibrate_for_same_actuator() {
+    // Scalar index 1 is the first vibrator.
+    let attributes = scalar_attributes(vec![ActuatorType::Rotate, ActuatorType::Vibrate]);
+    let mut state = ActuatorState::default();
+    state.update(
+      VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into(),
+      &attributes,
+    );
+    assert!(state.is_running());
+    let stop: ButtplugDeviceCommandMessageUnion = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(1, 0.0, ActuatorType::Vibrate)],
+    )
+    .into();
+    state.update(stop.clone(), &attributes);
+    assert!(!state.is_running());
+    assert_eq!(state.commands(), vec![stop]);
+  }
+
+  #[test]
+  fn test_rotate_and_scalar_rotate_share_state() {
+    let attributes = scalar_attributes(vec![ActuatorType::Vibrate, ActuatorType::Rotate]);
+    let mut state = ActuatorState::default();
+    state.update(
+      ScalarCmd::new(0, vec![ScalarSubcommand::new(1, 0.5, ActuatorType::Rotate)]).into(),
+      &attributes,
+    );
+    let rotate: ButtplugDeviceCommandMessageUnion =
+      RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.25, true)]).into();
+    state.update(rotate.clone(), &attributes);
+    assert_eq!(state.commands(), vec![rotate]);
+  }
+
+  #[test]
+  fn test_commands_keep_untouched_actuators() {
+    let attributes = scalar_attributes(vec![ActuatorType::Vibrate, ActuatorType::Vibrate]);
+    let mut state = ActuatorState::default();
+    state.update(
+      VibrateCmd::new(
+        3,
+        vec![
+          VibrateSubcommand::new(0, 0.5),
+          VibrateSubcommand::new(1, 0.5),
+        ],
+      )
+      .into(),
+      &attributes,
+    );
+    state.update(
+      ScalarCmd::new(
+        3,
+        vec![ScalarSubcommand::new(0, 1.0, ActuatorType::Vibrate)],
+      )
+      .into(),
+      &attributes,
+    );
+    assert_eq!(
+      state.commands(),
+      vec![
+        VibrateCmd::new(3, vec![VibrateSubcommand::new(1, 0.5)]).into(),
+        ScalarCmd::new(
+          3,
+          vec![ScalarSubcommand::new(0, 1.0, ActuatorType::Vibrate)]
+        )
+        .into(),
+      ]
+    );
+  }
+}
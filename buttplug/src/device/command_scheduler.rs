@@ -0,0 +1,661 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Rate limiting and coalescing of outgoing device commands.
+//!
+//! Apps may send commands much faster than a device link can handle them (i.e.
+//! VibrateCmd at 60hz over BLE). Instead of queuing up every command as its
+//! own write, we hold commands until the device's minimum write interval has
+//! passed, merging any newer commands of the same type into the one that's
+//! waiting. Only the latest state ends up getting written. Commands are
+//! checked against the device's attributes before they're merged, so a bad
+//! command fails on its own instead of taking the commands it would have been
+//! merged with down with it.
+//!
+//! StopDeviceCmd skips the queue and drops anything still waiting, then waits
+//! for any write already in flight so the stop is always the last thing the
+//! device sees. Commands dropped by a stop resolve Ok, the same as commands
+//! merged into a newer one, since the device ends up in the state that was
+//! asked for last.
+
+use super::{protocol::ButtplugProtocol, ButtplugDeviceResultFuture, DeviceImpl};
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::{
+      self, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage, ButtplugDeviceMessageType,
+      ButtplugServerMessage, DeviceMessageAttributesMap, LinearCmd, PatternCmd, RotateCmd,
+      ScalarCmd, VibrateCmd,
+    },
+  },
+  util::async_manager,
+};
+use futures::future;
+use futures_timer::Delay;
+use std::{
+  collections::HashMap,
+  mem::{self, Discriminant},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use tokio::sync::{oneshot, Mutex as AsyncMutex};
+
+type CommandResult = Result<ButtplugServerMessage, ButtplugError>;
+
+struct PendingCommand {
+  message: ButtplugDeviceCommandMessageUnion,
+  waiters: Vec<oneshot::Sender<CommandResult>>,
+  // Stop count when the command was queued.
+  generation: u64,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+  // Only one command of each message type can be waiting at a time, newer
+  // ones get merged into it.
+  pending: HashMap<Discriminant<ButtplugDeviceCommandMessageUnion>, PendingCommand>,
+  // Earliest time the next command can be sent.
+  next_slot: Option<Instant>,
+  // Bumped by every stop, so a send task that was waiting when the stop came
+  // in can tell its command was dropped, and won't send one queued after.
+  generation: u64,
+  // When the last command or stop was written to the device.
+  last_write: Option<Instant>,
+}
+
+/// Sits between [ButtplugDevice][super::ButtplugDevice] and its protocol,
+/// holding back actuator commands to keep a minimum interval between writes.
+pub(super) struct DeviceCommandScheduler {
+  min_interval: Duration,
+  protocol: Arc<dyn ButtplugProtocol>,
+  device: Arc<DeviceImpl>,
+  // Message attributes as clients see them, to check commands against.
+  attributes: Arc<DeviceMessageAttributesMap>,
+  state: Arc<Mutex<SchedulerState>>,
+  // Held for the length of each actuator or stop write, so a stop can't go
+  // out while an earlier command is still being written.
+  write_lock: Arc<AsyncMutex<()>>,
+}
+
+impl DeviceCommandScheduler {
+  pub fn new(
+    protocol: Arc<dyn ButtplugProtocol>,
+    device: Arc<DeviceImpl>,
+    attributes: Arc<DeviceMessageAttributesMap>,
+    min_interval: Duration,
+  ) -> Self {
+    Self {
+      min_interval,
+      protocol,
+      device,
+      attributes,
+      state: Arc::new(Mutex::new(SchedulerState::default())),
+      write_lock: Arc::new(AsyncMutex::new(())),
+    }
+  }
+
+  pub fn send(&self, message: ButtplugDeviceCommandMessageUnion) -> ButtplugDeviceResultFuture {
+    if let ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) = message {
+      self.send_stop(message)
+    } else if is_actuator_command(&message) {
+      self.queue(message)
+    } else {
+      // Raw and sensor commands aren't state updates, so they can't go stale.
+      self.protocol.handle_command(self.device.clone(), message)
+    }
+  }
+
+  fn send_stop(&self, message: ButtplugDeviceCommandMessageUnion) -> ButtplugDeviceResultFuture {
+    // Stops skip the line. Anything still waiting to go out would just restart
+    // the device after the stop, so drop it. Dropped commands resolve Ok, they
+    // were superseded rather than failed.
+    let stale: Vec<PendingCommand> = {
+      let mut state = self.state.lock().unwrap();
+      state.next_slot = Some(Instant::now() + self.min_interval);
+      state.generation += 1;
+      state.pending.drain().map(|(_, pending)| pending).collect()
+    };
+    for pending in stale {
+      debug!(
+        "Dropping queued command superseded by stop: {:?}",
+        pending.message
+      );
+      for waiter in pending.waiters {
+        let _ = waiter.send(Ok(messages::Ok::default().into()));
+      }
+    }
+    let protocol = self.protocol.clone();
+    let device = self.device.clone();
+    let state = self.state.clone();
+    let write_lock = self.write_lock.clone();
+    Box::pin(async move {
+      // A command that was already handed to the protocol can't be dropped, so
+      // wait for its write to finish before stopping.
+      let _write_guard = write_lock.lock().await;
+      state.lock().unwrap().last_write = Some(Instant::now());
+      protocol.handle_command(device, message).await
+    })
+  }
+
+  fn queue(&self, message: ButtplugDeviceCommandMessageUnion) -> ButtplugDeviceResultFuture {
+    if let Err(err) = self
+      .protocol
+      .supports_message(&message)
+      .and_then(|_| check_feature_indexes(&message, &self.attributes))
+    {
+      return Box::pin(future::ready(Err(err)));
+    }
+    let (sender, receiver) = oneshot::channel();
+    let key = mem::discriminant(&message);
+    let mut state = self.state.lock().unwrap();
+    if let Some(pending) = state.pending.remove(&key) {
+      trace!(
+        "Merging command into queued command for device {}",
+        self.device.address()
+      );
+      let mut waiters = pending.waiters;
+      waiters.push(sender);
+      state.pending.insert(
+        key,
+        PendingCommand {
+          message: coalesce(pending.message, message),
+          waiters,
+          generation: pending.generation,
+        },
+      );
+    } else {
+      let now = Instant::now();
+      let slot = state.next_slot.map_or(now, |slot| slot.max(now));
+      state.next_slot = Some(slot + self.min_interval);
+      let generation = state.generation;
+      state.pending.insert(
+        key,
+        PendingCommand {
+          message,
+          waiters: vec![sender],
+          generation,
+        },
+      );
+      let state_clone = self.state.clone();
+      let protocol = self.protocol.clone();
+      let device = self.device.clone();
+      let write_lock = self.write_lock.clone();
+      let min_interval = self.min_interval;
+      async_manager::spawn(async move {
+        let mut send_at = slot;
+        loop {
+          let wait = send_at.saturating_duration_since(Instant::now());
+          if wait > Duration::from_millis(0) {
+            Delay::new(wait).await;
+          }
+          let _write_guard = write_lock.lock().await;
+          // This has to be checked with the write lock held, otherwise a stop
+          // could be written between taking the command and writing it.
+          let pending = {
+            let mut state = state_clone.lock().unwrap();
+            // If a stop came in while we were waiting, our command was dropped.
+            // Anything queued for the same type since then has its own task.
+            match state.pending.get(&key) {
+              Some(pending) if pending.generation == generation => {}
+              _ => return,
+            }
+            // A stop written since this was scheduled also counts toward the
+            // interval, so we may have to wait a bit longer.
+            let now = Instant::now();
+            if let Some(earliest) = state.last_write.map(|last| last + min_interval) {
+              if earliest > now {
+                send_at = earliest;
+                continue;
+              }
+            }
+            state.last_write = Some(now);
+            state.pending.remove(&key)
+          };
+          if let Some(pending) = pending {
+            let result = protocol.handle_command(device, pending.message).await;
+            for waiter in pending.waiters {
+              let _ = waiter.send(result.clone());
+            }
+          }
+          return;
+        }
+      })
+      .unwrap();
+    }
+    let address = self.device.address().to_owned();
+    Box::pin(async move {
+      receiver
+        .await
+        .unwrap_or_else(|_| Err(ButtplugDeviceError::DeviceNotConnected(address).into()))
+    })
+  }
+}
+
+/// Checks that every subcommand in a command refers to a feature the device
+/// has. The protocol would catch this too, but only after the command has been
+/// merged with others, failing all of them.
+fn check_feature_indexes(
+  message: &ButtplugDeviceCommandMessageUnion,
+  attributes: &DeviceMessageAttributesMap,
+) -> Result<(), ButtplugError> {
+  let feature_count = |message_type| {
+    attributes
+      .get(&message_type)
+      .and_then(|attrs| attrs.feature_count)
+      .unwrap_or(0)
+  };
+  let check_indexes = |indexes: Vec<u32>, feature_count: u32| -> Result<(), ButtplugError> {
+    match indexes.into_iter().find(|index| *index >= feature_count) {
+      Some(index) => Err(ButtplugDeviceError::DeviceFeatureIndexError(feature_count, index).into()),
+      None => Ok(()),
+    }
+  };
+  match message {
+    ButtplugDeviceCommandMessageUnion::VibrateCmd(msg) => check_indexes(
+      msg.speeds().iter().map(|cmd| cmd.index()).collect(),
+      feature_count(ButtplugDeviceMessageType::VibrateCmd),
+    ),
+    ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => check_indexes(
+      msg.rotations.iter().map(|cmd| cmd.index()).collect(),
+      feature_count(ButtplugDeviceMessageType::RotateCmd),
+    ),
+    ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => check_indexes(
+      msg.vectors().iter().map(|cmd| cmd.index()).collect(),
+      feature_count(ButtplugDeviceMessageType::LinearCmd),
+    ),
+    ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+      let actuator_types = attributes
+        .get(&ButtplugDeviceMessageType::ScalarCmd)
+        .and_then(|attrs| attrs.actuator_type.clone())
+        .unwrap_or_default();
+      for cmd in msg.scalars() {
+        match actuator_types.get(cmd.index() as usize) {
+          Some(actuator_type) if *actuator_type == cmd.actuator_type() => {}
+          Some(actuator_type) => {
+            return Err(
+              ButtplugDeviceError::ProtocolRequirementError(format!(
+                "ScalarCmd index {} is {}, device actuator is {}.",
+                cmd.index(),
+                cmd.actuator_type(),
+                actuator_type
+              ))
+              .into(),
+            )
+          }
+          None => {
+            return Err(
+              ButtplugDeviceError::DeviceFeatureIndexError(
+                actuator_types.len() as u32,
+                cmd.index(),
+              )
+              .into(),
+            )
+          }
+        }
+      }
+      Ok(())
+    }
+    ButtplugDeviceCommandMessageUnion::PatternCmd(msg) => {
+      let patterns = attributes
+        .get(&ButtplugDeviceMessageType::PatternCmd)
+        .and_then(|attrs| attrs.patterns.clone())
+        .unwrap_or_default();
+      for cmd in msg.patterns() {
+        let known_patterns = patterns.get(cmd.index() as usize).ok_or_else(|| {
+          ButtplugDeviceError::DeviceFeatureIndexError(patterns.len() as u32, cmd.index())
+        })?;
+        if let Some(name) = cmd.pattern() {
+          if !known_patterns.iter().any(|known| known == name) {
+            return Err(
+              ButtplugDeviceError::ProtocolRequirementError(format!(
+                "Pattern {} is not available on feature {}, available patterns are {:?}.",
+                name,
+                cmd.index(),
+                known_patterns
+              ))
+              .into(),
+            );
+          }
+        }
+      }
+      Ok(())
+    }
+    _ => Ok(()),
+  }
+}
+
+/// True for commands that set the state of a device's actuators, where only
+/// the latest command of each type matters.
+pub(super) fn is_actuator_command(message: &ButtplugDeviceCommandMessageUnion) -> bool {
+  matches!(
+    message,
+    ButtplugDeviceCommandMessageUnion::VibrateCmd(_)
+      | ButtplugDeviceCommandMessageUnion::ScalarCmd(_)
+      | ButtplugDeviceCommandMessageUnion::PatternCmd(_)
+      | ButtplugDeviceCommandMessageUnion::RotateCmd(_)
+      | ButtplugDeviceCommandMessageUnion::LinearCmd(_)
+      | ButtplugDeviceCommandMessageUnion::SingleMotorVibrateCmd(_)
+      | ButtplugDeviceCommandMessageUnion::FleshlightLaunchFW12Cmd(_)
+      | ButtplugDeviceCommandMessageUnion::VorzeA10CycloneCmd(_)
+      | ButtplugDeviceCommandMessageUnion::KiirooCmd(_)
+  )
+}
+
+/// True if a command leaves an actuator moving. Positional commands don't
+/// count, since the device comes to rest once it reaches the position.
+pub(super) fn is_running_command(message: &ButtplugDeviceCommandMessageUnion) -> bool {
+  match message {
+    ButtplugDeviceCommandMessageUnion::VibrateCmd(msg) => {
+      msg.speeds().iter().any(|cmd| cmd.speed() > 0.0)
+    }
+    ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+      msg.scalars().iter().any(|cmd| cmd.scalar() > 0.0)
+    }
+    ButtplugDeviceCommandMessageUnion::PatternCmd(msg) => {
+      msg.patterns().iter().any(|cmd| cmd.pattern().is_some())
+    }
+    ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+      msg.rotations.iter().any(|cmd| cmd.speed() > 0.0)
+    }
+    ButtplugDeviceCommandMessageUnion::SingleMotorVibrateCmd(msg) => msg.speed() > 0.0,
+    ButtplugDeviceCommandMessageUnion::VorzeA10CycloneCmd(msg) => msg.speed() > 0,
+    _ => false,
+  }
+}
+
+/// Merges a newer command into an older one of the same type. Subcommands
+/// from the newer command replace subcommands for the same feature index, and
+/// subcommands for features the newer command doesn't mention are kept.
+/// Commands without subcommands are full state updates, so the newer one wins.
+pub(super) fn coalesce(
+  older: ButtplugDeviceCommandMessageUnion,
+  newer: ButtplugDeviceCommandMessageUnion,
+) -> ButtplugDeviceCommandMessageUnion {
+  match (older, newer) {
+    (
+      ButtplugDeviceCommandMessageUnion::VibrateCmd(older),
+      ButtplugDeviceCommandMessageUnion::VibrateCmd(newer),
+    ) => {
+      let mut speeds: Vec<_> = older
+        .speeds()
+        .iter()
+        .filter(|old| !newer.speeds().iter().any(|new| new.index() == old.index()))
+        .cloned()
+        .collect();
+      speeds.extend(newer.speeds().iter().cloned());
+      VibrateCmd::new(newer.device_index(), speeds).into()
+    }
+    (
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(older),
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(newer),
+    ) => {
+      let mut scalars: Vec<_> = older
+        .scalars()
+        .iter()
+        .filter(|old| !newer.scalars().iter().any(|new| new.index() == old.index()))
+        .cloned()
+        .collect();
+      scalars.extend(newer.scalars().iter().cloned());
+      ScalarCmd::new(newer.device_index(), scalars).into()
+    }
+    (
+      ButtplugDeviceCommandMessageUnion::PatternCmd(older),
+      ButtplugDeviceCommandMessageUnion::PatternCmd(newer),
+    ) => {
+      let mut patterns: Vec<_> = older
+        .patterns()
+        .iter()
+        .filter(|old| {
+          !newer
+            .patterns()
+            .iter()
+            .any(|new| new.index() == old.index())
+        })
+        .cloned()
+        .collect();
+      patterns.extend(newer.patterns().iter().cloned());
+      PatternCmd::new(newer.device_index(), patterns).into()
+    }
+    (
+      ButtplugDeviceCommandMessageUnion::RotateCmd(older),
+      ButtplugDeviceCommandMessageUnion::RotateCmd(newer),
+    ) => {
+      let mut rotations: Vec<_> = older
+        .rotations
+        .iter()
+        .filter(|old| !newer.rotations.iter().any(|new| new.index() == old.index()))
+        .cloned()
+        .collect();
+      rotations.extend(newer.rotations.iter().cloned());
+      RotateCmd::new(newer.device_index(), rotations).into()
+    }
+    (
+      ButtplugDeviceCommandMessageUnion::LinearCmd(older),
+      ButtplugDeviceCommandMessageUnion::LinearCmd(newer),
+    ) => {
+      let mut vectors: Vec<_> = older
+        .vectors()
+        .iter()
+        .filter(|old| !newer.vectors().iter().any(|new| new.index() == old.index()))
+        .cloned()
+        .collect();
+      vectors.extend(newer.vectors().iter().cloned());
+      LinearCmd::new(newer.device_index(), vectors).into()
+    }
+    (_, newer) => newer,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    core::messages::{StopDeviceCmd, VibrateSubcommand},
+    device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
+    server::comm_managers::test::{
+      check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device,
+    },
+  };
+  use futures::future;
+
+  #[test]
+  fn test_scheduler_merges_commands_within_interval() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Massage Demo").await.unwrap();
+      let device = device.with_min_write_interval(Duration::from_millis(100));
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+      );
+      // Both of these land inside the interval, so they should go out as one
+      // command with the latest speed for each motor.
+      let first =
+        device.parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 1.0)]).into());
+      let second = device.parse_message(
+        VibrateCmd::new(
+          0,
+          vec![
+            VibrateSubcommand::new(0, 0.1),
+            VibrateSubcommand::new(1, 0.5),
+          ],
+        )
+        .into(),
+      );
+      for result in future::join_all(vec![first, second]).await {
+        assert!(result.is_ok());
+      }
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 13], false)),
+      );
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF2, 64], false)),
+      );
+      assert!(check_test_recv_empty(&command_receiver));
+    });
+  }
+
+  #[test]
+  fn test_scheduler_rejects_bad_commands_before_merging() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Massage Demo").await.unwrap();
+      let device = device.with_min_write_interval(Duration::from_millis(100));
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+      );
+      // The second command asks for a motor the device doesn't have. It should
+      // fail by itself, and the first should still go out.
+      let good =
+        device.parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 1.0)]).into());
+      let bad =
+        device.parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(5, 0.1)]).into());
+      assert!(bad.await.is_err());
+      assert!(good.await.is_ok());
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 127], false)),
+      );
+      assert!(check_test_recv_empty(&command_receiver));
+    });
+  }
+
+  #[test]
+  fn test_scheduler_stop_drops_queued_commands() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Massage Demo").await.unwrap();
+      let device = device.with_min_write_interval(Duration::from_millis(100));
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+      );
+      let queued =
+        device.parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 1.0)]).into());
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      assert!(queued.await.is_ok());
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false)),
+      );
+      Delay::new(Duration::from_millis(150)).await;
+      assert!(check_test_recv_empty(&command_receiver));
+    });
+  }
+
+  #[test]
+  fn test_scheduler_keeps_interval_after_stop() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Massage Demo").await.unwrap();
+      let device = device.with_min_write_interval(Duration::from_millis(100));
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+      );
+      let dropped =
+        device.parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 1.0)]).into());
+      Delay::new(Duration::from_millis(50)).await;
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false)),
+      );
+      assert!(dropped.await.is_ok());
+      // The task for the dropped command wakes up before this one is due, and
+      // shouldn't send it early.
+      let restarted =
+        device.parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into());
+      Delay::new(Duration::from_millis(75)).await;
+      assert!(check_test_recv_empty(&command_receiver));
+      assert!(restarted.await.is_ok());
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+      );
+    });
+  }
+
+  #[test]
+  fn test_scheduler_stop_waits_for_in_flight_write() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Massage Demo").await.unwrap();
+      let device = device.with_min_write_interval(Duration::from_millis(100));
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      // Get the vibrate stuck in a slow write, then send a stop that would
+      // otherwise reach the device first.
+      test_device.set_write_delay(Some(Duration::from_millis(100)));
+      let running =
+        device.parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into());
+      Delay::new(Duration::from_millis(20)).await;
+      test_device.set_write_delay(None);
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      assert!(running.await.is_ok());
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+      );
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false)),
+      );
+      assert!(check_test_recv_empty(&command_receiver));
+    });
+  }
+
+  #[test]
+  fn test_coalesce_vibrate_keeps_untouched_features() {
+    let older = VibrateCmd::new(
+      0,
+      vec![
+        VibrateSubcommand::new(0, 0.5),
+        VibrateSubcommand::new(1, 0.5),
+      ],
+    );
+    let newer = VibrateCmd::new(0, vec![VibrateSubcommand::new(1, 1.0)]);
+    match coalesce(older.into(), newer.into()) {
+      ButtplugDeviceCommandMessageUnion::VibrateCmd(merged) => {
+        assert_eq!(
+          *merged.speeds(),
+          vec![
+            VibrateSubcommand::new(0, 0.5),
+            VibrateSubcommand::new(1, 1.0)
+          ]
+        );
+      }
+      _ => panic!("Merged command should still be a VibrateCmd"),
+    }
+  }
+}
@@ -13,7 +13,9 @@ use super::protocol::{
 use crate::{
   core::{
     errors::{ButtplugDeviceError, ButtplugError},
-    messages::{ButtplugDeviceMessageType, DeviceMessageAttributes, DeviceMessageAttributesMap},
+    messages::{
+      ButtplugDeviceMessageType, DeviceMessageAttributes, DeviceMessageAttributesMap, ResponseCurve,
+    },
   },
   device::Endpoint,
 };
@@ -201,11 +203,46 @@ pub enum DeviceSpecifier {
   Websocket(WebsocketSpecifier),
 }
 
+/// Output limits for one message type, set in the user device configuration.
+/// Clients can't send commands that go outside of these.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DeviceOutputLimits {
+  /// Maximum speed for each feature, 0.0-1.0.
+  #[serde(rename = "MaxSpeed")]
+  pub max_speed: Option<Vec<f64>>,
+  /// (Minimum, maximum) position for each feature, 0.0-1.0.
+  #[serde(rename = "PositionRange")]
+  pub position_range: Option<Vec<(f64, f64)>>,
+  #[serde(rename = "ResponseCurve")]
+  pub response_curve: Option<ResponseCurve>,
+}
+
+impl DeviceOutputLimits {
+  /// Overwrites any limits that are also set in `other`.
+  pub fn merge(&mut self, other: &DeviceOutputLimits) {
+    if other.max_speed.is_some() {
+      self.max_speed = other.max_speed.clone();
+    }
+    if other.position_range.is_some() {
+      self.position_range = other.position_range.clone();
+    }
+    if other.response_curve.is_some() {
+      self.response_curve = other.response_curve.clone();
+    }
+  }
+}
+
+pub type DeviceOutputLimitsMap = HashMap<ButtplugDeviceMessageType, DeviceOutputLimits>;
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct ProtocolAttributes {
   identifier: Option<Vec<String>>,
   name: Option<HashMap<String, String>>,
   messages: Option<DeviceMessageAttributesMap>,
+  /// Minimum time between commands sent to the device, in milliseconds.
+  #[serde(rename = "min-write-interval")]
+  min_write_interval: Option<u32>,
+  limits: Option<DeviceOutputLimitsMap>,
 }
 
 #[derive(Deserialize, Debug, Clone, Default)]
@@ -224,6 +261,9 @@ pub struct ProtocolDefinition {
   pub defaults: Option<ProtocolAttributes>,
   #[serde(default)]
   pub configurations: Vec<ProtocolAttributes>,
+  /// Output limits for specific devices, keyed by device address.
+  #[serde(rename = "device-limits", default)]
+  pub device_limits: HashMap<String, DeviceOutputLimitsMap>,
 }
 
 fn option_some_eq<T>(a: &Option<T>, b: &T) -> bool
@@ -312,6 +352,8 @@ impl ProtocolDefinition {
       error!("Lovense connect service specifier set for user configuration, ignoring.");
     }
 
+    self.device_limits.extend(other.device_limits);
+
     // If new defaults are set, overwrite.
     if other.defaults.is_some() {
       self.defaults = other.defaults;
@@ -335,6 +377,9 @@ pub struct DeviceProtocolConfiguration {
   allow_raw_messages: bool,
   defaults: Option<ProtocolAttributes>,
   configurations: Vec<ProtocolAttributes>,
+  /// Limits set for this specific device by address, which take precedence
+  /// over anything set for the protocol or identifier.
+  device_limits: Option<DeviceOutputLimitsMap>,
 }
 
 impl DeviceProtocolConfiguration {
@@ -347,9 +392,15 @@ impl DeviceProtocolConfiguration {
       allow_raw_messages,
       defaults,
       configurations,
+      device_limits: None,
     }
   }
 
+  pub fn with_device_limits(mut self, limits: Option<DeviceOutputLimitsMap>) -> Self {
+    self.device_limits = limits;
+    self
+  }
+
   pub fn get_attributes(
     &self,
     identifier: &str,
@@ -414,6 +465,50 @@ impl DeviceProtocolConfiguration {
       attributes.extend(msg_attrs.clone());
     }
 
+    // Limits stack, so a device specific max speed can still use the curve
+    // set for the protocol.
+    let mut limits = DeviceOutputLimitsMap::new();
+    for limits_map in [
+      self
+        .defaults
+        .as_ref()
+        .and_then(|attrs| attrs.limits.as_ref()),
+      device_attrs.limits.as_ref(),
+      self.device_limits.as_ref(),
+    ]
+    .iter()
+    .flatten()
+    {
+      for (message_type, message_limits) in limits_map.iter() {
+        limits
+          .entry(*message_type)
+          .or_default()
+          .merge(message_limits);
+      }
+    }
+    for (message_type, message_limits) in limits {
+      if let Some((min, max)) = message_limits
+        .position_range
+        .iter()
+        .flatten()
+        .find(|(min, max)| min > max)
+      {
+        return Err(
+          ButtplugDeviceError::DeviceConfigurationFileError(format!(
+            "Position range minimum {} is larger than maximum {} for {}.",
+            min, max, identifier
+          ))
+          .into(),
+        );
+      }
+      // Limits only apply to messages the device actually supports.
+      if let Some(msg_attrs) = attributes.get_mut(&message_type) {
+        msg_attrs.max_speed = message_limits.max_speed;
+        msg_attrs.position_range = message_limits.position_range;
+        msg_attrs.response_curve = message_limits.response_curve;
+      }
+    }
+
     // Everything needs to be able to stop.
     attributes
       .entry(ButtplugDeviceMessageType::StopDeviceCmd)
@@ -424,6 +519,24 @@ impl DeviceProtocolConfiguration {
   }
 }
 
+impl DeviceProtocolConfiguration {
+  /// Returns the minimum write interval for a device, in milliseconds. A
+  /// device specific setting takes precedence over the protocol default.
+  pub fn get_min_write_interval(&self, identifier: &str) -> Option<u32> {
+    self
+      .configurations
+      .iter()
+      .find(|attrs| attrs.identifier.iter().flatten().any(|id| id == identifier))
+      .and_then(|attrs| attrs.min_write_interval)
+      .or_else(|| {
+        self
+          .defaults
+          .as_ref()
+          .and_then(|attrs| attrs.min_write_interval)
+      })
+  }
+}
+
 pub struct DeviceConfigurationManager {
   allow_raw_messages: bool,
   protocol_definitions: Arc<DashMap<String, ProtocolDefinition>>,
@@ -524,9 +637,14 @@ impl DeviceConfigurationManager {
 #[cfg(test)]
 mod test {
   use super::{
-    BluetoothLESpecifier, DeviceProtocolConfiguration, DeviceSpecifier, SerialSpecifier
+    BluetoothLESpecifier, DeviceOutputLimits, DeviceOutputLimitsMap, DeviceProtocolConfiguration,
+    DeviceSpecifier, ProtocolAttributes, SerialSpecifier,
+  };
+  use crate::{
+    core::messages::{ButtplugDeviceMessageType, ResponseCurve},
+    device::configuration_manager::ProtocolDefinition,
+    util::device_configuration::create_test_dcm,
   };
-  use crate::{core::messages::ButtplugDeviceMessageType, device::configuration_manager::ProtocolDefinition, util::device_configuration::create_test_dcm};
 /*
   #[test]
   fn test_load_config() {
@@ -607,6 +725,77 @@ mod test {
     assert!(!message_map.contains_key(&ButtplugDeviceMessageType::RawUnsubscribeCmd));
   }
 
+  #[test]
+  fn test_output_limits_stacking() {
+    let defaults: ProtocolAttributes = serde_json::from_str(
+      r#"{
+        "name": { "en-us": "Limited Device" },
+        "messages": { "VibrateCmd": { "FeatureCount": 2, "StepCount": [20, 20] } },
+        "limits": { "VibrateCmd": { "MaxSpeed": [0.5, 0.5], "ResponseCurve": { "Gamma": 2.0 } } }
+      }"#,
+    )
+    .unwrap();
+    let configuration: ProtocolAttributes = serde_json::from_str(
+      r#"{
+        "identifier": ["P"],
+        "name": { "en-us": "Limited Device P" },
+        "limits": {
+          "VibrateCmd": { "MaxSpeed": [0.75, 0.75] },
+          "LinearCmd": { "PositionRange": [[0.2, 0.8]] }
+        }
+      }"#,
+    )
+    .unwrap();
+    let mut address_limits = DeviceOutputLimitsMap::new();
+    address_limits.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceOutputLimits {
+        max_speed: Some(vec![0.25, 1.0]),
+        ..Default::default()
+      },
+    );
+    let proto_config =
+      DeviceProtocolConfiguration::new(false, Some(defaults.clone()), vec![configuration.clone()]);
+    let (_, message_map) = proto_config.get_attributes("P", &[]).unwrap();
+    let vibrate_attrs = message_map
+      .get(&ButtplugDeviceMessageType::VibrateCmd)
+      .unwrap();
+    assert_eq!(vibrate_attrs.max_speed, Some(vec![0.75, 0.75]));
+    assert_eq!(
+      vibrate_attrs.response_curve,
+      Some(ResponseCurve::Gamma(2.0))
+    );
+    // Device doesn't support LinearCmd, so those limits shouldn't show up.
+    assert!(!message_map.contains_key(&ButtplugDeviceMessageType::LinearCmd));
+
+    let proto_config =
+      DeviceProtocolConfiguration::new(false, Some(defaults.clone()), vec![configuration])
+        .with_device_limits(Some(address_limits));
+    let (_, message_map) = proto_config.get_attributes("P", &[]).unwrap();
+    let vibrate_attrs = message_map
+      .get(&ButtplugDeviceMessageType::VibrateCmd)
+      .unwrap();
+    assert_eq!(vibrate_attrs.max_speed, Some(vec![0.25, 1.0]));
+    assert_eq!(
+      vibrate_attrs.response_curve,
+      Some(ResponseCurve::Gamma(2.0))
+    );
+  }
+
+  #[test]
+  fn test_output_limits_invalid_position_range() {
+    let defaults: ProtocolAttributes = serde_json::from_str(
+      r#"{
+        "name": { "en-us": "Limited Device" },
+        "messages": { "LinearCmd": { "FeatureCount": 1, "StepCount": [100] } },
+        "limits": { "LinearCmd": { "PositionRange": [[0.8, 0.2]] } }
+      }"#,
+    )
+    .unwrap();
+    let proto_config = DeviceProtocolConfiguration::new(false, Some(defaults), vec![]);
+    assert!(proto_config.get_attributes("P", &[]).is_err());
+  }
+
   #[test]
   fn test_user_config_loading() {
     // Assume we have a nobra's entry in the device config.
@@ -0,0 +1,302 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Rules for deciding which devices the server is allowed to connect to.
+//!
+//! Deny rules always win. If there are any allow rules, devices also need to
+//! match at least one of them to be connected, so "only Lovense over BLE" is a
+//! single allow rule, and "never serial" is a single deny rule.
+
+use super::configuration_manager::DeviceSpecifier;
+use dashmap::DashSet;
+use serde::Deserialize;
+use std::fmt;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceFilterAction {
+  Allow,
+  Deny,
+}
+
+/// Bus a device was found on. Names match the specifier names in the device
+/// configuration file.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceBusType {
+  #[serde(rename = "btle")]
+  BluetoothLE,
+  #[serde(rename = "hid")]
+  HID,
+  #[serde(rename = "usb")]
+  USB,
+  #[serde(rename = "serial")]
+  Serial,
+  #[serde(rename = "xinput")]
+  XInput,
+  #[serde(rename = "lovense-connect-service")]
+  LovenseConnectService,
+  #[serde(rename = "websocket")]
+  Websocket,
+}
+
+impl From<&DeviceSpecifier> for DeviceBusType {
+  fn from(specifier: &DeviceSpecifier) -> Self {
+    match specifier {
+      DeviceSpecifier::BluetoothLE(_) => DeviceBusType::BluetoothLE,
+      DeviceSpecifier::HID(_) => DeviceBusType::HID,
+      DeviceSpecifier::USB(_) => DeviceBusType::USB,
+      DeviceSpecifier::Serial(_) => DeviceBusType::Serial,
+      DeviceSpecifier::XInput(_) => DeviceBusType::XInput,
+      DeviceSpecifier::LovenseConnectService(_) => DeviceBusType::LovenseConnectService,
+      DeviceSpecifier::Websocket(_) => DeviceBusType::Websocket,
+    }
+  }
+}
+
+/// What we know about a device when deciding whether to connect to it.
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceFilterInfo<'a> {
+  pub name: &'a str,
+  pub address: &'a str,
+  /// Protocol the device matched in the device configuration, if any.
+  pub protocol: Option<&'a str>,
+  pub bus: DeviceBusType,
+}
+
+/// A single filter rule. Unset fields match any device, so a rule with only a
+/// protocol set matches every device of that protocol.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceFilterRule {
+  pub action: DeviceFilterAction,
+  pub protocol: Option<String>,
+  /// Device name, `*` matches any run of characters.
+  pub name: Option<String>,
+  pub bus: Option<DeviceBusType>,
+  pub address: Option<String>,
+}
+
+impl DeviceFilterRule {
+  pub fn new(action: DeviceFilterAction) -> Self {
+    Self {
+      action,
+      protocol: None,
+      name: None,
+      bus: None,
+      address: None,
+    }
+  }
+
+  pub fn protocol(&mut self, protocol: &str) -> &mut Self {
+    self.protocol = Some(protocol.to_owned());
+    self
+  }
+
+  pub fn name(&mut self, name: &str) -> &mut Self {
+    self.name = Some(name.to_owned());
+    self
+  }
+
+  pub fn bus(&mut self, bus: DeviceBusType) -> &mut Self {
+    self.bus = Some(bus);
+    self
+  }
+
+  pub fn address(&mut self, address: &str) -> &mut Self {
+    self.address = Some(address.to_owned());
+    self
+  }
+
+  pub fn matches(&self, device: &DeviceFilterInfo) -> bool {
+    if let Some(protocol) = &self.protocol {
+      if device.protocol != Some(protocol.as_str()) {
+        return false;
+      }
+    }
+    if let Some(name) = &self.name {
+      if !wildcard_match(name, device.name) {
+        return false;
+      }
+    }
+    if let Some(bus) = self.bus {
+      if bus != device.bus {
+        return false;
+      }
+    }
+    if let Some(address) = &self.address {
+      if address != device.address {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+impl fmt::Display for DeviceFilterRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?} rule [", self.action)?;
+    let mut fields = vec![];
+    if let Some(protocol) = &self.protocol {
+      fields.push(format!("protocol: {}", protocol));
+    }
+    if let Some(name) = &self.name {
+      fields.push(format!("name: {}", name));
+    }
+    if let Some(bus) = &self.bus {
+      fields.push(format!("bus: {:?}", bus));
+    }
+    if let Some(address) = &self.address {
+      fields.push(format!("address: {}", address));
+    }
+    write!(f, "{}]", fields.join(", "))
+  }
+}
+
+/// Matches a name against a pattern where `*` matches any run of characters.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+  let mut parts = pattern.split('*');
+  // split always returns at least one item.
+  let first = parts.next().unwrap();
+  if !name.starts_with(first) {
+    return false;
+  }
+  let mut remaining = &name[first.len()..];
+  let parts: Vec<&str> = parts.collect();
+  match parts.split_last() {
+    // No asterisks, so it has to be an exact match.
+    None => remaining.is_empty(),
+    Some((last, middle)) => {
+      for part in middle {
+        match remaining.find(part) {
+          Some(index) => remaining = &remaining[index + part.len()..],
+          None => return false,
+        }
+      }
+      remaining.len() >= last.len() && remaining.ends_with(last)
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceFilterResult {
+  Allowed,
+  /// Device matched a deny rule.
+  Denied(DeviceFilterRule),
+  /// There are allow rules, but the device didn't match any of them.
+  NotAllowed,
+}
+
+/// Set of filter rules, which can be changed while the server is running.
+#[derive(Default, Debug)]
+pub struct DeviceFilter {
+  rules: DashSet<DeviceFilterRule>,
+}
+
+impl DeviceFilter {
+  pub fn add_rule(&self, rule: DeviceFilterRule) {
+    self.rules.insert(rule);
+  }
+
+  /// Returns false if the rule didn't exist.
+  pub fn remove_rule(&self, rule: &DeviceFilterRule) -> bool {
+    self.rules.remove(rule).is_some()
+  }
+
+  pub fn rules(&self) -> Vec<DeviceFilterRule> {
+    self.rules.iter().map(|rule| rule.clone()).collect()
+  }
+
+  pub fn clear(&self) {
+    self.rules.clear();
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.rules.is_empty()
+  }
+
+  pub fn check(&self, device: &DeviceFilterInfo) -> DeviceFilterResult {
+    if let Some(rule) = self
+      .rules
+      .iter()
+      .find(|rule| rule.action == DeviceFilterAction::Deny && rule.matches(device))
+    {
+      return DeviceFilterResult::Denied(rule.clone());
+    }
+    let mut allow_rules = self
+      .rules
+      .iter()
+      .filter(|rule| rule.action == DeviceFilterAction::Allow)
+      .peekable();
+    if allow_rules.peek().is_none() || allow_rules.any(|rule| rule.matches(device)) {
+      DeviceFilterResult::Allowed
+    } else {
+      DeviceFilterResult::NotAllowed
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn lovense_ble() -> DeviceFilterInfo<'static> {
+    DeviceFilterInfo {
+      name: "LVS-Edge",
+      address: "00:11:22:33:44:55",
+      protocol: Some("lovense"),
+      bus: DeviceBusType::BluetoothLE,
+    }
+  }
+
+  #[test]
+  fn test_wildcard_match() {
+    assert!(wildcard_match("LVS-*", "LVS-Edge"));
+    assert!(wildcard_match("*Edge", "LVS-Edge"));
+    assert!(wildcard_match("L*-*e", "LVS-Edge"));
+    assert!(wildcard_match("LVS-Edge", "LVS-Edge"));
+    assert!(!wildcard_match("LVS-Edg", "LVS-Edge"));
+    assert!(!wildcard_match("LVS-*x", "LVS-Edge"));
+    assert!(!wildcard_match("LVS*S-", "LVS-"));
+  }
+
+  #[test]
+  fn test_empty_filter_allows_everything() {
+    let filter = DeviceFilter::default();
+    assert_eq!(filter.check(&lovense_ble()), DeviceFilterResult::Allowed);
+  }
+
+  #[test]
+  fn test_allow_and_deny_rules() {
+    let filter = DeviceFilter::default();
+    let mut allow_lovense_ble = DeviceFilterRule::new(DeviceFilterAction::Allow);
+    allow_lovense_ble
+      .protocol("lovense")
+      .bus(DeviceBusType::BluetoothLE);
+    filter.add_rule(allow_lovense_ble.clone());
+    let mut deny_serial = DeviceFilterRule::new(DeviceFilterAction::Deny);
+    deny_serial.bus(DeviceBusType::Serial);
+    filter.add_rule(deny_serial.clone());
+
+    assert_eq!(filter.check(&lovense_ble()), DeviceFilterResult::Allowed);
+    let lovense_serial = DeviceFilterInfo {
+      bus: DeviceBusType::Serial,
+      ..lovense_ble()
+    };
+    assert_eq!(
+      filter.check(&lovense_serial),
+      DeviceFilterResult::Denied(deny_serial)
+    );
+    let other_ble = DeviceFilterInfo {
+      protocol: Some("aneros"),
+      ..lovense_ble()
+    };
+    assert_eq!(filter.check(&other_ble), DeviceFilterResult::NotAllowed);
+
+    assert!(filter.remove_rule(&allow_lovense_ble));
+    assert!(!filter.remove_rule(&allow_lovense_ble));
+    assert_eq!(filter.check(&other_ble), DeviceFilterResult::Allowed);
+  }
+}
@@ -0,0 +1,153 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Counters for commands, reads and writes going through a device connection.
+
+use super::Endpoint;
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::Mutex,
+  time::{Duration, Instant, SystemTime},
+};
+
+/// Number of recent writes kept around for calculating latency percentiles.
+const LATENCY_SAMPLE_COUNT: usize = 1000;
+
+#[derive(Default)]
+struct DeviceMetricsState {
+  commands_received: u64,
+  bytes_written: HashMap<Endpoint, u64>,
+  bytes_read: HashMap<Endpoint, u64>,
+  writes: u64,
+  write_errors: u64,
+  read_errors: u64,
+  total_write_latency: Duration,
+  recent_write_latencies: VecDeque<Duration>,
+  last_command_time: Option<SystemTime>,
+}
+
+/// Collects metrics for a single device connection. Owned by
+/// [DeviceImpl][super::DeviceImpl], so a reconnected device starts over.
+pub struct DeviceMetrics {
+  connected_at: Instant,
+  state: Mutex<DeviceMetricsState>,
+}
+
+impl Default for DeviceMetrics {
+  fn default() -> Self {
+    Self {
+      connected_at: Instant::now(),
+      state: Mutex::new(DeviceMetricsState::default()),
+    }
+  }
+}
+
+impl DeviceMetrics {
+  pub fn record_command(&self) {
+    let mut state = self.state.lock().unwrap();
+    state.commands_received += 1;
+    state.last_command_time = Some(SystemTime::now());
+  }
+
+  pub fn record_write(&self, endpoint: Endpoint, bytes: usize, latency: Duration, succeeded: bool) {
+    let mut state = self.state.lock().unwrap();
+    if !succeeded {
+      state.write_errors += 1;
+      return;
+    }
+    *state.bytes_written.entry(endpoint).or_insert(0) += bytes as u64;
+    state.writes += 1;
+    state.total_write_latency += latency;
+    if state.recent_write_latencies.len() == LATENCY_SAMPLE_COUNT {
+      state.recent_write_latencies.pop_front();
+    }
+    state.recent_write_latencies.push_back(latency);
+  }
+
+  pub fn record_read(&self, endpoint: Endpoint, bytes: usize, succeeded: bool) {
+    let mut state = self.state.lock().unwrap();
+    if succeeded {
+      *state.bytes_read.entry(endpoint).or_insert(0) += bytes as u64;
+    } else {
+      state.read_errors += 1;
+    }
+  }
+
+  pub fn snapshot(&self) -> DeviceMetricsSnapshot {
+    let state = self.state.lock().unwrap();
+    let average_write_latency = if state.writes > 0 {
+      Some(state.total_write_latency / state.writes as u32)
+    } else {
+      None
+    };
+    let p99_write_latency = if state.recent_write_latencies.is_empty() {
+      None
+    } else {
+      let mut latencies: Vec<Duration> = state.recent_write_latencies.iter().cloned().collect();
+      latencies.sort();
+      let index = ((latencies.len() as f64 * 0.99).ceil() as usize).max(1) - 1;
+      Some(latencies[index])
+    };
+    DeviceMetricsSnapshot {
+      commands_received: state.commands_received,
+      bytes_written: state.bytes_written.clone(),
+      bytes_read: state.bytes_read.clone(),
+      write_errors: state.write_errors,
+      read_errors: state.read_errors,
+      average_write_latency,
+      p99_write_latency,
+      last_command_time: state.last_command_time,
+      uptime: self.connected_at.elapsed(),
+    }
+  }
+}
+
+/// Point in time copy of [DeviceMetrics].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceMetricsSnapshot {
+  /// Device command messages received, before any coalescing.
+  pub commands_received: u64,
+  /// Bytes successfully written, per endpoint.
+  pub bytes_written: HashMap<Endpoint, u64>,
+  /// Bytes successfully read, per endpoint.
+  pub bytes_read: HashMap<Endpoint, u64>,
+  pub write_errors: u64,
+  pub read_errors: u64,
+  /// Average over all successful writes. None if nothing has been written.
+  pub average_write_latency: Option<Duration>,
+  /// 99th percentile over the last 1000 successful writes. None if nothing has
+  /// been written.
+  pub p99_write_latency: Option<Duration>,
+  pub last_command_time: Option<SystemTime>,
+  /// Time since the device connected.
+  pub uptime: Duration,
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_write_latency_stats() {
+    let metrics = DeviceMetrics::default();
+    for ms in 1..=100 {
+      metrics.record_write(Endpoint::Tx, 2, Duration::from_millis(ms), true);
+    }
+    metrics.record_write(Endpoint::Tx, 2, Duration::from_millis(500), false);
+    metrics.record_command();
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.commands_received, 1);
+    assert!(snapshot.last_command_time.is_some());
+    assert_eq!(snapshot.bytes_written.get(&Endpoint::Tx), Some(&200));
+    assert_eq!(snapshot.write_errors, 1);
+    assert_eq!(
+      snapshot.average_write_latency,
+      Some(Duration::from_micros(50500))
+    );
+    assert_eq!(snapshot.p99_write_latency, Some(Duration::from_millis(99)));
+  }
+}
@@ -0,0 +1,365 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Recordings of everything that goes between a device and its protocol.
+//!
+//! Recordings are JSON lines files, one [RecordedDeviceEntry] per line,
+//! starting with a `Connected` entry describing the device. They can be played
+//! back with the replay comm manager, to check that a build still sends the
+//! same bytes as the build the recording was made with.
+
+use super::{
+  ButtplugDeviceEvent, ButtplugDeviceImplCreator, DeviceImpl, DeviceImplInternal, DeviceReadCmd,
+  DeviceSubscribeCmd, DeviceUnsubscribeCmd, DeviceWriteCmd, Endpoint,
+};
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::RawReading,
+    ButtplugResultFuture,
+  },
+  device::configuration_manager::{DeviceSpecifier, ProtocolDefinition},
+  util::async_manager,
+};
+use async_trait::async_trait;
+use futures::{future::BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use std::{
+  fmt::{self, Debug},
+  fs::{self, File},
+  io::{BufWriter, Write},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum RecordedDeviceEvent {
+  Connected {
+    name: String,
+    address: String,
+    endpoints: Vec<Endpoint>,
+  },
+  Write {
+    endpoint: Endpoint,
+    data: Vec<u8>,
+    write_with_response: bool,
+  },
+  Read {
+    endpoint: Endpoint,
+    data: Vec<u8>,
+  },
+  Subscribe {
+    endpoint: Endpoint,
+  },
+  Unsubscribe {
+    endpoint: Endpoint,
+  },
+  Notification {
+    endpoint: Endpoint,
+    data: Vec<u8>,
+  },
+  Disconnected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedDeviceEntry {
+  /// Milliseconds since the recording started.
+  pub time_ms: u64,
+  #[serde(flatten)]
+  pub event: RecordedDeviceEvent,
+}
+
+fn recording_error<E: fmt::Display>(err: E) -> ButtplugError {
+  ButtplugDeviceError::DeviceCommunicationError(format!("Device recording error: {}", err)).into()
+}
+
+/// Parses a recording. Blank lines are skipped.
+pub fn parse_recording(recording: &str) -> Result<Vec<RecordedDeviceEntry>, ButtplugError> {
+  recording
+    .lines()
+    .filter(|line| !line.trim().is_empty())
+    .map(|line| serde_json::from_str(line).map_err(recording_error))
+    .collect()
+}
+
+pub fn load_recording<P: AsRef<Path>>(path: P) -> Result<Vec<RecordedDeviceEntry>, ButtplugError> {
+  parse_recording(&fs::read_to_string(path).map_err(recording_error)?)
+}
+
+/// Writes recording entries as they happen.
+pub struct DeviceRecorder {
+  start: Instant,
+  writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl DeviceRecorder {
+  pub fn new(writer: Box<dyn Write + Send>) -> Self {
+    Self {
+      start: Instant::now(),
+      writer: Mutex::new(writer),
+    }
+  }
+
+  pub fn to_file<P: AsRef<Path>>(path: P) -> Result<Self, ButtplugError> {
+    let file = File::create(path).map_err(recording_error)?;
+    Ok(Self::new(Box::new(BufWriter::new(file))))
+  }
+
+  pub fn record(&self, event: RecordedDeviceEvent) {
+    let entry = RecordedDeviceEntry {
+      time_ms: self.start.elapsed().as_millis() as u64,
+      event,
+    };
+    // Entries are flushed as we go, so recordings survive crashes, which is
+    // usually when we want them most.
+    let mut writer = self.writer.lock().unwrap();
+    let result = serde_json::to_string(&entry)
+      .map_err(recording_error)
+      .and_then(|line| writeln!(writer, "{}", line).map_err(recording_error))
+      .and_then(|_| writer.flush().map_err(recording_error));
+    if let Err(err) = result {
+      error!("Cannot record device event: {}", err);
+    }
+  }
+}
+
+/// Wraps a device implementation, recording everything that goes through it.
+pub(super) struct RecordingDeviceImpl {
+  internal_impl: Box<dyn DeviceImplInternal>,
+  recorder: Arc<DeviceRecorder>,
+  event_sender: broadcast::Sender<ButtplugDeviceEvent>,
+  cancellation_token: CancellationToken,
+}
+
+impl RecordingDeviceImpl {
+  pub fn new(internal_impl: Box<dyn DeviceImplInternal>, recorder: Arc<DeviceRecorder>) -> Self {
+    // Events are relayed through our own channel, so that notifications are
+    // recorded before the protocol sees them. Otherwise, a protocol could
+    // react to a notification before it's recorded, and the recording would
+    // have things out of order.
+    let (event_sender, _) = broadcast::channel(256);
+    let cancellation_token = CancellationToken::new();
+    let mut receiver = internal_impl.event_stream();
+    let sender = event_sender.clone();
+    let event_recorder = recorder.clone();
+    let token = cancellation_token.child_token();
+    async_manager::spawn(async move {
+      loop {
+        let event = select! {
+          event = receiver.recv().fuse() => event,
+          _ = token.cancelled().fuse() => return,
+        };
+        match event {
+          Ok(event) => {
+            match &event {
+              ButtplugDeviceEvent::Notification(_, endpoint, data) => {
+                event_recorder.record(RecordedDeviceEvent::Notification {
+                  endpoint: *endpoint,
+                  data: data.clone(),
+                })
+              }
+              ButtplugDeviceEvent::Removed(_) => {
+                event_recorder.record(RecordedDeviceEvent::Disconnected)
+              }
+              ButtplugDeviceEvent::Connected(_) => {}
+            }
+            let _ = sender.send(event);
+          }
+          Err(broadcast::error::RecvError::Lagged(count)) => {
+            error!("Device recorder missed {} device events.", count);
+          }
+          Err(broadcast::error::RecvError::Closed) => return,
+        }
+      }
+    })
+    .unwrap();
+    Self {
+      internal_impl,
+      recorder,
+      event_sender,
+      cancellation_token,
+    }
+  }
+}
+
+impl Drop for RecordingDeviceImpl {
+  fn drop(&mut self) {
+    self.cancellation_token.cancel();
+  }
+}
+
+impl DeviceImplInternal for RecordingDeviceImpl {
+  fn connected(&self) -> bool {
+    self.internal_impl.connected()
+  }
+
+  fn disconnect(&self) -> ButtplugResultFuture {
+    self.internal_impl.disconnect()
+  }
+
+  fn event_stream(&self) -> broadcast::Receiver<ButtplugDeviceEvent> {
+    self.event_sender.subscribe()
+  }
+
+  fn read_value(
+    &self,
+    msg: DeviceReadCmd,
+  ) -> BoxFuture<'static, Result<RawReading, ButtplugError>> {
+    let recorder = self.recorder.clone();
+    let fut = self.internal_impl.read_value(msg);
+    Box::pin(async move {
+      let result = fut.await;
+      if let Ok(reading) = &result {
+        recorder.record(RecordedDeviceEvent::Read {
+          endpoint: reading.endpoint(),
+          data: reading.data().clone(),
+        });
+      }
+      result
+    })
+  }
+
+  fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture {
+    // Record writes before they go out, in case the device answers before the
+    // write future finishes.
+    self.recorder.record(RecordedDeviceEvent::Write {
+      endpoint: msg.endpoint,
+      data: msg.data.clone(),
+      write_with_response: msg.write_with_response,
+    });
+    self.internal_impl.write_value(msg)
+  }
+
+  fn subscribe(&self, msg: DeviceSubscribeCmd) -> ButtplugResultFuture {
+    self.recorder.record(RecordedDeviceEvent::Subscribe {
+      endpoint: msg.endpoint,
+    });
+    self.internal_impl.subscribe(msg)
+  }
+
+  fn unsubscribe(&self, msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture {
+    self.recorder.record(RecordedDeviceEvent::Unsubscribe {
+      endpoint: msg.endpoint,
+    });
+    self.internal_impl.unsubscribe(msg)
+  }
+
+  fn supports_rssi(&self) -> bool {
+    self.internal_impl.supports_rssi()
+  }
+
+  fn rssi_level(&self) -> BoxFuture<'static, Result<i32, ButtplugError>> {
+    self.internal_impl.rssi_level()
+  }
+}
+
+/// Wraps another device creator, recording every device it creates to a new
+/// file in `directory`.
+pub struct RecordingDeviceImplCreator {
+  creator: Box<dyn ButtplugDeviceImplCreator>,
+  directory: PathBuf,
+}
+
+impl RecordingDeviceImplCreator {
+  pub fn new(creator: Box<dyn ButtplugDeviceImplCreator>, directory: &Path) -> Self {
+    Self {
+      creator,
+      directory: directory.to_owned(),
+    }
+  }
+}
+
+impl Debug for RecordingDeviceImplCreator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RecordingDeviceImplCreator")
+      .field("creator", &self.creator)
+      .field("directory", &self.directory)
+      .finish()
+  }
+}
+
+#[async_trait]
+impl ButtplugDeviceImplCreator for RecordingDeviceImplCreator {
+  fn get_specifier(&self) -> DeviceSpecifier {
+    self.creator.get_specifier()
+  }
+
+  async fn try_create_device_impl(
+    &mut self,
+    protocol: ProtocolDefinition,
+  ) -> Result<DeviceImpl, ButtplugError> {
+    let device_impl = self.creator.try_create_device_impl(protocol).await?;
+    let address: String = device_impl
+      .address()
+      .chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+      .collect();
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_millis();
+    let path = self
+      .directory
+      .join(format!("{}-{}.jsonl", address, timestamp));
+    match DeviceRecorder::to_file(&path) {
+      Ok(recorder) => {
+        info!(
+          "Recording device {} to {}",
+          device_impl.name(),
+          path.display()
+        );
+        Ok(device_impl.with_recorder(Arc::new(recorder)))
+      }
+      Err(err) => {
+        // Not being able to record shouldn't keep people from using their
+        // device.
+        error!("Cannot record device {}: {}", device_impl.name(), err);
+        Ok(device_impl)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_recording_entry_format() {
+    let entry = RecordedDeviceEntry {
+      time_ms: 12,
+      event: RecordedDeviceEvent::Write {
+        endpoint: Endpoint::Tx,
+        data: vec![0xF1, 64],
+        write_with_response: false,
+      },
+    };
+    let line = serde_json::to_string(&entry).unwrap();
+    assert_eq!(
+      line,
+      r#"{"time_ms":12,"type":"Write","endpoint":"tx","data":[241,64],"write_with_response":false}"#
+    );
+    let recording = format!(
+      "{}\n\n{}\n",
+      line, r#"{"time_ms":15,"type":"Disconnected"}"#
+    );
+    assert_eq!(
+      parse_recording(&recording).unwrap(),
+      vec![
+        entry,
+        RecordedDeviceEntry {
+          time_ms: 15,
+          event: RecordedDeviceEvent::Disconnected,
+        }
+      ]
+    );
+  }
+}
@@ -0,0 +1,284 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Translation of the deprecated LovenseCmd message into generic commands.
+//!
+//! Spec v0/v1 apps written for Lovense toys send Lovense's own command strings
+//! (i.e. "Vibrate:10;"). We turn those into the generic commands that do the
+//! same thing, so they work on anything with the right features.
+
+use crate::core::{
+  errors::{ButtplugDeviceError, ButtplugError},
+  messages::{
+    ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessageType, DeviceMessageAttributesMap,
+    PatternCmd, PatternSubcommand, RotateCmd, RotationSubcommand, VibrateCmd, VibrateSubcommand,
+  },
+};
+
+/// Lovense speeds go from 0 to 20.
+const LOVENSE_MAX_LEVEL: u32 = 20;
+
+/// Keeps the rotation state that Lovense commands are relative to, since
+/// RotateChange flips the direction of whatever is currently rotating.
+#[derive(Default)]
+pub(super) struct LovenseCmdTranslator {
+  clockwise: bool,
+  rotate_speed: f64,
+}
+
+impl LovenseCmdTranslator {
+  /// Translates a Lovense command string, which may hold several
+  /// semicolon-terminated commands, into generic commands for a device with
+  /// the given message attributes.
+  pub fn translate(
+    &mut self,
+    device_index: u32,
+    command: &str,
+    attributes: &DeviceMessageAttributesMap,
+  ) -> Result<Vec<ButtplugDeviceCommandMessageUnion>, ButtplugError> {
+    let mut commands = vec![];
+    for part in command
+      .split(';')
+      .map(str::trim)
+      .filter(|part| !part.is_empty())
+    {
+      let mut fields = part.split(':');
+      let name = fields.next().unwrap_or_default();
+      let args: Vec<&str> = fields.collect();
+      let command = match (name, args.as_slice()) {
+        ("Vibrate", [level]) => {
+          let speed = parse_level(part, level)?;
+          let count = feature_count(attributes, ButtplugDeviceMessageType::VibrateCmd)?;
+          VibrateCmd::new(
+            device_index,
+            (0..count)
+              .map(|index| VibrateSubcommand::new(index, speed))
+              .collect(),
+          )
+          .into()
+        }
+        ("Rotate", [level]) => {
+          self.rotate_speed = parse_level(part, level)?;
+          self.rotate_cmd(device_index, attributes)?
+        }
+        ("RotateTrue", [level]) | ("RotateFalse", [level]) => {
+          self.clockwise = name == "RotateTrue";
+          self.rotate_speed = parse_level(part, level)?;
+          self.rotate_cmd(device_index, attributes)?
+        }
+        ("RotateChange", []) => {
+          self.clockwise = !self.clockwise;
+          self.rotate_cmd(device_index, attributes)?
+        }
+        ("Preset", [preset]) => preset_cmd(device_index, part, preset, attributes)?,
+        (name, [level]) if name.starts_with("Vibrate") => {
+          // VibrateN, where N counts motors from 1.
+          let motor = name["Vibrate".len()..]
+            .parse::<u32>()
+            .ok()
+            .filter(|motor| *motor > 0)
+            .ok_or_else(|| unsupported_command(part))?;
+          let speed = parse_level(part, level)?;
+          let count = feature_count(attributes, ButtplugDeviceMessageType::VibrateCmd)?;
+          if motor > count {
+            return Err(ButtplugDeviceError::DeviceFeatureIndexError(count, motor - 1).into());
+          }
+          VibrateCmd::new(device_index, vec![VibrateSubcommand::new(motor - 1, speed)]).into()
+        }
+        _ => return Err(unsupported_command(part)),
+      };
+      commands.push(command);
+    }
+    if commands.is_empty() {
+      return Err(unsupported_command(command));
+    }
+    Ok(commands)
+  }
+
+  fn rotate_cmd(
+    &self,
+    device_index: u32,
+    attributes: &DeviceMessageAttributesMap,
+  ) -> Result<ButtplugDeviceCommandMessageUnion, ButtplugError> {
+    let count = feature_count(attributes, ButtplugDeviceMessageType::RotateCmd)?;
+    Ok(
+      RotateCmd::new(
+        device_index,
+        (0..count)
+          .map(|index| RotationSubcommand::new(index, self.rotate_speed, self.clockwise))
+          .collect(),
+      )
+      .into(),
+    )
+  }
+}
+
+/// Presets are numbered from 1 in the order the device config lists patterns
+/// for the first feature. Preset 0 stops the pattern.
+fn preset_cmd(
+  device_index: u32,
+  part: &str,
+  preset: &str,
+  attributes: &DeviceMessageAttributesMap,
+) -> Result<ButtplugDeviceCommandMessageUnion, ButtplugError> {
+  let preset = preset
+    .parse::<usize>()
+    .map_err(|_| unsupported_command(part))?;
+  let pattern = if preset == 0 {
+    None
+  } else {
+    let name = attributes
+      .get(&ButtplugDeviceMessageType::PatternCmd)
+      .and_then(|attrs| attrs.patterns.as_ref())
+      .and_then(|patterns| patterns.first())
+      .and_then(|names| names.get(preset - 1))
+      .ok_or(ButtplugDeviceError::MessageNotSupported(
+        ButtplugDeviceMessageType::PatternCmd,
+      ))?;
+    Some(name.as_str())
+  };
+  Ok(PatternCmd::new(device_index, vec![PatternSubcommand::new(0, pattern)]).into())
+}
+
+fn parse_level(part: &str, level: &str) -> Result<f64, ButtplugError> {
+  match level.parse::<u32>() {
+    Ok(level) if level <= LOVENSE_MAX_LEVEL => Ok(level as f64 / LOVENSE_MAX_LEVEL as f64),
+    _ => Err(
+      ButtplugDeviceError::ProtocolRequirementError(format!(
+        "LovenseCmd {} has an invalid level, levels go from 0 to {}.",
+        part, LOVENSE_MAX_LEVEL
+      ))
+      .into(),
+    ),
+  }
+}
+
+fn feature_count(
+  attributes: &DeviceMessageAttributesMap,
+  message_type: ButtplugDeviceMessageType,
+) -> Result<u32, ButtplugError> {
+  attributes
+    .get(&message_type)
+    .and_then(|attrs| attrs.feature_count)
+    .ok_or_else(|| ButtplugDeviceError::MessageNotSupported(message_type).into())
+}
+
+fn unsupported_command(command: &str) -> ButtplugError {
+  ButtplugDeviceError::ProtocolRequirementError(format!("LovenseCmd {} is not supported.", command))
+    .into()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::core::messages::DeviceMessageAttributes;
+
+  fn attributes() -> DeviceMessageAttributesMap {
+    let mut attributes = DeviceMessageAttributesMap::new();
+    attributes.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(2),
+        ..Default::default()
+      },
+    );
+    attributes.insert(
+      ButtplugDeviceMessageType::RotateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        ..Default::default()
+      },
+    );
+    attributes.insert(
+      ButtplugDeviceMessageType::PatternCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        patterns: Some(vec![vec!["Pulse".to_owned(), "Wave".to_owned()]]),
+        ..Default::default()
+      },
+    );
+    attributes
+  }
+
+  #[test]
+  fn test_translate_vibrate() {
+    let mut translator = LovenseCmdTranslator::default();
+    assert_eq!(
+      translator
+        .translate(3, "Vibrate:10;", &attributes())
+        .unwrap(),
+      vec![VibrateCmd::new(
+        3,
+        vec![
+          VibrateSubcommand::new(0, 0.5),
+          VibrateSubcommand::new(1, 0.5)
+        ]
+      )
+      .into()]
+    );
+    assert_eq!(
+      translator
+        .translate(3, "Vibrate2:5;Vibrate1:20;", &attributes())
+        .unwrap(),
+      vec![
+        VibrateCmd::new(3, vec![VibrateSubcommand::new(1, 0.25)]).into(),
+        VibrateCmd::new(3, vec![VibrateSubcommand::new(0, 1.0)]).into()
+      ]
+    );
+    assert!(translator
+      .translate(3, "Vibrate3:5;", &attributes())
+      .is_err());
+    assert!(translator
+      .translate(3, "Vibrate:21;", &attributes())
+      .is_err());
+  }
+
+  #[test]
+  fn test_translate_rotate() {
+    let mut translator = LovenseCmdTranslator::default();
+    assert_eq!(
+      translator.translate(0, "Rotate:4;", &attributes()).unwrap(),
+      vec![RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.2, false)]).into()]
+    );
+    assert_eq!(
+      translator
+        .translate(0, "RotateChange;", &attributes())
+        .unwrap(),
+      vec![RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.2, true)]).into()]
+    );
+    assert_eq!(
+      translator
+        .translate(0, "RotateFalse:10;", &attributes())
+        .unwrap(),
+      vec![RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.5, false)]).into()]
+    );
+  }
+
+  #[test]
+  fn test_translate_preset() {
+    let mut translator = LovenseCmdTranslator::default();
+    assert_eq!(
+      translator.translate(0, "Preset:2;", &attributes()).unwrap(),
+      vec![PatternCmd::new(0, vec![PatternSubcommand::new(0, Some("Wave"))]).into()]
+    );
+    assert_eq!(
+      translator.translate(0, "Preset:0;", &attributes()).unwrap(),
+      vec![PatternCmd::new(0, vec![PatternSubcommand::new(0, None)]).into()]
+    );
+    assert!(translator.translate(0, "Preset:3;", &attributes()).is_err());
+  }
+
+  #[test]
+  fn test_translate_unsupported() {
+    let mut translator = LovenseCmdTranslator::default();
+    assert!(translator.translate(0, "Battery;", &attributes()).is_err());
+    assert!(translator.translate(0, "", &attributes()).is_err());
+    assert!(translator
+      .translate(0, "Rotate:5;", &DeviceMessageAttributesMap::new())
+      .is_err());
+  }
+}
@@ -1,4 +1,10 @@
+mod actuator_state;
+mod command_scheduler;
 pub mod configuration_manager;
+pub mod device_filter;
+pub mod device_metrics;
+pub mod device_recording;
+mod lovense_cmd_translator;
 pub mod protocol;
 use serde::{
   de::{self, Visitor},
@@ -8,15 +14,17 @@ use std::{
   fmt::{self, Debug},
   str::FromStr,
   string::ToString,
-  sync::Arc,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
 };
 
 use crate::{
   core::{
-    errors::ButtplugError,
+    errors::{ButtplugDeviceError, ButtplugError},
     messages::{
-      self, ButtplugDeviceCommandMessageUnion, ButtplugServerMessage, DeviceMessageAttributesMap,
-      RawReadCmd, RawReading, RawSubscribeCmd, RawUnsubscribeCmd, RawWriteCmd,
+      self, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage, ButtplugDeviceMessageType,
+      ButtplugMessage, ButtplugServerMessage, DeviceConnectionStage, DeviceMessageAttributesMap,
+      RawReadCmd, RawReading, RawSubscribeCmd, RawUnsubscribeCmd, RawWriteCmd, SensorType,
     },
     ButtplugResultFuture,
   },
@@ -25,10 +33,19 @@ use crate::{
     protocol::ButtplugProtocol,
   },
 };
+use actuator_state::ActuatorState;
 use async_trait::async_trait;
+use command_scheduler::{is_actuator_command, DeviceCommandScheduler};
 use configuration_manager::DeviceProtocolConfiguration;
 use core::hash::{Hash, Hasher};
-use futures::future::BoxFuture;
+use device_metrics::{DeviceMetrics, DeviceMetricsSnapshot};
+use device_recording::{DeviceRecorder, RecordedDeviceEvent, RecordingDeviceImpl};
+use futures::{
+  future::{self, BoxFuture},
+  Future, FutureExt,
+};
+use futures_timer::Delay;
+use lovense_cmd_translator::LovenseCmdTranslator;
 use tokio::sync::broadcast;
 
 // We need this array to be exposed in our WASM FFI, but the only way to do that
@@ -303,6 +320,7 @@ pub struct DeviceImpl {
   address: String,
   endpoints: Vec<Endpoint>,
   internal_impl: Box<dyn DeviceImplInternal>,
+  metrics: Arc<DeviceMetrics>,
 }
 
 impl DeviceImpl {
@@ -317,9 +335,25 @@ impl DeviceImpl {
       address: address.to_owned(),
       endpoints: endpoints.into(),
       internal_impl,
+      metrics: Arc::new(DeviceMetrics::default()),
     }
   }
 
+  /// Records everything that goes through this device from here on.
+  pub fn with_recorder(mut self, recorder: Arc<DeviceRecorder>) -> Self {
+    recorder.record(RecordedDeviceEvent::Connected {
+      name: self.name.clone(),
+      address: self.address.clone(),
+      endpoints: self.endpoints.clone(),
+    });
+    self.internal_impl = Box::new(RecordingDeviceImpl::new(self.internal_impl, recorder));
+    self
+  }
+
+  pub fn metrics(&self) -> &DeviceMetrics {
+    &self.metrics
+  }
+
   pub fn name(&self) -> &str {
     &self.name
   }
@@ -348,11 +382,30 @@ impl DeviceImpl {
     &self,
     msg: DeviceReadCmd,
   ) -> BoxFuture<'static, Result<RawReading, ButtplugError>> {
-    self.internal_impl.read_value(msg)
+    let endpoint = msg.endpoint;
+    let metrics = self.metrics.clone();
+    let fut = self.internal_impl.read_value(msg);
+    Box::pin(async move {
+      let result = fut.await;
+      match &result {
+        Ok(reading) => metrics.record_read(endpoint, reading.data().len(), true),
+        Err(_) => metrics.record_read(endpoint, 0, false),
+      }
+      result
+    })
   }
 
   pub fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture {
-    self.internal_impl.write_value(msg)
+    let endpoint = msg.endpoint;
+    let bytes = msg.data.len();
+    let metrics = self.metrics.clone();
+    let fut = self.internal_impl.write_value(msg);
+    Box::pin(async move {
+      let start = Instant::now();
+      let result = fut.await;
+      metrics.record_write(endpoint, bytes, start.elapsed(), result.is_ok());
+      result
+    })
   }
 
   pub fn subscribe(&self, msg: DeviceSubscribeCmd) -> ButtplugResultFuture {
@@ -362,6 +415,14 @@ impl DeviceImpl {
   pub fn unsubscribe(&self, msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture {
     self.internal_impl.unsubscribe(msg)
   }
+
+  pub fn supports_rssi(&self) -> bool {
+    self.internal_impl.supports_rssi()
+  }
+
+  pub fn rssi_level(&self) -> BoxFuture<'static, Result<i32, ButtplugError>> {
+    self.internal_impl.rssi_level()
+  }
 }
 
 pub trait DeviceImplInternal: Sync + Send {
@@ -375,6 +436,17 @@ pub trait DeviceImplInternal: Sync + Send {
   fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture;
   fn subscribe(&self, msg: DeviceSubscribeCmd) -> ButtplugResultFuture;
   fn unsubscribe(&self, msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture;
+  /// Whether the connection can report its signal strength. Devices that can
+  /// get RSSILevelCmd, whatever their protocol.
+  fn supports_rssi(&self) -> bool {
+    false
+  }
+  /// Current signal strength of the connection, in dBm.
+  fn rssi_level(&self) -> BoxFuture<'static, Result<i32, ButtplugError>> {
+    Box::pin(future::ready(Err(
+      ButtplugDeviceError::MessageNotSupported(ButtplugDeviceMessageType::RSSILevelCmd).into(),
+    )))
+  }
 }
 
 #[async_trait]
@@ -386,9 +458,76 @@ pub trait ButtplugDeviceImplCreator: Sync + Send + Debug {
   ) -> Result<DeviceImpl, ButtplugError>;
 }
 
+/// Time limits for each stage of device creation. None means no limit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeviceCreationTimeouts {
+  pub connect: Option<Duration>,
+  pub initialize: Option<Duration>,
+}
+
+/// Error from [ButtplugDevice::try_create_device_with_timeouts], along with
+/// the stage it happened in.
+#[derive(Debug, Clone)]
+pub struct DeviceCreationError {
+  pub stage: DeviceConnectionStage,
+  pub error: ButtplugError,
+}
+
+async fn with_stage_timeout<T, F>(
+  stage: DeviceConnectionStage,
+  timeout: Option<Duration>,
+  fut: F,
+) -> Result<T, DeviceCreationError>
+where
+  F: Future<Output = Result<T, ButtplugError>>,
+{
+  let timeout_fut = async {
+    match timeout {
+      Some(timeout) => Delay::new(timeout).await,
+      None => future::pending::<()>().await,
+    }
+  };
+  let result = select! {
+    result = fut.fuse() => result,
+    _ = timeout_fut.fuse() => Err(
+      ButtplugDeviceError::DeviceConnectionTimeout(format!(
+        "{:?} stage did not finish within {:?}",
+        stage,
+        timeout.unwrap_or_default()
+      ))
+      .into(),
+    ),
+  };
+  result.map_err(|error| DeviceCreationError { stage, error })
+}
+
+/// True if a command failed because we lost (or never had) a connection to the
+/// device, rather than because the device or protocol couldn't handle it.
+fn is_connection_error(result: &Result<ButtplugServerMessage, ButtplugError>) -> bool {
+  matches!(
+    result,
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceNotConnected(_)
+        | ButtplugDeviceError::DeviceConnectionError(_)
+        | ButtplugDeviceError::DeviceCommunicationError(_)
+    ))
+  )
+}
+
 pub struct ButtplugDevice {
-  protocol: Box<dyn ButtplugProtocol>,
+  protocol: Arc<dyn ButtplugProtocol>,
   device: Arc<DeviceImpl>,
+  /// Only exists if the device has a minimum write interval configured,
+  /// otherwise commands go straight to the protocol.
+  scheduler: Option<DeviceCommandScheduler>,
+  /// Latest command sent to each actuator, so the device's state can be
+  /// restored if it reconnects.
+  actuator_state: Arc<Mutex<ActuatorState>>,
+  /// Rotation state for translating LovenseCmd.
+  lovense_translator: Mutex<LovenseCmdTranslator>,
+  /// Message attributes as clients see them. These don't change once the
+  /// device is created, so they're only worked out once.
+  message_attributes: Arc<DeviceMessageAttributesMap>,
 }
 
 impl Debug for ButtplugDevice {
@@ -416,7 +555,38 @@ impl PartialEq for ButtplugDevice {
 
 impl ButtplugDevice {
   pub fn new(protocol: Box<dyn ButtplugProtocol>, device: Arc<DeviceImpl>) -> Self {
-    Self { protocol, device }
+    let mut message_attributes =
+      protocol::client_message_attributes(&protocol.message_attributes());
+    if device.supports_rssi() {
+      message_attributes
+        .entry(ButtplugDeviceMessageType::RSSILevelCmd)
+        .or_default();
+    }
+    Self {
+      protocol: Arc::from(protocol),
+      device,
+      scheduler: None,
+      actuator_state: Arc::new(Mutex::new(ActuatorState::default())),
+      lovense_translator: Mutex::new(LovenseCmdTranslator::default()),
+      message_attributes: Arc::new(message_attributes),
+    }
+  }
+
+  /// Sets the minimum time between commands sent to the device. Commands that
+  /// come in faster than this are merged, so only the latest state is sent.
+  /// Stop commands are always sent immediately.
+  pub fn with_min_write_interval(mut self, interval: Duration) -> Self {
+    self.scheduler = if interval > Duration::from_millis(0) {
+      Some(DeviceCommandScheduler::new(
+        self.protocol.clone(),
+        self.device.clone(),
+        self.message_attributes.clone(),
+        interval,
+      ))
+    } else {
+      None
+    };
+    self
   }
 
   pub fn address(&self) -> &str {
@@ -425,8 +595,24 @@ impl ButtplugDevice {
 
   pub async fn try_create_device(
     device_config_mgr: Arc<DeviceConfigurationManager>,
-    mut device_creator: Box<dyn ButtplugDeviceImplCreator>,
+    device_creator: Box<dyn ButtplugDeviceImplCreator>,
   ) -> Result<Option<ButtplugDevice>, ButtplugError> {
+    Self::try_create_device_with_timeouts(
+      device_config_mgr,
+      device_creator,
+      DeviceCreationTimeouts::default(),
+    )
+    .await
+    .map_err(|err| err.error)
+  }
+
+  /// Same as [ButtplugDevice::try_create_device], but with time limits on each
+  /// stage, and errors that say which stage failed.
+  pub async fn try_create_device_with_timeouts(
+    device_config_mgr: Arc<DeviceConfigurationManager>,
+    mut device_creator: Box<dyn ButtplugDeviceImplCreator>,
+    timeouts: DeviceCreationTimeouts,
+  ) -> Result<Option<ButtplugDevice>, DeviceCreationError> {
     // First off, we need to see if we even have a configuration available
     // for the device we're trying to create. If we don't, return Ok(None),
     // because this isn't actually an error. However, if we *do* have a
@@ -447,35 +633,61 @@ impl ButtplugDevice {
         // TODO Should we even return a config from the device_config_mgr if the
         // protocol isn't there?
         if device_config_mgr.has_protocol(&*config_name) {
-          match device_creator.try_create_device_impl(config).await {
-            Ok(device_impl) => {
-              info!(
-                address = tracing::field::display(device_impl.address()),
-                "Found Buttplug Device {}",
-                device_impl.name()
-              );
-              // If we've made it this far, we now have a connected device
-              // implementation with endpoints set up. We now need to run whatever
-              // protocol initialization might need to happen. We'll fetch a protocol
-              // creator, pass the device implementation to it, then let it do
-              // whatever it needs. For most protocols, this is a no-op. However, for
-              // devices like Lovense, some Kiiroo, etc, this can get fairly
-              // complicated.
-              let sharable_device_impl = Arc::new(device_impl);
-              match device_config_mgr.get_protocol_creator(&*config_name)(
-                sharable_device_impl.clone(),
-                device_protocol_config,
-              )
-              .await
-              {
-                Ok(protocol_impl) => Ok(Some(ButtplugDevice::new(
-                  protocol_impl,
-                  sharable_device_impl,
-                ))),
-                Err(e) => Err(e),
+          let device_limits = config.device_limits.clone();
+          let device_impl = with_stage_timeout(
+            DeviceConnectionStage::Connect,
+            timeouts.connect,
+            device_creator.try_create_device_impl(config),
+          )
+          .await?;
+          info!(
+            address = tracing::field::display(device_impl.address()),
+            "Found Buttplug Device {}",
+            device_impl.name()
+          );
+          // If we've made it this far, we now have a connected device
+          // implementation with endpoints set up. We now need to run whatever
+          // protocol initialization might need to happen. We'll fetch a protocol
+          // creator, pass the device implementation to it, then let it do
+          // whatever it needs. For most protocols, this is a no-op. However, for
+          // devices like Lovense, some Kiiroo, etc, this can get fairly
+          // complicated.
+          let device_protocol_config = device_protocol_config
+            .with_device_limits(device_limits.get(device_impl.address()).cloned());
+          // The protocol consumes the config, but we still need it to look up
+          // the write interval once we know which identifier the device uses.
+          let write_interval_config = device_protocol_config.clone();
+          let sharable_device_impl = Arc::new(device_impl);
+          match with_stage_timeout(
+            DeviceConnectionStage::Initialize,
+            timeouts.initialize,
+            device_config_mgr.get_protocol_creator(&*config_name)(
+              sharable_device_impl.clone(),
+              device_protocol_config,
+            ),
+          )
+          .await
+          {
+            Ok((protocol_impl, identifier)) => {
+              let min_write_interval = write_interval_config
+                .get_min_write_interval(&identifier)
+                .unwrap_or(0);
+              Ok(Some(
+                ButtplugDevice::new(protocol_impl, sharable_device_impl)
+                  .with_min_write_interval(Duration::from_millis(min_write_interval.into())),
+              ))
+            }
+            Err(err) => {
+              // We're connected but can't talk to the device, so don't leave
+              // the connection hanging around.
+              if let Err(disconnect_err) = sharable_device_impl.disconnect().await {
+                debug!(
+                  "Error disconnecting device after failed initialization: {}",
+                  disconnect_err
+                );
               }
+              Err(err)
             }
-            Err(e) => Err(e),
           }
         } else {
           info!("Protocol {} not available", config_name);
@@ -510,20 +722,169 @@ impl ButtplugDevice {
     self.device.disconnect()
   }
 
+  /// Message attributes as clients see them, see
+  /// [protocol::client_message_attributes].
   pub fn message_attributes(&self) -> DeviceMessageAttributesMap {
-    self.protocol.message_attributes()
+    (*self.message_attributes).clone()
   }
 
   pub fn parse_message(
     &self,
     message: ButtplugDeviceCommandMessageUnion,
   ) -> ButtplugDeviceResultFuture {
-    self.protocol.handle_command(self.device.clone(), message)
+    // LovenseCmd always goes through the translator, so it gets the same
+    // command state and output limits as the messages it stands for.
+    if let ButtplugDeviceCommandMessageUnion::LovenseCmd(msg) = &message {
+      return self.parse_lovense_cmd(msg);
+    }
+    // Signal strength comes from the connection, not the protocol, so it
+    // doesn't need to be in the protocol's attributes.
+    if let ButtplugDeviceCommandMessageUnion::RSSILevelCmd(msg) = &message {
+      if self.device.supports_rssi() {
+        return self
+          .protocol
+          .handle_rssi_level_cmd(self.device.clone(), msg.clone());
+      }
+    }
+    self.device.metrics().record_command();
+    let mut state_update = None;
+    if let ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) = message {
+      self.actuator_state.lock().unwrap().clear();
+    } else if is_actuator_command(&message) && self.protocol.supports_message(&message).is_ok() {
+      state_update = Some(message.clone());
+    }
+    let fut = if let Some(scheduler) = &self.scheduler {
+      scheduler.send(message)
+    } else {
+      self.protocol.handle_command(self.device.clone(), message)
+    };
+    let state_update = match state_update {
+      Some(state_update) => state_update,
+      None => return fut,
+    };
+    let actuator_state = self.actuator_state.clone();
+    let attributes = self.message_attributes.clone();
+    Box::pin(async move {
+      let result = fut.await;
+      // Only keep state the protocol accepted. Commands that fail because the
+      // device dropped are exactly the ones we want to resend if it comes
+      // back though, so keep those too.
+      if result.is_ok() || is_connection_error(&result) {
+        actuator_state
+          .lock()
+          .unwrap()
+          .update(state_update, &attributes);
+      }
+      result
+    })
+  }
+
+  /// Runs the generic commands a LovenseCmd translates to, in order.
+  fn parse_lovense_cmd(&self, message: &messages::LovenseCmd) -> ButtplugDeviceResultFuture {
+    let commands = match self.lovense_translator.lock().unwrap().translate(
+      message.device_index(),
+      message.command(),
+      &self.message_attributes,
+    ) {
+      Ok(commands) => commands,
+      Err(err) => return Box::pin(future::ready(Err(err))),
+    };
+    let futs: Vec<_> = commands
+      .into_iter()
+      .map(|mut command| {
+        command.set_id(message.id());
+        self.parse_message(command)
+      })
+      .collect();
+    let id = message.id();
+    Box::pin(async move {
+      for fut in futs {
+        fut.await?;
+      }
+      Ok(messages::Ok::new(id).into())
+    })
+  }
+
+  /// Latest actuator commands sent to the device since it was last stopped.
+  /// Sending these to a new connection to the same device puts it back in the
+  /// same state.
+  pub fn actuator_state(&self) -> Vec<ButtplugDeviceCommandMessageUnion> {
+    self.actuator_state.lock().unwrap().commands()
+  }
+
+  pub fn metrics(&self) -> DeviceMetricsSnapshot {
+    self.device.metrics().snapshot()
+  }
+
+  /// True if any actuator was last told to move and hasn't been stopped since.
+  pub fn is_running(&self) -> bool {
+    self.actuator_state.lock().unwrap().is_running()
   }
 
   pub fn event_stream(&self) -> broadcast::Receiver<ButtplugDeviceEvent> {
     self.device.event_stream()
   }
 
+  /// Readings for every sensor that reports through `endpoint`, as the
+  /// sensor index, type and values, parsed from data the device sent there.
+  pub fn parse_sensor_data(
+    &self,
+    endpoint: Endpoint,
+    data: &[u8],
+  ) -> Vec<(u32, SensorType, Vec<i32>)> {
+    let attributes = self.protocol.message_attributes();
+    let (endpoints, sensor_types) = match attributes
+      .get(&ButtplugDeviceMessageType::SensorSubscribeCmd)
+      .and_then(|attrs| attrs.endpoints.as_ref().zip(attrs.sensor_type.as_ref()))
+    {
+      Some(sensors) => sensors,
+      None => return vec![],
+    };
+    endpoints
+      .iter()
+      .zip(sensor_types.iter())
+      .enumerate()
+      .filter(|(_, (sensor_endpoint, _))| **sensor_endpoint == endpoint)
+      .filter_map(|(index, (_, sensor_type))| {
+        self
+          .protocol
+          .parse_sensor_data(index as u32, *sensor_type, data)
+          .map(|values| (index as u32, *sensor_type, values))
+      })
+      .collect()
+  }
+
   // TODO Handle raw messages here.
 }
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    core::messages::{
+      ButtplugDeviceCommandMessageUnion, RotateCmd, RotationSubcommand, VibrateCmd,
+      VibrateSubcommand,
+    },
+    server::comm_managers::test::new_bluetoothle_test_device,
+    util::async_manager,
+  };
+
+  #[test]
+  fn test_actuator_state_skips_rejected_commands() {
+    async_manager::block_on(async move {
+      let (device, _) = new_bluetoothle_test_device("Massage Demo").await.unwrap();
+      let accepted: ButtplugDeviceCommandMessageUnion =
+        VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into();
+      assert!(device.parse_message(accepted.clone()).await.is_ok());
+      // The device only has two vibrators and nothing that rotates.
+      assert!(device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(5, 1.0)]).into())
+        .await
+        .is_err());
+      assert!(device
+        .parse_message(RotateCmd::new(0, vec![RotationSubcommand::new(0, 1.0, true)]).into())
+        .await
+        .is_err());
+      assert_eq!(device.actuator_state(), vec![accepted]);
+    });
+  }
+}
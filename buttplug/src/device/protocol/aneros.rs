@@ -1,6 +1,11 @@
 use super::{ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler};
 use crate::{
-  core::messages::{self, ButtplugDeviceCommandMessageUnion, DeviceMessageAttributesMap},
+  core::{
+    errors::ButtplugError,
+    messages::{
+      self, ButtplugDeviceCommandMessageUnion, ButtplugServerMessage, DeviceMessageAttributesMap,
+    },
+  },
   device::{
     protocol::{generic_command_manager::GenericCommandManager, ButtplugProtocolProperties},
     DeviceImpl, DeviceWriteCmd, Endpoint,
@@ -46,34 +51,60 @@ impl ButtplugProtocolCommandHandler for Aneros {
     let manager = self.manager.clone();
     Box::pin(async move {
       let result = manager.lock().await.update_vibration(&message, false)?;
-      let mut fut_vec = vec![];
-      if let Some(cmds) = result {
-        for (index, cmd) in cmds.iter().enumerate() {
-          if let Some(speed) = cmd {
-            fut_vec.push(device.write_value(DeviceWriteCmd::new(
-              Endpoint::Tx,
-              vec![0xF1 + (index as u8), *speed as u8],
-              false,
-            )));
-          }
-        }
-      }
-      // TODO Just use join_all here
-      for fut in fut_vec {
-        // TODO Do something about possible errors here
-        fut.await?;
-      }
-      Ok(messages::Ok::default().into())
+      send_vibration(device, result).await
     })
   }
+
+  fn handle_scalar_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::ScalarCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager
+        .lock()
+        .await
+        .update_scalar_vibration(&message, false)?;
+      send_vibration(device, result).await
+    })
+  }
+}
+
+async fn send_vibration(
+  device: Arc<DeviceImpl>,
+  result: Option<Vec<Option<u32>>>,
+) -> Result<ButtplugServerMessage, ButtplugError> {
+  let mut fut_vec = vec![];
+  if let Some(cmds) = result {
+    for (index, cmd) in cmds.iter().enumerate() {
+      if let Some(speed) = cmd {
+        fut_vec.push(device.write_value(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0xF1 + (index as u8), *speed as u8],
+          false,
+        )));
+      }
+    }
+  }
+  // TODO Just use join_all here
+  for fut in fut_vec {
+    // TODO Do something about possible errors here
+    fut.await?;
+  }
+  Ok(messages::Ok::default().into())
 }
 
 #[cfg(all(test, feature = "server"))]
 mod test {
   use crate::{
-    core::messages::{StopDeviceCmd, VibrateCmd, VibrateSubcommand},
+    core::messages::{
+      ActuatorType, ScalarCmd, ScalarSubcommand, StopDeviceCmd, VibrateCmd, VibrateSubcommand,
+    },
     device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
-    server::comm_managers::test::{check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device},
+    server::comm_managers::test::{
+      check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device,
+    },
     util::async_manager,
   };
 
@@ -132,4 +163,67 @@ mod test {
       );
     });
   }
+
+  #[test]
+  pub fn test_aneros_protocol_scalar_cmd() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Massage Demo").await.unwrap();
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      device
+        .parse_message(
+          ScalarCmd::new(
+            0,
+            vec![ScalarSubcommand::new(1, 0.5, ActuatorType::Vibrate)],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF2, 64], false)),
+      );
+      // VibrateCmd shares state with ScalarCmd, so the unchanged vibrator
+      // isn't written again.
+      device
+        .parse_message(
+          VibrateCmd::new(
+            0,
+            vec![
+              VibrateSubcommand::new(0, 0.1),
+              VibrateSubcommand::new(1, 0.5),
+            ],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 13], false)),
+      );
+      assert!(check_test_recv_empty(&command_receiver));
+      assert!(device
+        .parse_message(
+          ScalarCmd::new(
+            0,
+            vec![ScalarSubcommand::new(0, 0.5, ActuatorType::Constrict)],
+          )
+          .into(),
+        )
+        .await
+        .is_err());
+      assert!(device
+        .parse_message(
+          ScalarCmd::new(
+            0,
+            vec![ScalarSubcommand::new(2, 0.5, ActuatorType::Vibrate)],
+          )
+          .into(),
+        )
+        .await
+        .is_err());
+      assert!(check_test_recv_empty(&command_receiver));
+    });
+  }
 }
@@ -1,19 +1,84 @@
 use crate::core::{
   errors::{ButtplugDeviceError, ButtplugError},
   messages::{
-    ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessageType, DeviceMessageAttributesMap,
-    LinearCmd, RotateCmd, RotationSubcommand, VibrateCmd, VibrateSubcommand,
+    ActuatorType, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessageType,
+    DeviceMessageAttributes, DeviceMessageAttributesMap, LinearCmd, PatternCmd, PatternSubcommand,
+    ResponseCurve, RotateCmd, RotationSubcommand, ScalarCmd, ScalarSubcommand, VibrateCmd,
+    VibrateSubcommand,
   },
 };
 
+/// Output limits for a single message type, taken from the device message
+/// attributes.
+#[derive(Default)]
+struct OutputLimits {
+  max_speeds: Vec<f64>,
+  response_curve: Option<ResponseCurve>,
+}
+
+impl OutputLimits {
+  fn new(attributes: &DeviceMessageAttributes, feature_count: usize) -> Self {
+    let mut max_speeds = vec![1.0; feature_count];
+    if let Some(limits) = &attributes.max_speed {
+      for (max_speed, limit) in max_speeds.iter_mut().zip(limits.iter()) {
+        *max_speed = limit.clamp(0.0, 1.0);
+      }
+    }
+    Self {
+      max_speeds,
+      response_curve: attributes.response_curve.clone(),
+    }
+  }
+
+  /// Converts a 0.0-1.0 speed into device steps, running it through the
+  /// response curve and then scaling it down to the feature's max speed.
+  fn speed_to_steps(&self, index: usize, speed: f64, step_count: u32) -> u32 {
+    let speed = match &self.response_curve {
+      Some(curve) => curve.apply(speed),
+      None => speed,
+    };
+    let max_speed = self.max_speeds[index];
+    // When calculating speeds, round up. This follows how we calculated
+    // things in buttplug-js and buttplug-csharp, so it's more for history
+    // than anything, but it's what users will expect. Rounding up still can't
+    // put us over the limit though.
+    let steps = (speed * max_speed * step_count as f64).ceil() as u32;
+    steps.min((max_speed * step_count as f64).floor() as u32)
+  }
+
+  /// Whether these limits change the output at all.
+  fn is_limited(&self) -> bool {
+    self.response_curve.is_some() || self.max_speeds.iter().any(|max_speed| *max_speed < 1.0)
+  }
+}
+
+/// Actuator type and steps for each ScalarCmd feature, None for features that
+/// don't need to be updated.
+pub type ScalarSteps = Vec<Option<(ActuatorType, u32)>>;
+
 pub struct GenericCommandManager {
   sent_vibration: bool,
   sent_rotation: bool,
+  sent_scalar: bool,
   _sent_linear: bool,
   vibrations: Vec<u32>,
   vibration_step_counts: Vec<u32>,
+  vibration_limits: OutputLimits,
   rotations: Vec<(u32, bool)>,
   rotation_step_counts: Vec<u32>,
+  rotation_limits: OutputLimits,
+  scalars: Vec<u32>,
+  scalar_step_counts: Vec<u32>,
+  scalar_actuator_types: Vec<ActuatorType>,
+  scalar_limits: OutputLimits,
+  // Pattern playing on each feature, as a 1-based position in its Patterns
+  // list, or 0 if stopped.
+  patterns: Vec<u32>,
+  pattern_names: Vec<Vec<String>>,
+  // Firmware patterns run at whatever speed the device picks, so they can't
+  // be held to output limits. If any are configured, patterns can't be
+  // started.
+  patterns_limited: bool,
   _linears: Vec<(u32, u32)>,
   _linear_step_counts: Vec<u32>,
   stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
@@ -23,8 +88,15 @@ impl GenericCommandManager {
   pub fn new(attributes: &DeviceMessageAttributesMap) -> Self {
     let mut vibrations: Vec<u32> = vec![];
     let mut vibration_step_counts: Vec<u32> = vec![];
+    let mut vibration_limits = OutputLimits::default();
     let mut rotations: Vec<(u32, bool)> = vec![];
     let mut rotation_step_counts: Vec<u32> = vec![];
+    let mut rotation_limits = OutputLimits::default();
+    let mut scalars: Vec<u32> = vec![];
+    let mut scalar_step_counts: Vec<u32> = vec![];
+    let mut scalar_actuator_types: Vec<ActuatorType> = vec![];
+    let mut scalar_limits = OutputLimits::default();
+    let mut pattern_names: Vec<Vec<String>> = vec![];
     let mut linears: Vec<(u32, u32)> = vec![];
     let mut linear_step_counts: Vec<u32> = vec![];
 
@@ -38,6 +110,7 @@ impl GenericCommandManager {
       if let Some(step_counts) = &attr.step_count {
         vibration_step_counts = step_counts.clone();
       }
+      vibration_limits = OutputLimits::new(attr, vibrations.len());
 
       let mut subcommands = vec![];
       for i in 0..vibrations.len() {
@@ -52,6 +125,7 @@ impl GenericCommandManager {
       if let Some(step_counts) = &attr.step_count {
         rotation_step_counts = step_counts.clone();
       }
+      rotation_limits = OutputLimits::new(attr, rotations.len());
 
       // TODO Can we assume clockwise is false here? We might send extra
       // messages on Lovense since it'll require both a speed and change
@@ -63,6 +137,34 @@ impl GenericCommandManager {
       }
       stop_commands.push(RotateCmd::new(0, subcommands).into());
     }
+    if let Some(attr) = attributes.get(&ButtplugDeviceMessageType::ScalarCmd) {
+      if let Some(count) = attr.feature_count {
+        scalars = vec![0; count as usize];
+      }
+      if let Some(step_counts) = &attr.step_count {
+        scalar_step_counts = step_counts.clone();
+      }
+      if let Some(actuator_types) = &attr.actuator_type {
+        scalar_actuator_types = actuator_types.clone();
+      }
+      scalar_limits = OutputLimits::new(attr, scalars.len());
+
+      let mut subcommands = vec![];
+      for (i, actuator_type) in scalar_actuator_types.iter().enumerate() {
+        subcommands.push(ScalarSubcommand::new(i as u32, 0.0, *actuator_type));
+      }
+      stop_commands.push(ScalarCmd::new(0, subcommands).into());
+    }
+    if let Some(attr) = attributes.get(&ButtplugDeviceMessageType::PatternCmd) {
+      if let Some(names) = &attr.patterns {
+        pattern_names = names.clone();
+      }
+      let mut subcommands = vec![];
+      for i in 0..pattern_names.len() {
+        subcommands.push(PatternSubcommand::new(i as u32, None));
+      }
+      stop_commands.push(PatternCmd::new(0, subcommands).into());
+    }
     if let Some(attr) = attributes.get(&ButtplugDeviceMessageType::LinearCmd) {
       if let Some(count) = attr.feature_count {
         linears = vec![(0, 0); count as usize];
@@ -72,15 +174,28 @@ impl GenericCommandManager {
       }
     }
 
+    let patterns_limited =
+      vibration_limits.is_limited() || rotation_limits.is_limited() || scalar_limits.is_limited();
+
     Self {
       sent_vibration: false,
       sent_rotation: false,
+      sent_scalar: false,
       _sent_linear: false,
       vibrations,
       rotations,
       _linears: linears,
       vibration_step_counts,
+      vibration_limits,
       rotation_step_counts,
+      rotation_limits,
+      scalars,
+      scalar_step_counts,
+      scalar_actuator_types,
+      scalar_limits,
+      patterns: vec![0; pattern_names.len()],
+      pattern_names,
+      patterns_limited,
       _linear_step_counts: linear_step_counts,
       stop_commands,
     }
@@ -132,10 +247,11 @@ impl GenericCommandManager {
         );
       }
 
-      // When calculating speeds, round up. This follows how we calculated
-      // things in buttplug-js and buttplug-csharp, so it's more for history
-      // than anything, but it's what users will expect.
-      let speed = (speed_command.speed() * self.vibration_step_counts[index] as f64).ceil() as u32;
+      let speed = self.vibration_limits.speed_to_steps(
+        index,
+        speed_command.speed(),
+        self.vibration_step_counts[index],
+      );
 
       // If we've already sent commands, we don't want to send them again,
       // because some of our communication busses are REALLY slow. Make sure
@@ -203,10 +319,11 @@ impl GenericCommandManager {
         );
       }
 
-      // When calculating speeds, round up. This follows how we calculated
-      // things in buttplug-js and buttplug-csharp, so it's more for history
-      // than anything, but it's what users will expect.
-      let speed = (rotate_command.speed() * self.rotation_step_counts[index] as f64).ceil() as u32;
+      let speed = self.rotation_limits.speed_to_steps(
+        index,
+        rotate_command.speed(),
+        self.rotation_step_counts[index],
+      );
       let clockwise = rotate_command.clockwise();
       // If we've already sent commands, we don't want to send them again,
       // because some of our communication busses are REALLY slow. Make sure
@@ -226,6 +343,180 @@ impl GenericCommandManager {
     Ok(result)
   }
 
+  /// Same as [GenericCommandManager::update_vibration], but for any kind of
+  /// actuator. Steps come back with the actuator type from the device config,
+  /// which the command has to match.
+  pub fn update_scalar(
+    &mut self,
+    msg: &ScalarCmd,
+    match_all: bool,
+  ) -> Result<Option<ScalarSteps>, ButtplugError> {
+    if msg.scalars().is_empty() {
+      return Err(
+        ButtplugDeviceError::ProtocolRequirementError(
+          "ScalarCmd has 0 commands, will not do anything.".to_owned(),
+        )
+        .into(),
+      );
+    }
+
+    let mut changed_value = false;
+    let mut result: ScalarSteps = vec![None; self.scalars.len()];
+    if match_all {
+      for (index, (scalar, actuator_type)) in self
+        .scalars
+        .iter()
+        .zip(self.scalar_actuator_types.iter())
+        .enumerate()
+      {
+        result[index] = Some((*actuator_type, *scalar));
+      }
+    }
+    for scalar_command in msg.scalars() {
+      let index = scalar_command.index() as usize;
+      // The feature count, step counts and actuator types all come from the
+      // device config, so don't trust them to line up.
+      if index >= self.scalars.len()
+        || index >= self.scalar_actuator_types.len()
+        || index >= self.scalar_step_counts.len()
+      {
+        return Err(
+          ButtplugDeviceError::DeviceFeatureIndexError(self.scalars.len() as u32, index as u32)
+            .into(),
+        );
+      }
+      let actuator_type = self.scalar_actuator_types[index];
+      if scalar_command.actuator_type() != actuator_type {
+        return Err(
+          ButtplugDeviceError::ProtocolRequirementError(format!(
+            "ScalarCmd index {} is {}, device actuator is {}.",
+            index,
+            scalar_command.actuator_type(),
+            actuator_type
+          ))
+          .into(),
+        );
+      }
+
+      let scalar = self.scalar_limits.speed_to_steps(
+        index,
+        scalar_command.scalar(),
+        self.scalar_step_counts[index],
+      );
+
+      // Same as vibration, skip unchanged values unless the device needs all
+      // of them every time.
+      if !self.sent_scalar || scalar != self.scalars[index] || match_all {
+        if scalar != self.scalars[index] || !self.sent_scalar {
+          changed_value = true;
+        }
+        self.scalars[index] = scalar;
+        result[index] = Some((actuator_type, scalar));
+      }
+    }
+
+    self.sent_scalar = true;
+
+    if !changed_value {
+      Ok(None)
+    } else {
+      Ok(Some(result))
+    }
+  }
+
+  /// [GenericCommandManager::update_scalar] for protocols that only drive
+  /// vibrators, returning vibration steps the same way
+  /// [GenericCommandManager::update_vibration] does.
+  pub fn update_scalar_vibration(
+    &mut self,
+    msg: &ScalarCmd,
+    match_all: bool,
+  ) -> Result<Option<Vec<Option<u32>>>, ButtplugError> {
+    if let Some(scalar) = msg
+      .scalars()
+      .iter()
+      .find(|scalar| scalar.actuator_type() != ActuatorType::Vibrate)
+    {
+      return Err(
+        ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Device only has vibrators, cannot handle {} at ScalarCmd index {}.",
+          scalar.actuator_type(),
+          scalar.index()
+        ))
+        .into(),
+      );
+    }
+    Ok(self.update_scalar(msg, match_all)?.map(|steps| {
+      steps
+        .into_iter()
+        .map(|step| step.map(|(_, speed)| speed))
+        .collect()
+    }))
+  }
+
+  /// Converts pattern names into 1-based positions in the feature's Patterns
+  /// list, with 0 meaning stop. Only features whose pattern changed get a
+  /// value. Features start out stopped, so stopping a feature that never
+  /// played anything doesn't write to the device. Starting a pattern on a
+  /// device with output limits is an error.
+  pub fn update_pattern(&mut self, msg: &PatternCmd) -> Result<Vec<Option<u32>>, ButtplugError> {
+    if msg.patterns().is_empty() {
+      return Err(
+        ButtplugDeviceError::ProtocolRequirementError(
+          "PatternCmd has 0 commands, will not do anything.".to_owned(),
+        )
+        .into(),
+      );
+    }
+
+    let mut result: Vec<Option<u32>> = vec![None; self.patterns.len()];
+    for pattern_command in msg.patterns() {
+      let index = pattern_command.index() as usize;
+      if index >= self.patterns.len() {
+        return Err(
+          ButtplugDeviceError::ProtocolRequirementError(format!(
+            "PatternCmd has {} commands, device has {} pattern features.",
+            msg.patterns().len(),
+            self.patterns.len()
+          ))
+          .into(),
+        );
+      }
+      let pattern = match pattern_command.pattern() {
+        Some(_) if self.patterns_limited => {
+          return Err(
+            ButtplugDeviceError::ProtocolRequirementError(format!(
+              "Patterns can't be started on feature {}, as output limits are configured for this device.",
+              index
+            ))
+            .into(),
+          )
+        }
+        Some(name) => match self.pattern_names[index]
+          .iter()
+          .position(|known| known == name)
+        {
+          Some(position) => position as u32 + 1,
+          None => {
+            return Err(
+              ButtplugDeviceError::ProtocolRequirementError(format!(
+                "Pattern {} is not available on feature {}, available patterns are {:?}.",
+                name, index, self.pattern_names[index]
+              ))
+              .into(),
+            )
+          }
+        },
+        None => 0,
+      };
+      if pattern != self.patterns[index] {
+        self.patterns[index] = pattern;
+        result[index] = Some(pattern);
+      }
+    }
+    Ok(result)
+  }
+
   pub fn _update_linear(
     &mut self,
     _msg: &LinearCmd,
@@ -257,9 +548,13 @@ impl GenericCommandManager {
 mod test {
 
   use super::GenericCommandManager;
-  use crate::core::messages::{
-    ButtplugDeviceMessageType, DeviceMessageAttributes, DeviceMessageAttributesMap, RotateCmd,
-    RotationSubcommand, VibrateCmd, VibrateSubcommand,
+  use crate::core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::{
+      ActuatorType, ButtplugDeviceMessageType, DeviceMessageAttributes, DeviceMessageAttributesMap,
+      PatternCmd, PatternSubcommand, ResponseCurve, RotateCmd, RotationSubcommand, ScalarCmd,
+      ScalarSubcommand, VibrateCmd, VibrateSubcommand,
+    },
   };
   #[test]
   pub fn test_command_generator_vibration() {
@@ -299,6 +594,44 @@ mod test {
     assert!(mgr.update_vibration(&vibrate_msg_invalid, false).is_err());
   }
 
+  #[test]
+  pub fn test_command_generator_vibration_limits() {
+    let mut attributes_map = DeviceMessageAttributesMap::new();
+
+    let vibrate_attributes = DeviceMessageAttributes {
+      feature_count: Some(2),
+      step_count: Some(vec![20, 20]),
+      max_speed: Some(vec![0.5, 1.0]),
+      response_curve: Some(ResponseCurve::Gamma(2.0)),
+      ..Default::default()
+    };
+    attributes_map.insert(ButtplugDeviceMessageType::VibrateCmd, vibrate_attributes);
+    let mut mgr = GenericCommandManager::new(&attributes_map);
+    let vibrate_msg = VibrateCmd::new(
+      0,
+      vec![
+        VibrateSubcommand::new(0, 1.0),
+        VibrateSubcommand::new(1, 1.0),
+      ],
+    );
+    assert_eq!(
+      mgr.update_vibration(&vibrate_msg, false).unwrap(),
+      Some(vec![Some(10), Some(20)])
+    );
+    let vibrate_msg_2 = VibrateCmd::new(
+      0,
+      vec![
+        VibrateSubcommand::new(0, 0.5),
+        VibrateSubcommand::new(1, 0.5),
+      ],
+    );
+    // 0.5 on a gamma 2 curve is 0.25.
+    assert_eq!(
+      mgr.update_vibration(&vibrate_msg_2, false).unwrap(),
+      Some(vec![Some(3), Some(5)])
+    );
+  }
+
   #[test]
   pub fn test_command_generator_rotation() {
     let mut attributes_map = DeviceMessageAttributesMap::new();
@@ -337,5 +670,170 @@ mod test {
     assert!(mgr.update_rotation(&rotate_msg_invalid).is_err());
   }
 
+  #[test]
+  pub fn test_command_generator_scalar() {
+    let mut attributes_map = DeviceMessageAttributesMap::new();
+
+    let scalar_attributes = DeviceMessageAttributes {
+      feature_count: Some(2),
+      step_count: Some(vec![20, 10]),
+      actuator_type: Some(vec![ActuatorType::Vibrate, ActuatorType::Constrict]),
+      ..Default::default()
+    };
+    attributes_map.insert(ButtplugDeviceMessageType::ScalarCmd, scalar_attributes);
+    let mut mgr = GenericCommandManager::new(&attributes_map);
+    let scalar_msg = ScalarCmd::new(
+      0,
+      vec![
+        ScalarSubcommand::new(0, 0.5, ActuatorType::Vibrate),
+        ScalarSubcommand::new(1, 0.5, ActuatorType::Constrict),
+      ],
+    );
+    assert_eq!(
+      mgr.update_scalar(&scalar_msg, false).unwrap(),
+      Some(vec![
+        Some((ActuatorType::Vibrate, 10)),
+        Some((ActuatorType::Constrict, 5))
+      ])
+    );
+    assert_eq!(mgr.update_scalar(&scalar_msg, false).unwrap(), None);
+    let scalar_msg_2 = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(1, 1.0, ActuatorType::Constrict)],
+    );
+    assert_eq!(
+      mgr.update_scalar(&scalar_msg_2, true).unwrap(),
+      Some(vec![
+        Some((ActuatorType::Vibrate, 10)),
+        Some((ActuatorType::Constrict, 10))
+      ])
+    );
+    let scalar_msg_wrong_type = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(1, 0.5, ActuatorType::Vibrate)],
+    );
+    assert!(mgr.update_scalar(&scalar_msg_wrong_type, false).is_err());
+    let scalar_msg_invalid = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(2, 0.5, ActuatorType::Vibrate)],
+    );
+    assert!(matches!(
+      mgr.update_scalar(&scalar_msg_invalid, false),
+      Err(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceFeatureIndexError(2, 2)
+      ))
+    ));
+    assert_eq!(mgr.get_stop_commands().len(), 1);
+  }
+
+  #[test]
+  pub fn test_command_generator_scalar_missing_step_count() {
+    let mut attributes_map = DeviceMessageAttributesMap::new();
+
+    // A config with fewer step counts than features shouldn't panic.
+    let scalar_attributes = DeviceMessageAttributes {
+      feature_count: Some(2),
+      step_count: Some(vec![20]),
+      actuator_type: Some(vec![ActuatorType::Vibrate, ActuatorType::Vibrate]),
+      ..Default::default()
+    };
+    attributes_map.insert(ButtplugDeviceMessageType::ScalarCmd, scalar_attributes);
+    let mut mgr = GenericCommandManager::new(&attributes_map);
+    let scalar_msg = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(1, 0.5, ActuatorType::Vibrate)],
+    );
+    assert!(matches!(
+      mgr.update_scalar(&scalar_msg, false),
+      Err(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceFeatureIndexError(2, 1)
+      ))
+    ));
+  }
+
+  #[test]
+  pub fn test_command_generator_scalar_vibration() {
+    let mut attributes_map = DeviceMessageAttributesMap::new();
+
+    let scalar_attributes = DeviceMessageAttributes {
+      feature_count: Some(2),
+      step_count: Some(vec![20, 20]),
+      actuator_type: Some(vec![ActuatorType::Vibrate, ActuatorType::Vibrate]),
+      ..Default::default()
+    };
+    attributes_map.insert(ButtplugDeviceMessageType::ScalarCmd, scalar_attributes);
+    let mut mgr = GenericCommandManager::new(&attributes_map);
+    let scalar_msg = ScalarCmd::new(
+      0,
+      vec![
+        ScalarSubcommand::new(0, 0.5, ActuatorType::Vibrate),
+        ScalarSubcommand::new(1, 0.75, ActuatorType::Vibrate),
+      ],
+    );
+    assert_eq!(
+      mgr.update_scalar_vibration(&scalar_msg, false).unwrap(),
+      Some(vec![Some(10), Some(15)])
+    );
+    assert_eq!(
+      mgr.update_scalar_vibration(&scalar_msg, false).unwrap(),
+      None
+    );
+    let scalar_msg_constrict = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(0, 0.5, ActuatorType::Constrict)],
+    );
+    assert!(mgr
+      .update_scalar_vibration(&scalar_msg_constrict, false)
+      .is_err());
+  }
+
+  #[test]
+  pub fn test_command_generator_pattern() {
+    let mut attributes_map = DeviceMessageAttributesMap::new();
+
+    let pattern_attributes = DeviceMessageAttributes {
+      feature_count: Some(1),
+      patterns: Some(vec![vec!["Pulse".to_owned(), "Wave".to_owned()]]),
+      ..Default::default()
+    };
+    attributes_map.insert(ButtplugDeviceMessageType::PatternCmd, pattern_attributes);
+    let mut mgr = GenericCommandManager::new(&attributes_map);
+    let stop_msg = PatternCmd::new(0, vec![PatternSubcommand::new(0, None)]);
+    assert_eq!(mgr.update_pattern(&stop_msg).unwrap(), vec![None]);
+    let wave_msg = PatternCmd::new(0, vec![PatternSubcommand::new(0, Some("Wave"))]);
+    assert_eq!(mgr.update_pattern(&wave_msg).unwrap(), vec![Some(2)]);
+    assert_eq!(mgr.update_pattern(&wave_msg).unwrap(), vec![None]);
+    assert_eq!(mgr.update_pattern(&stop_msg).unwrap(), vec![Some(0)]);
+    let unknown_msg = PatternCmd::new(0, vec![PatternSubcommand::new(0, Some("Earthquake"))]);
+    assert!(mgr.update_pattern(&unknown_msg).is_err());
+    let invalid_msg = PatternCmd::new(0, vec![PatternSubcommand::new(1, None)]);
+    assert!(mgr.update_pattern(&invalid_msg).is_err());
+    assert_eq!(mgr.get_stop_commands(), vec![stop_msg.into()]);
+  }
+
+  #[test]
+  pub fn test_command_generator_pattern_with_limits() {
+    let mut attributes_map = DeviceMessageAttributesMap::new();
+
+    let vibrate_attributes = DeviceMessageAttributes {
+      feature_count: Some(1),
+      step_count: Some(vec![20]),
+      max_speed: Some(vec![0.5]),
+      ..Default::default()
+    };
+    let pattern_attributes = DeviceMessageAttributes {
+      feature_count: Some(1),
+      patterns: Some(vec![vec!["Pulse".to_owned()]]),
+      ..Default::default()
+    };
+    attributes_map.insert(ButtplugDeviceMessageType::VibrateCmd, vibrate_attributes);
+    attributes_map.insert(ButtplugDeviceMessageType::PatternCmd, pattern_attributes);
+    let mut mgr = GenericCommandManager::new(&attributes_map);
+    let pulse_msg = PatternCmd::new(0, vec![PatternSubcommand::new(0, Some("Pulse"))]);
+    assert!(mgr.update_pattern(&pulse_msg).is_err());
+    let stop_msg = PatternCmd::new(0, vec![PatternSubcommand::new(0, None)]);
+    assert_eq!(mgr.update_pattern(&stop_msg).unwrap(), vec![None]);
+  }
+
   // TODO Write test for vibration stop generator
 }
@@ -0,0 +1,244 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Translation of the deprecated KiirooCmd message into generic commands.
+//!
+//! Kiiroo commands are a single step from 0 to 4, sent by video sync apps at
+//! the moment each stroke should land. Linear devices move to the step's
+//! position over the time since the last command, vibrators vibrate at the
+//! step's level.
+
+use super::{
+  fleshlight_launch_helper::get_duration, ButtplugDeviceResultFuture,
+  ButtplugProtocolCommandHandler,
+};
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::{
+      ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage, ButtplugDeviceMessageType,
+      DeviceMessageAttributesMap, KiirooCmd, LinearCmd, VectorSubcommand, VibrateCmd,
+      VibrateSubcommand,
+    },
+  },
+  device::DeviceImpl,
+};
+use futures::future;
+use std::{
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// Kiiroo steps go from 0 to 4.
+const KIIROO_MAX_STEP: u32 = 4;
+/// Commands further apart than this aren't part of the same stroke sequence.
+const KIIROO_MAX_STROKE_INTERVAL: Duration = Duration::from_millis(1000);
+/// Speed used to move when there's no stroke timing to go on, i.e. on the
+/// first command or after a pause.
+const KIIROO_IDLE_SPEED: f64 = 0.2;
+
+#[derive(Default)]
+struct KiirooState {
+  previous_command: Option<Instant>,
+  previous_position: f64,
+}
+
+/// Turns KiirooCmd messages into LinearCmd or VibrateCmd messages, depending
+/// on what the device supports. Linear devices get preference.
+#[derive(Default)]
+pub struct KiirooCmdTranslator {
+  state: Mutex<KiirooState>,
+}
+
+impl KiirooCmdTranslator {
+  /// Translates `message` and runs the result through the protocol's own
+  /// command handlers.
+  pub fn handle(
+    &self,
+    protocol: &dyn ButtplugProtocolCommandHandler,
+    device: Arc<DeviceImpl>,
+    message: KiirooCmd,
+  ) -> ButtplugDeviceResultFuture {
+    match self.translate(&message, &protocol.message_attributes()) {
+      Ok(command) => protocol.handle_command(device, command),
+      Err(err) => Box::pin(future::ready(Err(err))),
+    }
+  }
+
+  pub fn translate(
+    &self,
+    message: &KiirooCmd,
+    attributes: &DeviceMessageAttributesMap,
+  ) -> Result<ButtplugDeviceCommandMessageUnion, ButtplugError> {
+    self.translate_at(message, attributes, Instant::now())
+  }
+
+  fn translate_at(
+    &self,
+    message: &KiirooCmd,
+    attributes: &DeviceMessageAttributesMap,
+    now: Instant,
+  ) -> Result<ButtplugDeviceCommandMessageUnion, ButtplugError> {
+    let level = parse_step(message.command())? as f64 / KIIROO_MAX_STEP as f64;
+    let mut state = self
+      .state
+      .lock()
+      .expect("Kiiroo state lock should never be poisoned");
+    let interval = state
+      .previous_command
+      .replace(now)
+      .map(|previous| now.duration_since(previous));
+    if attributes.contains_key(&ButtplugDeviceMessageType::LinearCmd) {
+      let distance = (level - state.previous_position).abs();
+      state.previous_position = level;
+      let duration = match interval {
+        Some(interval) if interval <= KIIROO_MAX_STROKE_INTERVAL => interval.as_millis() as u32,
+        _ => get_duration(distance, KIIROO_IDLE_SPEED),
+      };
+      Ok(
+        LinearCmd::new(
+          message.device_index(),
+          vec![VectorSubcommand::new(0, duration, level)],
+        )
+        .into(),
+      )
+    } else if let Some(count) = attributes
+      .get(&ButtplugDeviceMessageType::VibrateCmd)
+      .and_then(|attrs| attrs.feature_count)
+    {
+      Ok(
+        VibrateCmd::new(
+          message.device_index(),
+          (0..count)
+            .map(|index| VibrateSubcommand::new(index, level))
+            .collect(),
+        )
+        .into(),
+      )
+    } else {
+      Err(ButtplugDeviceError::MessageNotSupported(ButtplugDeviceMessageType::KiirooCmd).into())
+    }
+  }
+}
+
+fn parse_step(command: &str) -> Result<u32, ButtplugError> {
+  match command.trim().parse::<u32>() {
+    Ok(step) if step <= KIIROO_MAX_STEP => Ok(step),
+    _ => Err(
+      ButtplugDeviceError::ProtocolRequirementError(format!(
+        "KiirooCmd {} is not a valid step, steps go from 0 to {}.",
+        command, KIIROO_MAX_STEP
+      ))
+      .into(),
+    ),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::core::messages::DeviceMessageAttributes;
+
+  fn attributes(message_type: ButtplugDeviceMessageType) -> DeviceMessageAttributesMap {
+    let mut attributes = DeviceMessageAttributesMap::new();
+    attributes.insert(
+      message_type,
+      DeviceMessageAttributes {
+        feature_count: Some(2),
+        ..Default::default()
+      },
+    );
+    attributes
+  }
+
+  #[test]
+  fn test_translate_linear() {
+    let translator = KiirooCmdTranslator::default();
+    let attributes = attributes(ButtplugDeviceMessageType::LinearCmd);
+    let start = Instant::now();
+    assert_eq!(
+      translator
+        .translate_at(&KiirooCmd::new(0, "4"), &attributes, start)
+        .unwrap(),
+      LinearCmd::new(
+        0,
+        vec![VectorSubcommand::new(
+          0,
+          get_duration(1.0, KIIROO_IDLE_SPEED),
+          1.0
+        )]
+      )
+      .into()
+    );
+    // Strokes take as long as the gap between commands.
+    assert_eq!(
+      translator
+        .translate_at(
+          &KiirooCmd::new(0, "1"),
+          &attributes,
+          start + Duration::from_millis(300)
+        )
+        .unwrap(),
+      LinearCmd::new(0, vec![VectorSubcommand::new(0, 300, 0.25)]).into()
+    );
+    // After a pause, go back to moving at idle speed.
+    assert_eq!(
+      translator
+        .translate_at(
+          &KiirooCmd::new(0, "3"),
+          &attributes,
+          start + Duration::from_millis(5000)
+        )
+        .unwrap(),
+      LinearCmd::new(
+        0,
+        vec![VectorSubcommand::new(
+          0,
+          get_duration(0.5, KIIROO_IDLE_SPEED),
+          0.75
+        )]
+      )
+      .into()
+    );
+  }
+
+  #[test]
+  fn test_translate_vibrate() {
+    let translator = KiirooCmdTranslator::default();
+    assert_eq!(
+      translator
+        .translate(
+          &KiirooCmd::new(2, "2"),
+          &attributes(ButtplugDeviceMessageType::VibrateCmd)
+        )
+        .unwrap(),
+      VibrateCmd::new(
+        2,
+        vec![
+          VibrateSubcommand::new(0, 0.5),
+          VibrateSubcommand::new(1, 0.5)
+        ]
+      )
+      .into()
+    );
+  }
+
+  #[test]
+  fn test_translate_invalid() {
+    let translator = KiirooCmdTranslator::default();
+    let attributes = attributes(ButtplugDeviceMessageType::VibrateCmd);
+    assert!(translator
+      .translate(&KiirooCmd::new(0, "5"), &attributes)
+      .is_err());
+    assert!(translator
+      .translate(&KiirooCmd::new(0, "up"), &attributes)
+      .is_err());
+    assert!(translator
+      .translate(&KiirooCmd::new(0, "2"), &DeviceMessageAttributesMap::new())
+      .is_err());
+  }
+}
@@ -1,6 +1,6 @@
 use super::{
-  fleshlight_launch_helper::get_speed, ButtplugDeviceResultFuture, ButtplugProtocol,
-  ButtplugProtocolCommandHandler,
+  fleshlight_launch_helper::get_speed, kiiroo_cmd_translator::KiirooCmdTranslator,
+  ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler,
 };
 use crate::{
   core::{
@@ -29,6 +29,7 @@ pub struct KiirooV2 {
   _manager: Arc<Mutex<GenericCommandManager>>,
   stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
   previous_position: Arc<AtomicU8>,
+  kiiroo_translator: KiirooCmdTranslator,
 }
 
 impl ButtplugProtocol for KiirooV2 {
@@ -44,6 +45,7 @@ impl ButtplugProtocol for KiirooV2 {
       stop_commands: manager.get_stop_commands(),
       _manager: Arc::new(Mutex::new(manager)),
       previous_position: Arc::new(AtomicU8::new(0)),
+      kiiroo_translator: KiirooCmdTranslator::default(),
     })
   }
 
@@ -78,6 +80,14 @@ impl ButtplugProtocolCommandHandler for KiirooV2 {
     self.handle_fleshlight_launch_fw12_cmd(device, fl_cmd)
   }
 
+  fn handle_kiiroo_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::KiirooCmd,
+  ) -> ButtplugDeviceResultFuture {
+    self.kiiroo_translator.handle(self, device, message)
+  }
+
   fn handle_fleshlight_launch_fw12_cmd(
     &self,
     device: Arc<DeviceImpl>,
@@ -102,7 +112,7 @@ impl ButtplugProtocolCommandHandler for KiirooV2 {
 #[cfg(all(test, feature = "server"))]
 mod test {
   use crate::{
-    core::messages::{FleshlightLaunchFW12Cmd, LinearCmd, VectorSubcommand},
+    core::messages::{FleshlightLaunchFW12Cmd, KiirooCmd, LinearCmd, VectorSubcommand},
     device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
     server::comm_managers::test::{check_test_recv_value, new_bluetoothle_test_device},
     util::async_manager,
@@ -124,6 +134,26 @@ mod test {
     });
   }
 
+  #[test]
+  pub fn test_kiiroov2_kiiroocmd() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Launch").await.unwrap();
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      device
+        .parse_message(KiirooCmd::new(0, "4").into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![99, 20], false)),
+      );
+      assert!(device
+        .parse_message(KiirooCmd::new(0, "5").into())
+        .await
+        .is_err());
+    });
+  }
+
   #[test]
   pub fn test_kiiroov2_linearcmd() {
     async_manager::block_on(async move {
@@ -1,6 +1,6 @@
 use super::{
-  fleshlight_launch_helper::get_speed, ButtplugDeviceResultFuture, ButtplugProtocol,
-  ButtplugProtocolCommandHandler,
+  fleshlight_launch_helper::get_speed, kiiroo_cmd_translator::KiirooCmdTranslator,
+  ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler,
 };
 use crate::{
   core::messages::{
@@ -25,6 +25,7 @@ pub struct KiirooV21 {
   manager: Arc<Mutex<GenericCommandManager>>,
   stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
   previous_position: Arc<AtomicU8>,
+  kiiroo_translator: KiirooCmdTranslator,
 }
 
 impl ButtplugProtocol for KiirooV21 {
@@ -40,6 +41,7 @@ impl ButtplugProtocol for KiirooV21 {
       stop_commands: manager.get_stop_commands(),
       manager: Arc::new(Mutex::new(manager)),
       previous_position: Arc::new(AtomicU8::new(0)),
+      kiiroo_translator: KiirooCmdTranslator::default(),
     })
   }
 }
@@ -85,6 +87,14 @@ impl ButtplugProtocolCommandHandler for KiirooV21 {
     self.handle_fleshlight_launch_fw12_cmd(device, fl_cmd)
   }
 
+  fn handle_kiiroo_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::KiirooCmd,
+  ) -> ButtplugDeviceResultFuture {
+    self.kiiroo_translator.handle(self, device, message)
+  }
+
   fn handle_fleshlight_launch_fw12_cmd(
     &self,
     device: Arc<DeviceImpl>,
@@ -110,8 +120,8 @@ impl ButtplugProtocolCommandHandler for KiirooV21 {
 mod test {
   use crate::{
     core::messages::{
-      FleshlightLaunchFW12Cmd, LinearCmd, StopDeviceCmd, VectorSubcommand, VibrateCmd,
-      VibrateSubcommand,
+      FleshlightLaunchFW12Cmd, KiirooCmd, LinearCmd, StopDeviceCmd, VectorSubcommand,
+      VibrateCmd, VibrateSubcommand,
     },
     device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
     server::comm_managers::test::{check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device},
@@ -162,6 +172,23 @@ mod test {
     });
   }
 
+  #[test]
+  pub fn test_kiiroov21_kiiroocmd() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Cliona").await.unwrap();
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      assert!(check_test_recv_empty(&command_receiver));
+      device
+        .parse_message(KiirooCmd::new(0, "2").into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0x01, 50], false)),
+      );
+    });
+  }
+
   #[test]
   pub fn test_kiiroov21_vibratecmd() {
     async_manager::block_on(async move {
@@ -1,6 +1,6 @@
 use super::{
-  fleshlight_launch_helper::get_speed, ButtplugDeviceResultFuture, ButtplugProtocol,
-  ButtplugProtocolCommandHandler,
+  fleshlight_launch_helper::get_speed, kiiroo_cmd_translator::KiirooCmdTranslator,
+  ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler,
 };
 use crate::{
   core::{
@@ -31,6 +31,7 @@ pub struct KiirooV21Initialized {
   manager: Arc<Mutex<GenericCommandManager>>,
   stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
   previous_position: Arc<AtomicU8>,
+  kiiroo_translator: KiirooCmdTranslator,
 }
 
 impl ButtplugProtocol for KiirooV21Initialized {
@@ -46,6 +47,7 @@ impl ButtplugProtocol for KiirooV21Initialized {
       stop_commands: manager.get_stop_commands(),
       manager: Arc::new(Mutex::new(manager)),
       previous_position: Arc::new(AtomicU8::new(0)),
+      kiiroo_translator: KiirooCmdTranslator::default(),
     })
   }
 
@@ -113,6 +115,14 @@ impl ButtplugProtocolCommandHandler for KiirooV21Initialized {
     self.handle_fleshlight_launch_fw12_cmd(device, fl_cmd)
   }
 
+  fn handle_kiiroo_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::KiirooCmd,
+  ) -> ButtplugDeviceResultFuture {
+    self.kiiroo_translator.handle(self, device, message)
+  }
+
   fn handle_fleshlight_launch_fw12_cmd(
     &self,
     device: Arc<DeviceImpl>,
@@ -138,8 +148,8 @@ impl ButtplugProtocolCommandHandler for KiirooV21Initialized {
 mod test {
   use crate::{
     core::messages::{
-      FleshlightLaunchFW12Cmd, LinearCmd, StopDeviceCmd, VectorSubcommand, VibrateCmd,
-      VibrateSubcommand,
+      FleshlightLaunchFW12Cmd, KiirooCmd, LinearCmd, StopDeviceCmd, VectorSubcommand,
+      VibrateCmd, VibrateSubcommand,
     },
     device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
     server::comm_managers::test::{check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device},
@@ -183,6 +193,42 @@ mod test {
     });
   }
 
+  #[test]
+  pub fn test_kiiroov21initialized_kiiroocmd() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Onyx2.1").await.unwrap();
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x03u8, 0x00u8, 0x64u8, 0x19u8],
+          true,
+        )),
+      );
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x03u8, 0x00u8, 0x64u8, 0x00u8],
+          true,
+        )),
+      );
+      device
+        .parse_message(KiirooCmd::new(0, "4").into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x03, 0x00, 20, 99],
+          false,
+        )),
+      );
+    });
+  }
+
   #[test]
   pub fn test_kiiroov21initialized_linearcmd() {
     async_manager::block_on(async move {
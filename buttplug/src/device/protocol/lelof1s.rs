@@ -1,14 +1,18 @@
-use super::{ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler};
+use super::{
+  sensor_endpoint, ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler,
+};
 use crate::core::errors::ButtplugError;
 use crate::device::DeviceSubscribeCmd;
 use crate::{
-  core::messages::{self, ButtplugDeviceCommandMessageUnion, DeviceMessageAttributesMap},
+  core::messages::{
+    self, ButtplugDeviceCommandMessageUnion, DeviceMessageAttributesMap, SensorType,
+  },
   device::{
     protocol::{generic_command_manager::GenericCommandManager, ButtplugProtocolProperties},
     DeviceImpl, DeviceWriteCmd, Endpoint,
   },
 };
-use futures::future::BoxFuture;
+use futures::future::{self, BoxFuture};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -73,12 +77,63 @@ impl ButtplugProtocolCommandHandler for LeloF1s {
       Ok(messages::Ok::default().into())
     })
   }
+
+  // Rx is subscribed for the whole connection (see initialize), so sensor
+  // subscriptions only need to check the sensor exists.
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    _device: Arc<DeviceImpl>,
+    message: messages::SensorSubscribeCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let result = sensor_endpoint(
+      &self.message_attributes,
+      message.sensor_index(),
+      message.sensor_type(),
+    );
+    Box::pin(future::ready(
+      result.map(|_| messages::Ok::default().into()),
+    ))
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    _device: Arc<DeviceImpl>,
+    message: messages::SensorUnsubscribeCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let result = sensor_endpoint(
+      &self.message_attributes,
+      message.sensor_index(),
+      message.sensor_type(),
+    );
+    Box::pin(future::ready(
+      result.map(|_| messages::Ok::default().into()),
+    ))
+  }
+
+  // Rx reports are tagged with what they're reporting: 0x01 is the button
+  // state, 0x02 is a big endian 16 bit pressure reading.
+  fn parse_sensor_data(
+    &self,
+    _sensor_index: u32,
+    sensor_type: SensorType,
+    data: &[u8],
+  ) -> Option<Vec<i32>> {
+    match (sensor_type, data) {
+      (SensorType::Button, [0x01, state]) => Some(vec![*state as i32]),
+      (SensorType::Pressure, [0x02, high, low]) => {
+        Some(vec![u16::from_be_bytes([*high, *low]) as i32])
+      }
+      _ => None,
+    }
+  }
 }
 
 #[cfg(all(test, feature = "server"))]
 mod test {
   use crate::{
-    core::messages::{StopDeviceCmd, VibrateCmd, VibrateSubcommand},
+    core::messages::{
+      SensorSubscribeCmd, SensorType, StopDeviceCmd, VibrateCmd, VibrateSubcommand,
+    },
     device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
     server::comm_managers::test::{check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device},
     util::async_manager,
@@ -144,4 +199,29 @@ mod test {
       );
     });
   }
+  #[test]
+  pub fn test_lelof1s_sensors() {
+    async_manager::block_on(async move {
+      let (device, _) = new_bluetoothle_test_device("F1s").await.unwrap();
+      device
+        .parse_message(SensorSubscribeCmd::new(0, 1, SensorType::Pressure).into())
+        .await
+        .unwrap();
+      assert!(device
+        .parse_message(SensorSubscribeCmd::new(0, 1, SensorType::Button).into())
+        .await
+        .is_err());
+      assert_eq!(
+        device.parse_sensor_data(Endpoint::Rx, &[0x01, 0x01]),
+        vec![(0, SensorType::Button, vec![1])]
+      );
+      assert_eq!(
+        device.parse_sensor_data(Endpoint::Rx, &[0x02, 0x01, 0x02]),
+        vec![(1, SensorType::Pressure, vec![0x102])]
+      );
+      assert!(device
+        .parse_sensor_data(Endpoint::Rx, &[0x03, 0x00])
+        .is_empty());
+    });
+  }
 }
@@ -0,0 +1,68 @@
+use super::{ButtplugProtocol, ButtplugProtocolCommandHandler};
+use crate::{
+  core::messages::{ButtplugDeviceCommandMessageUnion, DeviceMessageAttributesMap},
+  device::protocol::ButtplugProtocolProperties,
+};
+
+/// The Libo Karen is a kegel trainer with a pressure sensor and nothing to
+/// control, so everything it does is covered by the default sensor handling.
+#[derive(ButtplugProtocolProperties)]
+pub struct LiboKaren {
+  name: String,
+  message_attributes: DeviceMessageAttributesMap,
+  stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
+}
+
+impl ButtplugProtocol for LiboKaren {
+  fn new_protocol(
+    name: &str,
+    message_attributes: DeviceMessageAttributesMap,
+  ) -> Box<dyn ButtplugProtocol> {
+    Box::new(Self {
+      name: name.to_owned(),
+      message_attributes,
+      stop_commands: vec![],
+    })
+  }
+}
+
+impl ButtplugProtocolCommandHandler for LiboKaren {}
+
+#[cfg(all(test, feature = "server"))]
+mod test {
+  use crate::{
+    core::messages::{SensorSubscribeCmd, SensorType, SensorUnsubscribeCmd},
+    device::Endpoint,
+    server::comm_managers::test::new_bluetoothle_test_device,
+    util::async_manager,
+  };
+
+  #[test]
+  pub fn test_libo_karen_sensor() {
+    async_manager::block_on(async move {
+      let (device, _) = new_bluetoothle_test_device("SuoYinQiu").await.unwrap();
+      device
+        .parse_message(SensorSubscribeCmd::new(0, 0, SensorType::Pressure).into())
+        .await
+        .unwrap();
+      assert_eq!(
+        device.parse_sensor_data(Endpoint::RxPressure, &[0x64]),
+        vec![(0, SensorType::Pressure, vec![100])]
+      );
+      assert!(device.parse_sensor_data(Endpoint::Rx, &[0x64]).is_empty());
+      device
+        .parse_message(SensorUnsubscribeCmd::new(0, 0, SensorType::Pressure).into())
+        .await
+        .unwrap();
+      // Wrong sensor index or type.
+      assert!(device
+        .parse_message(SensorSubscribeCmd::new(0, 1, SensorType::Pressure).into())
+        .await
+        .is_err());
+      assert!(device
+        .parse_message(SensorSubscribeCmd::new(0, 0, SensorType::Button).into())
+        .await
+        .is_err());
+    });
+  }
+}
@@ -181,6 +181,25 @@ impl ButtplugProtocolCommandHandler for Lovense {
     })
   }
 
+  fn handle_pattern_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    msg: messages::PatternCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager.lock().await.update_pattern(&msg)?;
+      // Presets are numbered in the order the device config lists them, and
+      // preset 0 stops whatever is playing.
+      if let Some(preset) = result[0] {
+        let lovense_cmd = format!("Preset:{};", preset).as_bytes().to_vec();
+        let fut = device.write_value(DeviceWriteCmd::new(Endpoint::Tx, lovense_cmd, false));
+        fut.await?;
+      }
+      Ok(messages::Ok::default().into())
+    })
+  }
+
   fn handle_battery_level_cmd(
     &self,
     device: Arc<DeviceImpl>,
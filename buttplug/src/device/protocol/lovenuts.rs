@@ -1,6 +1,11 @@
 use super::{ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler};
 use crate::{
-  core::messages::{self, ButtplugDeviceCommandMessageUnion, DeviceMessageAttributesMap},
+  core::{
+    errors::ButtplugError,
+    messages::{
+      self, ButtplugDeviceCommandMessageUnion, ButtplugServerMessage, DeviceMessageAttributesMap,
+    },
+  },
   device::{
     protocol::{generic_command_manager::GenericCommandManager, ButtplugProtocolProperties},
     DeviceImpl, DeviceWriteCmd, Endpoint,
@@ -42,26 +47,48 @@ impl ButtplugProtocolCommandHandler for LoveNuts {
     let manager = self.manager.clone();
     Box::pin(async move {
       let result = manager.lock().await.update_vibration(&message, false)?;
-      if let Some(cmds) = result {
-        if let Some(speed) = cmds[0] {
-          let mut data: Vec<u8> = vec![0x45, 0x56, 0x4f, 0x4c];
-          for _ in 0..10 {
-            let mut b: u8 = speed as u8;
-            b |= (speed as u8) << 4;
-            data.push(b);
-          }
-          data.push(0x00);
-          data.push(0xff);
+      send_vibration(device, result).await
+    })
+  }
+
+  fn handle_scalar_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::ScalarCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager
+        .lock()
+        .await
+        .update_scalar_vibration(&message, false)?;
+      send_vibration(device, result).await
+    })
+  }
+}
 
-          device
-            .write_value(DeviceWriteCmd::new(Endpoint::Tx, data, false))
-            .await?;
-        }
+async fn send_vibration(
+  device: Arc<DeviceImpl>,
+  result: Option<Vec<Option<u32>>>,
+) -> Result<ButtplugServerMessage, ButtplugError> {
+  if let Some(cmds) = result {
+    if let Some(speed) = cmds[0] {
+      let mut data: Vec<u8> = vec![0x45, 0x56, 0x4f, 0x4c];
+      for _ in 0..10 {
+        let mut b: u8 = speed as u8;
+        b |= (speed as u8) << 4;
+        data.push(b);
       }
+      data.push(0x00);
+      data.push(0xff);
 
-      Ok(messages::Ok::default().into())
-    })
+      device
+        .write_value(DeviceWriteCmd::new(Endpoint::Tx, data, false))
+        .await?;
+    }
   }
+
+  Ok(messages::Ok::default().into())
 }
 
 #[cfg(all(test, feature = "server"))]
@@ -5,12 +5,14 @@ pub mod fleshlight_launch_helper;
 pub mod fredorch;
 pub mod generic_command_manager;
 pub mod jejoue;
+pub mod kiiroo_cmd_translator;
 pub mod kiiroo_v2;
 pub mod kiiroo_v21;
 pub mod kiiroo_v21_initialized;
 pub mod kiiroo_v2_vibrator;
 pub mod lelof1s;
 pub mod libo_elle;
+pub mod libo_karen;
 pub mod libo_shark;
 pub mod libo_vibes;
 pub mod lovehoney_desire;
@@ -47,24 +49,26 @@ use crate::{
   core::{
     errors::{ButtplugDeviceError, ButtplugError},
     messages::{
-      self, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage, ButtplugDeviceMessageType,
-      ButtplugMessage, DeviceMessageAttributesMap, RawReading, VibrateCmd, VibrateSubcommand,
+      self, ActuatorType, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage,
+      ButtplugDeviceMessageType, ButtplugMessage, DeviceMessageAttributes,
+      DeviceMessageAttributesMap, RawReading, SensorType, VibrateCmd, VibrateSubcommand,
     },
   },
   device::{
     configuration_manager::DeviceProtocolConfiguration, ButtplugDeviceResultFuture, DeviceReadCmd,
-    Endpoint,
+    DeviceSubscribeCmd, DeviceUnsubscribeCmd, Endpoint,
   },
 };
 use dashmap::DashMap;
 use futures::future::{self, BoxFuture};
 use std::sync::Arc;
 
+/// A newly created protocol, along with the identifier used to look up the
+/// device's configuration.
+pub type TryCreateProtocolResult = Result<(Box<dyn ButtplugProtocol>, String), ButtplugError>;
+
 pub type TryCreateProtocolFunc =
-  fn(
-    Arc<DeviceImpl>,
-    DeviceProtocolConfiguration,
-  ) -> BoxFuture<'static, Result<Box<dyn ButtplugProtocol>, ButtplugError>>;
+  fn(Arc<DeviceImpl>, DeviceProtocolConfiguration) -> BoxFuture<'static, TryCreateProtocolResult>;
 
 pub fn add_to_protocol_map<T>(map: &DashMap<String, TryCreateProtocolFunc>, protocol_name: &str)
 where
@@ -91,6 +95,7 @@ pub fn get_default_protocol_map() -> DashMap<String, TryCreateProtocolFunc> {
   );
   add_to_protocol_map::<lelof1s::LeloF1s>(&map, "lelo-f1s");
   add_to_protocol_map::<libo_elle::LiboElle>(&map, "libo-elle");
+  add_to_protocol_map::<libo_karen::LiboKaren>(&map, "libo-karen");
   add_to_protocol_map::<libo_shark::LiboShark>(&map, "libo-shark");
   add_to_protocol_map::<libo_vibes::LiboVibes>(&map, "libo-vibes");
   add_to_protocol_map::<lovehoney_desire::LovehoneyDesire>(&map, "lovehoney-desire");
@@ -128,10 +133,14 @@ pub fn get_default_protocol_map() -> DashMap<String, TryCreateProtocolFunc> {
 }
 
 pub trait ButtplugProtocol: ButtplugProtocolCommandHandler + Sync {
+  /// Initializes the device and creates the protocol for it. Also returns the
+  /// identifier the device configuration was looked up with, which is the
+  /// device name unless [ButtplugProtocol::initialize] came up with something
+  /// more specific (like the device type Lovense toys report).
   fn try_create(
     device_impl: Arc<DeviceImpl>,
     config: DeviceProtocolConfiguration,
-  ) -> BoxFuture<'static, Result<Box<dyn ButtplugProtocol>, ButtplugError>>
+  ) -> BoxFuture<'static, TryCreateProtocolResult>
   where
     Self: Sized,
   {
@@ -145,7 +154,7 @@ pub trait ButtplugProtocol: ButtplugProtocolCommandHandler + Sync {
       };
       let (names, attrs) = config.get_attributes(&device_identifier, &endpoints)?;
       let name = names.get("en-us").unwrap().clone();
-      Ok(Self::new_protocol(&name, attrs))
+      Ok((Self::new_protocol(&name, attrs), device_identifier))
     })
   }
 
@@ -217,6 +226,14 @@ pub trait ButtplugProtocolProperties {
         &ButtplugDeviceMessageType::RawWriteCmd,
         &self.message_attributes(),
       ),
+      ButtplugDeviceCommandMessageUnion::LovenseCmd(_) => check_message_support(
+        &ButtplugDeviceMessageType::LovenseCmd,
+        &self.message_attributes(),
+      ),
+      ButtplugDeviceCommandMessageUnion::PatternCmd(_) => check_message_support(
+        &ButtplugDeviceMessageType::PatternCmd,
+        &self.message_attributes(),
+      ),
       ButtplugDeviceCommandMessageUnion::RotateCmd(_) => check_message_support(
         &ButtplugDeviceMessageType::RotateCmd,
         &self.message_attributes(),
@@ -225,9 +242,21 @@ pub trait ButtplugProtocolProperties {
         &ButtplugDeviceMessageType::RSSILevelCmd,
         &self.message_attributes(),
       ),
+      // ScalarCmd and VibrateCmd can be translated into each other for
+      // vibrators, so check against what clients were told.
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(_) => check_message_support(
+        &ButtplugDeviceMessageType::ScalarCmd,
+        &client_message_attributes(&self.message_attributes()),
+      ),
       // We translate SingleMotorVibrateCmd into Vibrate, so this one is special.
       ButtplugDeviceCommandMessageUnion::SingleMotorVibrateCmd(_) => check_message_support(
         &ButtplugDeviceMessageType::VibrateCmd,
+        &client_message_attributes(&self.message_attributes()),
+      ),
+      // Unsubscribing is allowed wherever subscribing is.
+      ButtplugDeviceCommandMessageUnion::SensorSubscribeCmd(_)
+      | ButtplugDeviceCommandMessageUnion::SensorUnsubscribeCmd(_) => check_message_support(
+        &ButtplugDeviceMessageType::SensorSubscribeCmd,
         &self.message_attributes(),
       ),
       ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) => check_message_support(
@@ -236,7 +265,7 @@ pub trait ButtplugProtocolProperties {
       ),
       ButtplugDeviceCommandMessageUnion::VibrateCmd(_) => check_message_support(
         &ButtplugDeviceMessageType::VibrateCmd,
-        &self.message_attributes(),
+        &client_message_attributes(&self.message_attributes()),
       ),
       ButtplugDeviceCommandMessageUnion::VorzeA10CycloneCmd(_) => check_message_support(
         &ButtplugDeviceMessageType::VorzeA10CycloneCmd,
@@ -246,6 +275,224 @@ pub trait ButtplugProtocolProperties {
   }
 }
 
+/// Message attributes as clients see them. Devices configured with VibrateCmd
+/// also get ScalarCmd with vibrate actuators, and devices configured with
+/// ScalarCmd get VibrateCmd for their vibrate actuators, so older clients can
+/// still use them.
+pub fn client_message_attributes(
+  message_attributes: &DeviceMessageAttributesMap,
+) -> DeviceMessageAttributesMap {
+  let mut attributes = message_attributes.clone();
+  match (
+    message_attributes.get(&ButtplugDeviceMessageType::VibrateCmd),
+    message_attributes.get(&ButtplugDeviceMessageType::ScalarCmd),
+  ) {
+    (Some(vibrate_attrs), None) => {
+      let feature_count = vibrate_attrs.feature_count.unwrap_or(0);
+      if feature_count > 0 {
+        attributes.insert(
+          ButtplugDeviceMessageType::ScalarCmd,
+          DeviceMessageAttributes {
+            actuator_type: Some(vec![ActuatorType::Vibrate; feature_count as usize]),
+            ..vibrate_attrs.clone()
+          },
+        );
+      }
+    }
+    (None, Some(scalar_attrs)) => {
+      let vibrators = vibrate_scalar_indexes(scalar_attrs);
+      if !vibrators.is_empty() {
+        attributes.insert(
+          ButtplugDeviceMessageType::VibrateCmd,
+          DeviceMessageAttributes {
+            feature_count: Some(vibrators.len() as u32),
+            step_count: select_features(&scalar_attrs.step_count, &vibrators),
+            max_speed: select_features(&scalar_attrs.max_speed, &vibrators),
+            response_curve: scalar_attrs.response_curve.clone(),
+            ..Default::default()
+          },
+        );
+      }
+    }
+    _ => {}
+  }
+  attributes
+}
+
+/// ScalarCmd indexes of the vibrate actuators, in order, so VibrateCmd index
+/// N is ScalarCmd index `vibrate_scalar_indexes(..)[N]`.
+fn vibrate_scalar_indexes(scalar_attrs: &DeviceMessageAttributes) -> Vec<u32> {
+  scalar_attrs
+    .actuator_type
+    .iter()
+    .flatten()
+    .enumerate()
+    .filter(|(_, actuator_type)| **actuator_type == ActuatorType::Vibrate)
+    .map(|(index, _)| index as u32)
+    .collect()
+}
+
+fn select_features<T: Clone>(values: &Option<Vec<T>>, indexes: &[u32]) -> Option<Vec<T>> {
+  values.as_ref().map(|values| {
+    indexes
+      .iter()
+      .filter_map(|index| values.get(*index as usize).cloned())
+      .collect()
+  })
+}
+
+/// Translates a VibrateCmd into a ScalarCmd, for devices configured with
+/// ScalarCmd.
+fn vibrate_to_scalar_cmd(
+  message: &messages::VibrateCmd,
+  message_attributes: &DeviceMessageAttributesMap,
+) -> Result<messages::ScalarCmd, ButtplugError> {
+  let vibrators = message_attributes
+    .get(&ButtplugDeviceMessageType::ScalarCmd)
+    .map(vibrate_scalar_indexes)
+    .unwrap_or_default();
+  let scalars = message
+    .speeds()
+    .iter()
+    .map(|speed| match vibrators.get(speed.index() as usize) {
+      Some(index) => Ok(messages::ScalarSubcommand::new(
+        *index,
+        speed.speed(),
+        ActuatorType::Vibrate,
+      )),
+      None => Err(ButtplugDeviceError::DeviceFeatureIndexError(
+        vibrators.len() as u32,
+        speed.index(),
+      )),
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  let mut scalar_cmd = messages::ScalarCmd::new(message.device_index(), scalars);
+  scalar_cmd.set_id(message.id());
+  Ok(scalar_cmd)
+}
+
+/// Translates a ScalarCmd into a VibrateCmd, for devices configured with
+/// VibrateCmd. Only works if every subcommand is for a vibrator.
+fn scalar_to_vibrate_cmd(
+  message: &messages::ScalarCmd,
+) -> Result<messages::VibrateCmd, ButtplugError> {
+  let speeds = message
+    .scalars()
+    .iter()
+    .map(|scalar| {
+      if scalar.actuator_type() == ActuatorType::Vibrate {
+        Ok(VibrateSubcommand::new(scalar.index(), scalar.scalar()))
+      } else {
+        Err(ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Device only has vibrators, cannot handle {} at ScalarCmd index {}.",
+          scalar.actuator_type(),
+          scalar.index()
+        )))
+      }
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  let mut vibrate_cmd = VibrateCmd::new(message.device_index(), speeds);
+  vibrate_cmd.set_id(message.id());
+  Ok(vibrate_cmd)
+}
+
+/// Scales the positions in a LinearCmd into the position range set for each
+/// feature in the user device configuration, if any.
+fn apply_position_range(
+  message: messages::LinearCmd,
+  message_attributes: &DeviceMessageAttributesMap,
+) -> messages::LinearCmd {
+  let ranges = match message_attributes
+    .get(&ButtplugDeviceMessageType::LinearCmd)
+    .and_then(|attrs| attrs.position_range.as_ref())
+  {
+    Some(ranges) => ranges,
+    None => return message,
+  };
+  let vectors = message
+    .vectors()
+    .iter()
+    .map(|vector| match ranges.get(vector.index() as usize) {
+      Some((min, max)) => messages::VectorSubcommand::new(
+        vector.index(),
+        vector.duration(),
+        min + vector.position() * (max - min),
+      ),
+      None => vector.clone(),
+    })
+    .collect();
+  let mut limited = messages::LinearCmd::new(message.device_index(), vectors);
+  limited.set_id(message.id());
+  limited
+}
+
+/// FleshlightLaunchFW12Cmd moves the same actuator as LinearCmd, so it gets
+/// the same position range.
+fn apply_fleshlight_position_range(
+  message: messages::FleshlightLaunchFW12Cmd,
+  message_attributes: &DeviceMessageAttributesMap,
+) -> messages::FleshlightLaunchFW12Cmd {
+  let (min, max) = match message_attributes
+    .get(&ButtplugDeviceMessageType::LinearCmd)
+    .and_then(|attrs| attrs.position_range.as_ref())
+    .and_then(|ranges| ranges.first())
+  {
+    Some(range) => *range,
+    None => return message,
+  };
+  let position = min + (message.position() as f64 / 99f64) * (max - min);
+  let mut limited = messages::FleshlightLaunchFW12Cmd::new(
+    message.device_index(),
+    (position * 99f64).round() as u8,
+    message.speed(),
+  );
+  limited.set_id(message.id());
+  limited
+}
+
+/// Endpoint that a sensor reports through, after checking the sensor exists
+/// and is of the type the client expects.
+fn sensor_endpoint(
+  message_attributes: &DeviceMessageAttributesMap,
+  sensor_index: u32,
+  sensor_type: SensorType,
+) -> Result<Endpoint, ButtplugError> {
+  let attributes = message_attributes
+    .get(&ButtplugDeviceMessageType::SensorSubscribeCmd)
+    .ok_or(ButtplugDeviceError::MessageNotSupported(
+      ButtplugDeviceMessageType::SensorSubscribeCmd,
+    ))?;
+  let sensor_count = attributes.feature_count.unwrap_or(0);
+  if sensor_index >= sensor_count {
+    return Err(ButtplugDeviceError::DeviceFeatureIndexError(sensor_count, sensor_index).into());
+  }
+  let configured_type = attributes
+    .sensor_type
+    .as_ref()
+    .and_then(|types| types.get(sensor_index as usize));
+  if configured_type != Some(&sensor_type) {
+    return Err(
+      ButtplugDeviceError::ProtocolRequirementError(format!(
+        "Sensor {} is not a {} sensor.",
+        sensor_index, sensor_type
+      ))
+      .into(),
+    );
+  }
+  attributes
+    .endpoints
+    .as_ref()
+    .and_then(|endpoints| endpoints.get(sensor_index as usize))
+    .copied()
+    .ok_or_else(|| {
+      ButtplugDeviceError::ProtocolAttributesNotFound(format!(
+        "No endpoint configured for sensor {}.",
+        sensor_index
+      ))
+      .into()
+    })
+}
+
 fn print_type_of<T>(_: &T) -> &'static str {
   std::any::type_name::<T>()
 }
@@ -263,13 +510,26 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
       return Box::pin(future::ready(Err(err)));
     }
     match command_message {
-      ButtplugDeviceCommandMessageUnion::FleshlightLaunchFW12Cmd(msg) => {
-        self.handle_fleshlight_launch_fw12_cmd(device, msg)
-      }
+      // Position limits are applied here instead of in the protocols, so
+      // every linear protocol gets them.
+      ButtplugDeviceCommandMessageUnion::FleshlightLaunchFW12Cmd(msg) => self
+        .handle_fleshlight_launch_fw12_cmd(
+          device,
+          apply_fleshlight_position_range(msg, &self.message_attributes()),
+        ),
       ButtplugDeviceCommandMessageUnion::KiirooCmd(msg) => self.handle_kiiroo_cmd(device, msg),
-      ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => self.handle_linear_cmd(device, msg),
+      ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => self.handle_linear_cmd(
+        device,
+        apply_position_range(msg, &self.message_attributes()),
+      ),
       ButtplugDeviceCommandMessageUnion::RawReadCmd(msg) => self.handle_raw_read_cmd(device, msg),
       ButtplugDeviceCommandMessageUnion::RawWriteCmd(msg) => self.handle_raw_write_cmd(device, msg),
+      // ButtplugDevice translates LovenseCmd into generic commands, so it never
+      // gets this far.
+      ButtplugDeviceCommandMessageUnion::LovenseCmd(msg) => {
+        self.command_unimplemented(print_type_of(&msg))
+      }
+      ButtplugDeviceCommandMessageUnion::PatternCmd(msg) => self.handle_pattern_cmd(device, msg),
       ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => self.handle_rotate_cmd(device, msg),
       ButtplugDeviceCommandMessageUnion::SingleMotorVibrateCmd(msg) => {
         self.handle_single_motor_vibrate_cmd(device, msg)
@@ -283,7 +543,34 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
       ButtplugDeviceCommandMessageUnion::RawUnsubscribeCmd(msg) => {
         self.handle_raw_unsubscribe_cmd(device, msg)
       }
-      ButtplugDeviceCommandMessageUnion::VibrateCmd(msg) => self.handle_vibrate_cmd(device, msg),
+      ButtplugDeviceCommandMessageUnion::SensorSubscribeCmd(msg) => {
+        self.handle_sensor_subscribe_cmd(device, msg)
+      }
+      ButtplugDeviceCommandMessageUnion::SensorUnsubscribeCmd(msg) => {
+        self.handle_sensor_unsubscribe_cmd(device, msg)
+      }
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+        let attributes = self.message_attributes();
+        if attributes.contains_key(&ButtplugDeviceMessageType::ScalarCmd) {
+          self.handle_scalar_cmd(device, msg)
+        } else {
+          match scalar_to_vibrate_cmd(&msg) {
+            Ok(vibrate_cmd) => self.handle_vibrate_cmd(device, vibrate_cmd),
+            Err(err) => Box::pin(future::ready(Err(err))),
+          }
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::VibrateCmd(msg) => {
+        let attributes = self.message_attributes();
+        if attributes.contains_key(&ButtplugDeviceMessageType::VibrateCmd) {
+          self.handle_vibrate_cmd(device, msg)
+        } else {
+          match vibrate_to_scalar_cmd(&msg, &attributes) {
+            Ok(scalar_cmd) => self.handle_scalar_cmd(device, scalar_cmd),
+            Err(err) => Box::pin(future::ready(Err(err))),
+          }
+        }
+      }
       ButtplugDeviceCommandMessageUnion::VorzeA10CycloneCmd(msg) => {
         self.handle_vorze_a10_cyclone_cmd(device, msg)
       }
@@ -332,8 +619,7 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
     // protocol, meaning spec v0 and v1 programs will still be forward
     // compatible with vibrators.
     let vibrator_count;
-    if let Some(attr) = self
-      .message_attributes()
+    if let Some(attr) = client_message_attributes(&self.message_attributes())
       .get(&ButtplugDeviceMessageType::VibrateCmd)
     {
       if let Some(count) = attr.feature_count {
@@ -407,6 +693,54 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
     Box::pin(async move { fut.await.map(|_| messages::Ok::new(id).into()) })
   }
 
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::SensorSubscribeCmd,
+  ) -> ButtplugDeviceResultFuture {
+    match sensor_endpoint(
+      &self.message_attributes(),
+      message.sensor_index(),
+      message.sensor_type(),
+    ) {
+      Ok(endpoint) => {
+        let fut = device.subscribe(DeviceSubscribeCmd::new(endpoint));
+        Box::pin(async move { fut.await.map(|_| messages::Ok::default().into()) })
+      }
+      Err(err) => Box::pin(future::ready(Err(err))),
+    }
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::SensorUnsubscribeCmd,
+  ) -> ButtplugDeviceResultFuture {
+    match sensor_endpoint(
+      &self.message_attributes(),
+      message.sensor_index(),
+      message.sensor_type(),
+    ) {
+      Ok(endpoint) => {
+        let fut = device.unsubscribe(DeviceUnsubscribeCmd::new(endpoint));
+        Box::pin(async move { fut.await.map(|_| messages::Ok::default().into()) })
+      }
+      Err(err) => Box::pin(future::ready(Err(err))),
+    }
+  }
+
+  /// Turns data the device sent on a sensor's endpoint into the sensor's
+  /// values, or returns None if the data isn't a reading for that sensor. By
+  /// default, every byte is a value.
+  fn parse_sensor_data(
+    &self,
+    _sensor_index: u32,
+    _sensor_type: SensorType,
+    data: &[u8],
+  ) -> Option<Vec<i32>> {
+    Some(data.iter().map(|value| *value as i32).collect())
+  }
+
   fn command_unimplemented(&self, command: &str) -> ButtplugDeviceResultFuture {
     #[cfg(build = "debug")]
     unimplemented!("Command not implemented for this protocol");
@@ -452,6 +786,22 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
     self.command_unimplemented(print_type_of(&message))
   }
 
+  fn handle_scalar_cmd(
+    &self,
+    _device: Arc<DeviceImpl>,
+    message: messages::ScalarCmd,
+  ) -> ButtplugDeviceResultFuture {
+    self.command_unimplemented(print_type_of(&message))
+  }
+
+  fn handle_pattern_cmd(
+    &self,
+    _device: Arc<DeviceImpl>,
+    message: messages::PatternCmd,
+  ) -> ButtplugDeviceResultFuture {
+    self.command_unimplemented(print_type_of(&message))
+  }
+
   fn handle_rotate_cmd(
     &self,
     _device: Arc<DeviceImpl>,
@@ -494,9 +844,13 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
 
   fn handle_rssi_level_cmd(
     &self,
-    _device: Arc<DeviceImpl>,
+    device: Arc<DeviceImpl>,
     message: messages::RSSILevelCmd,
   ) -> ButtplugDeviceResultFuture {
-    self.command_unimplemented(print_type_of(&message))
+    let fut = device.rssi_level();
+    Box::pin(async move {
+      let rssi_level = fut.await?;
+      Ok(messages::RSSILevelReading::new(message.device_index(), rssi_level).into())
+    })
   }
 }
@@ -1,7 +1,9 @@
 use super::{ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler};
 use crate::core::errors::ButtplugError;
 use crate::{
-  core::messages::{self, ButtplugDeviceCommandMessageUnion, DeviceMessageAttributesMap},
+  core::messages::{
+    self, ButtplugDeviceCommandMessageUnion, ButtplugServerMessage, DeviceMessageAttributesMap,
+  },
   device::{
     protocol::{generic_command_manager::GenericCommandManager, ButtplugProtocolProperties},
     DeviceImpl, DeviceWriteCmd, Endpoint,
@@ -53,28 +55,54 @@ impl ButtplugProtocolCommandHandler for PrettyLove {
     let manager = self.manager.clone();
     Box::pin(async move {
       let result = manager.lock().await.update_vibration(&message, false)?;
-      if let Some(cmds) = result {
-        if let Some(speed) = cmds[0] {
-          device
-            .write_value(DeviceWriteCmd::new(
-              Endpoint::Tx,
-              vec![0x00u8, speed as u8],
-              true,
-            ))
-            .await?;
-        }
-      }
-      Ok(messages::Ok::default().into())
+      send_vibration(device, result).await
+    })
+  }
+
+  fn handle_scalar_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::ScalarCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager
+        .lock()
+        .await
+        .update_scalar_vibration(&message, false)?;
+      send_vibration(device, result).await
     })
   }
 }
 
+async fn send_vibration(
+  device: Arc<DeviceImpl>,
+  result: Option<Vec<Option<u32>>>,
+) -> Result<ButtplugServerMessage, ButtplugError> {
+  if let Some(cmds) = result {
+    if let Some(speed) = cmds[0] {
+      device
+        .write_value(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x00u8, speed as u8],
+          true,
+        ))
+        .await?;
+    }
+  }
+  Ok(messages::Ok::default().into())
+}
+
 #[cfg(all(test, feature = "server"))]
 mod test {
   use crate::{
-    core::messages::{StopDeviceCmd, VibrateCmd, VibrateSubcommand},
+    core::messages::{
+      ActuatorType, ScalarCmd, ScalarSubcommand, StopDeviceCmd, VibrateCmd, VibrateSubcommand,
+    },
     device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
-    server::comm_managers::test::{check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device},
+    server::comm_managers::test::{
+      check_test_recv_empty, check_test_recv_value, new_bluetoothle_test_device,
+    },
     util::async_manager,
   };
 
@@ -113,4 +141,39 @@ mod test {
       assert!(check_test_recv_empty(&command_receiver));
     });
   }
+
+  #[test]
+  pub fn test_prettylove_protocol_scalar_cmd() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Aogu BLE Device")
+        .await
+        .unwrap();
+      let command_receiver = test_device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+      device
+        .parse_message(
+          ScalarCmd::new(
+            0,
+            vec![ScalarSubcommand::new(0, 1.0, ActuatorType::Vibrate)],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0x00, 0x03], true)),
+      );
+      assert!(check_test_recv_empty(&command_receiver));
+
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0x00, 0x00], true)),
+      );
+      assert!(check_test_recv_empty(&command_receiver));
+    });
+  }
 }
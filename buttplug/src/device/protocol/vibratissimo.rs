@@ -4,7 +4,7 @@ use crate::{
     errors::ButtplugError,
     messages::{
       self, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage, DeviceMessageAttributesMap,
-      VibrateCmd, VibrateSubcommand,
+      PatternCmd, PatternSubcommand, VibrateCmd, VibrateSubcommand,
     },
   },
   device::{
@@ -16,6 +16,10 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use futures::future::BoxFuture;
 
+// TxMode value for speeds written to TxVibrate. Firmware patterns come right
+// after it, in the order the device config lists them.
+const VIBRATISSIMO_MANUAL_MODE: u8 = 0x03;
+
 #[derive(ButtplugProtocolProperties)]
 pub struct Vibratissimo {
   name: String,
@@ -61,13 +65,54 @@ impl ButtplugProtocolCommandHandler for Vibratissimo {
     device: Arc<DeviceImpl>,
     message: messages::StopDeviceCmd,
   ) -> ButtplugDeviceResultFuture {
-    self.handle_vibrate_cmd(
+    let pattern_fut = if self
+      .stop_commands
+      .iter()
+      .any(|command| matches!(command, ButtplugDeviceCommandMessageUnion::PatternCmd(_)))
+    {
+      Some(self.handle_pattern_cmd(
+        device.clone(),
+        PatternCmd::new(message.device_index(), vec![PatternSubcommand::new(0, None)]),
+      ))
+    } else {
+      None
+    };
+    let vibrate_fut = self.handle_vibrate_cmd(
       device,
       VibrateCmd::new(
         message.device_index(),
         vec![VibrateSubcommand::new(0, 0f64)],
       ),
-    )
+    );
+    Box::pin(async move {
+      if let Some(fut) = pattern_fut {
+        fut.await?;
+      }
+      vibrate_fut.await
+    })
+  }
+
+  fn handle_pattern_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    message: messages::PatternCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager.lock().await.update_pattern(&message)?;
+      // Going back to manual mode stops the pattern, and picks up whatever
+      // speed was last written to TxVibrate.
+      if let Some(pattern) = result[0] {
+        device
+          .write_value(DeviceWriteCmd::new(
+            Endpoint::TxMode,
+            vec![VIBRATISSIMO_MANUAL_MODE + pattern as u8, 0xff],
+            false,
+          ))
+          .await?;
+      }
+      Ok(messages::Ok::default().into())
+    })
   }
 
   fn handle_vibrate_cmd(
@@ -92,7 +137,7 @@ impl ButtplugProtocolCommandHandler for Vibratissimo {
         // Put the device in write mode
         fut_vec.push(device.write_value(DeviceWriteCmd::new(
           Endpoint::TxMode,
-          vec![0x03, 0xff],
+          vec![VIBRATISSIMO_MANUAL_MODE, 0xff],
           false,
         )));
         fut_vec.push(device.write_value(DeviceWriteCmd::new(
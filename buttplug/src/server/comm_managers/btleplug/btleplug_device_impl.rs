@@ -202,6 +202,9 @@ impl<T: Peripheral + 'static> BtlePlugDeviceImpl<T> {
   }
 }
 
+// btleplug 0.8 only exposes advertisement data through properties(), with no
+// RSSI for connected peripherals, so BLE devices keep the default
+// supports_rssi() of false until it does.
 impl<T: Peripheral + 'static> DeviceImplInternal for BtlePlugDeviceImpl<T> {
   fn event_stream(&self) -> broadcast::Receiver<ButtplugDeviceEvent> {
     self.event_stream.subscribe()
@@ -4,8 +4,12 @@ pub mod btleplug;
 pub mod lovense_connect_service;
 #[cfg(feature = "lovense-dongle-manager")]
 pub mod lovense_dongle;
+#[cfg(feature = "replay-manager")]
+pub mod replay;
 #[cfg(feature = "serial-manager")]
 pub mod serialport;
+#[cfg(feature = "simulator-manager")]
+pub mod simulator;
 #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
 pub mod xinput;
 
@@ -62,4 +66,10 @@ pub enum ButtplugDeviceSpecificError {
   #[cfg(feature = "serial-manager")]
   #[error("Serial error: {0}")]
   SerialError(String),
+  #[cfg(feature = "replay-manager")]
+  #[error("Replay error: {0}")]
+  ReplayError(String),
+  #[cfg(feature = "simulator-manager")]
+  #[error("Simulator error: {0}")]
+  SimulatorError(String),
 }
@@ -0,0 +1,21 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Plays back devices recorded with
+//! [device_recording][crate::device::device_recording].
+//!
+//! Send the replayed device the same client messages that were sent while
+//! recording, then call [ReplayDevice::verify] to check that this build wrote
+//! the same bytes the recorded build did.
+
+pub mod replay_comm_manager;
+pub mod replay_device;
+
+pub use replay_comm_manager::{
+  ReplayDeviceCommunicationManager, ReplayDeviceCommunicationManagerBuilder,
+};
+pub use replay_device::{ReplayDevice, ReplayMismatch};
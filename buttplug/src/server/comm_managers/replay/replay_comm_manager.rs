@@ -0,0 +1,99 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::replay_device::{ReplayDevice, ReplayDeviceImplCreator};
+use crate::{
+  core::{errors::ButtplugError, ButtplugResultFuture},
+  device::device_recording::{load_recording, RecordedDeviceEntry},
+  server::comm_managers::{
+    DeviceCommunicationEvent, DeviceCommunicationManager, DeviceCommunicationManagerBuilder,
+  },
+};
+use std::{path::Path, sync::Arc};
+use tokio::sync::mpsc::Sender;
+
+#[derive(Default)]
+pub struct ReplayDeviceCommunicationManagerBuilder {
+  sender: Option<Sender<DeviceCommunicationEvent>>,
+  devices: Vec<Arc<ReplayDevice>>,
+}
+
+impl ReplayDeviceCommunicationManagerBuilder {
+  pub fn recording(mut self, recording: Vec<RecordedDeviceEntry>) -> Result<Self, ButtplugError> {
+    self.devices.push(Arc::new(ReplayDevice::new(recording)?));
+    Ok(self)
+  }
+
+  pub fn recording_file<P: AsRef<Path>>(self, path: P) -> Result<Self, ButtplugError> {
+    self.recording(load_recording(path)?)
+  }
+
+  /// Handles for verifying the replayed devices. Grab these before handing
+  /// the builder to the device manager.
+  pub fn devices(&self) -> Vec<Arc<ReplayDevice>> {
+    self.devices.clone()
+  }
+}
+
+impl DeviceCommunicationManagerBuilder for ReplayDeviceCommunicationManagerBuilder {
+  fn event_sender(mut self, sender: Sender<DeviceCommunicationEvent>) -> Self {
+    self.sender = Some(sender);
+    self
+  }
+
+  fn finish(mut self) -> Box<dyn DeviceCommunicationManager> {
+    Box::new(ReplayDeviceCommunicationManager {
+      device_sender: self.sender.take().unwrap(),
+      devices: self.devices,
+    })
+  }
+}
+
+/// Comm manager for devices played back from recordings. Every scan finds all
+/// replayed devices that aren't currently connected.
+pub struct ReplayDeviceCommunicationManager {
+  device_sender: Sender<DeviceCommunicationEvent>,
+  devices: Vec<Arc<ReplayDevice>>,
+}
+
+impl DeviceCommunicationManager for ReplayDeviceCommunicationManager {
+  fn name(&self) -> &'static str {
+    "ReplayDeviceCommunicationManager"
+  }
+
+  fn start_scanning(&self) -> ButtplugResultFuture {
+    let devices = self.devices.clone();
+    let device_sender = self.device_sender.clone();
+    Box::pin(async move {
+      for device in devices.iter().filter(|device| !device.connected()) {
+        if device_sender
+          .send(DeviceCommunicationEvent::DeviceFound {
+            name: device.name().to_owned(),
+            address: device.address().to_owned(),
+            creator: Box::new(ReplayDeviceImplCreator::new(device.clone())),
+          })
+          .await
+          .is_err()
+        {
+          error!("Device channel no longer open.");
+        }
+      }
+      if device_sender
+        .send(DeviceCommunicationEvent::ScanningFinished)
+        .await
+        .is_err()
+      {
+        error!("Error sending scanning finished. Scanning may not register as finished now!");
+      }
+      Ok(())
+    })
+  }
+
+  fn stop_scanning(&self) -> ButtplugResultFuture {
+    Box::pin(futures::future::ready(Ok(())))
+  }
+}
@@ -0,0 +1,370 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::super::ButtplugDeviceSpecificError;
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::RawReading,
+    ButtplugResultFuture,
+  },
+  device::{
+    configuration_manager::{BluetoothLESpecifier, DeviceSpecifier, ProtocolDefinition},
+    device_recording::{RecordedDeviceEntry, RecordedDeviceEvent},
+    ButtplugDeviceEvent, ButtplugDeviceImplCreator, DeviceImpl, DeviceImplInternal, DeviceReadCmd,
+    DeviceSubscribeCmd, DeviceUnsubscribeCmd, DeviceWriteCmd, Endpoint,
+  },
+};
+use async_trait::async_trait;
+use futures::future::{self, BoxFuture};
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  fmt::{self, Debug},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+};
+use tokio::sync::broadcast;
+
+/// A write that didn't match the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayMismatch {
+  /// Position of the write in the recording, counting writes only.
+  pub write_index: usize,
+  /// None if the recording had no writes left.
+  pub expected: Option<(Endpoint, Vec<u8>)>,
+  pub actual: (Endpoint, Vec<u8>),
+}
+
+/// Recorded writes, each with the notifications the device sent back before
+/// the next write.
+struct ReplayStep {
+  endpoint: Endpoint,
+  data: Vec<u8>,
+  notifications: Vec<(Endpoint, Vec<u8>)>,
+}
+
+#[derive(Default)]
+struct ReplayState {
+  next_step: usize,
+  mismatches: Vec<ReplayMismatch>,
+  subscriptions: HashSet<Endpoint>,
+  /// Notifications recorded before the first write, sent once something
+  /// subscribes.
+  initial_notifications: Vec<(Endpoint, Vec<u8>)>,
+  reads: HashMap<Endpoint, VecDeque<Vec<u8>>>,
+}
+
+/// Device recreated from a recording. Writes are checked against the
+/// recording in order, and reads and notifications are answered with what the
+/// device sent when it was recorded.
+pub struct ReplayDevice {
+  name: String,
+  address: String,
+  endpoints: Vec<Endpoint>,
+  steps: Vec<ReplayStep>,
+  connected: AtomicBool,
+  state: Mutex<ReplayState>,
+  event_sender: broadcast::Sender<ButtplugDeviceEvent>,
+}
+
+impl ReplayDevice {
+  pub(super) fn new(recording: Vec<RecordedDeviceEntry>) -> Result<Self, ButtplugError> {
+    let mut entries = recording.into_iter().map(|entry| entry.event);
+    let (name, address, endpoints) = match entries.next() {
+      Some(RecordedDeviceEvent::Connected {
+        name,
+        address,
+        endpoints,
+      }) => (name, address, endpoints),
+      _ => {
+        return Err(
+          ButtplugDeviceError::from(ButtplugDeviceSpecificError::ReplayError(
+            "Recording does not start with a Connected entry.".to_owned(),
+          ))
+          .into(),
+        )
+      }
+    };
+    let mut steps: Vec<ReplayStep> = vec![];
+    let mut state = ReplayState::default();
+    for event in entries {
+      match event {
+        RecordedDeviceEvent::Write { endpoint, data, .. } => steps.push(ReplayStep {
+          endpoint,
+          data,
+          notifications: vec![],
+        }),
+        RecordedDeviceEvent::Notification { endpoint, data } => match steps.last_mut() {
+          Some(step) => step.notifications.push((endpoint, data)),
+          None => state.initial_notifications.push((endpoint, data)),
+        },
+        RecordedDeviceEvent::Read { endpoint, data } => {
+          state.reads.entry(endpoint).or_default().push_back(data)
+        }
+        // Subscriptions are up to the protocol, and the replay is over when
+        // the test says it is.
+        RecordedDeviceEvent::Subscribe { .. }
+        | RecordedDeviceEvent::Unsubscribe { .. }
+        | RecordedDeviceEvent::Disconnected => {}
+        RecordedDeviceEvent::Connected { .. } => {
+          warn!("Recording has more than one Connected entry, ignoring.");
+        }
+      }
+    }
+    let (event_sender, _) = broadcast::channel(256);
+    Ok(Self {
+      name,
+      address,
+      endpoints,
+      steps,
+      connected: AtomicBool::new(false),
+      state: Mutex::new(state),
+      event_sender,
+    })
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn address(&self) -> &str {
+    &self.address
+  }
+
+  pub fn connected(&self) -> bool {
+    self.connected.load(Ordering::SeqCst)
+  }
+
+  pub fn mismatches(&self) -> Vec<ReplayMismatch> {
+    self.state.lock().unwrap().mismatches.clone()
+  }
+
+  /// Recorded writes that haven't happened yet.
+  pub fn remaining_writes(&self) -> Vec<(Endpoint, Vec<u8>)> {
+    let next_step = self.state.lock().unwrap().next_step;
+    self
+      .steps
+      .iter()
+      .skip(next_step)
+      .map(|step| (step.endpoint, step.data.clone()))
+      .collect()
+  }
+
+  /// Checks that every recorded write happened, in order, and nothing else
+  /// was written.
+  pub fn verify(&self) -> Result<(), ButtplugError> {
+    let mismatches = self.mismatches();
+    let remaining = self.remaining_writes();
+    if mismatches.is_empty() && remaining.is_empty() {
+      return Ok(());
+    }
+    let mut problems: Vec<String> = mismatches
+      .iter()
+      .map(|mismatch| match &mismatch.expected {
+        Some((endpoint, data)) => format!(
+          "write {} expected {:?} on {}, got {:?} on {}",
+          mismatch.write_index, data, endpoint, mismatch.actual.1, mismatch.actual.0
+        ),
+        None => format!(
+          "unexpected write {:?} on {} after recording ended",
+          mismatch.actual.1, mismatch.actual.0
+        ),
+      })
+      .collect();
+    if !remaining.is_empty() {
+      problems.push(format!("{} recorded writes never happened", remaining.len()));
+    }
+    Err(
+      ButtplugDeviceError::from(ButtplugDeviceSpecificError::ReplayError(format!(
+        "Replay of {} ({}) did not match recording: {}",
+        self.name,
+        self.address,
+        problems.join("; ")
+      )))
+      .into(),
+    )
+  }
+
+  fn send_notifications(&self, state: &ReplayState, notifications: &[(Endpoint, Vec<u8>)]) {
+    for (endpoint, data) in notifications {
+      if state.subscriptions.contains(endpoint) {
+        let _ = self.event_sender.send(ButtplugDeviceEvent::Notification(
+          self.address.clone(),
+          *endpoint,
+          data.clone(),
+        ));
+      }
+    }
+  }
+
+  fn check_connected(&self) -> Result<(), ButtplugError> {
+    if self.connected() {
+      Ok(())
+    } else {
+      Err(ButtplugDeviceError::DeviceNotConnected(self.address.clone()).into())
+    }
+  }
+
+  fn write(&self, endpoint: Endpoint, data: &[u8]) -> Result<(), ButtplugError> {
+    self.check_connected()?;
+    let mut state = self.state.lock().unwrap();
+    let write_index = state.next_step;
+    match self.steps.get(write_index) {
+      Some(step) => {
+        if step.endpoint != endpoint || step.data != data {
+          state.mismatches.push(ReplayMismatch {
+            write_index,
+            expected: Some((step.endpoint, step.data.clone())),
+            actual: (endpoint, data.to_vec()),
+          });
+        }
+        // Keep going even if the write was different, so one changed command
+        // doesn't make everything after it mismatch too.
+        state.next_step += 1;
+        self.send_notifications(&state, &step.notifications);
+      }
+      None => state.mismatches.push(ReplayMismatch {
+        write_index,
+        expected: None,
+        actual: (endpoint, data.to_vec()),
+      }),
+    }
+    Ok(())
+  }
+
+  fn read(&self, endpoint: Endpoint) -> Result<Vec<u8>, ButtplugError> {
+    self.check_connected()?;
+    let mut state = self.state.lock().unwrap();
+    // Once we're out of recorded reads, keep returning the last one.
+    let reads = state.reads.entry(endpoint).or_default();
+    let data = if reads.len() > 1 {
+      reads.pop_front()
+    } else {
+      reads.front().cloned()
+    };
+    Ok(data.unwrap_or_default())
+  }
+
+  fn subscribe(&self, endpoint: Endpoint) -> Result<(), ButtplugError> {
+    self.check_connected()?;
+    let mut state = self.state.lock().unwrap();
+    state.subscriptions.insert(endpoint);
+    let initial_notifications: Vec<_> = state
+      .initial_notifications
+      .iter()
+      .filter(|(notification_endpoint, _)| *notification_endpoint == endpoint)
+      .cloned()
+      .collect();
+    state
+      .initial_notifications
+      .retain(|(notification_endpoint, _)| *notification_endpoint != endpoint);
+    self.send_notifications(&state, &initial_notifications);
+    Ok(())
+  }
+
+  fn disconnect(&self) {
+    if self.connected.swap(false, Ordering::SeqCst) {
+      let _ = self
+        .event_sender
+        .send(ButtplugDeviceEvent::Removed(self.address.clone()));
+    }
+  }
+}
+
+pub struct ReplayDeviceImplCreator {
+  device: Arc<ReplayDevice>,
+}
+
+impl ReplayDeviceImplCreator {
+  pub(super) fn new(device: Arc<ReplayDevice>) -> Self {
+    Self { device }
+  }
+}
+
+impl Debug for ReplayDeviceImplCreator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ReplayDeviceImplCreator")
+      .field("name", &self.device.name())
+      .field("address", &self.device.address())
+      .finish()
+  }
+}
+
+#[async_trait]
+impl ButtplugDeviceImplCreator for ReplayDeviceImplCreator {
+  fn get_specifier(&self) -> DeviceSpecifier {
+    DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(self.device.name()))
+  }
+
+  async fn try_create_device_impl(
+    &mut self,
+    _: ProtocolDefinition,
+  ) -> Result<DeviceImpl, ButtplugError> {
+    self.device.connected.store(true, Ordering::SeqCst);
+    Ok(DeviceImpl::new(
+      self.device.name(),
+      self.device.address(),
+      &self.device.endpoints,
+      Box::new(ReplayDeviceImpl {
+        device: self.device.clone(),
+      }),
+    ))
+  }
+}
+
+pub struct ReplayDeviceImpl {
+  device: Arc<ReplayDevice>,
+}
+
+impl DeviceImplInternal for ReplayDeviceImpl {
+  fn event_stream(&self) -> broadcast::Receiver<ButtplugDeviceEvent> {
+    self.device.event_sender.subscribe()
+  }
+
+  fn connected(&self) -> bool {
+    self.device.connected()
+  }
+
+  fn disconnect(&self) -> ButtplugResultFuture {
+    self.device.disconnect();
+    Box::pin(future::ready(Ok(())))
+  }
+
+  fn read_value(
+    &self,
+    msg: DeviceReadCmd,
+  ) -> BoxFuture<'static, Result<RawReading, ButtplugError>> {
+    let result = self
+      .device
+      .read(msg.endpoint)
+      .map(|data| RawReading::new(0, msg.endpoint, data));
+    Box::pin(future::ready(result))
+  }
+
+  fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture {
+    Box::pin(future::ready(self.device.write(msg.endpoint, &msg.data)))
+  }
+
+  fn subscribe(&self, msg: DeviceSubscribeCmd) -> ButtplugResultFuture {
+    Box::pin(future::ready(self.device.subscribe(msg.endpoint)))
+  }
+
+  fn unsubscribe(&self, msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture {
+    let result = self.device.check_connected().map(|_| {
+      self
+        .device
+        .state
+        .lock()
+        .unwrap()
+        .subscriptions
+        .remove(&msg.endpoint);
+    });
+    Box::pin(future::ready(result))
+  }
+}
@@ -0,0 +1,27 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Virtual devices that answer the way real hardware does.
+//!
+//! Unlike the [test][super::test] comm manager, which only records what's
+//! written to it, simulated devices reply to scripted requests (a Lovense
+//! `DeviceType;` query, for instance), so protocols initialize just like they
+//! would against real hardware. The simulated device handles keep track of
+//! what's been written to them, so applications can check what "the device"
+//! is doing.
+
+pub mod simulator_comm_manager;
+pub mod simulator_config;
+pub mod simulator_device;
+
+pub use simulator_comm_manager::{
+  SimulatorDeviceCommunicationManager, SimulatorDeviceCommunicationManagerBuilder,
+};
+pub use simulator_config::{
+  SimulatedActuatorConfig, SimulatedData, SimulatedDeviceConfig, SimulatedReply, SimulatorConfig,
+};
+pub use simulator_device::SimulatedDevice;
@@ -0,0 +1,116 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{
+  simulator_config::{SimulatedDeviceConfig, SimulatorConfig},
+  simulator_device::{SimulatedDevice, SimulatedDeviceImplCreator},
+};
+use crate::{
+  core::{errors::ButtplugError, ButtplugResultFuture},
+  server::comm_managers::{
+    DeviceCommunicationEvent, DeviceCommunicationManager, DeviceCommunicationManagerBuilder,
+  },
+};
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+
+#[derive(Default)]
+pub struct SimulatorDeviceCommunicationManagerBuilder {
+  sender: Option<Sender<DeviceCommunicationEvent>>,
+  devices: Vec<Arc<SimulatedDevice>>,
+}
+
+impl SimulatorDeviceCommunicationManagerBuilder {
+  pub fn device(mut self, config: SimulatedDeviceConfig) -> Self {
+    let address = config
+      .address
+      .clone()
+      .unwrap_or_else(|| format!("simulated-device-{}", self.devices.len()));
+    self
+      .devices
+      .push(Arc::new(SimulatedDevice::new(config, &address)));
+    self
+  }
+
+  pub fn config(self, config: SimulatorConfig) -> Self {
+    config
+      .devices
+      .into_iter()
+      .fold(self, |builder, device| builder.device(device))
+  }
+
+  pub fn config_json(self, json: &str) -> Result<Self, ButtplugError> {
+    Ok(self.config(SimulatorConfig::from_json(json)?))
+  }
+
+  /// Handles for inspecting and poking at the simulated devices. Grab these
+  /// before handing the builder to the device manager.
+  pub fn devices(&self) -> Vec<Arc<SimulatedDevice>> {
+    self.devices.clone()
+  }
+}
+
+impl DeviceCommunicationManagerBuilder for SimulatorDeviceCommunicationManagerBuilder {
+  fn event_sender(mut self, sender: Sender<DeviceCommunicationEvent>) -> Self {
+    self.sender = Some(sender);
+    self
+  }
+
+  fn finish(mut self) -> Box<dyn DeviceCommunicationManager> {
+    Box::new(SimulatorDeviceCommunicationManager {
+      device_sender: self.sender.take().unwrap(),
+      devices: self.devices,
+    })
+  }
+}
+
+/// Comm manager for virtual devices described by a
+/// [SimulatorConfig][super::simulator_config::SimulatorConfig], for developing
+/// and testing applications without hardware. Every scan finds all simulated
+/// devices that aren't currently connected.
+pub struct SimulatorDeviceCommunicationManager {
+  device_sender: Sender<DeviceCommunicationEvent>,
+  devices: Vec<Arc<SimulatedDevice>>,
+}
+
+impl DeviceCommunicationManager for SimulatorDeviceCommunicationManager {
+  fn name(&self) -> &'static str {
+    "SimulatorDeviceCommunicationManager"
+  }
+
+  fn start_scanning(&self) -> ButtplugResultFuture {
+    let devices = self.devices.clone();
+    let device_sender = self.device_sender.clone();
+    Box::pin(async move {
+      for device in devices.iter().filter(|device| !device.connected()) {
+        if device_sender
+          .send(DeviceCommunicationEvent::DeviceFound {
+            name: device.name().to_owned(),
+            address: device.address().to_owned(),
+            creator: Box::new(SimulatedDeviceImplCreator::new(device.clone())),
+          })
+          .await
+          .is_err()
+        {
+          error!("Device channel no longer open.");
+        }
+      }
+      if device_sender
+        .send(DeviceCommunicationEvent::ScanningFinished)
+        .await
+        .is_err()
+      {
+        error!("Error sending scanning finished. Scanning may not register as finished now!");
+      }
+      Ok(())
+    })
+  }
+
+  fn stop_scanning(&self) -> ButtplugResultFuture {
+    Box::pin(futures::future::ready(Ok(())))
+  }
+}
@@ -0,0 +1,213 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Declarative descriptions of simulated devices.
+//!
+//! A simulator file looks like this:
+//!
+//! ```json
+//! {
+//!   "devices": [
+//!     {
+//!       "name": "LVS-Edge",
+//!       "address": "simulated-edge",
+//!       "rssi_level": -60,
+//!       "replies": [
+//!         { "endpoint": "tx", "request": "DeviceType;", "reply_endpoint": "rx", "reply": "P:37:0082059AD3BD;" },
+//!         { "endpoint": "tx", "request": "Battery;", "reply_endpoint": "rx", "reply": "85;" }
+//!       ],
+//!       "actuators": [
+//!         { "name": "Vibrate", "endpoint": "tx", "prefix": "Vibrate:" },
+//!         { "name": "Vibrate1", "endpoint": "tx", "prefix": "Vibrate1:" },
+//!         { "name": "Vibrate2", "endpoint": "tx", "prefix": "Vibrate2:" }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Data can be given either as a string or as an array of bytes.
+
+use super::super::ButtplugDeviceSpecificError;
+use crate::{
+  core::errors::{ButtplugDeviceError, ButtplugError},
+  device::{configuration_manager::BluetoothLESpecifier, Endpoint},
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Bytes sent to or from a simulated device.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum SimulatedData {
+  Text(String),
+  Bytes(Vec<u8>),
+}
+
+impl SimulatedData {
+  pub fn as_bytes(&self) -> &[u8] {
+    match self {
+      SimulatedData::Text(text) => text.as_bytes(),
+      SimulatedData::Bytes(bytes) => bytes,
+    }
+  }
+}
+
+/// Scripted reply. When exactly `request` is written to `endpoint`, `reply` is
+/// sent as a notification on `reply_endpoint`, as long as something has
+/// subscribed to it.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SimulatedReply {
+  pub endpoint: Endpoint,
+  pub request: SimulatedData,
+  pub reply_endpoint: Endpoint,
+  pub reply: SimulatedData,
+}
+
+/// Tracks the value of an actuator from the writes that set it.
+///
+/// Writes to `endpoint` that start with `prefix` update the actuator. If the
+/// prefix is text, the value is the number written after it (so `Vibrate1:`
+/// reads 10 from `Vibrate1:10;`). If the prefix is bytes, the value is the byte
+/// right after it.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SimulatedActuatorConfig {
+  pub name: String,
+  pub endpoint: Endpoint,
+  pub prefix: SimulatedData,
+}
+
+impl SimulatedActuatorConfig {
+  /// Value this actuator would be set to by writing `data` to `endpoint`, if
+  /// the write is meant for it.
+  pub fn parse_value(&self, endpoint: Endpoint, data: &[u8]) -> Option<u32> {
+    if endpoint != self.endpoint || !data.starts_with(self.prefix.as_bytes()) {
+      return None;
+    }
+    let remaining = &data[self.prefix.as_bytes().len()..];
+    match self.prefix {
+      SimulatedData::Text(_) => {
+        let digits: String = remaining
+          .iter()
+          .take_while(|byte| byte.is_ascii_digit())
+          .map(|byte| *byte as char)
+          .collect();
+        digits.parse().ok()
+      }
+      SimulatedData::Bytes(_) => remaining.first().map(|byte| *byte as u32),
+    }
+  }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SimulatedDeviceConfig {
+  /// Name the device advertises.
+  pub name: String,
+  /// If not set, the comm manager makes one up.
+  #[serde(default)]
+  pub address: Option<String>,
+  /// Specifier used to match the device to a protocol. If not set, the device
+  /// is matched on its name only.
+  #[serde(default)]
+  pub btle: Option<BluetoothLESpecifier>,
+  /// If empty, the device gets whatever endpoints the protocol it matched
+  /// expects.
+  #[serde(default)]
+  pub endpoints: Vec<Endpoint>,
+  #[serde(default)]
+  pub replies: Vec<SimulatedReply>,
+  /// Data returned when reading each endpoint. Endpoints not listed here read
+  /// back empty.
+  #[serde(default)]
+  pub reads: HashMap<Endpoint, SimulatedData>,
+  #[serde(default)]
+  pub actuators: Vec<SimulatedActuatorConfig>,
+  /// Signal strength the device starts out reporting, in dBm. If not set, the
+  /// device doesn't report signal strength.
+  #[serde(default)]
+  pub rssi_level: Option<i32>,
+}
+
+impl SimulatedDeviceConfig {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_owned(),
+      address: None,
+      btle: None,
+      endpoints: vec![],
+      replies: vec![],
+      reads: HashMap::new(),
+      actuators: vec![],
+      rssi_level: None,
+    }
+  }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SimulatorConfig {
+  pub devices: Vec<SimulatedDeviceConfig>,
+}
+
+impl SimulatorConfig {
+  pub fn from_json(json: &str) -> Result<Self, ButtplugError> {
+    serde_json::from_str(json).map_err(|err| {
+      ButtplugDeviceError::from(ButtplugDeviceSpecificError::SimulatorError(format!(
+        "Cannot parse simulator configuration: {}",
+        err
+      )))
+      .into()
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_actuator_value_parsing() {
+    let text = SimulatedActuatorConfig {
+      name: "Vibrate1".to_owned(),
+      endpoint: Endpoint::Tx,
+      prefix: SimulatedData::Text("Vibrate1:".to_owned()),
+    };
+    assert_eq!(text.parse_value(Endpoint::Tx, b"Vibrate1:12;"), Some(12));
+    assert_eq!(text.parse_value(Endpoint::Tx, b"Vibrate2:12;"), None);
+    assert_eq!(text.parse_value(Endpoint::Rx, b"Vibrate1:12;"), None);
+    let bytes = SimulatedActuatorConfig {
+      name: "Vibrator".to_owned(),
+      endpoint: Endpoint::Tx,
+      prefix: SimulatedData::Bytes(vec![0xF1]),
+    };
+    assert_eq!(bytes.parse_value(Endpoint::Tx, &[0xF1, 64]), Some(64));
+    assert_eq!(bytes.parse_value(Endpoint::Tx, &[0xF2, 64]), None);
+  }
+
+  #[test]
+  fn test_config_parsing() {
+    let config = SimulatorConfig::from_json(
+      r#"{
+        "devices": [
+          {
+            "name": "Massage Demo",
+            "reads": { "rx": [1, 2, 3] },
+            "replies": [
+              { "endpoint": "tx", "request": [1], "reply_endpoint": "rx", "reply": "ok" }
+            ]
+          }
+        ]
+      }"#,
+    )
+    .unwrap();
+    let device = &config.devices[0];
+    assert_eq!(device.name, "Massage Demo");
+    assert_eq!(device.reads[&Endpoint::Rx].as_bytes(), &[1, 2, 3]);
+    assert_eq!(device.replies[0].request.as_bytes(), &[1]);
+    assert_eq!(device.replies[0].reply.as_bytes(), b"ok");
+    assert!(SimulatorConfig::from_json(r#"{ "devices": [{}] }"#).is_err());
+  }
+}
@@ -0,0 +1,300 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::simulator_config::{SimulatedData, SimulatedDeviceConfig};
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::{ButtplugDeviceMessageType, RawReading},
+    ButtplugResultFuture,
+  },
+  device::{
+    configuration_manager::{BluetoothLESpecifier, DeviceSpecifier, ProtocolDefinition},
+    ButtplugDeviceEvent, ButtplugDeviceImplCreator, DeviceImpl, DeviceImplInternal, DeviceReadCmd,
+    DeviceSubscribeCmd, DeviceUnsubscribeCmd, DeviceWriteCmd, Endpoint,
+  },
+};
+use async_trait::async_trait;
+use dashmap::{DashMap, DashSet};
+use futures::future::{self, BoxFuture};
+use std::{
+  collections::BTreeMap,
+  fmt::{self, Debug},
+  sync::{
+    atomic::{AtomicBool, AtomicI32, Ordering},
+    Arc,
+  },
+};
+use tokio::sync::broadcast;
+
+/// A virtual device, along with everything that's been done to it.
+///
+/// Handles stay valid across connections, so state can be inspected while the
+/// device is connected, after it disconnects, and after it reconnects.
+pub struct SimulatedDevice {
+  config: SimulatedDeviceConfig,
+  address: String,
+  connected: AtomicBool,
+  endpoints: DashSet<Endpoint>,
+  subscriptions: DashSet<Endpoint>,
+  last_writes: DashMap<Endpoint, Vec<u8>>,
+  actuator_state: DashMap<String, u32>,
+  rssi_level: AtomicI32,
+  event_sender: broadcast::Sender<ButtplugDeviceEvent>,
+}
+
+impl SimulatedDevice {
+  pub(super) fn new(config: SimulatedDeviceConfig, address: &str) -> Self {
+    let (event_sender, _) = broadcast::channel(256);
+    Self {
+      rssi_level: AtomicI32::new(config.rssi_level.unwrap_or_default()),
+      config,
+      address: address.to_owned(),
+      connected: AtomicBool::new(false),
+      endpoints: DashSet::new(),
+      subscriptions: DashSet::new(),
+      last_writes: DashMap::new(),
+      actuator_state: DashMap::new(),
+      event_sender,
+    }
+  }
+
+  pub fn name(&self) -> &str {
+    &self.config.name
+  }
+
+  pub fn address(&self) -> &str {
+    &self.address
+  }
+
+  pub fn connected(&self) -> bool {
+    self.connected.load(Ordering::SeqCst)
+  }
+
+  /// Last value set on an actuator, if anything has set it yet.
+  pub fn actuator_state(&self, name: &str) -> Option<u32> {
+    self.actuator_state.get(name).map(|value| *value)
+  }
+
+  /// Every actuator that's been set, by name.
+  pub fn actuator_states(&self) -> BTreeMap<String, u32> {
+    self
+      .actuator_state
+      .iter()
+      .map(|state| (state.key().clone(), *state.value()))
+      .collect()
+  }
+
+  pub fn last_write(&self, endpoint: Endpoint) -> Option<Vec<u8>> {
+    self.last_writes.get(&endpoint).map(|data| data.clone())
+  }
+
+  /// Changes the signal strength the device reports, as if it was moved
+  /// closer or further away. Does nothing for devices that weren't configured
+  /// with a signal strength.
+  pub fn set_rssi_level(&self, rssi_level: i32) {
+    self.rssi_level.store(rssi_level, Ordering::SeqCst);
+  }
+
+  pub fn subscribed(&self, endpoint: Endpoint) -> bool {
+    self.subscriptions.contains(&endpoint)
+  }
+
+  /// Sends unprompted data from the device, like a button press. Like real
+  /// hardware, nothing is sent unless the endpoint is subscribed. Returns
+  /// whether the notification was sent.
+  pub fn send_notification(&self, endpoint: Endpoint, data: &[u8]) -> bool {
+    if !self.connected() || !self.subscribed(endpoint) {
+      return false;
+    }
+    let _ = self.event_sender.send(ButtplugDeviceEvent::Notification(
+      self.address.clone(),
+      endpoint,
+      data.to_vec(),
+    ));
+    true
+  }
+
+  /// Drops the connection, as if the device was turned off or went out of
+  /// range. Scanning will find it again.
+  pub fn disconnect(&self) {
+    if self.connected.swap(false, Ordering::SeqCst) {
+      self.subscriptions.clear();
+      // Nobody may be listening, i.e. if initialization failed.
+      let _ = self
+        .event_sender
+        .send(ButtplugDeviceEvent::Removed(self.address.clone()));
+    }
+  }
+
+  fn connect(&self, endpoints: &[Endpoint]) {
+    self.endpoints.clear();
+    for endpoint in endpoints {
+      self.endpoints.insert(*endpoint);
+    }
+    self.subscriptions.clear();
+    self.connected.store(true, Ordering::SeqCst);
+  }
+
+  fn check_endpoint(&self, endpoint: Endpoint) -> Result<(), ButtplugError> {
+    if !self.connected() {
+      Err(ButtplugDeviceError::DeviceNotConnected(self.address.clone()).into())
+    } else if !self.endpoints.contains(&endpoint) {
+      Err(ButtplugDeviceError::InvalidEndpoint(endpoint).into())
+    } else {
+      Ok(())
+    }
+  }
+
+  fn write(&self, endpoint: Endpoint, data: &[u8]) -> Result<(), ButtplugError> {
+    self.check_endpoint(endpoint)?;
+    self.last_writes.insert(endpoint, data.to_vec());
+    for actuator in &self.config.actuators {
+      if let Some(value) = actuator.parse_value(endpoint, data) {
+        self.actuator_state.insert(actuator.name.clone(), value);
+      }
+    }
+    for reply in &self.config.replies {
+      if reply.endpoint == endpoint && reply.request.as_bytes() == data {
+        self.send_notification(reply.reply_endpoint, reply.reply.as_bytes());
+      }
+    }
+    Ok(())
+  }
+
+  fn read(&self, endpoint: Endpoint) -> Result<Vec<u8>, ButtplugError> {
+    self.check_endpoint(endpoint)?;
+    Ok(
+      self
+        .config
+        .reads
+        .get(&endpoint)
+        .map(SimulatedData::as_bytes)
+        .unwrap_or_default()
+        .to_vec(),
+    )
+  }
+}
+
+pub struct SimulatedDeviceImplCreator {
+  device: Arc<SimulatedDevice>,
+}
+
+impl SimulatedDeviceImplCreator {
+  pub(super) fn new(device: Arc<SimulatedDevice>) -> Self {
+    Self { device }
+  }
+}
+
+impl Debug for SimulatedDeviceImplCreator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SimulatedDeviceImplCreator")
+      .field("name", &self.device.name())
+      .field("address", &self.device.address())
+      .finish()
+  }
+}
+
+#[async_trait]
+impl ButtplugDeviceImplCreator for SimulatedDeviceImplCreator {
+  fn get_specifier(&self) -> DeviceSpecifier {
+    DeviceSpecifier::BluetoothLE(
+      self
+        .device
+        .config
+        .btle
+        .clone()
+        .unwrap_or_else(|| BluetoothLESpecifier::new_from_device(self.device.name())),
+    )
+  }
+
+  async fn try_create_device_impl(
+    &mut self,
+    protocol: ProtocolDefinition,
+  ) -> Result<DeviceImpl, ButtplugError> {
+    let mut endpoints = self.device.config.endpoints.clone();
+    if endpoints.is_empty() {
+      if let Some(btle) = &protocol.btle {
+        for endpoint_map in btle.services.values() {
+          endpoints.extend(endpoint_map.keys());
+        }
+      }
+    }
+    self.device.connect(&endpoints);
+    Ok(DeviceImpl::new(
+      self.device.name(),
+      self.device.address(),
+      &endpoints,
+      Box::new(SimulatedDeviceImpl {
+        device: self.device.clone(),
+      }),
+    ))
+  }
+}
+
+pub struct SimulatedDeviceImpl {
+  device: Arc<SimulatedDevice>,
+}
+
+impl DeviceImplInternal for SimulatedDeviceImpl {
+  fn event_stream(&self) -> broadcast::Receiver<ButtplugDeviceEvent> {
+    self.device.event_sender.subscribe()
+  }
+
+  fn connected(&self) -> bool {
+    self.device.connected()
+  }
+
+  fn disconnect(&self) -> ButtplugResultFuture {
+    self.device.disconnect();
+    Box::pin(future::ready(Ok(())))
+  }
+
+  fn read_value(
+    &self,
+    msg: DeviceReadCmd,
+  ) -> BoxFuture<'static, Result<RawReading, ButtplugError>> {
+    let result = self
+      .device
+      .read(msg.endpoint)
+      .map(|data| RawReading::new(0, msg.endpoint, data));
+    Box::pin(future::ready(result))
+  }
+
+  fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture {
+    Box::pin(future::ready(self.device.write(msg.endpoint, &msg.data)))
+  }
+
+  fn subscribe(&self, msg: DeviceSubscribeCmd) -> ButtplugResultFuture {
+    let result = self.device.check_endpoint(msg.endpoint).map(|_| {
+      self.device.subscriptions.insert(msg.endpoint);
+    });
+    Box::pin(future::ready(result))
+  }
+
+  fn unsubscribe(&self, msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture {
+    let result = self.device.check_endpoint(msg.endpoint).map(|_| {
+      self.device.subscriptions.remove(&msg.endpoint);
+    });
+    Box::pin(future::ready(result))
+  }
+
+  fn supports_rssi(&self) -> bool {
+    self.device.config.rssi_level.is_some()
+  }
+
+  fn rssi_level(&self) -> BoxFuture<'static, Result<i32, ButtplugError>> {
+    let result = if !self.supports_rssi() {
+      Err(ButtplugDeviceError::MessageNotSupported(ButtplugDeviceMessageType::RSSILevelCmd).into())
+    } else if !self.device.connected() {
+      Err(ButtplugDeviceError::DeviceNotConnected(self.device.address.clone()).into())
+    } else {
+      Ok(self.device.rssi_level.load(Ordering::SeqCst))
+    };
+    Box::pin(future::ready(result))
+  }
+}
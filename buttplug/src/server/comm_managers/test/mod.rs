@@ -1,3 +1,5 @@
+#[cfg(all(feature = "server", feature = "serialize-json"))]
+pub mod protocol_conformance;
 mod test_device;
 #[cfg(feature = "server")]
 mod test_device_comm_manager;
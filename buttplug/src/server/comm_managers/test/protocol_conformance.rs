@@ -0,0 +1,371 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Protocol tests described by data instead of code.
+//!
+//! Each test case names one or more devices, the client messages to send
+//! them, and the bytes each message should write to each endpoint. Cases run
+//! against a full server with the test comm manager, so device matching,
+//! protocol initialization and message handling are all covered.
+//!
+//! ```json
+//! {
+//!   "cases": [
+//!     {
+//!       "description": "Edge vibrates each motor separately",
+//!       "devices": [{ "name": "LVS-Edge", "expected_name": "Lovense Edge" }],
+//!       "init": [
+//!         { "endpoint": "tx", "request": "DeviceType;", "reply_endpoint": "rx", "reply": "P:37:0082059AD3BD;" }
+//!       ],
+//!       "steps": [
+//!         {
+//!           "message": { "VibrateCmd": { "Speeds": [{ "Index": 0, "Speed": 0.5 }, { "Index": 1, "Speed": 0.25 }] } },
+//!           "writes": [
+//!             { "endpoint": "tx", "data": "Vibrate1:10;" },
+//!             { "endpoint": "tx", "data": "Vibrate2:5;" }
+//!           ]
+//!         }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Messages are written as they would be sent by a client, minus the `Id` and
+//! `DeviceIndex` fields, which the harness fills in. Messages from older spec
+//! versions (i.e. `FleshlightLaunchFW12Cmd`) can be used too. Data can be
+//! given either as a string or as an array of bytes.
+
+use super::{TestDeviceCommunicationManagerBuilder, TestDeviceInternal};
+use crate::{
+  core::messages::{
+    ButtplugClientMessage, ButtplugServerMessage, ButtplugSpecV0ClientMessage,
+    ButtplugSpecV1ClientMessage, ButtplugSpecV2ClientMessage, ButtplugSpecV3ClientMessage,
+    RequestServerInfo, StartScanning, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+  },
+  device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
+  server::{ButtplugServer, ButtplugServerBuilder, DeviceConnectionPolicy},
+  util::stream::recv_now,
+};
+use futures::{pin_mut, FutureExt, StreamExt};
+use futures_timer::Delay;
+use serde::Deserialize;
+use std::{fmt, time::Duration};
+
+/// How long to wait for a device to show up before failing the case.
+const DEVICE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ProtocolTestData {
+  Text(String),
+  Bytes(Vec<u8>),
+}
+
+impl ProtocolTestData {
+  pub fn as_bytes(&self) -> &[u8] {
+    match self {
+      ProtocolTestData::Text(text) => text.as_bytes(),
+      ProtocolTestData::Bytes(bytes) => bytes,
+    }
+  }
+}
+
+/// Notification the test device sends when `request` is written to
+/// `endpoint`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProtocolTestReply {
+  pub endpoint: Endpoint,
+  pub request: ProtocolTestData,
+  pub reply_endpoint: Endpoint,
+  pub reply: ProtocolTestData,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProtocolTestDevice {
+  /// Name the device advertises.
+  pub name: String,
+  /// Name the device should be added under, if it should be checked.
+  #[serde(default)]
+  pub expected_name: Option<String>,
+  /// Replies for this device only, on top of the ones for the whole case.
+  #[serde(default)]
+  pub init: Vec<ProtocolTestReply>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ProtocolTestWrite {
+  pub endpoint: Endpoint,
+  pub data: ProtocolTestData,
+  #[serde(default)]
+  pub write_with_response: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProtocolTestStep {
+  /// Client message, as JSON.
+  pub message: serde_json::Value,
+  /// Writes the message should cause, in order. Endpoints not listed here
+  /// shouldn't be written to at all.
+  #[serde(default)]
+  pub writes: Vec<ProtocolTestWrite>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProtocolTestCase {
+  pub description: String,
+  /// Every device runs through the same steps, each on its own server.
+  pub devices: Vec<ProtocolTestDevice>,
+  #[serde(default)]
+  pub init: Vec<ProtocolTestReply>,
+  /// Writes expected during initialization. If not set, initialization writes
+  /// aren't checked.
+  #[serde(default)]
+  pub init_writes: Option<Vec<ProtocolTestWrite>>,
+  pub steps: Vec<ProtocolTestStep>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProtocolTestFile {
+  pub cases: Vec<ProtocolTestCase>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolTestFailure {
+  pub description: String,
+  pub device: String,
+  pub reason: String,
+}
+
+impl fmt::Display for ProtocolTestFailure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ({}): {}", self.description, self.device, self.reason)
+  }
+}
+
+pub fn parse_protocol_tests(json: &str) -> Result<Vec<ProtocolTestCase>, serde_json::Error> {
+  serde_json::from_str::<ProtocolTestFile>(json).map(|file| file.cases)
+}
+
+/// Runs a case for each of its devices, returning every failure.
+pub async fn run_protocol_test(case: &ProtocolTestCase) -> Vec<ProtocolTestFailure> {
+  let mut failures = vec![];
+  for device in &case.devices {
+    if let Err(reason) = run_protocol_test_device(case, device).await {
+      failures.push(ProtocolTestFailure {
+        description: case.description.clone(),
+        device: device.name.clone(),
+        reason,
+      });
+    }
+  }
+  failures
+}
+
+fn build_message(
+  message: &serde_json::Value,
+  device_index: u32,
+) -> Result<ButtplugClientMessage, String> {
+  let (message_type, mut fields) = match message.as_object().map(|object| object.iter().next()) {
+    Some(Some((message_type, fields))) => (message_type.clone(), fields.clone()),
+    _ => return Err(format!("Message {} is not a message object", message)),
+  };
+  if let Some(fields) = fields.as_object_mut() {
+    fields.insert("Id".to_owned(), 1.into());
+    fields.insert("DeviceIndex".to_owned(), device_index.into());
+  }
+  let message = serde_json::json!({ message_type: fields });
+  // Try the newest spec first, so older messages that were carried forward
+  // come through as their newest version.
+  if let Ok(message) = serde_json::from_value::<ButtplugSpecV3ClientMessage>(message.clone()) {
+    return Ok(message.into());
+  }
+  if let Ok(message) = serde_json::from_value::<ButtplugSpecV2ClientMessage>(message.clone()) {
+    return Ok(message.into());
+  }
+  if let Ok(message) = serde_json::from_value::<ButtplugSpecV1ClientMessage>(message.clone()) {
+    return Ok(message.into());
+  }
+  serde_json::from_value::<ButtplugSpecV0ClientMessage>(message.clone())
+    .map(|message| message.into())
+    .map_err(|err| format!("Cannot parse message {}: {}", message, err))
+}
+
+/// Takes every write the device has received since the last call, per
+/// endpoint.
+fn take_writes(device: &TestDeviceInternal) -> Vec<(Endpoint, Vec<DeviceImplCommand>)> {
+  let mut endpoints = device.endpoints();
+  endpoints.sort_by_key(|endpoint| endpoint.to_string());
+  endpoints
+    .into_iter()
+    .map(|endpoint| {
+      let receiver = device.get_endpoint_receiver(&endpoint).unwrap();
+      let mut receiver = receiver.lock().unwrap();
+      let mut commands = vec![];
+      while let Some(Some(command)) = recv_now(&mut receiver) {
+        commands.push(command);
+      }
+      (endpoint, commands)
+    })
+    .collect()
+}
+
+/// Shows data as text when it's printable, since a lot of protocols are
+/// text based.
+fn describe_writes(commands: &[DeviceImplCommand]) -> String {
+  let writes: Vec<String> = commands
+    .iter()
+    .map(|command| match command {
+      DeviceImplCommand::Write(write) => {
+        let data = match std::str::from_utf8(&write.data) {
+          Ok(text) if text.chars().all(|c| c.is_ascii_graphic() || c == ' ') => {
+            format!("{:?}", text)
+          }
+          _ => format!("{:?}", write.data),
+        };
+        if write.write_with_response {
+          format!("{} (with response)", data)
+        } else {
+          data
+        }
+      }
+      command => format!("{:?}", command),
+    })
+    .collect();
+  format!("[{}]", writes.join(", "))
+}
+
+fn check_writes(
+  device: &TestDeviceInternal,
+  expected: &[ProtocolTestWrite],
+  context: &str,
+) -> Result<(), String> {
+  for (endpoint, actual) in take_writes(device) {
+    let expected: Vec<DeviceImplCommand> = expected
+      .iter()
+      .filter(|write| write.endpoint == endpoint)
+      .map(|write| {
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          endpoint,
+          write.data.as_bytes().to_vec(),
+          write.write_with_response,
+        ))
+      })
+      .collect();
+    if actual != expected {
+      return Err(format!(
+        "{}: expected {} on {}, got {}",
+        context,
+        describe_writes(&expected),
+        endpoint,
+        describe_writes(&actual)
+      ));
+    }
+  }
+  if let Some(write) = expected
+    .iter()
+    .find(|write| !device.endpoints().contains(&write.endpoint))
+  {
+    return Err(format!(
+      "{}: expected a write on {}, which the device doesn't have",
+      context, write.endpoint
+    ));
+  }
+  Ok(())
+}
+
+async fn wait_for_device(
+  server: &ButtplugServer,
+  device: &ProtocolTestDevice,
+) -> Result<u32, String> {
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  server
+    .parse_message(
+      RequestServerInfo::new("Protocol Test", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await
+    .map_err(|err| format!("Handshake failed: {}", err.original_error()))?;
+  server
+    .parse_message(StartScanning::default().into())
+    .await
+    .map_err(|err| format!("Cannot start scanning: {}", err.original_error()))?;
+  let mut timeout = Delay::new(DEVICE_CONNECTION_TIMEOUT).fuse();
+  loop {
+    select! {
+      msg = recv.next().fuse() => match msg {
+        Some(ButtplugServerMessage::DeviceAdded(added)) => {
+          if let Some(expected_name) = &device.expected_name {
+            if added.device_name() != expected_name {
+              return Err(format!(
+                "Expected device to be named {}, got {}",
+                expected_name,
+                added.device_name()
+              ));
+            }
+          }
+          return Ok(added.device_index());
+        }
+        Some(ButtplugServerMessage::DeviceConnectionFailed(failed)) => {
+          return Err(format!(
+            "Device failed to connect during {:?}: {}",
+            failed.stage(),
+            failed.error_message()
+          ));
+        }
+        Some(_) => continue,
+        None => return Err("Server event stream closed".to_owned()),
+      },
+      _ = timeout => {
+        return Err("Device never connected. Does it match a protocol?".to_owned());
+      }
+    }
+  }
+}
+
+async fn run_protocol_test_device(
+  case: &ProtocolTestCase,
+  device: &ProtocolTestDevice,
+) -> Result<(), String> {
+  let mut policy = DeviceConnectionPolicy::default();
+  policy.initialize_timeout(Some(Duration::from_secs(2)));
+  let server = ButtplugServerBuilder::default()
+    .connection_policy(policy)
+    .finish()
+    .map_err(|err| format!("Cannot create server: {}", err))?;
+  let builder = TestDeviceCommunicationManagerBuilder::default();
+  let helper = builder.helper();
+  server
+    .device_manager()
+    .add_comm_manager(builder)
+    .map_err(|err| format!("Cannot add comm manager: {}", err))?;
+  let test_device = helper.add_ble_device(&device.name).await;
+  for reply in case.init.iter().chain(device.init.iter()) {
+    test_device.add_reply(
+      reply.endpoint,
+      reply.request.as_bytes(),
+      reply.reply_endpoint,
+      reply.reply.as_bytes(),
+    );
+  }
+  let device_index = wait_for_device(&server, device).await?;
+  match &case.init_writes {
+    Some(init_writes) => check_writes(&test_device, init_writes, "Initialization")?,
+    None => {
+      take_writes(&test_device);
+    }
+  }
+  for (index, step) in case.steps.iter().enumerate() {
+    let message = build_message(&step.message, device_index)?;
+    server
+      .parse_message(message)
+      .await
+      .map_err(|err| format!("Step {} failed: {}", index, err.original_error()))?;
+    check_writes(&test_device, &step.writes, &format!("Step {}", index))?;
+  }
+  Ok(())
+}
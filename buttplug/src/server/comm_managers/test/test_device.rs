@@ -1,7 +1,7 @@
 use crate::{
   core::{
     errors::{ButtplugDeviceError, ButtplugError},
-    messages::RawReading,
+    messages::{ButtplugDeviceMessageType, RawReading},
     ButtplugResultFuture,
   },
   device::{
@@ -14,9 +14,11 @@ use crate::{
 use async_trait::async_trait;
 use dashmap::DashMap;
 use futures::future::{self, BoxFuture};
+use futures_timer::Delay;
 use std::{
   fmt::{self, Debug},
-  sync::Arc,
+  sync::{Arc, Mutex},
+  time::Duration,
 };
 use tokio::sync::{broadcast, mpsc};
 
@@ -100,10 +102,23 @@ impl TestDeviceEndpointChannel {
   }
 }
 
+/// Notification sent when `request` is written to `endpoint`, for protocols
+/// that wait on an answer from the device during initialization.
+#[derive(Debug, Clone)]
+struct TestDeviceReply {
+  endpoint: Endpoint,
+  request: Vec<u8>,
+  reply_endpoint: Endpoint,
+  reply: Vec<u8>,
+}
+
 pub struct TestDeviceInternal {
   name: String,
   address: String,
   endpoint_channels: Arc<DashMap<Endpoint, TestDeviceEndpointChannel>>,
+  replies: Arc<Mutex<Vec<TestDeviceReply>>>,
+  rssi_level: Arc<Mutex<Option<i32>>>,
+  write_delay: Arc<Mutex<Option<Duration>>>,
   event_sender: broadcast::Sender<ButtplugDeviceEvent>,
 }
 
@@ -114,6 +129,9 @@ impl TestDeviceInternal {
       name: name.to_owned(),
       address: address.to_owned(),
       endpoint_channels: Arc::new(DashMap::new()),
+      replies: Arc::new(Mutex::new(vec![])),
+      rssi_level: Arc::new(Mutex::new(None)),
+      write_delay: Arc::new(Mutex::new(None)),
       event_sender,
     }
   }
@@ -144,6 +162,44 @@ impl TestDeviceInternal {
       .map(|el| el.value().receiver.clone())
   }
 
+  pub fn endpoints(&self) -> Vec<Endpoint> {
+    self
+      .endpoint_channels
+      .iter()
+      .map(|channel| *channel.key())
+      .collect()
+  }
+
+  /// Sends `reply` as a notification on `reply_endpoint` whenever exactly
+  /// `request` is written to `endpoint`.
+  pub fn add_reply(
+    &self,
+    endpoint: Endpoint,
+    request: &[u8],
+    reply_endpoint: Endpoint,
+    reply: &[u8],
+  ) {
+    self.replies.lock().unwrap().push(TestDeviceReply {
+      endpoint,
+      request: request.to_vec(),
+      reply_endpoint,
+      reply: reply.to_vec(),
+    });
+  }
+
+  /// Signal strength the device reports. Devices only report signal strength
+  /// once this has been set.
+  pub fn set_rssi_level(&self, rssi_level: Option<i32>) {
+    *self.rssi_level.lock().unwrap() = rssi_level;
+  }
+
+  /// Holds each write for `write_delay` before it reaches the endpoint
+  /// channel, to simulate a slow link. Only affects writes started after this
+  /// is set.
+  pub fn set_write_delay(&self, write_delay: Option<Duration>) {
+    *self.write_delay.lock().unwrap() = write_delay;
+  }
+
   pub async fn add_endpoint(&self, endpoint: &Endpoint) {
     if !self.endpoint_channels.contains_key(endpoint) {
       let (sender, receiver) = mpsc::channel(256);
@@ -157,7 +213,8 @@ impl TestDeviceInternal {
     let sender = self.event_sender.clone();
     let address = self.address.clone();
     Box::pin(async move {
-      sender.send(ButtplugDeviceEvent::Removed(address)).unwrap();
+      // Nobody may be listening yet, i.e. if initialization failed.
+      let _ = sender.send(ButtplugDeviceEvent::Removed(address));
       Ok(())
     })
   }
@@ -170,6 +227,9 @@ pub struct TestDevice {
   // for creation in ButtplugDevice, so initialization and cloning order
   // matters here.
   pub endpoint_channels: Arc<DashMap<Endpoint, TestDeviceEndpointChannel>>,
+  replies: Arc<Mutex<Vec<TestDeviceReply>>>,
+  rssi_level: Arc<Mutex<Option<i32>>>,
+  write_delay: Arc<Mutex<Option<Duration>>>,
   event_sender: broadcast::Sender<ButtplugDeviceEvent>,
 }
 
@@ -179,6 +239,9 @@ impl TestDevice {
     Self {
       address: internal_device.address(),
       endpoint_channels: internal_device.endpoint_channels.clone(),
+      replies: internal_device.replies.clone(),
+      rssi_level: internal_device.rssi_level.clone(),
+      write_delay: internal_device.write_delay.clone(),
       event_sender: internal_device.sender(),
     }
   }
@@ -197,7 +260,8 @@ impl DeviceImplInternal for TestDevice {
     let sender = self.event_sender.clone();
     let address = self.address.clone();
     Box::pin(async move {
-      sender.send(ButtplugDeviceEvent::Removed(address)).unwrap();
+      // Nobody may be listening yet, i.e. if initialization failed.
+      let _ = sender.send(ButtplugDeviceEvent::Removed(address));
       Ok(())
     })
   }
@@ -211,12 +275,33 @@ impl DeviceImplInternal for TestDevice {
 
   fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture {
     let channels = self.endpoint_channels.clone();
+    let replies: Vec<TestDeviceReply> = self
+      .replies
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|reply| reply.endpoint == msg.endpoint && reply.request == msg.data)
+      .cloned()
+      .collect();
+    let event_sender = self.event_sender.clone();
+    let address = self.address.clone();
+    let write_delay = *self.write_delay.lock().unwrap();
     Box::pin(async move {
+      if let Some(write_delay) = write_delay {
+        Delay::new(write_delay).await;
+      }
       // Since we're only accessing a channel, we can use a read lock here.
       match channels.get(&msg.endpoint) {
         Some(device_channel) => {
           // We hold both ends, can unwrap.
           device_channel.sender.send(msg.into()).await.unwrap();
+          for reply in replies {
+            let _ = event_sender.send(ButtplugDeviceEvent::Notification(
+              address.clone(),
+              reply.reply_endpoint,
+              reply.reply,
+            ));
+          }
           Ok(())
         }
         None => Err(ButtplugDeviceError::InvalidEndpoint(msg.endpoint).into()),
@@ -231,4 +316,15 @@ impl DeviceImplInternal for TestDevice {
   fn unsubscribe(&self, _msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture {
     Box::pin(future::ready(Ok(())))
   }
+
+  fn supports_rssi(&self) -> bool {
+    self.rssi_level.lock().unwrap().is_some()
+  }
+
+  fn rssi_level(&self) -> BoxFuture<'static, Result<i32, ButtplugError>> {
+    let result = self.rssi_level.lock().unwrap().ok_or_else(|| {
+      ButtplugDeviceError::MessageNotSupported(ButtplugDeviceMessageType::RSSILevelCmd).into()
+    });
+    Box::pin(future::ready(result))
+  }
 }
@@ -12,7 +12,10 @@ use crate::{
 };
 use futures::future;
 use std::{
-  sync::Arc,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
   time::{SystemTime, UNIX_EPOCH},
 };
 use tokio::sync::{mpsc::Sender, Mutex};
@@ -93,10 +96,18 @@ impl TestDeviceCommunicationManagerHelper {
 #[derive(Default)]
 pub struct TestDeviceCommunicationManagerBuilder {
   sender: Option<tokio::sync::mpsc::Sender<DeviceCommunicationEvent>>,
-  devices: WaitingDeviceList
+  devices: WaitingDeviceList,
+  keep_scanning: bool,
 }
 
 impl TestDeviceCommunicationManagerBuilder {
+  /// Keep scanning after emitting the waiting devices, until scanning is
+  /// stopped, like a bluetooth manager would.
+  pub fn keep_scanning(mut self) -> Self {
+    self.keep_scanning = true;
+    self
+  }
+
   pub fn helper(&self) -> TestDeviceCommunicationManagerHelper {
     TestDeviceCommunicationManagerHelper::new(self.devices.clone())
   }
@@ -111,7 +122,8 @@ impl DeviceCommunicationManagerBuilder for TestDeviceCommunicationManagerBuilder
   fn finish(mut self) -> Box<dyn DeviceCommunicationManager> {
     Box::new(TestDeviceCommunicationManager::new(
       self.sender.take().unwrap(),
-      self.devices
+      self.devices,
+      self.keep_scanning,
     ))
   }
 }
@@ -119,13 +131,22 @@ impl DeviceCommunicationManagerBuilder for TestDeviceCommunicationManagerBuilder
 pub struct TestDeviceCommunicationManager {
   device_sender: Sender<DeviceCommunicationEvent>,
   devices: WaitingDeviceList,
+  keep_scanning: bool,
+  // Only used if keep_scanning is set, otherwise scans finish right away.
+  is_scanning: Arc<AtomicBool>,
 }
 
 impl TestDeviceCommunicationManager {
-  pub fn new(device_sender: Sender<DeviceCommunicationEvent>, devices: WaitingDeviceList) -> Self {
+  pub fn new(
+    device_sender: Sender<DeviceCommunicationEvent>,
+    devices: WaitingDeviceList,
+    keep_scanning: bool,
+  ) -> Self {
     Self {
       device_sender,
-      devices
+      devices,
+      keep_scanning,
+      is_scanning: Arc::new(AtomicBool::new(false)),
     }
   }
 }
@@ -138,9 +159,13 @@ impl DeviceCommunicationManager for TestDeviceCommunicationManager {
   fn start_scanning(&self) -> ButtplugResultFuture {
     let devices_vec = self.devices.clone();
     let device_sender = self.device_sender.clone();
+    let keep_scanning = self.keep_scanning;
+    let is_scanning = self.is_scanning.clone();
     Box::pin(async move {
       let mut devices = devices_vec.lock().await;
-      if devices.is_empty() {
+      if keep_scanning {
+        is_scanning.store(true, Ordering::SeqCst);
+      } else if devices.is_empty() {
         panic!("No devices for test device comm manager to emit!");
       }
       while let Some(d) = devices.pop() {
@@ -162,6 +187,9 @@ impl DeviceCommunicationManager for TestDeviceCommunicationManager {
           error!("Device channel no longer open.");
         }
       }
+      if keep_scanning {
+        return Ok(());
+      }
       if device_sender
         .send(DeviceCommunicationEvent::ScanningFinished)
         .await
@@ -174,7 +202,24 @@ impl DeviceCommunicationManager for TestDeviceCommunicationManager {
   }
 
   fn stop_scanning(&self) -> ButtplugResultFuture {
-    Box::pin(future::ready(Ok(())))
+    if !self.is_scanning.swap(false, Ordering::SeqCst) {
+      return Box::pin(future::ready(Ok(())));
+    }
+    let device_sender = self.device_sender.clone();
+    Box::pin(async move {
+      if device_sender
+        .send(DeviceCommunicationEvent::ScanningFinished)
+        .await
+        .is_err()
+      {
+        error!("Error sending scanning finished. Scanning may not register as finished now!");
+      }
+      Ok(())
+    })
+  }
+
+  fn scanning_status(&self) -> Arc<AtomicBool> {
+    self.is_scanning.clone()
   }
 }
 
@@ -0,0 +1,256 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Battery monitoring for devices that can report their battery level.
+//!
+//! Clients only find out about battery levels when they ask, and most don't
+//! ask until something's already gone wrong. The monitor polls each device
+//! that supports BatteryLevelCmd, keeps the last reading around so client
+//! requests don't have to wait on the hardware, and warns clients when the
+//! battery runs low.
+
+use crate::{
+  core::messages::{
+    BatteryLevelCmd, BatteryLevelLow, ButtplugDeviceMessageType, ButtplugServerMessage,
+  },
+  device::ButtplugDevice,
+  util::async_manager,
+};
+use futures::FutureExt;
+use futures_timer::Delay;
+use std::{
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+/// Settings for polling device batteries.
+#[derive(Debug, Clone)]
+pub struct BatteryMonitorPolicy {
+  /// Time between battery reads. Readings younger than this are used to
+  /// answer BatteryLevelCmd instead of reading the device again.
+  pub poll_interval: Duration,
+  /// Battery levels, from 0.0 to 1.0, that clients are warned about when a
+  /// device's battery drops to or below them. Each threshold only warns once,
+  /// until the battery charges back above it.
+  pub low_battery_thresholds: Vec<f64>,
+}
+
+impl Default for BatteryMonitorPolicy {
+  fn default() -> Self {
+    Self {
+      poll_interval: Duration::from_secs(60),
+      low_battery_thresholds: vec![0.2, 0.1, 0.05],
+    }
+  }
+}
+
+impl BatteryMonitorPolicy {
+  pub fn poll_interval(&mut self, interval: Duration) -> &mut Self {
+    self.poll_interval = interval;
+    self
+  }
+
+  pub fn low_battery_thresholds(&mut self, thresholds: &[f64]) -> &mut Self {
+    self.low_battery_thresholds = thresholds.to_vec();
+    self
+  }
+
+  /// Thresholds the level is at or below, highest first.
+  fn thresholds_reached(&self, battery_level: f64) -> Vec<f64> {
+    let mut reached: Vec<f64> = self
+      .low_battery_thresholds
+      .iter()
+      .copied()
+      .filter(|threshold| battery_level <= *threshold)
+      .collect();
+    reached.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    reached
+  }
+}
+
+#[derive(Default)]
+struct BatteryState {
+  last_reading: Option<(Instant, f64)>,
+  /// Number of thresholds the last reading was at or below.
+  thresholds_reached: usize,
+}
+
+/// Shared between the monitor handle and its polling task.
+struct BatteryTracker {
+  policy: BatteryMonitorPolicy,
+  device_index: u32,
+  state: Mutex<BatteryState>,
+  server_sender: broadcast::Sender<ButtplugServerMessage>,
+}
+
+impl BatteryTracker {
+  fn record(&self, battery_level: f64) {
+    let reached = self.policy.thresholds_reached(battery_level);
+    let newly_reached = {
+      let mut state = self.state.lock().unwrap();
+      state.last_reading = Some((Instant::now(), battery_level));
+      let newly_reached = reached.len() > state.thresholds_reached;
+      // Charging back above a threshold re-arms it.
+      state.thresholds_reached = reached.len();
+      newly_reached
+    };
+    if newly_reached {
+      let threshold = *reached.last().expect("Reached at least one threshold");
+      info!(
+        "Device {} battery at {}, at or below threshold {}.",
+        self.device_index, battery_level, threshold
+      );
+      if self
+        .server_sender
+        .send(BatteryLevelLow::new(self.device_index, battery_level, threshold).into())
+        .is_err()
+      {
+        debug!("Server not currently available, dropping Battery Level Low event.");
+      }
+    }
+  }
+
+  fn cached_level(&self) -> Option<f64> {
+    self
+      .state
+      .lock()
+      .unwrap()
+      .last_reading
+      .filter(|(time, _)| time.elapsed() < self.policy.poll_interval)
+      .map(|(_, level)| level)
+  }
+}
+
+async fn battery_monitor(
+  tracker: Arc<BatteryTracker>,
+  device: Arc<ButtplugDevice>,
+  token: CancellationToken,
+) {
+  loop {
+    match device
+      .parse_message(BatteryLevelCmd::new(tracker.device_index).into())
+      .await
+    {
+      Ok(ButtplugServerMessage::BatteryLevelReading(reading)) => {
+        tracker.record(reading.battery_level())
+      }
+      Ok(msg) => error!(
+        "Unexpected reply to battery poll for device {}: {:?}",
+        tracker.device_index, msg
+      ),
+      // Devices can be busy or briefly out of range, we'll try again next time.
+      Err(err) => debug!(
+        "Error polling battery for device {}: {:?}",
+        tracker.device_index, err
+      ),
+    }
+    select! {
+      _ = Delay::new(tracker.policy.poll_interval).fuse() => {}
+      _ = token.cancelled().fuse() => return,
+    }
+  }
+}
+
+/// Polls a device's battery level until dropped.
+pub(crate) struct DeviceBatteryMonitor {
+  tracker: Arc<BatteryTracker>,
+  token: CancellationToken,
+}
+
+impl Drop for DeviceBatteryMonitor {
+  fn drop(&mut self) {
+    self.token.cancel();
+  }
+}
+
+impl DeviceBatteryMonitor {
+  /// Starts monitoring the device, if it can report its battery level.
+  pub fn new(
+    policy: BatteryMonitorPolicy,
+    device_index: u32,
+    device: Arc<ButtplugDevice>,
+    server_sender: broadcast::Sender<ButtplugServerMessage>,
+  ) -> Option<Self> {
+    if !device
+      .message_attributes()
+      .contains_key(&ButtplugDeviceMessageType::BatteryLevelCmd)
+    {
+      return None;
+    }
+    let tracker = Arc::new(BatteryTracker {
+      policy,
+      device_index,
+      state: Mutex::new(BatteryState::default()),
+      server_sender,
+    });
+    let token = CancellationToken::new();
+    async_manager::spawn(battery_monitor(tracker.clone(), device, token.clone())).unwrap();
+    Some(Self { tracker, token })
+  }
+
+  /// Last battery level read from the device, if it was read within the poll
+  /// interval.
+  pub fn cached_level(&self) -> Option<f64> {
+    self.tracker.cached_level()
+  }
+
+  /// Records a battery level read outside of the monitor, i.e. by a client.
+  pub fn record(&self, battery_level: f64) {
+    self.tracker.record(battery_level);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn tracker(thresholds: &[f64]) -> (BatteryTracker, broadcast::Receiver<ButtplugServerMessage>) {
+    let (server_sender, receiver) = broadcast::channel(256);
+    let mut policy = BatteryMonitorPolicy::default();
+    policy.low_battery_thresholds(thresholds);
+    (
+      BatteryTracker {
+        policy,
+        device_index: 3,
+        state: Mutex::new(BatteryState::default()),
+        server_sender,
+      },
+      receiver,
+    )
+  }
+
+  #[test]
+  fn test_battery_thresholds() {
+    let (tracker, mut receiver) = tracker(&[0.1, 0.25]);
+    tracker.record(0.5);
+    assert!(receiver.try_recv().is_err());
+    tracker.record(0.2);
+    assert_eq!(
+      receiver.try_recv().unwrap(),
+      BatteryLevelLow::new(3, 0.2, 0.25).into()
+    );
+    // Already warned about this threshold.
+    tracker.record(0.15);
+    assert!(receiver.try_recv().is_err());
+    tracker.record(0.05);
+    assert_eq!(
+      receiver.try_recv().unwrap(),
+      BatteryLevelLow::new(3, 0.05, 0.1).into()
+    );
+    // Charging re-arms the thresholds it goes back above.
+    tracker.record(0.3);
+    tracker.record(0.08);
+    assert_eq!(
+      receiver.try_recv().unwrap(),
+      BatteryLevelLow::new(3, 0.08, 0.1).into()
+    );
+    assert!(receiver.try_recv().is_err());
+    assert_eq!(tracker.cached_level(), Some(0.08));
+  }
+}
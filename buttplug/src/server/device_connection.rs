@@ -0,0 +1,62 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Limits for connecting to newly found devices.
+
+use crate::device::DeviceCreationTimeouts;
+use std::time::Duration;
+
+/// Settings for connecting to devices found while scanning.
+///
+/// Connecting happens in two stages: connecting to the device itself, then
+/// running protocol initialization. Either can hang on misbehaving hardware
+/// (i.e. a Lovense toy that never answers its device type query), so each gets
+/// a time limit. Devices found while the maximum number of connections are
+/// already in progress wait for a free slot.
+#[derive(Debug, Clone)]
+pub struct DeviceConnectionPolicy {
+  /// Maximum number of devices connecting at once.
+  pub max_concurrent_connections: usize,
+  /// How long connecting to a device can take. None means no limit.
+  pub connect_timeout: Option<Duration>,
+  /// How long protocol initialization can take. None means no limit.
+  pub initialize_timeout: Option<Duration>,
+}
+
+impl Default for DeviceConnectionPolicy {
+  fn default() -> Self {
+    Self {
+      max_concurrent_connections: 4,
+      connect_timeout: Some(Duration::from_secs(30)),
+      initialize_timeout: Some(Duration::from_secs(10)),
+    }
+  }
+}
+
+impl DeviceConnectionPolicy {
+  pub fn max_concurrent_connections(&mut self, connections: usize) -> &mut Self {
+    self.max_concurrent_connections = connections;
+    self
+  }
+
+  pub fn connect_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+    self.connect_timeout = timeout;
+    self
+  }
+
+  pub fn initialize_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+    self.initialize_timeout = timeout;
+    self
+  }
+
+  pub(super) fn timeouts(&self) -> DeviceCreationTimeouts {
+    DeviceCreationTimeouts {
+      connect: self.connect_timeout,
+      initialize: self.initialize_timeout,
+    }
+  }
+}
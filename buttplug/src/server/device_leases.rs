@@ -0,0 +1,196 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Device lease tracking, for servers that share devices between multiple
+//! clients.
+
+use crate::core::errors::ButtplugDeviceError;
+use dashmap::DashMap;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceLeaseType {
+  /// Only the lease holder can command the device.
+  Exclusive,
+  /// Any number of clients can hold the lease and command the device.
+  Shared,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceLease {
+  lease_type: DeviceLeaseType,
+  holders: HashSet<u32>,
+}
+
+impl DeviceLease {
+  pub fn lease_type(&self) -> DeviceLeaseType {
+    self.lease_type
+  }
+
+  /// Ids of the clients holding this lease.
+  pub fn holders(&self) -> &HashSet<u32> {
+    &self.holders
+  }
+
+  fn add_holder(
+    &mut self,
+    client_id: u32,
+    device_index: u32,
+    lease_type: DeviceLeaseType,
+  ) -> Result<(), ButtplugDeviceError> {
+    let only_holder =
+      self.holders.is_empty() || (self.holders.len() == 1 && self.holders.contains(&client_id));
+    if !only_holder
+      && (lease_type == DeviceLeaseType::Exclusive || self.lease_type == DeviceLeaseType::Exclusive)
+    {
+      return Err(ButtplugDeviceError::DeviceLeasedByOtherClient(device_index));
+    }
+    if only_holder {
+      self.lease_type = lease_type;
+    }
+    self.holders.insert(client_id);
+    Ok(())
+  }
+}
+
+/// Keeps track of which clients hold leases on which devices.
+///
+/// Devices with no lease can be taken by anyone. Clients that send commands to
+/// a device without asking for a lease get a shared lease implicitly, so we
+/// know to stop the device if they disconnect.
+#[derive(Default)]
+pub struct DeviceLeaseManager {
+  leases: DashMap<u32, DeviceLease>,
+}
+
+impl DeviceLeaseManager {
+  pub fn lease(&self, device_index: u32) -> Option<DeviceLease> {
+    self.leases.get(&device_index).map(|lease| lease.clone())
+  }
+
+  /// Gives a client a lease on a device.
+  ///
+  /// A client that already holds the only lease on a device can switch
+  /// between exclusive and shared leases.
+  pub fn acquire(
+    &self,
+    client_id: u32,
+    device_index: u32,
+    lease_type: DeviceLeaseType,
+  ) -> Result<(), ButtplugDeviceError> {
+    let mut lease = self
+      .leases
+      .entry(device_index)
+      .or_insert_with(|| DeviceLease {
+        lease_type,
+        holders: HashSet::new(),
+      });
+    lease.add_holder(client_id, device_index, lease_type)
+  }
+
+  /// Checks that a client is allowed to command a device, taking a shared
+  /// lease for it if the device is free.
+  pub fn check_command(
+    &self,
+    client_id: u32,
+    device_index: u32,
+  ) -> Result<(), ButtplugDeviceError> {
+    // Look up and take the lease under the same entry lock, so another client
+    // can't slip an exclusive lease in between.
+    let mut lease = self
+      .leases
+      .entry(device_index)
+      .or_insert_with(|| DeviceLease {
+        lease_type: DeviceLeaseType::Shared,
+        holders: HashSet::new(),
+      });
+    if lease.holders.contains(&client_id) {
+      return Ok(());
+    }
+    lease.add_holder(client_id, device_index, DeviceLeaseType::Shared)
+  }
+
+  /// Releases a client's lease on a device. Returns true if nobody holds a
+  /// lease on the device afterward.
+  pub fn release(&self, client_id: u32, device_index: u32) -> Result<bool, ButtplugDeviceError> {
+    let released = match self.leases.get_mut(&device_index) {
+      Some(mut lease) => {
+        if !lease.holders.remove(&client_id) {
+          return Err(ButtplugDeviceError::DeviceLeaseNotHeld(device_index));
+        }
+        lease.holders.is_empty()
+      }
+      None => return Err(ButtplugDeviceError::DeviceLeaseNotHeld(device_index)),
+    };
+    if released {
+      self.leases.remove(&device_index);
+    }
+    Ok(released)
+  }
+
+  /// Releases all leases a client holds. Returns the indexes of devices that
+  /// no longer have any lease holders.
+  pub fn release_all(&self, client_id: u32) -> Vec<u32> {
+    let held: Vec<u32> = self
+      .leases
+      .iter()
+      .filter(|lease| lease.holders.contains(&client_id))
+      .map(|lease| *lease.key())
+      .collect();
+    held
+      .into_iter()
+      .filter(|index| matches!(self.release(client_id, *index), Ok(true)))
+      .collect()
+  }
+
+  /// Whether a client holds a lease, shared or exclusive, on a device.
+  pub fn holds_lease(&self, client_id: u32, device_index: u32) -> bool {
+    matches!(self.leases.get(&device_index), Some(lease) if lease.holders.contains(&client_id))
+  }
+
+  /// Device indexes a client currently holds leases for.
+  pub fn client_leases(&self, client_id: u32) -> Vec<u32> {
+    self
+      .leases
+      .iter()
+      .filter(|lease| lease.holders.contains(&client_id))
+      .map(|lease| *lease.key())
+      .collect()
+  }
+
+  pub fn remove_device(&self, device_index: u32) {
+    self.leases.remove(&device_index);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_exclusive_lease_blocks_other_clients() {
+    let leases = DeviceLeaseManager::default();
+    assert!(leases.acquire(1, 0, DeviceLeaseType::Exclusive).is_ok());
+    assert!(leases.acquire(2, 0, DeviceLeaseType::Shared).is_err());
+    assert!(leases.check_command(2, 0).is_err());
+    assert!(leases.check_command(1, 0).is_ok());
+    assert!(leases.release(1, 0).unwrap());
+    assert!(leases.check_command(2, 0).is_ok());
+  }
+
+  #[test]
+  fn test_shared_lease_allows_multiple_clients() {
+    let leases = DeviceLeaseManager::default();
+    assert!(leases.acquire(1, 0, DeviceLeaseType::Shared).is_ok());
+    assert!(leases.check_command(2, 0).is_ok());
+    assert!(leases.acquire(2, 0, DeviceLeaseType::Exclusive).is_err());
+    assert_eq!(leases.release_all(1), Vec::<u32>::new());
+    assert!(leases.acquire(2, 0, DeviceLeaseType::Exclusive).is_ok());
+    assert_eq!(leases.release_all(2), vec![0]);
+    assert!(leases.lease(0).is_none());
+  }
+}
@@ -12,42 +12,114 @@ use super::{
   comm_managers::{
     DeviceCommunicationEvent, DeviceCommunicationManager, DeviceCommunicationManagerBuilder,
   },
+  device_battery_monitor::{BatteryMonitorPolicy, DeviceBatteryMonitor},
+  device_connection::DeviceConnectionPolicy,
   device_manager_event_loop::DeviceManagerEventLoop,
+  device_manager_metrics::{CommManagerMetrics, ConnectedDeviceMetrics, DeviceManagerMetrics},
+  device_reconnect::{DeviceReconnectPolicy, ReconnectingDevice},
+  device_scanning::ScanningCommand,
+  device_store::{DeviceRegistry, StoredDeviceMap},
+  device_watchdog::DeviceWatchdog,
   ping_timer::PingTimer,
   ButtplugServerError,
 };
 use crate::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugMessageError, ButtplugUnknownError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError, ButtplugUnknownError},
     messages::{
-      self, ButtplugClientMessage, ButtplugDeviceCommandMessageUnion,
+      self, BatteryLevelReading, ButtplugClientMessage, ButtplugDeviceCommandMessageUnion,
       ButtplugDeviceManagerMessageUnion, ButtplugDeviceMessage, ButtplugMessage,
-      ButtplugServerMessage, DeviceList, DeviceMessageInfo,
+      ButtplugServerMessage, DeviceList, DeviceMessageInfo, DeviceRemoved,
     },
   },
   device::{
-    configuration_manager::{DeviceConfigurationManager, ProtocolDefinition}, protocol::ButtplugProtocol, ButtplugDevice,
+    configuration_manager::{DeviceConfigurationManager, ProtocolDefinition},
+    device_filter::{DeviceFilter, DeviceFilterAction, DeviceFilterRule},
+    protocol::ButtplugProtocol,
+    ButtplugDevice, Endpoint,
   },
   server::ButtplugServerResultFuture,
   util::async_manager,
 };
 use dashmap::{DashMap, DashSet};
-use futures::future;
+use futures::{future, future::BoxFuture, FutureExt};
 use std::{
   convert::TryFrom,
+  path::PathBuf,
   sync::{atomic::Ordering, Arc},
+  time::Duration,
 };
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::{dispatcher, Dispatch};
+
+/// Listing entry for a comm manager registered with the device manager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommManagerInfo {
+  pub name: String,
+  pub scanning: bool,
+  /// Number of connected devices found by this comm manager.
+  pub device_count: usize,
+}
 
 pub struct DeviceManager {
   // This uses a map to make sure we don't have 2 comm managers of the same type
   // register. Also means we can do lockless access since it's a Dashmap.
   comm_managers: Arc<DashMap<String, Box<dyn DeviceCommunicationManager>>>,
+  /// Cancels the task forwarding each comm manager's events to the event loop,
+  /// keyed by comm manager name.
+  comm_manager_tokens: DashMap<String, CancellationToken>,
   devices: Arc<DashMap<u32, Arc<ButtplugDevice>>>,
-  device_allow_list: Arc<DashSet<String>>,
-  device_deny_list: Arc<DashSet<String>>,
+  device_filter: Arc<DeviceFilter>,
+  /// (Device index, endpoint) pairs the client has sent RawSubscribeCmd for.
+  /// Used by the event loop to decide which device notifications get
+  /// forwarded to the client as RawReading events.
+  raw_subscriptions: Arc<DashSet<(u32, Endpoint)>>,
+  /// (Device index, sensor index) pairs the client has sent
+  /// SensorSubscribeCmd for. Used by the event loop to decide which sensors
+  /// get SensorReading events.
+  sensor_subscriptions: Arc<DashSet<(u32, u32)>>,
+  /// Maps device addresses to the name of the comm manager that found them,
+  /// so we know where to look when reconnecting.
+  device_comm_managers: Arc<DashMap<String, String>>,
+  /// Inactivity watchdogs for connected devices, keyed by device index. Empty
+  /// unless an inactivity timeout is set.
+  device_watchdogs: Arc<DashMap<u32, DeviceWatchdog>>,
+  /// Battery monitors for connected devices that can report their battery
+  /// level, keyed by device index. Empty unless battery monitoring is on.
+  device_battery_monitors: Arc<DashMap<u32, DeviceBatteryMonitor>>,
+  /// Disconnected devices the event loop is trying to reconnect to, keyed by
+  /// address.
+  reconnecting_devices: Arc<DashMap<String, ReconnectingDevice>>,
+  /// Addresses of devices removed along with their comm manager, whose
+  /// disconnect events the event loop should ignore.
+  removed_device_addresses: Arc<DashSet<String>>,
+  /// Indexes and user-assigned names for every device we've seen.
+  device_registry: Arc<DeviceRegistry>,
+  /// Counters for each comm manager, keyed by comm manager name.
+  comm_manager_metrics: Arc<DashMap<String, Arc<CommManagerMetrics>>>,
+  /// Scanning state lives in the event loop, this is how we talk to it.
+  scanning_command_sender: mpsc::Sender<ScanningCommand>,
   device_event_sender: mpsc::Sender<DeviceCommunicationEvent>,
+  server_sender: broadcast::Sender<ButtplugServerMessage>,
+  /// Cancelled when the device manager is dropped, to stop any device
+  /// connections still in progress.
+  shutdown_token: CancellationToken,
   config: Arc<DeviceConfigurationManager>,
+  /// Tracing dispatcher the device manager was created under, which is how
+  /// the server forwards logs to clients. Comm managers added later are set
+  /// up under it too.
+  dispatch: Dispatch,
+}
+
+/// Device manager state to update once a device has accepted a command.
+enum AcceptedCommandUpdate {
+  RawSubscribe(Endpoint),
+  RawUnsubscribe(Endpoint),
+  SensorSubscribe(u32),
+  SensorUnsubscribe(u32),
+  BatteryLevel,
+  None,
 }
 
 unsafe impl Send for DeviceManager {}
@@ -55,24 +127,59 @@ unsafe impl Send for DeviceManager {}
 unsafe impl Sync for DeviceManager {}
 
 impl DeviceManager {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     output_sender: broadcast::Sender<ButtplugServerMessage>,
     ping_timer: Arc<PingTimer>,
     allow_raw_messages: bool,
+    reconnect_policy: Option<DeviceReconnectPolicy>,
+    device_inactivity_timeout: Option<Duration>,
+    battery_monitor_policy: Option<BatteryMonitorPolicy>,
+    device_registry: DeviceRegistry,
+    connection_policy: DeviceConnectionPolicy,
+    device_recording_directory: Option<PathBuf>,
   ) -> Self {
     let config = Arc::new(DeviceConfigurationManager::new(allow_raw_messages));
     let devices = Arc::new(DashMap::new());
+    let comm_managers = Arc::new(DashMap::new());
+    let device_comm_managers = Arc::new(DashMap::new());
     let (device_event_sender, device_event_receiver) = mpsc::channel(256);
-    let device_allow_list = Arc::new(DashSet::new());
-    let device_deny_list = Arc::new(DashSet::new());
+    let device_filter = Arc::new(DeviceFilter::default());
+    let raw_subscriptions = Arc::new(DashSet::new());
+    let sensor_subscriptions = Arc::new(DashSet::new());
+    let device_watchdogs = Arc::new(DashMap::new());
+    let device_battery_monitors = Arc::new(DashMap::new());
+    let reconnecting_devices = Arc::new(DashMap::new());
+    let removed_device_addresses = Arc::new(DashSet::new());
+    let device_registry = Arc::new(device_registry);
+    let comm_manager_metrics = Arc::new(DashMap::new());
+    let (scanning_command_sender, scanning_command_receiver) = mpsc::channel(256);
+    let shutdown_token = CancellationToken::new();
     let mut event_loop = DeviceManagerEventLoop::new(
       config.clone(),
-      output_sender,
+      output_sender.clone(),
       devices.clone(),
-      device_allow_list.clone(),
-      device_deny_list.clone(),
+      device_filter.clone(),
+      raw_subscriptions.clone(),
+      sensor_subscriptions.clone(),
       ping_timer,
       device_event_receiver,
+      comm_managers.clone(),
+      device_comm_managers.clone(),
+      reconnect_policy,
+      reconnecting_devices.clone(),
+      removed_device_addresses.clone(),
+      device_watchdogs.clone(),
+      device_inactivity_timeout,
+      device_battery_monitors.clone(),
+      battery_monitor_policy,
+      device_registry.clone(),
+      comm_manager_metrics.clone(),
+      scanning_command_sender.clone(),
+      scanning_command_receiver,
+      connection_policy,
+      device_recording_directory,
+      shutdown_token.child_token(),
     );
     async_manager::spawn(async move {
       event_loop.run().await;
@@ -81,87 +188,59 @@ impl DeviceManager {
     Self {
       device_event_sender,
       devices,
-      device_allow_list,
-      device_deny_list,
-      comm_managers: Arc::new(DashMap::new()),
+      device_filter,
+      raw_subscriptions,
+      sensor_subscriptions,
+      comm_managers,
+      comm_manager_tokens: DashMap::new(),
+      device_comm_managers,
+      device_watchdogs,
+      device_battery_monitors,
+      reconnecting_devices,
+      removed_device_addresses,
+      device_registry,
+      comm_manager_metrics,
+      scanning_command_sender,
+      server_sender: output_sender,
+      shutdown_token,
       config,
+      dispatch: dispatcher::get_default(Dispatch::clone),
     }
   }
 
-  fn start_scanning(&self) -> ButtplugServerResultFuture {
+  fn start_scanning(&self, msg: messages::StartScanning) -> ButtplugServerResultFuture {
     if self.comm_managers.is_empty() {
-      ButtplugUnknownError::NoDeviceCommManagers.into()
-    } else {
-      let mgrs = self.comm_managers.clone();
-      let sender = self.device_event_sender.clone();
-      Box::pin(async move {
-        for mgr in mgrs.iter() {
-          if mgr.value().scanning_status().load(Ordering::SeqCst) {
-            return Err(ButtplugDeviceError::DeviceScanningAlreadyStarted.into());
-          }
-        }
-        let fut_vec: Vec<_> = mgrs
-          .iter()
-          .map(|guard| guard.value().start_scanning())
-          .collect();
-        // TODO If start_scanning fails anywhere, this will ignore it. We should maybe at least log?
-        future::join_all(fut_vec).await;
-        debug!("All managers started, sending ScanningStarted (and invoking ScanningFinished hack) signal to event loop.");
-        // HACK: In case everything somehow exited between the time all of our
-        // futures resolved and when we updated the event loop, act like we're a
-        // device comm manager and send a ScanningFinished message. This will
-        // cause the finish check to run just in case, so we don't get stuck.
-        //
-        // Ideally, this should be some sort of state machine, but for now, we
-        // can deal with this.
-        //
-        // At this point, it doesn't really matter what we return, only way that
-        // event loop could shut down is if the whole system is shutting down.
-        // So complain if our sends error out, but don't worry about returning
-        // an error.
-        if sender
-          .send(DeviceCommunicationEvent::ScanningStarted)
-          .await
-          .is_err()
-          || sender
-            .send(DeviceCommunicationEvent::ScanningFinished)
-            .await
-            .is_err()
-        {
-          debug!("Device manager event loop shut down, cannot send ScanningStarted");
-        }
-        Ok(messages::Ok::default().into())
-      })
+      return ButtplugUnknownError::NoDeviceCommManagers.into();
     }
+    let command_sender = self.scanning_command_sender.clone();
+    Box::pin(async move {
+      let (result_sender, result_receiver) = oneshot::channel();
+      send_scanning_command(
+        command_sender,
+        ScanningCommand::Start {
+          options: msg,
+          result_sender,
+        },
+        result_receiver,
+      )
+      .await
+    })
   }
 
   fn stop_scanning(&self) -> ButtplugServerResultFuture {
     if self.comm_managers.is_empty() {
-      ButtplugUnknownError::NoDeviceCommManagers.into()
-    } else {
-      let mgrs = self.comm_managers.clone();
-      Box::pin(async move {
-        let mut scanning_stopped = true;
-        for mgr in mgrs.iter() {
-          if mgr.value().scanning_status().load(Ordering::SeqCst) {
-            debug!("Device manager {} has not stopped scanning yet.", mgr.key());
-            scanning_stopped = false;
-            break;
-          }
-        }
-        if scanning_stopped {
-          return Err(ButtplugDeviceError::DeviceScanningAlreadyStopped.into());
-        }
-
-        let fut_vec: Vec<_> = mgrs
-          .iter()
-          .map(|guard| guard.value().stop_scanning())
-          .collect();
-        // TODO If stop_scanning fails anywhere, this will ignore it. We should maybe at least log?
-        future::join_all(fut_vec).await;
-        Ok(messages::Ok::default().into())
-      })
+      return ButtplugUnknownError::NoDeviceCommManagers.into();
     }
+    let command_sender = self.scanning_command_sender.clone();
+    Box::pin(async move {
+      let (result_sender, result_receiver) = oneshot::channel();
+      send_scanning_command(
+        command_sender,
+        ScanningCommand::Stop { result_sender },
+        result_receiver,
+      )
+      .await
+    })
   }
 
   fn stop_all_devices(&self) -> ButtplugServerResultFuture {
@@ -186,9 +265,71 @@ impl DeviceManager {
   ) -> ButtplugServerResultFuture {
     match self.devices.get(&device_msg.device_index()) {
       Some(device) => {
+        if let ButtplugDeviceCommandMessageUnion::BatteryLevelCmd(msg) = &device_msg {
+          if let Some(monitor) = self.device_battery_monitors.get(&msg.device_index()) {
+            if let Some(battery_level) = monitor.cached_level() {
+              return Box::pin(future::ready(Ok(
+                BatteryLevelReading::new(msg.device_index(), battery_level).into(),
+              )));
+            }
+          }
+        }
+        let device_index = device_msg.device_index();
+        // Work out what bookkeeping this command needs before handing it off
+        // to the device, so we don't have to keep a copy of the message.
+        let accepted_update = match &device_msg {
+          ButtplugDeviceCommandMessageUnion::RawSubscribeCmd(msg) => {
+            AcceptedCommandUpdate::RawSubscribe(msg.endpoint())
+          }
+          ButtplugDeviceCommandMessageUnion::RawUnsubscribeCmd(msg) => {
+            AcceptedCommandUpdate::RawUnsubscribe(msg.endpoint())
+          }
+          ButtplugDeviceCommandMessageUnion::SensorSubscribeCmd(msg) => {
+            AcceptedCommandUpdate::SensorSubscribe(msg.sensor_index())
+          }
+          ButtplugDeviceCommandMessageUnion::SensorUnsubscribeCmd(msg) => {
+            AcceptedCommandUpdate::SensorUnsubscribe(msg.sensor_index())
+          }
+          ButtplugDeviceCommandMessageUnion::BatteryLevelCmd(_) => AcceptedCommandUpdate::BatteryLevel,
+          _ => AcceptedCommandUpdate::None,
+        };
         let fut = device.parse_message(device_msg);
+        if let Some(watchdog) = self.device_watchdogs.get(&device_index) {
+          watchdog.update();
+        }
+        let raw_subscriptions = self.raw_subscriptions.clone();
+        let sensor_subscriptions = self.sensor_subscriptions.clone();
+        let device_battery_monitors = self.device_battery_monitors.clone();
         // Create a future to run the message through the device, then handle adding the id to the result.
-        Box::pin(async move { fut.await })
+        Box::pin(async move {
+          let result = fut.await?;
+          // Only update subscription tracking once the device has actually
+          // accepted the (un)subscribe, so we never forward notifications for
+          // an endpoint the hardware refused.
+          match accepted_update {
+            AcceptedCommandUpdate::RawSubscribe(endpoint) => {
+              raw_subscriptions.insert((device_index, endpoint));
+            }
+            AcceptedCommandUpdate::RawUnsubscribe(endpoint) => {
+              raw_subscriptions.remove(&(device_index, endpoint));
+            }
+            AcceptedCommandUpdate::SensorSubscribe(sensor_index) => {
+              sensor_subscriptions.insert((device_index, sensor_index));
+            }
+            AcceptedCommandUpdate::SensorUnsubscribe(sensor_index) => {
+              sensor_subscriptions.remove(&(device_index, sensor_index));
+            }
+            AcceptedCommandUpdate::BatteryLevel => {
+              if let (Some(monitor), ButtplugServerMessage::BatteryLevelReading(reading)) =
+                (device_battery_monitors.get(&device_index), &result)
+              {
+                monitor.record(reading.battery_level());
+              }
+            }
+            AcceptedCommandUpdate::None => {}
+          }
+          Ok(result)
+        })
       }
       None => ButtplugDeviceError::DeviceNotAvailable(device_msg.device_index()).into(),
     }
@@ -205,7 +346,10 @@ impl DeviceManager {
           .iter()
           .map(|device| {
             let dev = device.value();
-            DeviceMessageInfo::new(*device.key(), &dev.name(), dev.message_attributes())
+            let mut info =
+              DeviceMessageInfo::new(*device.key(), &dev.name(), dev.message_attributes());
+            info.device_display_name = self.device_registry.display_name(dev.address());
+            info
           })
           .collect();
         let mut device_list = DeviceList::new(devices);
@@ -213,7 +357,7 @@ impl DeviceManager {
         Box::pin(future::ready(Ok(device_list.into())))
       }
       ButtplugDeviceManagerMessageUnion::StopAllDevices(_) => self.stop_all_devices(),
-      ButtplugDeviceManagerMessageUnion::StartScanning(_) => self.start_scanning(),
+      ButtplugDeviceManagerMessageUnion::StartScanning(msg) => self.start_scanning(msg),
       ButtplugDeviceManagerMessageUnion::StopScanning(_) => self.stop_scanning(),
     }
   }
@@ -230,32 +374,196 @@ impl DeviceManager {
     }
   }
 
+  pub(crate) fn has_device(&self, device_index: u32) -> bool {
+    self.devices.contains_key(&device_index)
+  }
+
+  /// Stops forwarding all device notifications and sensor readings to the
+  /// client.
+  ///
+  /// This only clears our subscription tracking, it does not unsubscribe the
+  /// hardware endpoints, as protocols (i.e. Lovense) may be using the same
+  /// endpoints for their own communication.
+  pub(crate) fn clear_subscriptions(&self) {
+    self.raw_subscriptions.clear();
+    self.sensor_subscriptions.clear();
+  }
+
   pub fn add_comm_manager<T>(&self, builder: T) -> Result<(), ButtplugServerError>
   where
     T: DeviceCommunicationManagerBuilder,
   {
-    let mgr = builder
-      .event_sender(self.device_event_sender.clone())
-      .finish();
+    let _dispatch_guard = dispatcher::set_default(&self.dispatch);
+    // Each comm manager gets its own channel, so we can tell which manager
+    // found which device before passing events on to the event loop.
+    let (mgr_sender, mut mgr_receiver) = mpsc::channel(256);
+    let mgr = builder.event_sender(mgr_sender).finish();
     if self.comm_managers.contains_key(mgr.name()) {
       return Err(ButtplugServerError::DeviceManagerTypeAlreadyAdded(
         mgr.name().to_owned(),
       ));
     }
+    let name = mgr.name();
     let status = mgr.scanning_status();
     let sender = self.device_event_sender.clone();
-    // TODO This could run out of order and possibly cause weird scanning finished bugs?
+    let device_comm_managers = self.device_comm_managers.clone();
+    let metrics = Arc::new(CommManagerMetrics::default());
+    self
+      .comm_manager_metrics
+      .insert(name.to_owned(), metrics.clone());
+    let token = CancellationToken::new();
+    self
+      .comm_manager_tokens
+      .insert(name.to_owned(), token.clone());
     async_manager::spawn(async move {
-      sender
+      if sender
         .send(DeviceCommunicationEvent::DeviceManagerAdded(status))
         .await
-        .unwrap();
+        .is_err()
+      {
+        return;
+      }
+      loop {
+        let event = select! {
+          _ = token.cancelled().fuse() => break,
+          event = mgr_receiver.recv().fuse() => match event {
+            Some(event) => event,
+            None => break,
+          },
+        };
+        if let DeviceCommunicationEvent::DeviceFound { address, .. } = &event {
+          device_comm_managers.insert(address.clone(), name.to_owned());
+          metrics.record_device_found();
+        }
+        if sender.send(event).await.is_err() {
+          debug!("Device manager event loop shut down, stopping {} event forwarding.", name);
+          break;
+        }
+      }
     })
     .unwrap();
     self.comm_managers.insert(mgr.name().to_owned(), mgr);
     Ok(())
   }
 
+  /// Shuts down a comm manager, removing every device it found. Once this
+  /// resolves, a comm manager of the same type can be added again.
+  pub fn remove_comm_manager(
+    &self,
+    name: &str,
+  ) -> BoxFuture<'static, Result<(), ButtplugServerError>> {
+    let mgr = match self.comm_managers.remove(name) {
+      Some((_, mgr)) => mgr,
+      None => {
+        return future::ready(Err(ButtplugServerError::DeviceManagerTypeDoesNotExist(
+          name.to_owned(),
+        )))
+        .boxed()
+      }
+    };
+    info!("Removing comm manager {}.", name);
+    self.comm_manager_metrics.remove(name);
+    let token = self
+      .comm_manager_tokens
+      .remove(name)
+      .map(|(_, token)| token);
+    // Forget which devices came from this manager first, so the event loop
+    // doesn't try to reconnect them once they disconnect.
+    let mut addresses = vec![];
+    self.device_comm_managers.retain(|address, mgr_name| {
+      if mgr_name == name {
+        addresses.push(address.clone());
+        false
+      } else {
+        true
+      }
+    });
+    let mut disconnect_futs = vec![];
+    for address in &addresses {
+      let device_index = match self.device_registry.index(address) {
+        Some(index) => index,
+        None => continue,
+      };
+      if let Some((_, device)) = self.devices.remove(&device_index) {
+        self.device_watchdogs.remove(&device_index);
+        self.device_battery_monitors.remove(&device_index);
+        self.removed_device_addresses.insert(address.clone());
+        disconnect_futs.push(device.disconnect());
+      } else if let Some((_, reconnecting)) = self.reconnecting_devices.remove(address) {
+        reconnecting.token.cancel();
+      } else {
+        continue;
+      }
+      self
+        .raw_subscriptions
+        .retain(|(index, _)| *index != device_index);
+      self
+        .sensor_subscriptions
+        .retain(|(index, _)| *index != device_index);
+      if self
+        .server_sender
+        .send(DeviceRemoved::new(device_index).into())
+        .is_err()
+      {
+        debug!("Server not currently available, dropping Device Removed event.");
+      }
+    }
+    let sender = self.device_event_sender.clone();
+    Box::pin(async move {
+      if mgr.scanning_status().load(Ordering::SeqCst) {
+        if let Err(err) = mgr.stop_scanning().await {
+          error!("Error stopping scanning on removed comm manager: {}", err);
+        }
+      }
+      for result in future::join_all(disconnect_futs).await {
+        if let Err(err) = result {
+          error!(
+            "Error disconnecting device from removed comm manager: {}",
+            err
+          );
+        }
+      }
+      if let Some(token) = token {
+        token.cancel();
+      }
+      // The manager may have been part of a scan in progress. It won't be
+      // sending ScanningFinished anymore, so have the event loop check
+      // whether the scan is done without it.
+      if sender
+        .send(DeviceCommunicationEvent::ScanningFinished)
+        .await
+        .is_err()
+      {
+        debug!("Device manager event loop shut down, cannot send ScanningFinished.");
+      }
+      Ok(())
+    })
+  }
+
+  /// Lists registered comm managers.
+  pub fn comm_managers(&self) -> Vec<CommManagerInfo> {
+    let mut comm_managers: Vec<CommManagerInfo> = self
+      .comm_managers
+      .iter()
+      .map(|mgr| CommManagerInfo {
+        name: mgr.key().clone(),
+        scanning: mgr.scanning_status().load(Ordering::SeqCst),
+        device_count: self
+          .devices
+          .iter()
+          .filter(|device| {
+            self
+              .device_comm_managers
+              .get(device.address())
+              .map_or(false, |name| name.value() == mgr.key())
+          })
+          .count(),
+      })
+      .collect();
+    comm_managers.sort_by(|a, b| a.name.cmp(&b.name));
+    comm_managers
+  }
+
   pub fn add_protocol<T>(&self, protocol_name: &str) -> Result<(), ButtplugServerError>
   where
     T: ButtplugProtocol,
@@ -293,29 +601,131 @@ impl DeviceManager {
     self.config.remove_protocol_definition(name);    
   }
 
+  /// Sets the name shown to clients for a device, alongside the device's
+  /// actual name. The device doesn't need to be connected, but needs to have
+  /// been seen by the server at some point.
+  pub fn set_device_display_name(
+    &self,
+    device_index: u32,
+    display_name: Option<&str>,
+  ) -> Result<(), ButtplugError> {
+    if self.device_registry.update(device_index, |info| {
+      info.display_name = display_name.map(|name| name.to_owned())
+    }) {
+      Ok(())
+    } else {
+      Err(ButtplugDeviceError::DeviceNotAvailable(device_index).into())
+    }
+  }
+
+  pub fn set_device_user_notes(
+    &self,
+    device_index: u32,
+    user_notes: Option<&str>,
+  ) -> Result<(), ButtplugError> {
+    if self.device_registry.update(device_index, |info| {
+      info.user_notes = user_notes.map(|notes| notes.to_owned())
+    }) {
+      Ok(())
+    } else {
+      Err(ButtplugDeviceError::DeviceNotAvailable(device_index).into())
+    }
+  }
+
+  /// Everything stored about devices the server has seen, keyed by address.
+  pub fn stored_devices(&self) -> StoredDeviceMap {
+    self.device_registry.devices()
+  }
+
+  /// Snapshot of metrics for connected devices and comm managers.
+  pub fn metrics(&self) -> DeviceManagerMetrics {
+    DeviceManagerMetrics {
+      devices: self
+        .devices
+        .iter()
+        .map(|device| {
+          (
+            *device.key(),
+            ConnectedDeviceMetrics {
+              name: device.name().to_owned(),
+              address: device.address().to_owned(),
+              metrics: device.metrics(),
+            },
+          )
+        })
+        .collect(),
+      comm_managers: self
+        .comm_manager_metrics
+        .iter()
+        .map(|metrics| (metrics.key().clone(), metrics.snapshot()))
+        .collect(),
+    }
+  }
+
+  /// Adds a rule for which devices can be connected. Only affects devices
+  /// found after the rule is added.
+  pub fn add_device_filter_rule(&self, rule: DeviceFilterRule) {
+    info!("Adding device filter {}.", rule);
+    self.device_filter.add_rule(rule);
+  }
+
+  /// Returns false if the rule didn't exist.
+  pub fn remove_device_filter_rule(&self, rule: &DeviceFilterRule) -> bool {
+    info!("Removing device filter {}.", rule);
+    self.device_filter.remove_rule(rule)
+  }
+
+  pub fn device_filter_rules(&self) -> Vec<DeviceFilterRule> {
+    self.device_filter.rules()
+  }
+
+  pub fn clear_device_filter_rules(&self) {
+    info!("Clearing device filters.");
+    self.device_filter.clear();
+  }
+
   pub fn add_allowed_device(&self, address: &str) {
-    info!("Adding device address {} to allowed devices list.", address);
-    self.device_allow_list.insert(address.to_owned());
+    self.add_device_filter_rule(address_rule(DeviceFilterAction::Allow, address));
   }
 
   pub fn add_denied_device(&self, address: &str) {
-    info!("Adding device address {} to denied devices list.", address);
-    self.device_deny_list.insert(address.to_owned());
+    self.add_device_filter_rule(address_rule(DeviceFilterAction::Deny, address));
   }
 
   pub fn remove_allowed_device(&self, address: &str) {
-    info!("Removing device address {} from allowed devices list.", address);
-    self.device_allow_list.remove(address);
+    self.remove_device_filter_rule(&address_rule(DeviceFilterAction::Allow, address));
   }
 
   pub fn remove_denied_device(&self, address: &str) {
-    info!("Removing device address {} from denied devices list.", address);
-    self.device_deny_list.remove(address);
+    self.remove_device_filter_rule(&address_rule(DeviceFilterAction::Deny, address));
   }
 }
 
+/// Hands a scanning command to the event loop, which owns the scanning state,
+/// and waits for it to be carried out.
+async fn send_scanning_command(
+  command_sender: mpsc::Sender<ScanningCommand>,
+  command: ScanningCommand,
+  result_receiver: oneshot::Receiver<Result<(), ButtplugError>>,
+) -> Result<ButtplugServerMessage, ButtplugError> {
+  if command_sender.send(command).await.is_err() {
+    return Err(ButtplugUnknownError::DeviceManagerNotRunning.into());
+  }
+  match result_receiver.await {
+    Ok(result) => result.map(|_| messages::Ok::default().into()),
+    Err(_) => Err(ButtplugUnknownError::DeviceManagerNotRunning.into()),
+  }
+}
+
+fn address_rule(action: DeviceFilterAction, address: &str) -> DeviceFilterRule {
+  let mut rule = DeviceFilterRule::new(action);
+  rule.address(address);
+  rule
+}
+
 impl Drop for DeviceManager {
   fn drop(&mut self) {
     info!("Dropping device manager!");
+    self.shutdown_token.cancel();
   }
 }
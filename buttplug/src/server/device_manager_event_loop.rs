@@ -1,23 +1,45 @@
-use super::{comm_managers::DeviceCommunicationEvent, ping_timer::PingTimer};
+use super::{
+  comm_managers::{DeviceCommunicationEvent, DeviceCommunicationManager},
+  device_battery_monitor::{BatteryMonitorPolicy, DeviceBatteryMonitor},
+  device_connection::DeviceConnectionPolicy,
+  device_manager_metrics::CommManagerMetrics,
+  device_reconnect::{DeviceReconnectPolicy, DeviceReconnectTask, ReconnectingDevice},
+  device_scanning::{
+    ScanningCommand, ScanningResultSender, ScanningState, CONTINUOUS_SCAN_RESTART_DELAY,
+  },
+  device_store::DeviceRegistry,
+  device_watchdog::DeviceWatchdog,
+  ping_timer::PingTimer,
+};
 use crate::{
-  core::messages::{
-    ButtplugServerMessage, DeviceAdded, DeviceRemoved, ScanningFinished, StopDeviceCmd,
+  core::{
+    errors::{ButtplugDeviceError, ButtplugUnknownError},
+    messages::{
+      ButtplugMessage, ButtplugServerMessage, DeviceAdded, DeviceConnectionFailed,
+      DeviceReconnected, DeviceReconnecting, DeviceRemoved, RawReading, ScanningFinished,
+      ScanningFinishedReason, SensorReading, StartScanning, StopDeviceCmd,
+      BUTTPLUG_SERVER_EVENT_ID,
+    },
   },
   device::{
-    configuration_manager::DeviceConfigurationManager, ButtplugDevice, ButtplugDeviceEvent,
-    ButtplugDeviceImplCreator,
+    configuration_manager::DeviceConfigurationManager,
+    device_filter::{DeviceBusType, DeviceFilter, DeviceFilterInfo, DeviceFilterResult},
+    device_recording::RecordingDeviceImplCreator,
+    ButtplugDevice, ButtplugDeviceEvent, ButtplugDeviceImplCreator, Endpoint,
   },
   util::async_manager,
 };
 use dashmap::{DashMap, DashSet};
-use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures::{future, stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_timer::Delay;
 use std::{
-  sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-  },
+  collections::HashMap,
+  path::PathBuf,
+  sync::{atomic::Ordering, Arc},
+  time::{Duration, Instant},
 };
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, Semaphore};
+use tokio_util::sync::CancellationToken;
 use tracing;
 use tracing_futures::Instrument;
 
@@ -25,11 +47,16 @@ pub struct DeviceManagerEventLoop {
   device_config_manager: Arc<DeviceConfigurationManager>,
   device_index_generator: u32,
   device_map: Arc<DashMap<u32, Arc<ButtplugDevice>>>,
-  device_allow_list: Arc<DashSet<String>>,
-  device_deny_list: Arc<DashSet<String>>,
+  device_filter: Arc<DeviceFilter>,
+  /// (Device index, endpoint) pairs that the client has subscribed to via
+  /// RawSubscribeCmd. Notifications are only forwarded for these.
+  raw_subscriptions: Arc<DashSet<(u32, Endpoint)>>,
+  /// (Device index, sensor index) pairs that the client has subscribed to via
+  /// SensorSubscribeCmd. SensorReading events are only sent for these.
+  sensor_subscriptions: Arc<DashSet<(u32, u32)>>,
   ping_timer: Arc<PingTimer>,
   /// Maps device addresses to indexes, so they can be reused on reconnect.
-  device_index_map: Arc<DashMap<String, u32>>,
+  device_registry: Arc<DeviceRegistry>,
   /// Broadcaster that relays device events in the form of Buttplug Messages to
   /// whoever owns the Buttplug Server.
   server_sender: broadcast::Sender<ButtplugServerMessage>,
@@ -40,59 +67,173 @@ pub struct DeviceManagerEventLoop {
   device_event_sender: mpsc::Sender<ButtplugDeviceEvent>,
   /// Receiver for device events, which the event loops to handle events.
   device_event_receiver: mpsc::Receiver<ButtplugDeviceEvent>,
-  /// True if StartScanning has been called but no ScanningFinished has been
-  /// emitted yet.
-  scanning_in_progress: bool,
-  /// Holds the status of comm manager scanning states (scanning/not scanning).
-  comm_manager_scanning_statuses: Vec<Arc<AtomicBool>>,
+  scanning_state: ScanningState,
+  /// Receives scanning commands from the device manager, as well as the ones
+  /// we send ourselves when comm managers finish starting or stopping.
+  scanning_command_receiver: mpsc::Receiver<ScanningCommand>,
+  scanning_command_sender: mpsc::Sender<ScanningCommand>,
+  /// Number of reconnection scans running on each comm manager. These don't
+  /// show up in the scanning state, which is only for the client's session.
+  reconnect_scans: HashMap<String, usize>,
+  comm_managers: Arc<DashMap<String, Box<dyn DeviceCommunicationManager>>>,
+  /// Maps device addresses to the comm manager that found them.
+  device_comm_managers: Arc<DashMap<String, String>>,
+  /// If None, devices are removed as soon as they disconnect.
+  reconnect_policy: Option<DeviceReconnectPolicy>,
+  /// Devices that have disconnected and that we're trying to reconnect to,
+  /// keyed by address.
+  reconnecting_devices: Arc<DashMap<String, ReconnectingDevice>>,
+  /// Devices removed along with their comm manager. The device manager has
+  /// already sent DeviceRemoved for them, and a device with the same address
+  /// may have connected through a new comm manager since, so their disconnect
+  /// events are ignored.
+  removed_device_addresses: Arc<DashSet<String>>,
+  device_watchdogs: Arc<DashMap<u32, DeviceWatchdog>>,
+  /// If None, devices are never stopped for inactivity.
+  device_inactivity_timeout: Option<Duration>,
+  device_battery_monitors: Arc<DashMap<u32, DeviceBatteryMonitor>>,
+  /// If None, device batteries aren't monitored.
+  battery_monitor_policy: Option<BatteryMonitorPolicy>,
+  comm_manager_metrics: Arc<DashMap<String, Arc<CommManagerMetrics>>>,
+  connection_policy: DeviceConnectionPolicy,
+  /// Limits how many devices can be connecting at once.
+  connection_semaphore: Arc<Semaphore>,
+  /// Cancels device connections in progress. Replaced whenever scanning is
+  /// stopped, and cancelled along with its parent when the device manager
+  /// shuts down.
+  connection_token: CancellationToken,
+  shutdown_token: CancellationToken,
+  /// If set, new devices are recorded to files in this directory.
+  device_recording_directory: Option<PathBuf>,
 }
 
 impl DeviceManagerEventLoop {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     device_config_manager: Arc<DeviceConfigurationManager>,
     server_sender: broadcast::Sender<ButtplugServerMessage>,
     device_map: Arc<DashMap<u32, Arc<ButtplugDevice>>>,
-    device_allow_list: Arc<DashSet<String>>,
-    device_deny_list: Arc<DashSet<String>>,
+    device_filter: Arc<DeviceFilter>,
+    raw_subscriptions: Arc<DashSet<(u32, Endpoint)>>,
+    sensor_subscriptions: Arc<DashSet<(u32, u32)>>,
     ping_timer: Arc<PingTimer>,
     device_comm_receiver: mpsc::Receiver<DeviceCommunicationEvent>,
+    comm_managers: Arc<DashMap<String, Box<dyn DeviceCommunicationManager>>>,
+    device_comm_managers: Arc<DashMap<String, String>>,
+    reconnect_policy: Option<DeviceReconnectPolicy>,
+    reconnecting_devices: Arc<DashMap<String, ReconnectingDevice>>,
+    removed_device_addresses: Arc<DashSet<String>>,
+    device_watchdogs: Arc<DashMap<u32, DeviceWatchdog>>,
+    device_inactivity_timeout: Option<Duration>,
+    device_battery_monitors: Arc<DashMap<u32, DeviceBatteryMonitor>>,
+    battery_monitor_policy: Option<BatteryMonitorPolicy>,
+    device_registry: Arc<DeviceRegistry>,
+    comm_manager_metrics: Arc<DashMap<String, Arc<CommManagerMetrics>>>,
+    scanning_command_sender: mpsc::Sender<ScanningCommand>,
+    scanning_command_receiver: mpsc::Receiver<ScanningCommand>,
+    connection_policy: DeviceConnectionPolicy,
+    device_recording_directory: Option<PathBuf>,
+    shutdown_token: CancellationToken,
   ) -> Self {
     let (device_event_sender, device_event_receiver) = mpsc::channel(256);
     Self {
       device_config_manager,
       server_sender,
       device_map,
-      device_allow_list,
-      device_deny_list,
+      device_filter,
+      raw_subscriptions,
+      sensor_subscriptions,
       ping_timer,
       device_comm_receiver,
-      device_index_generator: 0,
-      device_index_map: Arc::new(DashMap::new()),
+      device_index_generator: device_registry.next_index(),
+      device_registry,
       device_event_sender,
       device_event_receiver,
-      scanning_in_progress: false,
-      comm_manager_scanning_statuses: vec![],
+      scanning_state: ScanningState::Idle,
+      scanning_command_receiver,
+      scanning_command_sender,
+      reconnect_scans: HashMap::new(),
+      comm_managers,
+      device_comm_managers,
+      reconnect_policy,
+      reconnecting_devices,
+      removed_device_addresses,
+      device_watchdogs,
+      device_inactivity_timeout,
+      device_battery_monitors,
+      battery_monitor_policy,
+      comm_manager_metrics,
+      connection_semaphore: Arc::new(Semaphore::new(
+        connection_policy.max_concurrent_connections.max(1),
+      )),
+      connection_policy,
+      connection_token: shutdown_token.child_token(),
+      shutdown_token,
+      device_recording_directory,
     }
   }
 
-  fn try_create_new_device(&mut self, device_creator: Box<dyn ButtplugDeviceImplCreator>) {
+  fn try_create_new_device(
+    &mut self,
+    name: String,
+    address: String,
+    device_creator: Box<dyn ButtplugDeviceImplCreator>,
+  ) {
     let device_event_sender_clone = self.device_event_sender.clone();
-    let create_device_future =
-      ButtplugDevice::try_create_device(self.device_config_manager.clone(), device_creator);
+    let metrics = self
+      .device_comm_managers
+      .get(&address)
+      .and_then(|mgr_name| self.comm_manager_metrics.get(mgr_name.value()))
+      .map(|metrics| metrics.value().clone());
+    let server_sender = self.server_sender.clone();
+    let semaphore = self.connection_semaphore.clone();
+    let token = self.connection_token.clone();
+    let device_creator = match &self.device_recording_directory {
+      None => device_creator,
+      Some(directory) => Box::new(RecordingDeviceImplCreator::new(device_creator, directory)),
+    };
+    let create_device_future = ButtplugDevice::try_create_device_with_timeouts(
+      self.device_config_manager.clone(),
+      device_creator,
+      self.connection_policy.timeouts(),
+    );
     async_manager::spawn(async move {
-      match create_device_future.await {
-        Ok(option_dev) => match option_dev {
-          Some(device) => {
-            if device_event_sender_clone
-              .send(ButtplugDeviceEvent::Connected(Arc::new(device)))
-              .await
-              .is_err() {
-              error!("Device manager disappeared before connection established, device will be dropped.");
-            }
+      let connect = async move {
+        // The semaphore is never closed, so this can't fail.
+        let _permit = semaphore.acquire_owned().await.unwrap();
+        create_device_future.await
+      };
+      let result = select! {
+        result = connect.fuse() => result,
+        _ = token.cancelled().fuse() => {
+          info!("Connection to device {} ({}) cancelled.", name, address);
+          return;
+        }
+      };
+      match result {
+        Ok(Some(device)) => {
+          if device_event_sender_clone
+            .send(ButtplugDeviceEvent::Connected(Arc::new(device)))
+            .await
+            .is_err() {
+            error!("Device manager disappeared before connection established, device will be dropped.");
           }
-          None => debug!("Device could not be matched to a protocol."),
-        },
-        Err(e) => error!("Device errored while trying to connect: {}", e),
+        }
+        Ok(None) => {
+          debug!("Device could not be matched to a protocol.");
+          if let Some(metrics) = metrics {
+            metrics.record_unmatched_device(&address, &name);
+          }
+        }
+        Err(err) => {
+          error!(
+            "Device {} ({}) errored while trying to connect during {:?}: {}",
+            name, address, err.stage, err.error
+          );
+          let _ = server_sender.send(
+            DeviceConnectionFailed::new(&name, &address, err.stage, &err.error.to_string()).into(),
+          );
+        }
       }
     }.instrument(tracing::Span::current()))
     .unwrap();
@@ -100,34 +241,16 @@ impl DeviceManagerEventLoop {
 
   async fn handle_device_communication(&mut self, event: DeviceCommunicationEvent) {
     match event {
-      DeviceCommunicationEvent::ScanningStarted => {
-        self.scanning_in_progress = true;
-      }
+      // Scanning sessions are tracked through scanning commands now, and
+      // scanning status is read from the comm managers directly, so these are
+      // only kept around for comm managers that still send them.
+      DeviceCommunicationEvent::ScanningStarted
+      | DeviceCommunicationEvent::DeviceManagerAdded(_) => {}
       DeviceCommunicationEvent::ScanningFinished => {
         debug!(
           "System signaled that scanning was finished, check to see if all managers are finished."
         );
-        if !self.scanning_in_progress {
-          debug!("Manager finished before scanning was fully started, continuing event loop.");
-          return;
-        }
-        if self
-          .comm_manager_scanning_statuses
-          .iter()
-          .any(|x| x.load(Ordering::SeqCst))
-        {
-          debug!("At least one manager still scanning, continuing event loop.");
-          return;
-        }
-        debug!("All managers finished, emitting ScanningFinished");
-        self.scanning_in_progress = false;
-        if self
-          .server_sender
-          .send(ScanningFinished::default().into())
-          .is_err()
-        {
-          info!("Server disappeared, exiting loop.");
-        }
+        self.check_scanning_finished();
       }
       DeviceCommunicationEvent::DeviceFound {
         name,
@@ -136,28 +259,35 @@ impl DeviceManagerEventLoop {
       } => {
         let span = info_span!(
           "device creation",
-          name = tracing::field::display(name),
+          name = tracing::field::display(name.clone()),
           address = tracing::field::display(address.clone())
         );
         let _enter = span.enter();
-        for denied_device in self.device_deny_list.iter() {
-          if *denied_device == address {
-            info!("Denied device address {} found, ignoring.", address);
-            return;
-          }
-        }
-        if !self.device_allow_list.is_empty() {
-          let mut is_allowed = false;
-          for allowed_device in self.device_allow_list.iter() {
-            if *allowed_device == address {
-              info!("Allowed device {} found, allowing connection.", address);
-              is_allowed = true;
-              break;
+        if !self.device_filter.is_empty() {
+          let specifier = creator.get_specifier();
+          let protocol = self
+            .device_config_manager
+            .find_protocol_definitions(&specifier)
+            .map(|(_, protocol, _)| protocol);
+          let info = DeviceFilterInfo {
+            name: &name,
+            address: &address,
+            protocol: protocol.as_deref(),
+            bus: DeviceBusType::from(&specifier),
+          };
+          match self.device_filter.check(&info) {
+            DeviceFilterResult::Allowed => {}
+            DeviceFilterResult::Denied(rule) => {
+              info!("Device {} ({}) matched device filter {}, ignoring.", name, address, rule);
+              return;
+            }
+            DeviceFilterResult::NotAllowed => {
+              info!(
+                "Device {} ({}) did not match any allow device filters, ignoring.",
+                name, address
+              );
+              return;
             }
-          }
-          if !is_allowed {
-            info!("Device address {} found but not in allow list, ignoring.", address);
-            return;
           }
         }
         
@@ -171,14 +301,338 @@ impl DeviceManagerEventLoop {
             return;
           }
         }
-        self.try_create_new_device(creator);
+        self.try_create_new_device(name, address, creator);
       }
-      DeviceCommunicationEvent::DeviceManagerAdded(status) => {
-        self.comm_manager_scanning_statuses.push(status);
-      },
     }
   }
 
+  fn handle_scanning_command(&mut self, command: ScanningCommand) {
+    match command {
+      ScanningCommand::Start {
+        options,
+        result_sender,
+      } => self.start_scanning(options, result_sender),
+      ScanningCommand::Stop { result_sender } => {
+        if matches!(self.scanning_state, ScanningState::Scanning { .. }) {
+          self.stop_scanning(ScanningFinishedReason::Stopped, Some(result_sender));
+        } else {
+          let _ = result_sender.send(Err(
+            ButtplugDeviceError::DeviceScanningAlreadyStopped.into(),
+          ));
+        }
+      }
+      ScanningCommand::CommManagersStarted => {
+        if let ScanningState::Scanning { starting, .. } = &mut self.scanning_state {
+          *starting = false;
+        }
+        self.check_scanning_finished();
+      }
+      ScanningCommand::CommManagersStopped => {
+        if let ScanningState::Stopping { stopping, .. } = &mut self.scanning_state {
+          *stopping = false;
+        }
+        self.check_scanning_finished();
+      }
+      ScanningCommand::StartReconnectScan { comm_manager } => {
+        self.start_reconnect_scan(comm_manager)
+      }
+      ScanningCommand::StopReconnectScan { comm_manager } => self.stop_reconnect_scan(comm_manager),
+    }
+  }
+
+  /// True if the client's scanning session is using a comm manager.
+  fn session_uses(&self, name: &str) -> bool {
+    match &self.scanning_state {
+      ScanningState::Scanning { comm_managers, .. } => comm_managers.iter().any(|mgr| mgr == name),
+      _ => false,
+    }
+  }
+
+  fn start_reconnect_scan(&mut self, name: String) {
+    let scan_fut = match self.comm_managers.get(&name) {
+      Some(mgr) => {
+        let count = self.reconnect_scans.entry(name.clone()).or_insert(0);
+        *count += 1;
+        // Only the first scan needs to start the comm manager, and the
+        // client's session may already have it running.
+        if *count > 1 || self.session_uses(&name) {
+          return;
+        }
+        mgr.start_scanning()
+      }
+      None => {
+        warn!(
+          "Comm manager {} no longer available, cannot scan for device.",
+          name
+        );
+        return;
+      }
+    };
+    async_manager::spawn(async move {
+      if let Err(err) = scan_fut.await {
+        error!("Error starting reconnection scan: {}", err);
+      }
+    })
+    .unwrap();
+  }
+
+  fn stop_reconnect_scan(&mut self, name: String) {
+    match self.reconnect_scans.get_mut(&name) {
+      Some(count) if *count > 1 => {
+        *count -= 1;
+        return;
+      }
+      Some(_) => {
+        self.reconnect_scans.remove(&name);
+      }
+      None => return,
+    }
+    // Leave the comm manager running if the client is scanning with it.
+    if self.session_uses(&name) {
+      return;
+    }
+    let scan_fut = match self.comm_managers.get(&name) {
+      Some(mgr) if mgr.scanning_status().load(Ordering::SeqCst) => mgr.stop_scanning(),
+      _ => return,
+    };
+    async_manager::spawn(async move {
+      if let Err(err) = scan_fut.await {
+        error!("Error stopping reconnection scan: {}", err);
+      }
+    })
+    .unwrap();
+  }
+
+  fn start_scanning(&mut self, options: StartScanning, result_sender: ScanningResultSender) {
+    if !matches!(self.scanning_state, ScanningState::Idle) {
+      let _ = result_sender.send(Err(
+        ButtplugDeviceError::DeviceScanningAlreadyStarted.into(),
+      ));
+      return;
+    }
+    let comm_managers: Vec<String> = match options.comm_managers() {
+      Some(names) => {
+        if let Some(name) = names
+          .iter()
+          .find(|name| !self.comm_managers.contains_key(*name))
+        {
+          let _ = result_sender.send(Err(
+            ButtplugUnknownError::DeviceCommManagerNotFound(name.clone()).into(),
+          ));
+          return;
+        }
+        names.clone()
+      }
+      None => self
+        .comm_managers
+        .iter()
+        .map(|mgr| mgr.key().clone())
+        .collect(),
+    };
+    if comm_managers.is_empty() {
+      let _ = result_sender.send(Err(ButtplugUnknownError::NoDeviceCommManagers.into()));
+      return;
+    }
+    if !self.client_comm_managers_finished(&comm_managers) {
+      let _ = result_sender.send(Err(
+        ButtplugDeviceError::DeviceScanningAlreadyStarted.into(),
+      ));
+      return;
+    }
+    self.scanning_state = ScanningState::Scanning {
+      comm_managers: comm_managers.clone(),
+      continuous: options.continuous(),
+      deadline: options
+        .duration()
+        .map(|duration| Instant::now() + Duration::from_millis(duration as u64)),
+      starting: true,
+      restart_at: None,
+    };
+    self.start_comm_managers(&comm_managers, Some(result_sender));
+  }
+
+  fn start_comm_managers(&self, names: &[String], result_sender: Option<ScanningResultSender>) {
+    let fut_vec: Vec<_> = names
+      .iter()
+      // Comm managers scanning for reconnecting devices are already running.
+      .filter(|name| !self.reconnect_scans.contains_key(*name))
+      .filter_map(|name| {
+        if let Some(metrics) = self.comm_manager_metrics.get(name) {
+          metrics.record_scan();
+        }
+        self.comm_managers.get(name).map(|mgr| mgr.start_scanning())
+      })
+      .collect();
+    let command_sender = self.scanning_command_sender.clone();
+    async_manager::spawn(async move {
+      for result in future::join_all(fut_vec).await {
+        if let Err(err) = result {
+          error!("Error starting scanning: {}", err);
+        }
+      }
+      if let Some(result_sender) = result_sender {
+        let _ = result_sender.send(Ok(()));
+      }
+      if command_sender
+        .send(ScanningCommand::CommManagersStarted)
+        .await
+        .is_err()
+      {
+        debug!("Device manager event loop shut down, cannot send CommManagersStarted.");
+      }
+    })
+    .unwrap();
+  }
+
+  fn stop_scanning(
+    &mut self,
+    reason: ScanningFinishedReason,
+    result_sender: Option<ScanningResultSender>,
+  ) {
+    let comm_managers = match &self.scanning_state {
+      ScanningState::Scanning { comm_managers, .. } => comm_managers.clone(),
+      _ => return,
+    };
+    // Comm managers scanning for reconnecting devices keep going, they get
+    // stopped once those scans are done.
+    let fut_vec: Vec<_> = comm_managers
+      .iter()
+      .filter(|name| !self.reconnect_scans.contains_key(*name))
+      .filter_map(|name| self.comm_managers.get(name).map(|mgr| mgr.stop_scanning()))
+      .collect();
+    if reason == ScanningFinishedReason::Stopped {
+      // Drop any connections still in progress from this scan. Devices that
+      // were already connected stay connected.
+      self.connection_token.cancel();
+      self.connection_token = self.shutdown_token.child_token();
+    }
+    self.scanning_state = ScanningState::Stopping {
+      comm_managers,
+      reason,
+      stopping: true,
+    };
+    let command_sender = self.scanning_command_sender.clone();
+    async_manager::spawn(async move {
+      for result in future::join_all(fut_vec).await {
+        if let Err(err) = result {
+          error!("Error stopping scanning: {}", err);
+        }
+      }
+      if let Some(result_sender) = result_sender {
+        let _ = result_sender.send(Ok(()));
+      }
+      if command_sender
+        .send(ScanningCommand::CommManagersStopped)
+        .await
+        .is_err()
+      {
+        debug!("Device manager event loop shut down, cannot send CommManagersStopped.");
+      }
+    })
+    .unwrap();
+  }
+
+  fn comm_managers_finished(&self, names: &[String]) -> bool {
+    names.iter().all(|name| {
+      self
+        .comm_managers
+        .get(name)
+        .map_or(true, |mgr| !mgr.scanning_status().load(Ordering::SeqCst))
+    })
+  }
+
+  /// Same as [Self::comm_managers_finished], but comm managers that are
+  /// scanning for reconnecting devices count as finished, as the client has
+  /// no say over those scans.
+  fn client_comm_managers_finished(&self, names: &[String]) -> bool {
+    let names: Vec<String> = names
+      .iter()
+      .filter(|name| !self.reconnect_scans.contains_key(*name))
+      .cloned()
+      .collect();
+    self.comm_managers_finished(&names)
+  }
+
+  /// Decides what to do once comm managers may have finished scanning:
+  /// nothing, restart them (continuous scanning), or end the session.
+  fn check_scanning_finished(&mut self) {
+    let finished = match &self.scanning_state {
+      ScanningState::Idle => {
+        debug!("Manager finished outside of a scanning session, continuing event loop.");
+        return;
+      }
+      ScanningState::Scanning { starting: true, .. }
+      | ScanningState::Stopping { stopping: true, .. } => {
+        debug!("Managers still starting or stopping, continuing event loop.");
+        return;
+      }
+      ScanningState::Scanning { comm_managers, .. } => self.comm_managers_finished(comm_managers),
+      // Comm managers scanning for reconnecting devices weren't stopped.
+      ScanningState::Stopping { comm_managers, .. } => {
+        self.client_comm_managers_finished(comm_managers)
+      }
+    };
+    if !finished {
+      debug!("At least one manager still scanning, continuing event loop.");
+      return;
+    }
+    let reason = match &mut self.scanning_state {
+      ScanningState::Scanning {
+        continuous: true,
+        restart_at,
+        ..
+      } => {
+        if restart_at.is_none() {
+          debug!("All managers finished, restarting continuous scan.");
+          *restart_at = Some(Instant::now() + CONTINUOUS_SCAN_RESTART_DELAY);
+        }
+        return;
+      }
+      ScanningState::Scanning {
+        deadline: Some(_), ..
+      } => {
+        debug!("All managers finished, waiting for scan duration to pass.");
+        return;
+      }
+      ScanningState::Stopping { reason, .. } => *reason,
+      _ => ScanningFinishedReason::Completed,
+    };
+    debug!("All managers finished, emitting ScanningFinished");
+    self.scanning_state = ScanningState::Idle;
+    if self
+      .server_sender
+      .send(ScanningFinished::new(reason).into())
+      .is_err()
+    {
+      info!("Server disappeared, exiting loop.");
+    }
+  }
+
+  fn handle_scanning_timer(&mut self) {
+    let now = Instant::now();
+    let comm_managers = match &mut self.scanning_state {
+      ScanningState::Scanning { deadline, .. }
+        if deadline.map_or(false, |deadline| deadline <= now) =>
+      {
+        info!("Scanning duration passed, stopping scanning.");
+        self.stop_scanning(ScanningFinishedReason::Timeout, None);
+        return;
+      }
+      ScanningState::Scanning {
+        comm_managers,
+        starting,
+        restart_at,
+        ..
+      } if restart_at.map_or(false, |restart_at| restart_at <= now) => {
+        *restart_at = None;
+        *starting = true;
+        comm_managers.clone()
+      }
+      _ => return,
+    };
+    self.start_comm_managers(&comm_managers, None);
+  }
+
   async fn handle_device_event(&mut self, device_event: ButtplugDeviceEvent) {
     trace!("Got device event: {:?}", device_event);
     match device_event {
@@ -192,12 +646,12 @@ impl DeviceManagerEventLoop {
         let generated_device_index = self.device_index_generator;
         self.device_index_generator += 1;
         // See if we have a reusable device index here.
-        let device_index = if let Some(id) = self.device_index_map.get(device.address()) {
-          *id.value()
+        let device_index = if let Some(index) = self.device_registry.index(device.address()) {
+          index
         } else {
           self
-            .device_index_map
-            .insert(device.address().to_owned(), generated_device_index);
+            .device_registry
+            .insert(device.address(), generated_device_index);
           generated_device_index
         };
         // Since we can now reuse device indexes, this means we might possibly
@@ -233,9 +687,45 @@ impl DeviceManagerEventLoop {
         })
         .unwrap();
 
+        if let Some((_, reconnecting)) = self.reconnecting_devices.remove(device.address()) {
+          reconnecting.token.cancel();
+          info!("Device {} reconnected, restoring state.", device_index);
+          // Restoring state means waiting on device writes, so do it off the
+          // event loop.
+          let restore_futs: Vec<_> = reconnecting
+            .device
+            .actuator_state()
+            .into_iter()
+            .map(|command| device.parse_message(command))
+            .collect();
+          async_manager::spawn(async move {
+            for fut in restore_futs {
+              if let Err(err) = fut.await {
+                error!("Error restoring device state after reconnect: {:?}", err);
+              }
+            }
+          })
+          .unwrap();
+          self.start_watchdog(device_index, &device);
+          self.start_battery_monitor(device_index, &device);
+          self.device_map.insert(device_index, device);
+          if self
+            .server_sender
+            .send(DeviceReconnected::new(device_index).into())
+            .is_err()
+          {
+            debug!("Server not currently available, dropping Device Reconnected event.");
+          }
+          return;
+        }
+
         info!("Assigning index {} to {}", device_index, device.name());
-        let device_added_message =
+        let mut device_added_message =
           DeviceAdded::new(device_index, &device.name(), &device.message_attributes());
+        device_added_message
+          .set_device_display_name(self.device_registry.display_name(device.address()));
+        self.start_watchdog(device_index, &device);
+        self.start_battery_monitor(device_index, &device);
         self.device_map.insert(device_index, device);
         // After that, we can send out to the server's event listeners to let
         // them know a device has been added.
@@ -248,8 +738,39 @@ impl DeviceManagerEventLoop {
         }
       }
       ButtplugDeviceEvent::Removed(address) => {
-        let device_index = *self.device_index_map.get(&address).unwrap().value();
-        self.device_map.remove(&device_index).unwrap();
+        if self.removed_device_addresses.remove(&address).is_some() {
+          debug!(
+            "Device {} was removed along with its comm manager, ignoring disconnect.",
+            address
+          );
+          return;
+        }
+        let device_index = self.device_registry.index(&address).unwrap();
+        self.device_watchdogs.remove(&device_index);
+        self.device_battery_monitors.remove(&device_index);
+        let device = match self.device_map.remove(&device_index) {
+          Some((_, device)) => device,
+          None => {
+            debug!("Device {} already removed, ignoring disconnect.", device_index);
+            return;
+          }
+        };
+        // Devices only get reconnected through the comm manager that found
+        // them, so if that's gone, so is the device.
+        if self.device_comm_managers.contains_key(&address) {
+          if let Some(policy) = self.reconnect_policy.clone() {
+            self.start_reconnecting(policy, address, device_index, device);
+            return;
+          }
+        }
+        // Subscriptions don't survive a disconnect, so make sure we don't
+        // forward anything if the device comes back under the same index.
+        self
+          .raw_subscriptions
+          .retain(|(index, _)| *index != device_index);
+        self
+          .sensor_subscriptions
+          .retain(|(index, _)| *index != device_index);
         if self
           .server_sender
           .send(DeviceRemoved::new(device_index).into())
@@ -258,13 +779,140 @@ impl DeviceManagerEventLoop {
           debug!("Server not currently available, dropping Device Removed event.");
         }
       }
-      ButtplugDeviceEvent::Notification(_address, _endpoint, _data) => {
-        // TODO At some point here we need to fill this in for RawSubscribe and
-        // other sensor subscriptions.
+      ButtplugDeviceEvent::Notification(address, endpoint, data) => {
+        let device_index = if let Some(index) = self.device_registry.index(&address) {
+          index
+        } else {
+          debug!(
+            "Got notification from unknown device address {}, dropping.",
+            address
+          );
+          return;
+        };
+        self.send_sensor_readings(device_index, endpoint, &data);
+        // Protocols may subscribe to endpoints for their own use (Lovense
+        // reads replies this way), so only forward notifications the client
+        // explicitly asked for.
+        if !self.raw_subscriptions.contains(&(device_index, endpoint)) {
+          trace!(
+            "No subscription for device {} endpoint {}, dropping notification.",
+            device_index,
+            endpoint
+          );
+          return;
+        }
+        let mut reading = RawReading::new(device_index, endpoint, data);
+        reading.set_id(BUTTPLUG_SERVER_EVENT_ID);
+        if self.server_sender.send(reading.into()).is_err() {
+          debug!("Server not currently available, dropping RawReading event.");
+        }
       }
     }
   }
 
+  /// Sends readings for the subscribed sensors that report through the
+  /// endpoint a notification came from.
+  fn send_sensor_readings(&self, device_index: u32, endpoint: Endpoint, data: &[u8]) {
+    let device = match self.device_map.get(&device_index) {
+      Some(device) => device.value().clone(),
+      None => return,
+    };
+    for (sensor_index, sensor_type, values) in device.parse_sensor_data(endpoint, data) {
+      if !self
+        .sensor_subscriptions
+        .contains(&(device_index, sensor_index))
+      {
+        continue;
+      }
+      if self
+        .server_sender
+        .send(SensorReading::new(device_index, sensor_index, sensor_type, values).into())
+        .is_err()
+      {
+        debug!("Server not currently available, dropping SensorReading event.");
+      }
+    }
+  }
+
+  fn start_watchdog(&self, device_index: u32, device: &Arc<ButtplugDevice>) {
+    if let Some(timeout) = self.device_inactivity_timeout {
+      let watchdog = DeviceWatchdog::new(
+        timeout,
+        device_index,
+        device.clone(),
+        self.server_sender.clone(),
+      );
+      // Restored state after a reconnect may have left the device running.
+      watchdog.update();
+      self.device_watchdogs.insert(device_index, watchdog);
+    }
+  }
+
+  fn start_battery_monitor(&self, device_index: u32, device: &Arc<ButtplugDevice>) {
+    if let Some(policy) = &self.battery_monitor_policy {
+      if let Some(monitor) = DeviceBatteryMonitor::new(
+        policy.clone(),
+        device_index,
+        device.clone(),
+        self.server_sender.clone(),
+      ) {
+        self.device_battery_monitors.insert(device_index, monitor);
+      }
+    }
+  }
+
+  fn start_reconnecting(
+    &self,
+    policy: DeviceReconnectPolicy,
+    address: String,
+    device_index: u32,
+    device: Arc<ButtplugDevice>,
+  ) {
+    info!(
+      "Device {} disconnected, trying to reconnect to {}.",
+      device_index, address
+    );
+    // The hardware subscriptions go away with the connection, so clients
+    // need to resubscribe once the device is back.
+    self
+      .raw_subscriptions
+      .retain(|(index, _)| *index != device_index);
+    self
+      .sensor_subscriptions
+      .retain(|(index, _)| *index != device_index);
+    let token = CancellationToken::new();
+    self.reconnecting_devices.insert(
+      address.clone(),
+      ReconnectingDevice {
+        device,
+        token: token.clone(),
+      },
+    );
+    if self
+      .server_sender
+      .send(DeviceReconnecting::new(device_index).into())
+      .is_err()
+    {
+      debug!("Server not currently available, dropping Device Reconnecting event.");
+    }
+    let task = DeviceReconnectTask {
+      policy,
+      comm_manager_name: self
+        .device_comm_managers
+        .get(&address)
+        .map(|name| name.value().clone()),
+      address,
+      device_index,
+      scanning_command_sender: self.scanning_command_sender.clone(),
+      reconnecting_devices: self.reconnecting_devices.clone(),
+      raw_subscriptions: self.raw_subscriptions.clone(),
+      sensor_subscriptions: self.sensor_subscriptions.clone(),
+      server_sender: self.server_sender.clone(),
+      token,
+    };
+    async_manager::spawn(task.run()).unwrap();
+  }
+
   async fn handle_ping_timeout(&self) {
     error!("Pinged out, stopping devices");
     let mut fut_vec = FuturesUnordered::new();
@@ -286,7 +934,24 @@ impl DeviceManagerEventLoop {
 
   pub async fn run(&mut self) {
     loop {
+      let scanning_wakeup = self.scanning_state.next_wakeup();
+      let scanning_timer = async move {
+        match scanning_wakeup {
+          Some(wakeup) => Delay::new(wakeup.saturating_duration_since(Instant::now())).await,
+          None => future::pending::<()>().await,
+        }
+      };
       select! {
+        _ = scanning_timer.fuse() => {
+          self.handle_scanning_timer();
+        },
+        scanning_command = self.scanning_command_receiver.recv().fuse() => {
+          if let Some(command) = scanning_command {
+            self.handle_scanning_command(command);
+          } else {
+            panic!("We shouldn't be able to get here since we also own the sender.");
+          }
+        },
         // If we have a ping timeout, stop all devices
         _ = self.ping_timer.ping_timeout_waiter().fuse() => {
           self.handle_ping_timeout().await;
@@ -307,5 +972,8 @@ impl DeviceManagerEventLoop {
         },
       }
     }
+    // Nothing's left to receive new devices, so stop any connections still in
+    // progress.
+    self.shutdown_token.cancel();
   }
 }
@@ -0,0 +1,235 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Snapshots of what the device manager and its devices have been up to.
+
+use crate::device::device_metrics::DeviceMetricsSnapshot;
+use dashmap::DashMap;
+use std::{
+  collections::BTreeMap,
+  fmt::Write,
+  sync::atomic::{AtomicU64, Ordering},
+  time::{Duration, UNIX_EPOCH},
+};
+
+/// Counters for a single comm manager.
+#[derive(Default)]
+pub(super) struct CommManagerMetrics {
+  scans_started: AtomicU64,
+  devices_found: AtomicU64,
+  /// Devices found that didn't match any protocol, address to name.
+  unmatched_devices: DashMap<String, String>,
+}
+
+impl CommManagerMetrics {
+  pub fn record_scan(&self) {
+    self.scans_started.fetch_add(1, Ordering::SeqCst);
+  }
+
+  pub fn record_device_found(&self) {
+    self.devices_found.fetch_add(1, Ordering::SeqCst);
+  }
+
+  pub fn record_unmatched_device(&self, address: &str, name: &str) {
+    self
+      .unmatched_devices
+      .insert(address.to_owned(), name.to_owned());
+  }
+
+  pub fn snapshot(&self) -> CommManagerMetricsSnapshot {
+    CommManagerMetricsSnapshot {
+      scans_started: self.scans_started.load(Ordering::SeqCst),
+      devices_found: self.devices_found.load(Ordering::SeqCst),
+      unmatched_devices: self
+        .unmatched_devices
+        .iter()
+        .map(|device| (device.key().clone(), device.value().clone()))
+        .collect(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommManagerMetricsSnapshot {
+  /// Scans started by scanning sessions, including restarts during continuous
+  /// scanning. Scans started to reconnect devices aren't counted.
+  pub scans_started: u64,
+  /// Devices found while scanning. Devices that show up in multiple scans are
+  /// counted every time.
+  pub devices_found: u64,
+  /// Devices found that didn't match any protocol, address to name.
+  pub unmatched_devices: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectedDeviceMetrics {
+  pub name: String,
+  pub address: String,
+  pub metrics: DeviceMetricsSnapshot,
+}
+
+/// Snapshot returned by
+/// [DeviceManager::metrics][super::device_manager::DeviceManager::metrics].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceManagerMetrics {
+  /// Currently connected devices, keyed by device index.
+  pub devices: BTreeMap<u32, ConnectedDeviceMetrics>,
+  /// Keyed by comm manager name.
+  pub comm_managers: BTreeMap<String, CommManagerMetricsSnapshot>,
+}
+
+fn escape_label(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+fn seconds(duration: Duration) -> f64 {
+  duration.as_secs_f64()
+}
+
+impl DeviceManagerMetrics {
+  /// Renders the snapshot in the Prometheus text exposition format.
+  pub fn to_prometheus(&self) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, metric_type: &str, help: &str, samples: Vec<(String, f64)>| {
+      let _ = writeln!(out, "# HELP {} {}", name, help);
+      let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+      for (labels, value) in samples {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+      }
+    };
+    let device_labels = |index: &u32, device: &ConnectedDeviceMetrics| {
+      format!(
+        "index=\"{}\",name=\"{}\",address=\"{}\"",
+        index,
+        escape_label(&device.name),
+        escape_label(&device.address)
+      )
+    };
+    let device_samples = |value: &dyn Fn(&DeviceMetricsSnapshot) -> Option<f64>| {
+      self
+        .devices
+        .iter()
+        .filter_map(|(index, device)| {
+          value(&device.metrics).map(|value| (device_labels(index, device), value))
+        })
+        .collect::<Vec<_>>()
+    };
+    let endpoint_samples = |bytes: &dyn Fn(&DeviceMetricsSnapshot) -> Vec<(String, u64)>| {
+      let mut samples = vec![];
+      for (index, device) in &self.devices {
+        let mut endpoints = bytes(&device.metrics);
+        endpoints.sort();
+        for (endpoint, count) in endpoints {
+          samples.push((
+            format!("{},endpoint=\"{}\"", device_labels(index, device), endpoint),
+            count as f64,
+          ));
+        }
+      }
+      samples
+    };
+
+    metric(
+      "buttplug_device_commands_received_total",
+      "counter",
+      "Device command messages received.",
+      device_samples(&|m| Some(m.commands_received as f64)),
+    );
+    metric(
+      "buttplug_device_bytes_written_total",
+      "counter",
+      "Bytes written to the device.",
+      endpoint_samples(&|m| {
+        m.bytes_written
+          .iter()
+          .map(|(endpoint, count)| (endpoint.to_string(), *count))
+          .collect()
+      }),
+    );
+    metric(
+      "buttplug_device_bytes_read_total",
+      "counter",
+      "Bytes read from the device.",
+      endpoint_samples(&|m| {
+        m.bytes_read
+          .iter()
+          .map(|(endpoint, count)| (endpoint.to_string(), *count))
+          .collect()
+      }),
+    );
+    metric(
+      "buttplug_device_write_errors_total",
+      "counter",
+      "Failed writes to the device.",
+      device_samples(&|m| Some(m.write_errors as f64)),
+    );
+    metric(
+      "buttplug_device_read_errors_total",
+      "counter",
+      "Failed reads from the device.",
+      device_samples(&|m| Some(m.read_errors as f64)),
+    );
+    metric(
+      "buttplug_device_write_latency_average_seconds",
+      "gauge",
+      "Average write latency.",
+      device_samples(&|m| m.average_write_latency.map(seconds)),
+    );
+    metric(
+      "buttplug_device_write_latency_p99_seconds",
+      "gauge",
+      "99th percentile write latency over recent writes.",
+      device_samples(&|m| m.p99_write_latency.map(seconds)),
+    );
+    metric(
+      "buttplug_device_last_command_timestamp_seconds",
+      "gauge",
+      "Unix time of the last command sent to the device.",
+      device_samples(&|m| {
+        m.last_command_time
+          .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+          .map(seconds)
+      }),
+    );
+    metric(
+      "buttplug_device_uptime_seconds",
+      "gauge",
+      "Time since the device connected.",
+      device_samples(&|m| Some(seconds(m.uptime))),
+    );
+
+    let comm_manager_samples = |value: &dyn Fn(&CommManagerMetricsSnapshot) -> f64| {
+      self
+        .comm_managers
+        .iter()
+        .map(|(name, metrics)| (format!("comm_manager=\"{}\"", escape_label(name)), value(metrics)))
+        .collect::<Vec<_>>()
+    };
+    metric(
+      "buttplug_comm_manager_scans_started_total",
+      "counter",
+      "Scans started by the comm manager.",
+      comm_manager_samples(&|m| m.scans_started as f64),
+    );
+    metric(
+      "buttplug_comm_manager_devices_found_total",
+      "counter",
+      "Devices found by the comm manager.",
+      comm_manager_samples(&|m| m.devices_found as f64),
+    );
+    metric(
+      "buttplug_comm_manager_unmatched_devices",
+      "gauge",
+      "Distinct devices found that didn't match any protocol.",
+      comm_manager_samples(&|m| m.unmatched_devices.len() as f64),
+    );
+    out
+  }
+}
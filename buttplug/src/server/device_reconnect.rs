@@ -0,0 +1,172 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Reconnection handling for devices that drop their connection.
+
+use super::device_scanning::ScanningCommand;
+use crate::{
+  core::messages::{ButtplugServerMessage, DeviceRemoved},
+  device::{ButtplugDevice, Endpoint},
+};
+use dashmap::{DashMap, DashSet};
+use futures::FutureExt;
+use futures_timer::Delay;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+
+/// Settings for reconnecting to devices that drop their connection.
+///
+/// Each attempt asks the comm manager that originally found the device to
+/// scan for it again, waiting longer after every failed attempt. If the device
+/// shows up again under the same address, it keeps its device index and has
+/// its last actuator state restored.
+#[derive(Debug, Clone)]
+pub struct DeviceReconnectPolicy {
+  /// Number of scans to run before giving up and removing the device.
+  pub max_attempts: u32,
+  /// How long the first scan runs before being considered a failed attempt.
+  pub initial_delay: Duration,
+  /// The wait doubles after each failed attempt, up to this amount.
+  pub max_delay: Duration,
+}
+
+impl Default for DeviceReconnectPolicy {
+  fn default() -> Self {
+    Self {
+      max_attempts: 5,
+      initial_delay: Duration::from_secs(1),
+      max_delay: Duration::from_secs(30),
+    }
+  }
+}
+
+impl DeviceReconnectPolicy {
+  pub fn max_attempts(&mut self, attempts: u32) -> &mut Self {
+    self.max_attempts = attempts;
+    self
+  }
+
+  pub fn initial_delay(&mut self, delay: Duration) -> &mut Self {
+    self.initial_delay = delay;
+    self
+  }
+
+  pub fn max_delay(&mut self, delay: Duration) -> &mut Self {
+    self.max_delay = delay;
+    self
+  }
+}
+
+/// A disconnected device we're trying to get back.
+pub(super) struct ReconnectingDevice {
+  /// The device as it was before disconnecting, used to restore state.
+  pub device: Arc<ButtplugDevice>,
+  /// Cancelled once the device reconnects, to stop the retry task.
+  pub token: CancellationToken,
+}
+
+/// Everything the retry task needs, shared with the device manager event loop.
+pub(super) struct DeviceReconnectTask {
+  pub policy: DeviceReconnectPolicy,
+  pub address: String,
+  pub device_index: u32,
+  pub comm_manager_name: Option<String>,
+  pub scanning_command_sender: mpsc::Sender<ScanningCommand>,
+  pub reconnecting_devices: Arc<DashMap<String, ReconnectingDevice>>,
+  pub raw_subscriptions: Arc<DashSet<(u32, Endpoint)>>,
+  pub sensor_subscriptions: Arc<DashSet<(u32, u32)>>,
+  pub server_sender: broadcast::Sender<ButtplugServerMessage>,
+  pub token: CancellationToken,
+}
+
+impl DeviceReconnectTask {
+  pub async fn run(self) {
+    let mut delay = self.policy.initial_delay;
+    for attempt in 1..=self.policy.max_attempts {
+      info!(
+        "Reconnection attempt {} of {} for device {}",
+        attempt, self.policy.max_attempts, self.address
+      );
+      let started_scan = self.start_scan().await;
+      select! {
+        _ = self.token.cancelled().fuse() => {},
+        _ = Delay::new(delay).fuse() => {},
+      };
+      if started_scan {
+        self.stop_scan().await;
+      }
+      if self.token.is_cancelled() {
+        debug!("Device {} reconnected, stopping retries.", self.address);
+        return;
+      }
+      delay = (delay * 2).min(self.policy.max_delay);
+    }
+    // The event loop may have just gotten the device back, whoever removes
+    // the entry gets to decide what happened.
+    if self.reconnecting_devices.remove(&self.address).is_none() {
+      return;
+    }
+    info!(
+      "Could not reconnect to device {}, removing device.",
+      self.address
+    );
+    self
+      .raw_subscriptions
+      .retain(|(index, _)| *index != self.device_index);
+    self
+      .sensor_subscriptions
+      .retain(|(index, _)| *index != self.device_index);
+    if self
+      .server_sender
+      .send(DeviceRemoved::new(self.device_index).into())
+      .is_err()
+    {
+      debug!("Server not currently available, dropping Device Removed event.");
+    }
+  }
+
+  /// Asks the comm manager that found the device to scan for it. Returns true
+  /// if we started a scan that we need to stop later.
+  async fn start_scan(&self) -> bool {
+    let comm_manager = match &self.comm_manager_name {
+      Some(name) => name.clone(),
+      None => {
+        warn!(
+          "No comm manager known for device {}, cannot scan for device.",
+          self.address
+        );
+        return false;
+      }
+    };
+    // The event loop keeps track of who is scanning with which comm manager,
+    // so we don't stop a scan a client started, or the other way around.
+    if self
+      .scanning_command_sender
+      .send(ScanningCommand::StartReconnectScan { comm_manager })
+      .await
+      .is_err()
+    {
+      debug!("Device manager event loop shut down, cannot start reconnection scan.");
+      return false;
+    }
+    true
+  }
+
+  async fn stop_scan(&self) {
+    if let Some(comm_manager) = self.comm_manager_name.clone() {
+      if self
+        .scanning_command_sender
+        .send(ScanningCommand::StopReconnectScan { comm_manager })
+        .await
+        .is_err()
+      {
+        debug!("Device manager event loop shut down, cannot stop reconnection scan.");
+      }
+    }
+  }
+}
@@ -0,0 +1,95 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Scanning state for the device manager event loop.
+//!
+//! Comm managers finish scanning on their own schedule (some never do, some
+//! finish right away), so the event loop tracks scanning sessions itself and
+//! decides when a session is over and ScanningFinished should go out.
+//!
+//! Reconnection scans are counted per comm manager, separately from the
+//! client's session. A comm manager is only started by whichever of them needs
+//! it first, and only stopped once neither needs it anymore.
+
+use crate::core::{
+  errors::ButtplugError,
+  messages::{ScanningFinishedReason, StartScanning},
+};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// How long to wait before restarting comm managers that finished scanning
+/// during a continuous scan, so managers that finish immediately don't spin.
+pub(super) const CONTINUOUS_SCAN_RESTART_DELAY: Duration = Duration::from_millis(500);
+
+pub(super) type ScanningResultSender = oneshot::Sender<Result<(), ButtplugError>>;
+
+pub(super) enum ScanningCommand {
+  Start {
+    options: StartScanning,
+    result_sender: ScanningResultSender,
+  },
+  Stop {
+    result_sender: ScanningResultSender,
+  },
+  /// Sent by the event loop to itself once every comm manager's
+  /// start_scanning call has returned.
+  CommManagersStarted,
+  /// Sent by the event loop to itself once every comm manager's stop_scanning
+  /// call has returned.
+  CommManagersStopped,
+  /// Sent by reconnection tasks to have a comm manager scan for a device they
+  /// lost. Every start has to be followed by a stop.
+  StartReconnectScan {
+    comm_manager: String,
+  },
+  StopReconnectScan {
+    comm_manager: String,
+  },
+}
+
+#[derive(Debug)]
+pub(super) enum ScanningState {
+  Idle,
+  Scanning {
+    /// Names of the comm managers taking part in this session.
+    comm_managers: Vec<String>,
+    continuous: bool,
+    /// When the session times out, if it was started with a duration.
+    deadline: Option<Instant>,
+    /// True while comm managers are being started. Managers can report that
+    /// they're finished before all of them have started, so we can't decide
+    /// whether the session is over until this clears.
+    starting: bool,
+    /// When to restart comm managers that finished during a continuous scan.
+    restart_at: Option<Instant>,
+  },
+  Stopping {
+    comm_managers: Vec<String>,
+    reason: ScanningFinishedReason,
+    /// True while comm managers are being stopped.
+    stopping: bool,
+  },
+}
+
+impl ScanningState {
+  /// Next time the event loop needs to act on this state without any outside
+  /// event happening.
+  pub fn next_wakeup(&self) -> Option<Instant> {
+    match self {
+      ScanningState::Scanning {
+        deadline,
+        restart_at,
+        ..
+      } => match (deadline, restart_at) {
+        (Some(deadline), Some(restart_at)) => Some(*deadline.min(restart_at)),
+        (deadline, restart_at) => deadline.or(*restart_at),
+      },
+      _ => None,
+    }
+  }
+}
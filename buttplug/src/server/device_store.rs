@@ -0,0 +1,241 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Persistent storage for device indexes and user-assigned device names.
+//!
+//! Without a store, device indexes only live as long as the server does, so
+//! anything referring to devices by index (saved scenes, scripts, etc) breaks
+//! on restart. With a store, a device keeps its index as long as it shows up
+//! under the same address.
+
+use crate::core::errors::{ButtplugDeviceError, ButtplugError};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  fmt::Debug,
+  fs,
+  io::ErrorKind,
+  path::PathBuf,
+  sync::{Arc, Mutex},
+};
+
+/// Everything we remember about a device, keyed by address.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StoredDeviceInfo {
+  pub index: u32,
+  /// Name the user has given the device, sent to clients along with the
+  /// device's actual name.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub display_name: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user_notes: Option<String>,
+}
+
+pub type StoredDeviceMap = HashMap<String, StoredDeviceInfo>;
+
+/// Storage backend for device information. Saves happen on the device manager
+/// event loop, so implementations should return quickly.
+pub trait DeviceStore: Debug + Send + Sync {
+  fn load(&self) -> Result<StoredDeviceMap, ButtplugError>;
+  fn save(&self, devices: &StoredDeviceMap) -> Result<(), ButtplugError>;
+}
+
+/// Keeps device information in memory. Doesn't survive restarts on its own,
+/// but can be shared between multiple servers in the same process.
+#[derive(Debug, Default)]
+pub struct InMemoryDeviceStore {
+  devices: Mutex<StoredDeviceMap>,
+}
+
+impl DeviceStore for InMemoryDeviceStore {
+  fn load(&self) -> Result<StoredDeviceMap, ButtplugError> {
+    Ok(self.devices.lock().unwrap().clone())
+  }
+
+  fn save(&self, devices: &StoredDeviceMap) -> Result<(), ButtplugError> {
+    *self.devices.lock().unwrap() = devices.clone();
+    Ok(())
+  }
+}
+
+/// Stores device information as a JSON file. A missing file is treated as an
+/// empty store, and gets created on the first save.
+#[derive(Debug)]
+pub struct JsonFileDeviceStore {
+  path: PathBuf,
+}
+
+impl JsonFileDeviceStore {
+  pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+    Self { path: path.into() }
+  }
+}
+
+fn store_error<E: std::fmt::Display>(err: E) -> ButtplugError {
+  ButtplugDeviceError::DeviceStoreError(err.to_string()).into()
+}
+
+impl DeviceStore for JsonFileDeviceStore {
+  fn load(&self) -> Result<StoredDeviceMap, ButtplugError> {
+    match fs::read_to_string(&self.path) {
+      Ok(contents) => serde_json::from_str(&contents).map_err(store_error),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(StoredDeviceMap::new()),
+      Err(err) => Err(store_error(err)),
+    }
+  }
+
+  fn save(&self, devices: &StoredDeviceMap) -> Result<(), ButtplugError> {
+    let contents = serde_json::to_string_pretty(devices).map_err(store_error)?;
+    // Write to a temp file first, so we don't lose everything if we die
+    // halfway through writing.
+    let temp_path = self.path.with_extension("tmp");
+    fs::write(&temp_path, contents).map_err(store_error)?;
+    fs::rename(&temp_path, &self.path).map_err(store_error)
+  }
+}
+
+/// Device indexes and names for every device the server has seen, backed by an
+/// optional [DeviceStore].
+#[derive(Debug, Default)]
+pub struct DeviceRegistry {
+  devices: DashMap<String, StoredDeviceInfo>,
+  store: Option<Arc<dyn DeviceStore>>,
+}
+
+impl DeviceRegistry {
+  /// Creates a registry, loading whatever is already in the store.
+  pub fn new(store: Option<Arc<dyn DeviceStore>>) -> Result<Self, ButtplugError> {
+    let devices = DashMap::new();
+    if let Some(store) = &store {
+      for (address, info) in store.load()? {
+        devices.insert(address, info);
+      }
+    }
+    Ok(Self { devices, store })
+  }
+
+  /// First index that isn't taken by any stored device.
+  pub fn next_index(&self) -> u32 {
+    self
+      .devices
+      .iter()
+      .map(|info| info.index + 1)
+      .max()
+      .unwrap_or(0)
+  }
+
+  pub fn get(&self, address: &str) -> Option<StoredDeviceInfo> {
+    self.devices.get(address).map(|info| info.clone())
+  }
+
+  pub fn index(&self, address: &str) -> Option<u32> {
+    self.devices.get(address).map(|info| info.index)
+  }
+
+  pub fn display_name(&self, address: &str) -> Option<String> {
+    self
+      .devices
+      .get(address)
+      .and_then(|info| info.display_name.clone())
+  }
+
+  pub fn devices(&self) -> StoredDeviceMap {
+    self
+      .devices
+      .iter()
+      .map(|info| (info.key().clone(), info.value().clone()))
+      .collect()
+  }
+
+  /// Remembers the index for a newly seen device.
+  pub fn insert(&self, address: &str, index: u32) {
+    self.devices.insert(
+      address.to_owned(),
+      StoredDeviceInfo {
+        index,
+        ..Default::default()
+      },
+    );
+    self.save();
+  }
+
+  /// Updates the stored information for the device with the given index.
+  /// Returns false if we've never seen a device with that index.
+  pub fn update<F>(&self, index: u32, update: F) -> bool
+  where
+    F: FnOnce(&mut StoredDeviceInfo),
+  {
+    let updated = match self.devices.iter_mut().find(|info| info.index == index) {
+      Some(mut info) => {
+        update(info.value_mut());
+        true
+      }
+      None => false,
+    };
+    if updated {
+      self.save();
+    }
+    updated
+  }
+
+  fn save(&self) {
+    if let Some(store) = &self.store {
+      if let Err(err) = store.save(&self.devices()) {
+        error!("Cannot save device information: {}", err);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_registry_reloads_from_store() {
+    let store: Arc<dyn DeviceStore> = Arc::new(InMemoryDeviceStore::default());
+    let registry = DeviceRegistry::new(Some(store.clone())).unwrap();
+    assert_eq!(registry.next_index(), 0);
+    registry.insert("edge", 0);
+    registry.insert("lush", 1);
+    assert!(registry.update(1, |info| {
+      info.display_name = Some("Alice's Lush".to_owned())
+    }));
+    assert!(!registry.update(5, |info| info.display_name = Some("Nobody".to_owned())));
+
+    let registry = DeviceRegistry::new(Some(store)).unwrap();
+    assert_eq!(registry.index("lush"), Some(1));
+    assert_eq!(
+      registry.display_name("lush"),
+      Some("Alice's Lush".to_owned())
+    );
+    assert_eq!(registry.display_name("edge"), None);
+    assert_eq!(registry.next_index(), 2);
+  }
+
+  #[test]
+  fn test_json_file_store() {
+    let path =
+      std::env::temp_dir().join(format!("buttplug-device-store-{}.json", std::process::id()));
+    let store = JsonFileDeviceStore::new(&path);
+    // Missing files are just empty stores.
+    assert!(store.load().unwrap().is_empty());
+    let mut devices = StoredDeviceMap::new();
+    devices.insert(
+      "edge".to_owned(),
+      StoredDeviceInfo {
+        index: 3,
+        display_name: Some("Alice's Edge".to_owned()),
+        user_notes: Some("Charge before use".to_owned()),
+      },
+    );
+    store.save(&devices).unwrap();
+    assert_eq!(store.load().unwrap(), devices);
+    fs::remove_file(&path).unwrap();
+  }
+}
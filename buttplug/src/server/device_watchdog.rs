@@ -0,0 +1,139 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2021 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Inactivity watchdog, for stopping devices whose controlling app has stopped
+//! sending commands.
+//!
+//! Ping timeouts only catch apps that have stopped talking to the server
+//! entirely. An app that's hung in its device handling can still be answering
+//! pings while a toy runs forever, so each device gets a watchdog that stops it
+//! if it's left running without any new commands.
+
+use crate::{
+  core::messages::{ButtplugServerMessage, DeviceStopReason, DeviceStopped, StopDeviceCmd},
+  device::ButtplugDevice,
+  util::async_manager,
+};
+use futures::{future, FutureExt};
+use futures_timer::Delay;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{broadcast, mpsc};
+
+enum WatchdogMessage {
+  Command,
+  End,
+}
+
+async fn device_watchdog(
+  timeout: Duration,
+  device_index: u32,
+  device: Arc<ButtplugDevice>,
+  mut watchdog_msg_receiver: mpsc::Receiver<WatchdogMessage>,
+  server_sender: broadcast::Sender<ButtplugServerMessage>,
+) {
+  let mut armed = false;
+  loop {
+    // Every message restarts the timer, so this only fires after a full
+    // timeout without any commands.
+    let timer = async {
+      if armed {
+        Delay::new(timeout).await;
+      } else {
+        future::pending::<()>().await;
+      }
+    };
+    select! {
+      _ = timer.fuse() => {
+        armed = false;
+        // Something else (StopAllDevices, ping timeout) may have stopped the
+        // device without going through us.
+        if !device.is_running() {
+          continue;
+        }
+        info!(
+          "Device {} has not received a command in {:?}, stopping device.",
+          device_index, timeout
+        );
+        // Goes through the same path as StopDeviceCmd, so the protocol's
+        // stop commands are sent and the device's state is cleared.
+        if let Err(err) = device
+          .parse_message(StopDeviceCmd::new(device_index).into())
+          .await
+        {
+          error!("Error stopping inactive device {}: {:?}", device_index, err);
+        }
+        if server_sender
+          .send(DeviceStopped::new(device_index, DeviceStopReason::InactivityTimeout).into())
+          .is_err()
+        {
+          debug!("Server not currently available, dropping Device Stopped event.");
+        }
+      }
+      msg = watchdog_msg_receiver.recv().fuse() => {
+        match msg {
+          Some(WatchdogMessage::Command) => armed = device.is_running(),
+          Some(WatchdogMessage::End) | None => return,
+        }
+      }
+    };
+  }
+}
+
+/// Stops a device if it's left running without receiving any commands for
+/// longer than the timeout. Dropping the watchdog shuts it down.
+pub struct DeviceWatchdog {
+  watchdog_msg_sender: mpsc::Sender<WatchdogMessage>,
+}
+
+impl Drop for DeviceWatchdog {
+  fn drop(&mut self) {
+    // Same as the ping timer, we can't block here, so send from a task.
+    let sender = self.watchdog_msg_sender.clone();
+    async_manager::spawn(async move {
+      if sender.send(WatchdogMessage::End).await.is_err() {
+        debug!("Receiver does not exist, assuming watchdog event loop already dead.");
+      }
+    })
+    .unwrap();
+  }
+}
+
+impl DeviceWatchdog {
+  pub fn new(
+    timeout: Duration,
+    device_index: u32,
+    device: Arc<ButtplugDevice>,
+    server_sender: broadcast::Sender<ButtplugServerMessage>,
+  ) -> Self {
+    let (sender, receiver) = mpsc::channel(256);
+    async_manager::spawn(device_watchdog(
+      timeout,
+      device_index,
+      device,
+      receiver,
+      server_sender,
+    ))
+    .unwrap();
+    Self {
+      watchdog_msg_sender: sender,
+    }
+  }
+
+  /// Lets the watchdog know the device was just sent a command, restarting the
+  /// timeout if the device is running.
+  pub fn update(&self) {
+    // If the channel is full, the watchdog has plenty of updates to get
+    // through already, so dropping this one doesn't change anything.
+    if self
+      .watchdog_msg_sender
+      .try_send(WatchdogMessage::Command)
+      .is_err()
+    {
+      trace!("Watchdog channel full or closed, dropping update.");
+    }
+  }
+}
@@ -8,39 +8,63 @@
 //! Handles client sessions, as well as discovery and communication with hardware.
 
 pub mod comm_managers;
+pub mod device_battery_monitor;
+pub mod device_connection;
+pub mod device_leases;
 pub mod device_manager;
 mod device_manager_event_loop;
+pub mod device_manager_metrics;
+pub mod device_reconnect;
+mod device_scanning;
+pub mod device_store;
+mod device_watchdog;
+pub mod multi_client_server;
 mod ping_timer;
 pub mod remote_server;
 
+pub use device_battery_monitor::BatteryMonitorPolicy;
+pub use device_connection::DeviceConnectionPolicy;
+pub use device_reconnect::DeviceReconnectPolicy;
+pub use device_store::{DeviceStore, InMemoryDeviceStore, JsonFileDeviceStore};
+pub use multi_client_server::ButtplugMultiClientServer;
 pub use remote_server::ButtplugRemoteServer;
 
 use crate::{
   core::{
     errors::*,
     messages::{
-      self, ButtplugClientMessage, ButtplugDeviceCommandMessageUnion,
-      ButtplugDeviceManagerMessageUnion, ButtplugMessage, ButtplugServerMessage, StopAllDevices,
+      self, serializer::MessageValidationLevel, ButtplugClientMessage,
+      ButtplugDeviceCommandMessageUnion, ButtplugDeviceManagerMessageUnion, ButtplugDeviceMessage,
+      ButtplugMessage, ButtplugMessageSpecVersion, ButtplugServerMessage, LogLevel, StopAllDevices,
       StopScanning, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
-  util::{async_manager, stream::convert_broadcast_receiver_to_stream, device_configuration::{DEVICE_CONFIGURATION_JSON, load_protocol_config_from_json}},
+  util::{
+    async_manager,
+    device_configuration::{load_protocol_config_from_json, DEVICE_CONFIGURATION_JSON},
+    logging::ButtplugLogLayer,
+    stream::convert_broadcast_receiver_to_stream,
+  },
 };
 use device_manager::DeviceManager;
+use device_store::DeviceRegistry;
 use futures::{
   future::{self, BoxFuture},
-  Stream,
+  FutureExt, Stream, StreamExt,
 };
 use ping_timer::PingTimer;
 use std::{
   convert::{TryFrom, TryInto},
+  path::PathBuf,
   sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU32, Ordering},
     Arc,
   },
+  time::Duration,
 };
 use thiserror::Error;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, Mutex};
+use tokio_util::sync::CancellationToken;
 use tracing_futures::Instrument;
 
 pub type ButtplugServerResult = Result<ButtplugServerMessage, ButtplugError>;
@@ -50,6 +74,8 @@ pub type ButtplugServerResultFuture = BoxFuture<'static, ButtplugServerResult>;
 pub enum ButtplugServerError {
   #[error("DeviceManager of type {0} has already been added.")]
   DeviceManagerTypeAlreadyAdded(String),
+  #[error("DeviceManager of type {0} does not exist and cannot be removed.")]
+  DeviceManagerTypeDoesNotExist(String),
   #[error("Buttplug Protocol of type {0} has already been added to the system.")]
   ProtocolAlreadyAdded(String),
   #[error("Buttplug Protocol of type {0} does not exist in the system and cannot be removed.")]
@@ -63,6 +89,26 @@ pub struct ButtplugServerBuilder {
   pub allow_raw_messages: bool,
   pub device_configuration_json: Option<String>,
   pub user_device_configuration_json: Option<String>,
+  /// If set, devices that drop their connection are kept around while the
+  /// server tries to reconnect to them.
+  pub reconnect_policy: Option<DeviceReconnectPolicy>,
+  /// If set, devices left running without receiving a command for this many
+  /// milliseconds are stopped.
+  pub device_inactivity_timeout: Option<u64>,
+  /// If set, the battery level of devices that can report it is polled, and
+  /// clients are sent BatteryLevelLow events when it runs low.
+  pub battery_monitor_policy: Option<BatteryMonitorPolicy>,
+  /// If set, device indexes and names are loaded from and saved to this
+  /// store, so they stay the same across server restarts.
+  pub device_store: Option<Arc<dyn DeviceStore>>,
+  /// Concurrency and time limits for connecting to newly found devices.
+  pub connection_policy: DeviceConnectionPolicy,
+  /// If set, traffic for every device that connects is recorded to a new
+  /// file in this directory. See [crate::device::device_recording].
+  pub device_recording_directory: Option<PathBuf>,
+  /// How strictly messages from remote clients are checked before they reach
+  /// the server. Only applies to connectors that deserialize messages.
+  pub message_validation_level: MessageValidationLevel,
 }
 
 impl Default for ButtplugServerBuilder {
@@ -73,6 +119,13 @@ impl Default for ButtplugServerBuilder {
       allow_raw_messages: false,
       device_configuration_json: Some(DEVICE_CONFIGURATION_JSON.to_owned()),
       user_device_configuration_json: None,
+      reconnect_policy: None,
+      device_inactivity_timeout: None,
+      battery_monitor_policy: None,
+      device_store: None,
+      connection_policy: DeviceConnectionPolicy::default(),
+      device_recording_directory: None,
+      message_validation_level: MessageValidationLevel::default(),
     }
   }
 }
@@ -103,7 +156,46 @@ impl ButtplugServerBuilder {
     self
   }
 
+  pub fn reconnect_policy(&mut self, policy: DeviceReconnectPolicy) -> &mut Self {
+    self.reconnect_policy = Some(policy);
+    self
+  }
+
+  pub fn device_inactivity_timeout(&mut self, timeout: u64) -> &mut Self {
+    self.device_inactivity_timeout = Some(timeout);
+    self
+  }
+
+  pub fn battery_monitor_policy(&mut self, policy: BatteryMonitorPolicy) -> &mut Self {
+    self.battery_monitor_policy = Some(policy);
+    self
+  }
+
+  pub fn device_store(&mut self, store: Arc<dyn DeviceStore>) -> &mut Self {
+    self.device_store = Some(store);
+    self
+  }
+
+  pub fn connection_policy(&mut self, policy: DeviceConnectionPolicy) -> &mut Self {
+    self.connection_policy = policy;
+    self
+  }
+
+  pub fn device_recording_directory<P: Into<PathBuf>>(&mut self, directory: P) -> &mut Self {
+    self.device_recording_directory = Some(directory.into());
+    self
+  }
+
+  pub fn message_validation_level(&mut self, level: MessageValidationLevel) -> &mut Self {
+    self.message_validation_level = level;
+    self
+  }
+
   pub fn finish(&self) -> Result<ButtplugServer, ButtplugError> {
+    // Set up log forwarding first, so the tasks we spawn while building the
+    // server pick it up.
+    let log_layer = ButtplugLogLayer::new();
+    let _log_guard = log_layer.set_default();
     // If the user config string exists, parse it.
     let user_config = if let Some(user_device_config) = &self.user_device_configuration_json {
       Some(load_protocol_config_from_json(&user_device_config)?)
@@ -122,6 +214,8 @@ impl ButtplugServerBuilder {
       user_config
     };
 
+    let device_registry = DeviceRegistry::new(self.device_store.clone())?;
+
     // Create the server
     debug!("Creating server '{}'", self.name);
     let (send, _) = broadcast::channel(256);
@@ -151,22 +245,38 @@ impl ButtplugServerBuilder {
     let device_manager = DeviceManager::new(
       send.clone(),
       ping_timer.clone(),
-      self.allow_raw_messages
+      self.allow_raw_messages,
+      self.reconnect_policy.clone(),
+      self
+        .device_inactivity_timeout
+        .filter(|timeout| *timeout > 0)
+        .map(Duration::from_millis),
+      self.battery_monitor_policy.clone(),
+      device_registry,
+      self.connection_policy.clone(),
+      self.device_recording_directory.clone(),
     );
 
     if let Some(devices) = device_config {
       for (name, def) in devices.protocols {
         device_manager.add_protocol_definition(&name, def);
       }
+      for rule in devices.device_filters {
+        device_manager.add_device_filter_rule(rule);
+      }
     }
 
     let server = ButtplugServer {
       server_name: self.name.clone(),
       max_ping_time: ping_time,
+      message_validation_level: self.message_validation_level,
       device_manager,
       ping_timer,
       connected,
+      message_version: Arc::new(AtomicU32::new(0)),
       output_sender: send,
+      log_layer,
+      log_forwarding_token: Arc::new(Mutex::new(None)),
     };
 
     // Add the device config
@@ -182,10 +292,18 @@ impl ButtplugServerBuilder {
 pub struct ButtplugServer {
   server_name: String,
   max_ping_time: u64,
+  message_validation_level: MessageValidationLevel,
   device_manager: DeviceManager,
   ping_timer: Arc<PingTimer>,
   connected: Arc<AtomicBool>,
+  /// Message spec version the client connected with.
+  message_version: Arc<AtomicU32>,
   output_sender: broadcast::Sender<ButtplugServerMessage>,
+  /// Source of Log messages for clients that send RequestLog.
+  log_layer: ButtplugLogLayer,
+  /// Cancels the task forwarding tracing output as Log messages, if the
+  /// client has sent a RequestLog.
+  log_forwarding_token: Arc<Mutex<Option<CancellationToken>>>,
 }
 
 impl Default for ButtplugServer {
@@ -199,13 +317,28 @@ impl ButtplugServer {
   pub fn event_stream(&self) -> impl Stream<Item = ButtplugServerMessage> {
     // Unlike the client API, we can expect anyone using the server to pin this
     // themselves.
-    convert_broadcast_receiver_to_stream(self.output_sender.subscribe())
+    let connected = self.connected.clone();
+    let message_version = self.message_version.clone();
+    convert_broadcast_receiver_to_stream(self.output_sender.subscribe()).filter(move |msg| {
+      future::ready(
+        !connected.load(Ordering::SeqCst)
+          || check_event_message_version(message_version.load(Ordering::SeqCst), msg),
+      )
+    })
   }
 
   pub fn device_manager(&self) -> &DeviceManager {
     &self.device_manager
   }
 
+  pub fn message_validation_level(&self) -> MessageValidationLevel {
+    self.message_validation_level
+  }
+
+  pub(crate) fn log_layer(&self) -> &ButtplugLogLayer {
+    &self.log_layer
+  }
+
   pub fn connected(&self) -> bool {
     self.connected.load(Ordering::SeqCst)
   }
@@ -219,16 +352,21 @@ impl ButtplugServer {
       StopAllDevices::default(),
     ));
     let connected = self.connected.clone();
-    Box::pin(async move {
+    let log_forwarding_token = self.log_forwarding_token.clone();
+    self.device_manager.clear_subscriptions();
+    Box::pin(self.log_layer.attach(async move {
       connected.store(false, Ordering::SeqCst);
       ping_timer.stop_ping_timer().await;
+      if let Some(token) = log_forwarding_token.lock().await.take() {
+        token.cancel();
+      }
       // Ignore returns here, we just want to stop.
       info!("Server disconnected, stopping device scanning if it was started...");
       let _ = stop_scanning_fut.await;
       info!("Server disconnected, stopping all devices...");
       let _ = stop_fut.await;
       Ok(())
-    })
+    }))
   }
 
   // This is the only method that returns ButtplugServerResult, as it handles
@@ -237,6 +375,9 @@ impl ButtplugServer {
     &self,
     msg: ButtplugClientMessage,
   ) -> BoxFuture<'static, Result<ButtplugServerMessage, messages::Error>> {
+    // Anything we log while handling the message, now or in the future we
+    // return, can be forwarded to the client.
+    let _log_guard = self.log_layer.set_default();
     trace!(
       "Buttplug Server {} received message to client parse: {:?}",
       self.server_name,
@@ -269,35 +410,48 @@ impl ButtplugServer {
     // return Result<ButtplugServerMessage, ButtplugError>, and we'll handle
     // tagging the result with the message id in the future we put out as the
     // return value from this method.
-    let out_fut = if ButtplugDeviceManagerMessageUnion::try_from(msg.clone()).is_ok()
+    let out_fut = self.route_message(msg);
+    // Simple way to set the ID on the way out. Just rewrap
+    // the returned future to make sure it happens.
+    Box::pin(
+      self.log_layer.attach(
+        async move {
+          out_fut
+            .await
+            .map(|mut ok_msg| {
+              ok_msg.set_id(id);
+              ok_msg
+            })
+            .map_err(|err| {
+              let mut error = messages::Error::from(err);
+              error.set_id(id);
+              error
+            })
+        }
+        .instrument(info_span!("Buttplug Server Message", id = id)),
+      ),
+    )
+  }
+
+  fn route_message(&self, msg: ButtplugClientMessage) -> ButtplugServerResultFuture {
+    if let Err(err) = check_message_spec_version(self.message_version.load(Ordering::SeqCst), &msg)
+    {
+      return err.into();
+    }
+    if ButtplugDeviceManagerMessageUnion::try_from(msg.clone()).is_ok()
       || ButtplugDeviceCommandMessageUnion::try_from(msg.clone()).is_ok()
     {
-      self.device_manager.parse_message(msg.clone())
+      self.device_manager.parse_message(msg)
     } else {
       match msg {
         ButtplugClientMessage::RequestServerInfo(rsi_msg) => self.perform_handshake(rsi_msg),
         ButtplugClientMessage::Ping(p) => self.handle_ping(p),
+        ButtplugClientMessage::RequestLog(l) => self.handle_request_log(l),
+        ButtplugClientMessage::RequestDeviceLease(l) => self.handle_device_lease(l.device_index()),
+        ButtplugClientMessage::ReleaseDeviceLease(l) => self.handle_device_lease(l.device_index()),
         _ => ButtplugMessageError::UnexpectedMessageType(format!("{:?}", msg)).into(),
       }
-    };
-    // Simple way to set the ID on the way out. Just rewrap
-    // the returned future to make sure it happens.
-    Box::pin(
-      async move {
-        out_fut
-          .await
-          .map(|mut ok_msg| {
-            ok_msg.set_id(id);
-            ok_msg
-          })
-          .map_err(|err| {
-            let mut error = messages::Error::from(err);
-            error.set_id(id);
-            error
-          })
-      }
-      .instrument(info_span!("Buttplug Server Message", id = id)),
-    )
+    }
   }
 
   fn perform_handshake(&self, msg: messages::RequestServerInfo) -> ButtplugServerResultFuture {
@@ -324,8 +478,10 @@ impl ButtplugServer {
       self.max_ping_time.try_into().unwrap(),
     );
     let connected = self.connected.clone();
+    let message_version = self.message_version.clone();
     Box::pin(async move {
       ping_timer.start_ping_timer().await;
+      message_version.store(msg.message_version() as u32, Ordering::SeqCst);
       connected.store(true, Ordering::SeqCst);
       debug!("Server handshake check successful.");
       Result::Ok(out_msg.into())
@@ -342,6 +498,134 @@ impl ButtplugServer {
       Result::Ok(messages::Ok::new(msg.id()).into())
     })
   }
+
+  // With only one client there's nobody to share devices with, so leases are
+  // always granted. See [ButtplugMultiClientServer] for actual arbitration.
+  fn handle_device_lease(&self, device_index: u32) -> ButtplugServerResultFuture {
+    if !self.device_manager.has_device(device_index) {
+      return ButtplugDeviceError::DeviceNotAvailable(device_index).into();
+    }
+    Box::pin(future::ready(Result::Ok(messages::Ok::default().into())))
+  }
+
+  fn handle_request_log(&self, msg: messages::RequestLog) -> ButtplugServerResultFuture {
+    forward_log_messages(
+      &self.log_layer,
+      msg,
+      self.output_sender.clone(),
+      self.log_forwarding_token.clone(),
+    )
+  }
+}
+
+/// Starts forwarding output from a log layer to a client as Log messages, at
+/// the level requested in the RequestLog, replacing whatever was forwarded
+/// before. Also used by [ButtplugMultiClientServer] sessions, which each keep
+/// their own log level.
+pub(super) fn forward_log_messages(
+  log_layer: &ButtplugLogLayer,
+  msg: messages::RequestLog,
+  output_sender: broadcast::Sender<ButtplugServerMessage>,
+  log_forwarding_token: Arc<Mutex<Option<CancellationToken>>>,
+) -> ButtplugServerResultFuture {
+  let log_level = msg.log_level();
+  // Subscribe now rather than in the future, so nothing logged while we
+  // reply gets lost.
+  let log_receiver = if log_level == LogLevel::Off {
+    None
+  } else {
+    Some(log_layer.log_message_receiver())
+  };
+  Box::pin(async move {
+    let mut current_token = log_forwarding_token.lock().await;
+    // Any new RequestLog replaces the last one, so stop whatever we were
+    // forwarding before.
+    if let Some(token) = current_token.take() {
+      token.cancel();
+    }
+    let mut log_receiver = match log_receiver {
+      Some(log_receiver) => log_receiver,
+      None => {
+        info!("Client turned off log forwarding.");
+        return Result::Ok(messages::Ok::new(msg.id()).into());
+      }
+    };
+    info!("Client requested log forwarding at level {:?}.", log_level);
+    let token = CancellationToken::new();
+    let child_token = token.child_token();
+    async_manager::spawn(async move {
+      loop {
+        select! {
+          _ = child_token.cancelled().fuse() => break,
+          log_msg = log_receiver.recv().fuse() => match log_msg {
+            Ok(log_msg) => {
+              if log_msg.log_level() > log_level {
+                continue;
+              }
+              if output_sender.send(log_msg.into()).is_err() {
+                break;
+              }
+            }
+            // If we fall behind, skip what we missed and keep going.
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+          }
+        }
+      }
+    })
+    .unwrap();
+    *current_token = Some(token);
+    Result::Ok(messages::Ok::new(msg.id()).into())
+  })
+}
+
+/// Fails for messages, or message options, added in spec v3 when the client
+/// connected with an older spec. Device leases and the StartScanning options
+/// are shared with older specs' message types, and in-process clients don't
+/// go through a serializer, so this is checked here.
+pub(super) fn check_message_spec_version(
+  message_version: u32,
+  msg: &ButtplugClientMessage,
+) -> Result<(), ButtplugError> {
+  let needs_v3 = match msg {
+    ButtplugClientMessage::RequestDeviceLease(_) | ButtplugClientMessage::ReleaseDeviceLease(_) => {
+      true
+    }
+    ButtplugClientMessage::StartScanning(msg) => {
+      msg.duration().is_some() || msg.comm_managers().is_some() || msg.continuous()
+    }
+    _ => false,
+  };
+  if needs_v3 && message_version < ButtplugMessageSpecVersion::Version3 as u32 {
+    Err(
+      ButtplugMessageError::VersionError(
+        "ButtplugClientMessage".to_owned(),
+        format!("{:?}", msg),
+        format!("ButtplugSpecV{}ClientMessage", message_version),
+      )
+      .into(),
+    )
+  } else {
+    Ok(())
+  }
+}
+
+/// Returns false for events added after the spec version the client
+/// connected with. Serializers turn messages an older spec can't represent
+/// into errors, so these are dropped before they reach the client instead.
+pub(super) fn check_event_message_version(
+  message_version: u32,
+  msg: &ButtplugServerMessage,
+) -> bool {
+  let is_v3_event = matches!(
+    msg,
+    ButtplugServerMessage::DeviceReconnecting(_)
+      | ButtplugServerMessage::DeviceReconnected(_)
+      | ButtplugServerMessage::DeviceStopped(_)
+      | ButtplugServerMessage::DeviceConnectionFailed(_)
+      | ButtplugServerMessage::BatteryLevelLow(_)
+  );
+  !is_v3_event || message_version >= ButtplugMessageSpecVersion::Version3 as u32
 }
 
 #[cfg(test)]
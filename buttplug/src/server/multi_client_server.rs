@@ -0,0 +1,883 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Server that lets multiple clients share the same devices.
+
+use super::{
+  check_event_message_version, check_message_spec_version,
+  device_leases::{DeviceLeaseManager, DeviceLeaseType},
+  forward_log_messages,
+  ping_timer::PingTimer,
+  remote_server::ButtplugServerConnectorError,
+  ButtplugServer, ButtplugServerBuilder, ButtplugServerResultFuture, DeviceManager,
+};
+use crate::{
+  connector::ButtplugConnector,
+  core::{
+    errors::*,
+    messages::{
+      self, ButtplugClientMessage, ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessage,
+      ButtplugMessage, ButtplugMessageValidator, ButtplugServerMessage, RawUnsubscribeCmd,
+      SensorType, SensorUnsubscribeCmd, StopDeviceCmd, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    },
+  },
+  device::Endpoint,
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
+};
+use dashmap::DashMap;
+use futures::{
+  future::{self, BoxFuture, Future},
+  select, FutureExt, Stream, StreamExt,
+};
+use std::{
+  collections::HashSet,
+  convert::{TryFrom, TryInto},
+  hash::Hash,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+  },
+};
+use tokio::sync::{broadcast, mpsc, Mutex, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing_futures::Instrument;
+
+// Clone derived here to satisfy tokio broadcast requirements.
+#[derive(Clone, Debug)]
+pub enum ButtplugMultiClientServerEvent {
+  /// Client with the given id finished its handshake, with the given client
+  /// name.
+  ClientConnected(u32, String),
+  /// Client with the given id disconnected or pinged out. Its leases have
+  /// already been released by the time this is emitted.
+  ClientDisconnected(u32),
+  DeviceAdded(u32, String),
+  DeviceRemoved(u32),
+}
+
+/// Tracks which clients are subscribed to readings from which devices, keyed
+/// by device index and endpoint or sensor, so readings only go to the clients
+/// that asked for them and devices are only unsubscribed once nobody is
+/// listening anymore.
+struct ClientSubscriptions<T> {
+  subscribers: DashMap<(u32, T), HashSet<u32>>,
+}
+
+impl<T> Default for ClientSubscriptions<T>
+where
+  T: Eq + Hash,
+{
+  fn default() -> Self {
+    Self {
+      subscribers: DashMap::new(),
+    }
+  }
+}
+
+impl<T> ClientSubscriptions<T>
+where
+  T: Eq + Hash + Copy,
+{
+  fn has_subscribers(&self, key: &(u32, T)) -> bool {
+    self.subscribers.contains_key(key)
+  }
+
+  fn is_subscribed(&self, client_id: u32, key: &(u32, T)) -> bool {
+    matches!(self.subscribers.get(key), Some(clients) if clients.contains(&client_id))
+  }
+
+  fn subscribe(&self, client_id: u32, key: (u32, T)) {
+    self.subscribers.entry(key).or_default().insert(client_id);
+  }
+
+  /// Removes a client's subscription. Returns true if nobody is subscribed
+  /// afterward.
+  fn unsubscribe(&self, client_id: u32, key: &(u32, T)) -> bool {
+    let empty = match self.subscribers.get_mut(key) {
+      Some(mut clients) => {
+        clients.remove(&client_id);
+        clients.is_empty()
+      }
+      None => return true,
+    };
+    if empty {
+      self.subscribers.remove(key);
+    }
+    empty
+  }
+
+  /// Removes all of a client's subscriptions. Returns the ones nobody is
+  /// subscribed to afterward.
+  fn remove_client(&self, client_id: u32) -> Vec<(u32, T)> {
+    let keys: Vec<(u32, T)> = self
+      .subscribers
+      .iter()
+      .filter(|clients| clients.contains(&client_id))
+      .map(|clients| *clients.key())
+      .collect();
+    keys
+      .into_iter()
+      .filter(|key| self.unsubscribe(client_id, key))
+      .collect()
+  }
+
+  fn remove_device(&self, device_index: u32) {
+    self
+      .subscribers
+      .retain(|(index, _), _| *index != device_index);
+  }
+}
+
+/// Per-client state for a [ButtplugMultiClientServer] connection.
+///
+/// Each session does its own handshake, ping timing, log forwarding and
+/// reading subscriptions, then hands device enumeration and commands off to
+/// the shared [ButtplugServer], after checking device leases.
+#[derive(Clone)]
+struct ButtplugClientSession {
+  client_id: u32,
+  server: Arc<ButtplugServer>,
+  leases: Arc<DeviceLeaseManager>,
+  raw_subscriptions: Arc<ClientSubscriptions<Endpoint>>,
+  sensor_subscriptions: Arc<ClientSubscriptions<(u32, SensorType)>>,
+  ping_timer: Arc<PingTimer>,
+  connected: Arc<AtomicBool>,
+  /// Message spec version the client connected with.
+  message_version: Arc<AtomicU32>,
+  event_sender: broadcast::Sender<ButtplugMultiClientServerEvent>,
+  /// Messages meant only for this client, currently just its Log messages.
+  output_sender: broadcast::Sender<ButtplugServerMessage>,
+  /// Cancels the task forwarding logs to this client, if it has sent a
+  /// RequestLog.
+  log_forwarding_token: Arc<Mutex<Option<CancellationToken>>>,
+}
+
+impl ButtplugClientSession {
+  fn connected(&self) -> bool {
+    self.connected.load(Ordering::SeqCst)
+  }
+
+  /// Whether an event from the shared server should go to this client.
+  /// Readings only go to clients that subscribed to them and still hold a
+  /// lease on the device. Logs are forwarded by each session on its own, so
+  /// the shared server shouldn't be sending any.
+  fn wants_event(&self, msg: &ButtplugServerMessage) -> bool {
+    match msg {
+      ButtplugServerMessage::RawReading(reading) => {
+        self
+          .leases
+          .holds_lease(self.client_id, reading.device_index())
+          && self.raw_subscriptions.is_subscribed(
+            self.client_id,
+            &(reading.device_index(), reading.endpoint()),
+          )
+      }
+      ButtplugServerMessage::SensorReading(reading) => {
+        self
+          .leases
+          .holds_lease(self.client_id, reading.device_index())
+          && self.sensor_subscriptions.is_subscribed(
+            self.client_id,
+            &(
+              reading.device_index(),
+              (reading.sensor_index(), reading.sensor_type()),
+            ),
+          )
+      }
+      ButtplugServerMessage::Log(_) => false,
+      _ => true,
+    }
+  }
+
+  fn parse_message(
+    &self,
+    msg: ButtplugClientMessage,
+  ) -> BoxFuture<'static, Result<ButtplugServerMessage, messages::Error>> {
+    let _log_guard = self.server.log_layer().set_default();
+    let id = msg.id();
+    if !self.connected() {
+      let error = if self.ping_timer.pinged_out() {
+        Some(ButtplugError::from(ButtplugPingError::PingedOut))
+      } else if !matches!(msg, ButtplugClientMessage::RequestServerInfo(_)) {
+        Some(ButtplugError::from(
+          ButtplugHandshakeError::RequestServerInfoExpected,
+        ))
+      } else {
+        None
+      };
+      if let Some(error) = error {
+        let mut return_error = messages::Error::from(error);
+        return_error.set_id(id);
+        return Box::pin(future::ready(Err(return_error)));
+      }
+    }
+    let out_fut = self.route_message(msg);
+    Box::pin(
+      self.server.log_layer().attach(
+        async move {
+          out_fut
+            .await
+            .map(|mut ok_msg| {
+              ok_msg.set_id(id);
+              ok_msg
+            })
+            .map_err(|err| {
+              let mut error = messages::Error::from(err);
+              error.set_id(id);
+              error
+            })
+        }
+        .instrument(info_span!("Buttplug Client Session Message", id = id)),
+      ),
+    )
+  }
+
+  fn route_message(&self, msg: ButtplugClientMessage) -> ButtplugServerResultFuture {
+    if let Err(err) = check_message_spec_version(self.message_version.load(Ordering::SeqCst), &msg)
+    {
+      return err.into();
+    }
+    match msg {
+      ButtplugClientMessage::RequestServerInfo(rsi_msg) => self.perform_handshake(rsi_msg),
+      ButtplugClientMessage::Ping(_) => self.handle_ping(),
+      ButtplugClientMessage::RequestLog(msg) => forward_log_messages(
+        self.server.log_layer(),
+        msg,
+        self.output_sender.clone(),
+        self.log_forwarding_token.clone(),
+      ),
+      ButtplugClientMessage::RequestDeviceLease(msg) => {
+        let lease_type = if msg.exclusive() {
+          DeviceLeaseType::Exclusive
+        } else {
+          DeviceLeaseType::Shared
+        };
+        self.handle_request_lease(msg.device_index(), lease_type)
+      }
+      ButtplugClientMessage::ReleaseDeviceLease(msg) => {
+        self.handle_release_lease(msg.device_index())
+      }
+      // StopAllDevices only applies to the devices this client is using,
+      // otherwise one client could stop everyone else.
+      ButtplugClientMessage::StopAllDevices(_) => {
+        let stop_fut = self.stop_devices(self.leases.client_leases(self.client_id));
+        Box::pin(async move {
+          stop_fut.await;
+          Result::Ok(messages::Ok::default().into())
+        })
+      }
+      _ => {
+        if let Ok(device_msg) = ButtplugDeviceCommandMessageUnion::try_from(msg.clone()) {
+          if let Err(err) = self
+            .leases
+            .check_command(self.client_id, device_msg.device_index())
+          {
+            return err.into();
+          }
+          match &device_msg {
+            ButtplugDeviceCommandMessageUnion::RawSubscribeCmd(cmd) => {
+              let key = (cmd.device_index(), cmd.endpoint());
+              return self.handle_subscribe(self.raw_subscriptions.clone(), key, msg);
+            }
+            ButtplugDeviceCommandMessageUnion::RawUnsubscribeCmd(cmd) => {
+              let key = (cmd.device_index(), cmd.endpoint());
+              return self.handle_unsubscribe(&self.raw_subscriptions, key, msg);
+            }
+            ButtplugDeviceCommandMessageUnion::SensorSubscribeCmd(cmd) => {
+              let key = (cmd.device_index(), (cmd.sensor_index(), cmd.sensor_type()));
+              return self.handle_subscribe(self.sensor_subscriptions.clone(), key, msg);
+            }
+            ButtplugDeviceCommandMessageUnion::SensorUnsubscribeCmd(cmd) => {
+              let key = (cmd.device_index(), (cmd.sensor_index(), cmd.sensor_type()));
+              return self.handle_unsubscribe(&self.sensor_subscriptions, key, msg);
+            }
+            _ => {}
+          }
+        }
+        self.server.route_message(msg)
+      }
+    }
+  }
+
+  fn perform_handshake(&self, msg: messages::RequestServerInfo) -> ButtplugServerResultFuture {
+    if self.connected() {
+      return ButtplugHandshakeError::HandshakeAlreadyHappened.into();
+    }
+    info!(
+      "Performing handshake check with client {} ({}) at message version {}.",
+      self.client_id,
+      msg.client_name(),
+      msg.message_version()
+    );
+    if BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION < msg.message_version() {
+      return ButtplugHandshakeError::MessageSpecVersionMismatch(
+        BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+        msg.message_version(),
+      )
+      .into();
+    }
+    let out_msg = messages::ServerInfo::new(
+      &self.server.server_name,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      self.server.max_ping_time.try_into().unwrap(),
+    );
+    let session = self.clone();
+    Box::pin(async move {
+      session.ping_timer.start_ping_timer().await;
+      session
+        .message_version
+        .store(msg.message_version() as u32, Ordering::SeqCst);
+      session.connected.store(true, Ordering::SeqCst);
+      if session
+        .event_sender
+        .send(ButtplugMultiClientServerEvent::ClientConnected(
+          session.client_id,
+          msg.client_name().clone(),
+        ))
+        .is_err()
+      {
+        trace!("No listeners for multi client server events, dropping ClientConnected.");
+      }
+      Result::Ok(out_msg.into())
+    })
+  }
+
+  fn handle_ping(&self) -> ButtplugServerResultFuture {
+    if self.server.max_ping_time == 0 {
+      return ButtplugPingError::PingTimerNotRunning.into();
+    }
+    let fut = self.ping_timer.update_ping_time();
+    Box::pin(async move {
+      fut.await;
+      Result::Ok(messages::Ok::default().into())
+    })
+  }
+
+  fn handle_request_lease(
+    &self,
+    device_index: u32,
+    lease_type: DeviceLeaseType,
+  ) -> ButtplugServerResultFuture {
+    if !self.server.device_manager.has_device(device_index) {
+      return ButtplugDeviceError::DeviceNotAvailable(device_index).into();
+    }
+    match self
+      .leases
+      .acquire(self.client_id, device_index, lease_type)
+    {
+      Ok(_) => {
+        info!(
+          "Client {} took {:?} lease on device {}",
+          self.client_id, lease_type, device_index
+        );
+        Box::pin(future::ready(Result::Ok(messages::Ok::default().into())))
+      }
+      Err(err) => err.into(),
+    }
+  }
+
+  fn handle_release_lease(&self, device_index: u32) -> ButtplugServerResultFuture {
+    match self.leases.release(self.client_id, device_index) {
+      Ok(device_free) => {
+        info!(
+          "Client {} released lease on device {}",
+          self.client_id, device_index
+        );
+        // Nobody else is using the device, so don't leave it running.
+        let stop_fut = self.stop_devices(if device_free {
+          vec![device_index]
+        } else {
+          vec![]
+        });
+        Box::pin(async move {
+          stop_fut.await;
+          Result::Ok(messages::Ok::default().into())
+        })
+      }
+      Err(err) => err.into(),
+    }
+  }
+
+  fn handle_subscribe<T>(
+    &self,
+    subscriptions: Arc<ClientSubscriptions<T>>,
+    key: (u32, T),
+    msg: ButtplugClientMessage,
+  ) -> ButtplugServerResultFuture
+  where
+    T: Eq + Hash + Copy + Send + Sync + 'static,
+  {
+    // Another client already has the device sending these readings, so
+    // there's nothing to tell the device.
+    if subscriptions.has_subscribers(&key) {
+      subscriptions.subscribe(self.client_id, key);
+      return Box::pin(future::ready(Result::Ok(messages::Ok::default().into())));
+    }
+    let fut = self.server.route_message(msg);
+    let client_id = self.client_id;
+    Box::pin(async move {
+      let reply = fut.await?;
+      subscriptions.subscribe(client_id, key);
+      Result::Ok(reply)
+    })
+  }
+
+  fn handle_unsubscribe<T>(
+    &self,
+    subscriptions: &ClientSubscriptions<T>,
+    key: (u32, T),
+    msg: ButtplugClientMessage,
+  ) -> ButtplugServerResultFuture
+  where
+    T: Eq + Hash + Copy,
+  {
+    // Leave the device subscribed while other clients still want readings.
+    if !subscriptions.unsubscribe(self.client_id, &key) {
+      return Box::pin(future::ready(Result::Ok(messages::Ok::default().into())));
+    }
+    self.server.route_message(msg)
+  }
+
+  fn stop_devices(&self, device_indexes: Vec<u32>) -> impl Future<Output = ()> {
+    let fut_vec: Vec<_> = device_indexes
+      .into_iter()
+      .map(|index| self.server.route_message(StopDeviceCmd::new(index).into()))
+      .collect();
+    async move {
+      // Devices may have disconnected already, so ignore errors here.
+      future::join_all(fut_vec).await;
+    }
+  }
+
+  /// Releases all of the client's leases and subscriptions, and stops any
+  /// devices nobody else is leasing.
+  fn disconnect(&self) -> impl Future<Output = ()> {
+    self.connected.store(false, Ordering::SeqCst);
+    let ping_fut = self.ping_timer.stop_ping_timer();
+    let mut unsubscribe_futs: Vec<_> = self
+      .raw_subscriptions
+      .remove_client(self.client_id)
+      .into_iter()
+      .map(|(device_index, endpoint)| {
+        self
+          .server
+          .route_message(RawUnsubscribeCmd::new(device_index, endpoint).into())
+      })
+      .collect();
+    unsubscribe_futs.extend(
+      self
+        .sensor_subscriptions
+        .remove_client(self.client_id)
+        .into_iter()
+        .map(|(device_index, (sensor_index, sensor_type))| {
+          self.server.route_message(
+            SensorUnsubscribeCmd::new(device_index, sensor_index, sensor_type).into(),
+          )
+        }),
+    );
+    let stop_fut = self.stop_devices(self.leases.release_all(self.client_id));
+    let log_forwarding_token = self.log_forwarding_token.clone();
+    let event_sender = self.event_sender.clone();
+    let client_id = self.client_id;
+    async move {
+      ping_fut.await;
+      if let Some(token) = log_forwarding_token.lock().await.take() {
+        token.cancel();
+      }
+      // Devices may have disconnected already, so ignore errors here.
+      future::join_all(unsubscribe_futs).await;
+      info!(
+        "Client {} disconnected, stopping devices it was using.",
+        client_id
+      );
+      stop_fut.await;
+      if event_sender
+        .send(ButtplugMultiClientServerEvent::ClientDisconnected(
+          client_id,
+        ))
+        .is_err()
+      {
+        trace!("No listeners for multi client server events, dropping ClientDisconnected.");
+      }
+    }
+  }
+}
+
+async fn run_session<ConnectorType>(
+  session: ButtplugClientSession,
+  connector: ConnectorType,
+  mut connector_receiver: mpsc::Receiver<ButtplugClientMessage>,
+  disconnect_notifier: Arc<Notify>,
+) where
+  ConnectorType: ButtplugConnector<ButtplugServerMessage, ButtplugClientMessage> + 'static,
+{
+  info!("Starting session loop for client {}", session.client_id);
+  let shared_connector = Arc::new(connector);
+  let server_receiver = session.server.event_stream();
+  pin_mut!(server_receiver);
+  let session_receiver = convert_broadcast_receiver_to_stream(session.output_sender.subscribe());
+  pin_mut!(session_receiver);
+  let ping_timeout = session.ping_timer.ping_timeout_waiter().fuse();
+  pin_mut!(ping_timeout);
+  loop {
+    select! {
+      connector_msg = connector_receiver.recv().fuse() => match connector_msg {
+        None => {
+          info!("Connector disconnected, exiting loop.");
+          break;
+        }
+        Some(client_message) => {
+          trace!("Got message from connector: {:?}", client_message);
+          let session_clone = session.clone();
+          let connector_clone = shared_connector.clone();
+          async_manager::spawn(async move {
+            if let Err(e) = client_message.is_valid() {
+              error!("Message not valid: {:?} - Error: {}", client_message, e);
+              let mut err_msg = messages::Error::from(ButtplugError::from(e));
+              err_msg.set_id(client_message.id());
+              let _ = connector_clone.send(err_msg.into()).await;
+              return;
+            }
+            let reply = match session_clone.parse_message(client_message).await {
+              Ok(ret_msg) => ret_msg,
+              Err(err_msg) => err_msg.into(),
+            };
+            if connector_clone.send(reply).await.is_err() {
+              error!("Cannot send reply to client, dropping and assuming session has exited.");
+            }
+          }).unwrap();
+        }
+      },
+      _ = disconnect_notifier.notified().fuse() => {
+        info!("Session disconnected via controller request, exiting loop.");
+        break;
+      },
+      _ = ping_timeout => {
+        error!("Client {} pinged out, ending session.", session.client_id);
+        let _ = shared_connector
+          .send(messages::Error::from(ButtplugError::from(ButtplugPingError::PingedOut)).into())
+          .await;
+        break;
+      },
+      server_msg = server_receiver.next().fuse() => match server_msg {
+        None => {
+          info!("Server disconnected via server disappearance, exiting loop.");
+          break;
+        }
+        Some(msg) => {
+          // Clients shouldn't get events until they've finished their
+          // handshake, or events their spec version doesn't have.
+          let message_version = session.message_version.load(Ordering::SeqCst);
+          if session.connected()
+            && check_event_message_version(message_version, &msg)
+            && session.wants_event(&msg)
+            && shared_connector.send(msg).await.is_err()
+          {
+            error!("Cannot send event to client, exiting session loop.");
+            break;
+          }
+        }
+      },
+      session_msg = session_receiver.next().fuse() => {
+        if let Some(msg) = session_msg {
+          if shared_connector.send(msg).await.is_err() {
+            error!("Cannot send event to client, exiting session loop.");
+            break;
+          }
+        }
+      },
+    };
+  }
+  session.disconnect().await;
+  info!("Exiting session loop for client {}", session.client_id);
+}
+
+/// Buttplug server that accepts multiple clients at the same time.
+///
+/// All clients share one [ButtplugServer], so they all see the same device
+/// list and device events. Device readings and logs are the exception, and
+/// only go to the clients that subscribed to or requested them. Clients can
+/// take exclusive or shared leases on devices with
+/// [RequestDeviceLease][messages::RequestDeviceLease]. Commands to devices the
+/// client has not leased take a shared lease implicitly, and fail if another
+/// client holds an exclusive lease.
+///
+/// When a client disconnects or pings out, its leases are released and any
+/// devices no other client is leasing are stopped.
+///
+/// Each call to [ButtplugMultiClientServer::start] runs one client session, so
+/// call it once per incoming connection, e.g. for every transport returned by
+/// [ButtplugWebsocketServerListener::accept][crate::connector::transport::ButtplugWebsocketServerListener::accept].
+pub struct ButtplugMultiClientServer {
+  server: Arc<ButtplugServer>,
+  leases: Arc<DeviceLeaseManager>,
+  raw_subscriptions: Arc<ClientSubscriptions<Endpoint>>,
+  sensor_subscriptions: Arc<ClientSubscriptions<(u32, SensorType)>>,
+  event_sender: broadcast::Sender<ButtplugMultiClientServerEvent>,
+  disconnect_notifier: Arc<Notify>,
+  next_client_id: Arc<AtomicU32>,
+  shutdown_token: CancellationToken,
+}
+
+impl Default for ButtplugMultiClientServer {
+  fn default() -> Self {
+    Self::new(ButtplugServerBuilder::default().finish().unwrap())
+  }
+}
+
+impl ButtplugMultiClientServer {
+  pub fn new(server: ButtplugServer) -> Self {
+    let (event_sender, _) = broadcast::channel(256);
+    let server = Arc::new(server);
+    let leases = Arc::new(DeviceLeaseManager::default());
+    let raw_subscriptions = Arc::new(ClientSubscriptions::default());
+    let sensor_subscriptions = Arc::new(ClientSubscriptions::default());
+    let shutdown_token = CancellationToken::new();
+    // Device events are tracked here instead of in the sessions, so we still
+    // clean up leases and report devices when no clients are connected.
+    let server_receiver = server.event_stream();
+    let leases_clone = leases.clone();
+    let raw_subscriptions_clone = raw_subscriptions.clone();
+    let sensor_subscriptions_clone = sensor_subscriptions.clone();
+    let event_sender_clone = event_sender.clone();
+    let token = shutdown_token.child_token();
+    async_manager::spawn(async move {
+      pin_mut!(server_receiver);
+      loop {
+        let event = select! {
+          _ = token.cancelled().fuse() => break,
+          msg = server_receiver.next().fuse() => match msg {
+            Some(ButtplugServerMessage::DeviceAdded(da)) => {
+              ButtplugMultiClientServerEvent::DeviceAdded(da.device_index(), da.device_name().clone())
+            }
+            Some(ButtplugServerMessage::DeviceRemoved(dr)) => {
+              leases_clone.remove_device(dr.device_index());
+              raw_subscriptions_clone.remove_device(dr.device_index());
+              sensor_subscriptions_clone.remove_device(dr.device_index());
+              ButtplugMultiClientServerEvent::DeviceRemoved(dr.device_index())
+            }
+            Some(_) => continue,
+            None => break,
+          }
+        };
+        if event_sender_clone.send(event).is_err() {
+          trace!("No listeners for multi client server events, dropping device event.");
+        }
+      }
+    })
+    .unwrap();
+    Self {
+      server,
+      leases,
+      raw_subscriptions,
+      sensor_subscriptions,
+      event_sender,
+      disconnect_notifier: Arc::new(Notify::new()),
+      next_client_id: Arc::new(AtomicU32::new(1)),
+      shutdown_token,
+    }
+  }
+
+  pub fn event_stream(&self) -> impl Stream<Item = ButtplugMultiClientServerEvent> {
+    convert_broadcast_receiver_to_stream(self.event_sender.subscribe())
+  }
+
+  /// Connects a client and runs its session until it disconnects, pings out,
+  /// or [ButtplugMultiClientServer::disconnect] is called.
+  pub fn start<ConnectorType>(
+    &self,
+    mut connector: ConnectorType,
+  ) -> impl Future<Output = Result<(), ButtplugServerConnectorError>>
+  where
+    ConnectorType: ButtplugConnector<ButtplugServerMessage, ButtplugClientMessage> + 'static,
+  {
+    let session = self.new_session();
+    let disconnect_notifier = self.disconnect_notifier.clone();
+    connector.set_message_validation_level(self.server.message_validation_level());
+    self.server.log_layer().attach(async move {
+      let (connector_sender, connector_receiver) = mpsc::channel(256);
+      connector
+        .connect(connector_sender)
+        .await
+        .map_err(|e| ButtplugServerConnectorError::ConnectorError(format!("{:?}", e)))?;
+      run_session(session, connector, connector_receiver, disconnect_notifier).await;
+      Ok(())
+    })
+  }
+
+  fn new_session(&self) -> ButtplugClientSession {
+    ButtplugClientSession {
+      client_id: self.next_client_id.fetch_add(1, Ordering::SeqCst),
+      server: self.server.clone(),
+      leases: self.leases.clone(),
+      raw_subscriptions: self.raw_subscriptions.clone(),
+      sensor_subscriptions: self.sensor_subscriptions.clone(),
+      ping_timer: Arc::new(PingTimer::new(self.server.max_ping_time)),
+      connected: Arc::new(AtomicBool::new(false)),
+      message_version: Arc::new(AtomicU32::new(0)),
+      event_sender: self.event_sender.clone(),
+      output_sender: broadcast::channel(256).0,
+      log_forwarding_token: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  /// Disconnects all currently connected clients.
+  pub async fn disconnect(&self) -> Result<(), ButtplugError> {
+    self.disconnect_notifier.notify_waiters();
+    Ok(())
+  }
+
+  pub fn leases(&self) -> &DeviceLeaseManager {
+    &self.leases
+  }
+
+  pub fn device_manager(&self) -> &DeviceManager {
+    self.server.device_manager()
+  }
+}
+
+impl Drop for ButtplugMultiClientServer {
+  fn drop(&mut self) {
+    self.disconnect_notifier.notify_waiters();
+    self.shutdown_token.cancel();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    core::messages::{
+      ButtplugMessageSpecVersion, LogLevel, RawReading, RawSubscribeCmd, RequestDeviceLease,
+      RequestLog, RequestServerInfo, StartScanning,
+    },
+    server::comm_managers::test::TestDeviceCommunicationManagerBuilder,
+  };
+
+  async fn connect_session(server: &ButtplugMultiClientServer) -> ButtplugClientSession {
+    let session = server.new_session();
+    session
+      .parse_message(
+        RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+      )
+      .await
+      .unwrap();
+    session
+  }
+
+  #[test]
+  fn test_raw_subscriptions_are_per_session() {
+    async_manager::block_on(async {
+      let mut builder = ButtplugServerBuilder::default();
+      builder.allow_raw_messages(true);
+      let server = ButtplugMultiClientServer::new(builder.finish().unwrap());
+      let events = server.event_stream();
+      pin_mut!(events);
+      let comm_builder = TestDeviceCommunicationManagerBuilder::default();
+      let helper = comm_builder.helper();
+      server
+        .device_manager()
+        .add_comm_manager(comm_builder)
+        .unwrap();
+      let _ = helper.add_ble_device("Massage Demo").await;
+      let session1 = connect_session(&server).await;
+      let session2 = connect_session(&server).await;
+      session1
+        .parse_message(StartScanning::default().into())
+        .await
+        .unwrap();
+      let device_index = loop {
+        if let Some(ButtplugMultiClientServerEvent::DeviceAdded(index, _)) = events.next().await {
+          break index;
+        }
+      };
+      let reading: ButtplugServerMessage =
+        RawReading::new(device_index, Endpoint::Tx, vec![1]).into();
+
+      session1
+        .parse_message(RawSubscribeCmd::new(device_index, Endpoint::Tx).into())
+        .await
+        .unwrap();
+      assert!(session1.wants_event(&reading));
+      assert!(!session2.wants_event(&reading));
+
+      session2
+        .parse_message(RawSubscribeCmd::new(device_index, Endpoint::Tx).into())
+        .await
+        .unwrap();
+      session1
+        .parse_message(RawUnsubscribeCmd::new(device_index, Endpoint::Tx).into())
+        .await
+        .unwrap();
+      assert!(!session1.wants_event(&reading));
+      assert!(session2.wants_event(&reading));
+
+      session2.disconnect().await;
+      assert!(!server
+        .raw_subscriptions
+        .has_subscribers(&(device_index, Endpoint::Tx)));
+    });
+  }
+
+  #[test]
+  fn test_device_lease_requires_spec_v3() {
+    async_manager::block_on(async {
+      let server =
+        ButtplugMultiClientServer::new(ButtplugServerBuilder::default().finish().unwrap());
+      let session = server.new_session();
+      session
+        .parse_message(
+          RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2).into(),
+        )
+        .await
+        .unwrap();
+      let reply = session
+        .parse_message(RequestDeviceLease::new(0, true).into())
+        .await;
+      assert!(matches!(
+        reply.unwrap_err().original_error(),
+        ButtplugError::ButtplugMessageError(ButtplugMessageError::VersionError(..))
+      ));
+      // Spec v3 clients get as far as looking for the device.
+      let session = connect_session(&server).await;
+      let reply = session
+        .parse_message(RequestDeviceLease::new(0, true).into())
+        .await;
+      assert!(matches!(
+        reply.unwrap_err().original_error(),
+        ButtplugError::ButtplugDeviceError(ButtplugDeviceError::DeviceNotAvailable(0))
+      ));
+    });
+  }
+
+  #[test]
+  fn test_log_forwarding_is_per_session() {
+    async_manager::block_on(async {
+      let server =
+        ButtplugMultiClientServer::new(ButtplugServerBuilder::default().finish().unwrap());
+      let session1 = connect_session(&server).await;
+      let session2 = connect_session(&server).await;
+      let mut session1_receiver = session1.output_sender.subscribe();
+      let mut session2_receiver = session2.output_sender.subscribe();
+      session1
+        .parse_message(RequestLog::new(LogLevel::Debug).into())
+        .await
+        .unwrap();
+      // The server logs that forwarding started, which only the client that
+      // asked should hear about.
+      match session1_receiver.recv().await.unwrap() {
+        ButtplugServerMessage::Log(log) => {
+          assert!(log
+            .log_message()
+            .contains("Client requested log forwarding"))
+        }
+        msg => panic!("Expected Log message, got {:?}", msg),
+      }
+      assert!(session2_receiver.try_recv().is_err());
+    });
+  }
+}
@@ -33,6 +33,9 @@ pub enum ButtplugServerCommand {
   Disconnect,
 }
 
+/// Runs a [ButtplugServer] for a single client connection. To let multiple
+/// clients share the same devices, use
+/// [ButtplugMultiClientServer][super::ButtplugMultiClientServer].
 pub struct ButtplugRemoteServer {
   server: Arc<ButtplugServer>,
   event_sender: broadcast::Sender<ButtplugRemoteServerEvent>,
@@ -159,6 +162,7 @@ impl ButtplugRemoteServer {
     let server_clone = self.server.clone();
     let event_sender_clone = self.event_sender.clone();
     let disconnect_notifier = self.disconnect_notifier.clone();
+    connector.set_message_validation_level(self.server.message_validation_level());
     async move {
       let (connector_sender, connector_receiver) = mpsc::channel(256);
       connector
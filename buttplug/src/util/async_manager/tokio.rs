@@ -3,6 +3,7 @@ use futures::{
   task::{FutureObj, Spawn, SpawnError, SpawnExt},
 };
 use tokio;
+use tracing_futures::WithSubscriber;
 
 #[derive(Default)]
 pub struct TokioAsyncManager {}
@@ -14,11 +15,13 @@ impl Spawn for TokioAsyncManager {
   }
 }
 
+// Spawned tasks keep the tracing dispatcher of whoever spawned them, so work
+// done for a server still reaches its log layer.
 pub fn spawn<Fut>(future: Fut) -> Result<(), SpawnError>
 where
   Fut: Future<Output = ()> + Send + 'static,
 {
-  TokioAsyncManager::default().spawn(future)
+  TokioAsyncManager::default().spawn(future.with_current_subscriber())
 }
 
 pub fn spawn_with_handle<Fut>(future: Fut) -> Result<RemoteHandle<Fut::Output>, SpawnError>
@@ -26,7 +29,7 @@ where
   Fut: Future + Send + 'static,
   Fut::Output: Send,
 {
-  TokioAsyncManager::default().spawn_with_handle(future)
+  TokioAsyncManager::default().spawn_with_handle(future.with_current_subscriber())
 }
 
 pub fn block_on<F>(f: F) -> <F as Future>::Output
@@ -2,6 +2,7 @@ use futures::{
   future::{Future, RemoteHandle},
   task::{FutureObj, Spawn, SpawnError, SpawnExt},
 };
+use tracing_futures::WithSubscriber;
 
 use wasm_bindgen_futures::spawn_local;
 
@@ -15,11 +16,13 @@ impl Spawn for WasmBindgenAsyncManager {
   }
 }
 
+// Spawned tasks keep the tracing dispatcher of whoever spawned them, so work
+// done for a server still reaches its log layer.
 pub fn spawn<Fut>(future: Fut) -> Result<(), SpawnError>
 where
   Fut: Future<Output = ()> + 'static,
 {
-  spawn_local(future);
+  spawn_local(future.with_current_subscriber());
   Ok(())
 }
 
@@ -28,7 +31,7 @@ where
   Fut: Future + Send + 'static,
   Fut::Output: Send,
 {
-  WasmBindgenAsyncManager::default().spawn_with_handle(future)
+  WasmBindgenAsyncManager::default().spawn_with_handle(future.with_current_subscriber())
 }
 
 pub fn block_on<F>(_: F) -> <F as Future>::Output
@@ -1,7 +1,10 @@
 
 use crate::{
   core::errors::{ButtplugError, ButtplugDeviceError},
-  device::configuration_manager::{ProtocolDefinition, DeviceConfigurationManager}
+  device::{
+    configuration_manager::{ProtocolDefinition, DeviceConfigurationManager},
+    device_filter::DeviceFilterRule,
+  },
 };
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -16,6 +19,9 @@ static DEVICE_CONFIGURATION_JSON_SCHEMA: &str =
 pub struct ProtocolConfiguration {
   pub version: u32,
   pub protocols: HashMap<String, ProtocolDefinition>,
+  /// Rules for which devices the server is allowed to connect to.
+  #[serde(rename = "device-filters", default)]
+  pub device_filters: Vec<DeviceFilterRule>,
 }
 
 impl ProtocolConfiguration {
@@ -30,6 +36,7 @@ impl ProtocolConfiguration {
         self.protocols.insert(protocol, conf);
       }
     }
+    self.device_filters.extend(other.device_filters);
   }
 }
 
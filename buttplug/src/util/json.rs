@@ -41,10 +41,20 @@ impl JSONValidator {
   ///
   /// - `json_str`: JSON string to validate.
   pub fn validate(&self, json_str: &str) -> Result<(), ButtplugSerializerError> {
-    let schema = self.scope.resolve(&self.id).unwrap();
     let check_value = serde_json::from_str(json_str)
       .map_err(|err| ButtplugSerializerError::JsonSerializerError(format!("{:?}", err)))?;
-    let state = schema.validate(&check_value);
+    self.validate_value(&check_value)
+  }
+
+  /// Validates already parsed json, for callers that need the parsed value
+  /// afterward and don't want to parse twice.
+  ///
+  /// # Parameters
+  ///
+  /// - `check_value`: JSON value to validate.
+  pub fn validate_value(&self, check_value: &Value) -> Result<(), ButtplugSerializerError> {
+    let schema = self.scope.resolve(&self.id).unwrap();
+    let state = schema.validate(check_value);
     if state.is_valid() {
       Ok(())
     } else {
@@ -1,6 +1,15 @@
-use crate::util::async_manager;
-use tokio::sync::mpsc::Sender;
-
+use crate::{core::messages::Log, util::async_manager};
+use futures::Future;
+use std::fmt::{self, Write};
+use tokio::sync::{broadcast, mpsc::Sender};
+use tracing::{
+  dispatcher::{self, DefaultGuard},
+  field::{Field, Visit},
+  span,
+  subscriber::Interest,
+  Dispatch, Event, Metadata, Subscriber,
+};
+use tracing_futures::{WithDispatch, WithSubscriber};
 use tracing_subscriber::fmt::MakeWriter;
 
 /// Convenience struct for handling tracing output from Buttplug.
@@ -45,3 +54,148 @@ impl MakeWriter for ChannelWriter {
     ChannelWriter::new(self.log_sender.clone())
   }
 }
+
+/// Collects the fields of a tracing event into a single log line.
+#[derive(Default)]
+struct LogMessageVisitor {
+  message: String,
+  fields: String,
+}
+
+impl Visit for LogMessageVisitor {
+  fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+    if field.name() == "message" {
+      let _ = write!(self.message, "{:?}", value);
+    } else {
+      let _ = write!(self.fields, " {}={:?}", field.name(), value);
+    }
+  }
+}
+
+/// Relays Buttplug logs to clients that ask for them.
+///
+/// Every server owns one of these, so embedders don't need to set anything up
+/// for RequestLog to work. The layer sits on top of whatever tracing
+/// dispatcher was the default when it was created (usually the executable's
+/// global subscriber), and the server installs it around its own work and
+/// tasks, so everything it logs still ends up where it did before. While a
+/// client is asking for logs, events are also turned into Log messages, which
+/// the server filters down to the level the client requested. If nothing has
+/// requested logs, events are passed on without being formatted.
+#[derive(Clone, Debug)]
+pub(crate) struct ButtplugLogLayer {
+  log_sender: broadcast::Sender<Log>,
+  dispatch: Dispatch,
+}
+
+impl ButtplugLogLayer {
+  pub(crate) fn new() -> Self {
+    let log_sender = broadcast::channel(256).0;
+    let subscriber = ButtplugLogSubscriber {
+      inner: dispatcher::get_default(Dispatch::clone),
+      log_sender: log_sender.clone(),
+    };
+    Self {
+      log_sender,
+      dispatch: Dispatch::new(subscriber),
+    }
+  }
+
+  /// Returns a receiver for all Log messages created by this layer, regardless
+  /// of level. Filtering is up to the receiver.
+  pub(crate) fn log_message_receiver(&self) -> broadcast::Receiver<Log> {
+    self.log_sender.subscribe()
+  }
+
+  /// Makes this layer the default dispatcher for the current thread until the
+  /// guard is dropped. Tasks spawned in the meantime keep using it.
+  pub(crate) fn set_default(&self) -> DefaultGuard {
+    dispatcher::set_default(&self.dispatch)
+  }
+
+  /// Runs the future with this layer as its default dispatcher.
+  pub(crate) fn attach<F: Future>(&self, future: F) -> WithDispatch<F> {
+    future.with_subscriber(self.dispatch.clone())
+  }
+}
+
+/// Passes everything on to the dispatcher the layer was created over, and
+/// copies events to the log channel while anyone is listening.
+struct ButtplugLogSubscriber {
+  inner: Dispatch,
+  log_sender: broadcast::Sender<Log>,
+}
+
+impl ButtplugLogSubscriber {
+  fn forwarding(&self) -> bool {
+    self.log_sender.receiver_count() > 0
+  }
+}
+
+impl Subscriber for ButtplugLogSubscriber {
+  fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+    let interest = self.inner.register_callsite(metadata);
+    // Whether we want an event depends on whether a client is listening right
+    // now, so ask every time.
+    if metadata.is_event() && !interest.is_always() {
+      Interest::sometimes()
+    } else {
+      interest
+    }
+  }
+
+  fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+    self.inner.enabled(metadata) || (metadata.is_event() && self.forwarding())
+  }
+
+  fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+    self.inner.new_span(span)
+  }
+
+  fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+    self.inner.record(span, values)
+  }
+
+  fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+    self.inner.record_follows_from(span, follows)
+  }
+
+  fn event(&self, event: &Event<'_>) {
+    let metadata = event.metadata();
+    if self.inner.enabled(metadata) {
+      self.inner.event(event);
+    }
+    if !self.forwarding() {
+      return;
+    }
+    let mut visitor = LogMessageVisitor::default();
+    event.record(&mut visitor);
+    let log_message = format!(
+      "{}: {}{}",
+      metadata.target(),
+      visitor.message,
+      visitor.fields
+    );
+    // Only fails if all receivers went away since we checked, in which case
+    // there's no one to tell.
+    let _ = self
+      .log_sender
+      .send(Log::new((*metadata.level()).into(), &log_message));
+  }
+
+  fn enter(&self, span: &span::Id) {
+    self.inner.enter(span)
+  }
+
+  fn exit(&self, span: &span::Id) {
+    self.inner.exit(span)
+  }
+
+  fn clone_span(&self, id: &span::Id) -> span::Id {
+    self.inner.clone_span(id)
+  }
+
+  fn try_close(&self, id: span::Id) -> bool {
+    self.inner.try_close(id)
+  }
+}
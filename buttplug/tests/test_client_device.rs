@@ -7,9 +7,16 @@ use buttplug::{
   connector::ButtplugInProcessClientConnector,
   core::{
     errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError},
-    messages::{self, ButtplugClientMessage},
+    messages::{
+      self, ButtplugClientMessage, ButtplugCurrentSpecServerMessage, ButtplugDeviceMessage,
+      SensorType,
+    },
+  },
+  device::{ButtplugDeviceEvent, Endpoint},
+  server::{
+    comm_managers::test::TestDeviceCommunicationManagerBuilder, ButtplugServerBuilder,
+    DeviceReconnectPolicy,
   },
-  server::comm_managers::test::TestDeviceCommunicationManagerBuilder,
   util::async_manager,
 };
 use futures::StreamExt;
@@ -53,6 +60,70 @@ fn test_client_device_connected_status() {
   });
 }
 
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_reconnecting_status() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let mut policy = DeviceReconnectPolicy::default();
+    policy.initial_delay(Duration::from_millis(50));
+    let server = ButtplugServerBuilder::default()
+      .reconnect_policy(policy)
+      .finish()
+      .unwrap();
+    let connector = ButtplugInProcessClientConnector::new(Some(server));
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector
+      .server_ref()
+      .device_manager()
+      .add_comm_manager(builder)
+      .unwrap();
+    let device = helper
+      .add_ble_device_with_address("Massage Demo", "reconnect-test")
+      .await;
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    let _ = helper
+      .add_ble_device_with_address("Massage Demo", "reconnect-test")
+      .await;
+    device.disconnect().await.unwrap();
+    let mut reconnecting = false;
+    while let Some(msg) = event_stream.next().await {
+      match msg {
+        ButtplugClientEvent::DeviceReconnecting(dev) => {
+          assert_eq!(dev.index(), test_device.index());
+          reconnecting = true;
+        }
+        ButtplugClientEvent::DeviceReconnected(dev) => {
+          assert!(reconnecting);
+          assert_eq!(dev.index(), test_device.index());
+          assert!(test_device.connected());
+          break;
+        }
+        ButtplugClientEvent::DeviceRemoved(_) => {
+          panic!("Device should not be removed while reconnecting.")
+        }
+        _ => {}
+      }
+    }
+    assert!(test_device
+      .vibrate(VibrateCommand::Speed(0.5))
+      .await
+      .is_ok());
+    client.disconnect().await.unwrap();
+  });
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_client_device_client_disconnected_status() {
@@ -248,6 +319,140 @@ fn test_client_repeated_deviceremoved_message() {
   });
 }
 
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_raw_subscription() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let server = ButtplugServerBuilder::default()
+      .allow_raw_messages(true)
+      .finish()
+      .unwrap();
+    let connector = ButtplugInProcessClientConnector::new(Some(server));
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector
+      .server_ref()
+      .device_manager()
+      .add_comm_manager(builder)
+      .unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    let mut device_event_stream = test_device.event_stream();
+    test_device.raw_subscribe(Endpoint::Rx).await.unwrap();
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Rx,
+      vec![0x1, 0x2, 0x3],
+    ));
+    while let Some(msg) = device_event_stream.next().await {
+      if let ButtplugClientDeviceEvent::Message(ButtplugCurrentSpecServerMessage::RawReading(
+        reading,
+      )) = msg
+      {
+        assert_eq!(reading.device_index(), test_device.index());
+        assert_eq!(reading.endpoint(), Endpoint::Rx);
+        assert_eq!(*reading.data(), vec![0x1, 0x2, 0x3]);
+        break;
+      }
+    }
+    test_device.raw_unsubscribe(Endpoint::Rx).await.unwrap();
+    client.disconnect().await.unwrap();
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_sensor_subscription() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector
+      .server_ref()
+      .device_manager()
+      .add_comm_manager(builder)
+      .unwrap();
+    let device = helper.add_ble_device("SuoYinQiu").await;
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    assert!(test_device
+      .subscribe_sensor(0, SensorType::Button)
+      .await
+      .is_err());
+    let mut readings = test_device
+      .subscribe_sensor(0, SensorType::Pressure)
+      .await
+      .unwrap();
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::RxPressure,
+      vec![0x40],
+    ));
+    assert_eq!(readings.next().await, Some(vec![0x40]));
+    test_device
+      .unsubscribe_sensor(0, SensorType::Pressure)
+      .await
+      .unwrap();
+    client.disconnect().await.unwrap();
+    // Readings stop with the connection.
+    assert_eq!(readings.next().await, None);
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_rssi_level() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector
+      .server_ref()
+      .device_manager()
+      .add_comm_manager(builder)
+      .unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    device.set_rssi_level(Some(-70));
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    assert_eq!(test_device.rssi_level().await.unwrap(), -70);
+    device.set_rssi_level(Some(-90));
+    assert_eq!(test_device.rssi_level().await.unwrap(), -90);
+    client.disconnect().await.unwrap();
+  });
+}
+
 // TODO Test invalid messages to device
 // TODO Test invalid parameters in message
 // TODO Test device invalidation across client connections (i.e. a device shouldn't be allowed to reconnect even if index is the same)
@@ -0,0 +1,136 @@
+#![cfg(all(feature = "simulator-manager", feature = "replay-manager"))]
+
+use buttplug::{
+  core::messages::{
+    self, ButtplugServerMessage, VibrateSubcommand, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+  },
+  device::{
+    device_recording::{load_recording, RecordedDeviceEvent},
+    Endpoint,
+  },
+  server::{
+    comm_managers::{
+      replay::ReplayDeviceCommunicationManagerBuilder,
+      simulator::SimulatorDeviceCommunicationManagerBuilder, DeviceCommunicationManagerBuilder,
+    },
+    ButtplugServer, ButtplugServerBuilder,
+  },
+  util::async_manager,
+};
+use futures::{pin_mut, StreamExt};
+use std::fs;
+
+const LOVENSE_EDGE_CONFIG: &str = r#"
+{
+  "devices": [
+    {
+      "name": "LVS-Edge",
+      "address": "recorded-edge",
+      "replies": [
+        { "endpoint": "tx", "request": "DeviceType;", "reply_endpoint": "rx", "reply": "P:37:0082059AD3BD;" }
+      ]
+    }
+  ]
+}
+"#;
+
+/// Connects to the only device the comm manager can find, sends it a vibrate
+/// command, and returns the server so the device stays connected.
+async fn run_session<T>(builder: T, server: ButtplugServer, speeds: (f64, f64)) -> ButtplugServer
+where
+  T: DeviceCommunicationManagerBuilder,
+{
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  server.device_manager().add_comm_manager(builder).unwrap();
+  server
+    .parse_message(
+      messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await
+    .unwrap();
+  server
+    .parse_message(messages::StartScanning::default().into())
+    .await
+    .unwrap();
+  let mut device_index = None;
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessage::DeviceAdded(added) = msg {
+      assert_eq!(added.device_name(), "Lovense Edge");
+      device_index = Some(added.device_index());
+      break;
+    }
+  }
+  server
+    .parse_message(
+      messages::VibrateCmd::new(
+        device_index.unwrap(),
+        vec![
+          VibrateSubcommand::new(0, speeds.0),
+          VibrateSubcommand::new(1, speeds.1),
+        ],
+      )
+      .into(),
+    )
+    .await
+    .unwrap();
+  server
+}
+
+#[test]
+fn test_record_and_replay_device() {
+  async_manager::block_on(async {
+    let directory = std::env::temp_dir().join(format!("buttplug-recording-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let server = ButtplugServerBuilder::default()
+      .device_recording_directory(&directory)
+      .finish()
+      .unwrap();
+    let simulator = SimulatorDeviceCommunicationManagerBuilder::default()
+      .config_json(LOVENSE_EDGE_CONFIG)
+      .unwrap();
+    let _server = run_session(simulator, server, (0.5, 0.25)).await;
+
+    let path = fs::read_dir(&directory)
+      .unwrap()
+      .next()
+      .unwrap()
+      .unwrap()
+      .path();
+    let recording = load_recording(&path).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+    assert!(matches!(
+      &recording[0].event,
+      RecordedDeviceEvent::Connected { address, .. } if address == "recorded-edge"
+    ));
+    let events: Vec<RecordedDeviceEvent> =
+      recording.iter().map(|entry| entry.event.clone()).collect();
+    assert!(events.contains(&RecordedDeviceEvent::Notification {
+      endpoint: Endpoint::Rx,
+      data: b"P:37:0082059AD3BD;".to_vec(),
+    }));
+    assert!(events.contains(&RecordedDeviceEvent::Write {
+      endpoint: Endpoint::Tx,
+      data: b"Vibrate1:10;".to_vec(),
+      write_with_response: false,
+    }));
+
+    // Same messages, same writes.
+    let replay = ReplayDeviceCommunicationManagerBuilder::default()
+      .recording(recording.clone())
+      .unwrap();
+    let device = replay.devices()[0].clone();
+    let _server = run_session(replay, ButtplugServer::default(), (0.5, 0.25)).await;
+    device.verify().unwrap();
+
+    // Different messages show up as mismatches.
+    let replay = ReplayDeviceCommunicationManagerBuilder::default()
+      .recording(recording)
+      .unwrap();
+    let device = replay.devices()[0].clone();
+    let _server = run_session(replay, ButtplugServer::default(), (0.5, 0.5)).await;
+    assert!(device.verify().is_err());
+    assert_eq!(device.mismatches().len(), 1);
+    assert_eq!(device.mismatches()[0].actual.1, b"Vibrate:10;".to_vec());
+  });
+}
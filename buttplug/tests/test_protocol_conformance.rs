@@ -0,0 +1,30 @@
+#![cfg(all(feature = "server", feature = "serialize-json"))]
+
+use buttplug::{
+  server::comm_managers::test::protocol_conformance::{parse_protocol_tests, run_protocol_test},
+  util::async_manager,
+};
+use std::{fs, path::Path};
+
+#[test]
+fn test_protocol_vectors() {
+  let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/protocol_vectors");
+  let mut paths: Vec<_> = fs::read_dir(&directory)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+    .collect();
+  paths.sort();
+  assert!(!paths.is_empty());
+  let mut failures = vec![];
+  for path in paths {
+    let cases = parse_protocol_tests(&fs::read_to_string(&path).unwrap())
+      .unwrap_or_else(|err| panic!("Cannot parse {}: {}", path.display(), err));
+    for case in cases {
+      for failure in async_manager::block_on(run_protocol_test(&case)) {
+        failures.push(format!("{}: {}", path.display(), failure));
+      }
+    }
+  }
+  assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -2,9 +2,12 @@ mod util;
 
 use buttplug::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError},
+    errors::{
+      ButtplugDeviceError, ButtplugError, ButtplugHandshakeError, ButtplugMessageError,
+      ButtplugUnknownError,
+    },
     messages::{
-      self, ButtplugMessageSpecVersion, ButtplugServerMessage,
+      self, ButtplugMessageSpecVersion, ButtplugServerMessage, ScanningFinishedReason,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
@@ -13,7 +16,7 @@ use buttplug::{
   server::comm_managers::test::{TestDeviceCommunicationManagerBuilder, check_test_recv_value},
   util::async_manager,
 };
-use futures::{pin_mut, Stream, StreamExt};
+use futures::{pin_mut, select, FutureExt, Stream, StreamExt};
 use futures_timer::Delay;
 use std::time::Duration;
 
@@ -26,7 +29,7 @@ async fn setup_test_server(
   match server.parse_message(msg_union).await.unwrap() {
     ButtplugServerMessage::ServerInfo(s) => assert_eq!(
       s,
-      messages::ServerInfo::new("Buttplug Server", ButtplugMessageSpecVersion::Version2, 0)
+      messages::ServerInfo::new("Buttplug Server", ButtplugMessageSpecVersion::Version3, 0)
     ),
     _ => panic!("Should've received ok"),
   }
@@ -216,6 +219,53 @@ fn test_invalid_device_index() {
   });
 }
 
+#[test]
+fn test_device_lease_requires_spec_v3() {
+  async_manager::block_on(async {
+    let msg =
+      messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2);
+    let (server, _) = setup_test_server(msg.into()).await;
+    let reply = server
+      .parse_message(messages::RequestDeviceLease::new(0, false).into())
+      .await;
+    assert!(matches!(
+      reply.unwrap_err().original_error(),
+      ButtplugError::ButtplugMessageError(ButtplugMessageError::VersionError(..))
+    ));
+    let reply = server
+      .parse_message(messages::ReleaseDeviceLease::new(0).into())
+      .await;
+    assert!(matches!(
+      reply.unwrap_err().original_error(),
+      ButtplugError::ButtplugMessageError(ButtplugMessageError::VersionError(..))
+    ));
+  });
+}
+
+#[test]
+fn test_start_scanning_options_require_spec_v3() {
+  async_manager::block_on(async {
+    let msg =
+      messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2);
+    let (server, _) = setup_test_server(msg.into()).await;
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    let mut scan = messages::StartScanning::default();
+    scan.set_duration(Some(1000));
+    let reply = server.parse_message(scan.into()).await;
+    assert!(matches!(
+      reply.unwrap_err().original_error(),
+      ButtplugError::ButtplugMessageError(ButtplugMessageError::VersionError(..))
+    ));
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+  });
+}
+
 #[test]
 fn test_device_index_generation() {
   async_manager::block_on(async {
@@ -314,6 +364,139 @@ fn test_server_scanning_finished() {
   });
 }
 
+async fn wait_for_scanning_finished(
+  recv: &mut (impl Stream<Item = ButtplugServerMessage> + Unpin),
+) -> Option<ScanningFinishedReason> {
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessage::ScanningFinished(msg) = msg {
+      return msg.reason();
+    }
+  }
+  panic!("Server event stream closed before ScanningFinished.");
+}
+
+#[test]
+fn test_server_timed_scanning() {
+  async_manager::block_on(async {
+    let (server, recv) = setup_test_server(
+      messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await;
+    pin_mut!(recv);
+    // The delay manager scans until it's told to stop, so only the duration
+    // can end this scan.
+    server
+      .device_manager()
+      .add_comm_manager(util::DelayDeviceCommunicationManagerBuilder::default())
+      .unwrap();
+    let mut start = messages::StartScanning::default();
+    start.set_duration(Some(100));
+    server.parse_message(start.into()).await.unwrap();
+    assert_eq!(
+      wait_for_scanning_finished(&mut recv).await,
+      Some(ScanningFinishedReason::Timeout)
+    );
+    // Scanning is over, so stopping again should fail and starting should work.
+    assert!(server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .is_err());
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .unwrap();
+    assert_eq!(
+      wait_for_scanning_finished(&mut recv).await,
+      Some(ScanningFinishedReason::Stopped)
+    );
+  });
+}
+
+#[test]
+fn test_server_scanning_comm_manager_selection() {
+  async_manager::block_on(async {
+    let (server, recv) = setup_test_server(
+      messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await;
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    server
+      .device_manager()
+      .add_comm_manager(util::DelayDeviceCommunicationManagerBuilder::default())
+      .unwrap();
+    helper.add_ble_device("Massage Demo").await;
+
+    let mut start = messages::StartScanning::default();
+    start.set_comm_managers(Some(vec!["NotARealCommManager".to_owned()]));
+    let err = server.parse_message(start.into()).await.unwrap_err();
+    assert!(matches!(
+      err.original_error(),
+      ButtplugError::ButtplugUnknownError(ButtplugUnknownError::DeviceCommManagerNotFound(_))
+    ));
+
+    // If the delay manager was included, this scan would never finish.
+    let mut start = messages::StartScanning::default();
+    start.set_comm_managers(Some(vec!["TestDeviceCommunicationManager".to_owned()]));
+    server.parse_message(start.into()).await.unwrap();
+    assert_eq!(
+      wait_for_scanning_finished(&mut recv).await,
+      Some(ScanningFinishedReason::Completed)
+    );
+    assert_eq!(
+      server.device_manager().metrics().comm_managers["DelayDeviceCommunicationManager"]
+        .scans_started,
+      0
+    );
+  });
+}
+
+#[test]
+fn test_server_continuous_scanning() {
+  async_manager::block_on(async {
+    let (server, recv) = setup_test_server(
+      messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await;
+    pin_mut!(recv);
+    server
+      .device_manager()
+      .add_comm_manager(util::InstantScanDeviceCommunicationManagerBuilder::default())
+      .unwrap();
+    let mut start = messages::StartScanning::default();
+    start.set_continuous(true);
+    server.parse_message(start.into()).await.unwrap();
+    // The manager finishes immediately, so without continuous scanning this
+    // would have finished already.
+    select! {
+      _ = Delay::new(Duration::from_millis(1200)).fuse() => {},
+      msg = recv.next().fuse() => panic!("Should not get any messages while scanning continuously: {:?}", msg),
+    };
+    let scans_started = server.device_manager().metrics().comm_managers
+      ["InstantScanDeviceCommunicationManager"]
+      .scans_started;
+    assert!(
+      scans_started >= 2,
+      "Scan was only started {} times",
+      scans_started
+    );
+    server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .unwrap();
+    assert_eq!(
+      wait_for_scanning_finished(&mut recv).await,
+      Some(ScanningFinishedReason::Stopped)
+    );
+  });
+}
+
 #[test]
 fn test_server_builder_null_device_config() {
   async_manager::block_on(async {
@@ -452,6 +635,120 @@ fn test_server_builder_user_device_config_old_config_version() {
   });
 }
 
+/// Collects every Log message that shows up within 100ms.
+async fn collect_log_messages(
+  recv: impl Stream<Item = ButtplugServerMessage>,
+) -> Vec<messages::Log> {
+  pin_mut!(recv);
+  let mut logs = vec![];
+  loop {
+    select! {
+      msg = recv.next().fuse() => match msg {
+        Some(ButtplugServerMessage::Log(log)) => logs.push(log),
+        Some(_) => continue,
+        None => break,
+      },
+      _ = Delay::new(Duration::from_millis(100)).fuse() => break,
+    };
+  }
+  logs
+}
+
+#[test]
+fn test_server_request_log() {
+  let msg =
+    messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2).into();
+  async_manager::block_on(async {
+    // No tracing setup needed, the server forwards its own logs.
+    let (server, recv) = setup_test_server(msg).await;
+    pin_mut!(recv);
+    let reply = server
+      .parse_message(messages::RequestLog::new(messages::LogLevel::Debug).into())
+      .await;
+    assert!(matches!(reply, Ok(ButtplugServerMessage::Ok(_))));
+    let log = loop {
+      match recv.next().await.unwrap() {
+        ButtplugServerMessage::Log(log) => break log,
+        _ => continue,
+      }
+    };
+    assert_eq!(log.log_level(), messages::LogLevel::Info);
+    assert!(log
+      .log_message()
+      .contains("Client requested log forwarding at level Debug"));
+    let reply = server
+      .parse_message(messages::RequestLog::new(messages::LogLevel::Off).into())
+      .await;
+    assert!(matches!(reply, Ok(ButtplugServerMessage::Ok(_))));
+  });
+}
+
+#[test]
+fn test_server_request_log_level_filtering() {
+  async_manager::block_on(async {
+    let levels = [
+      messages::LogLevel::Error,
+      messages::LogLevel::Warn,
+      messages::LogLevel::Info,
+      messages::LogLevel::Debug,
+      messages::LogLevel::Trace,
+    ];
+    for requested_level in levels.iter() {
+      let msg =
+        messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2);
+      let (server, recv) = setup_test_server(msg.into()).await;
+      assert!(server
+        .parse_message(messages::RequestLog::new(*requested_level).into())
+        .await
+        .is_ok());
+      // Every message is logged at trace level on the way in.
+      assert!(server
+        .parse_message(messages::RequestDeviceList::default().into())
+        .await
+        .is_ok());
+      let logs = collect_log_messages(recv).await;
+      assert!(logs.iter().all(|log| log.log_level() <= *requested_level));
+      assert_eq!(
+        logs
+          .iter()
+          .any(|log| log.log_message().contains("Client requested log forwarding")),
+        *requested_level >= messages::LogLevel::Info
+      );
+      assert_eq!(
+        logs
+          .iter()
+          .any(|log| log.log_message().contains("RequestDeviceList")),
+        *requested_level == messages::LogLevel::Trace
+      );
+    }
+  });
+}
+
+#[test]
+fn test_server_request_log_off_stops_forwarding() {
+  let msg =
+    messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2).into();
+  async_manager::block_on(async {
+    let (server, recv) = setup_test_server(msg).await;
+    assert!(server
+      .parse_message(messages::RequestLog::new(messages::LogLevel::Trace).into())
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::RequestLog::new(messages::LogLevel::Off).into())
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::RequestDeviceList::default().into())
+      .await
+      .is_ok());
+    let logs = collect_log_messages(recv).await;
+    assert!(!logs
+      .iter()
+      .any(|log| log.log_message().contains("RequestDeviceList")));
+  });
+}
+
 // TODO Test sending system message (Id 0)
 // TODO Test sending system message (Ok but Id > 0)
 // TODO Test repeated handshake
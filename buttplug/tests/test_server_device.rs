@@ -3,16 +3,27 @@ use buttplug::{
   core::{
     errors::{ButtplugDeviceError, ButtplugError},
     messages::{
-      self, ButtplugDeviceMessageType, ButtplugServerMessage, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      self, ButtplugDeviceMessage, ButtplugDeviceMessageType, ButtplugMessage,
+      ButtplugMessageSpecVersion, ButtplugServerMessage, DeviceConnectionStage,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
-  device::Endpoint,
-  server::{ButtplugServer, ButtplugServerBuilder},
-  server::comm_managers::test::TestDeviceCommunicationManagerBuilder,
-  util::async_manager,
+  device::{
+    device_filter::{DeviceFilterAction, DeviceFilterRule},
+    ButtplugDeviceEvent, DeviceImplCommand, DeviceWriteCmd, Endpoint,
+  },
+  server::comm_managers::test::{
+    check_test_recv_empty, check_test_recv_value, TestDeviceCommunicationManagerBuilder,
+  },
+  server::{
+    ButtplugServer, ButtplugServerBuilder, DeviceConnectionPolicy, DeviceReconnectPolicy,
+    DeviceStore, InMemoryDeviceStore,
+  },
+  util::{async_manager, device_configuration::get_internal_config_version},
 };
 use futures::{pin_mut, StreamExt};
-use std::matches;
+use futures_timer::Delay;
+use std::{matches, sync::Arc, time::Duration};
 
 // Test devices that have protocols that support movements not all devices do.
 // For instance, the Onyx+ is part of a protocol that supports vibration, but
@@ -286,3 +297,840 @@ fn test_repeated_address_additions() {
     }
   });
 }
+
+#[test]
+fn test_server_raw_subscription_notifications() {
+  async_manager::block_on(async {
+    let server = ButtplugServerBuilder::default()
+      .allow_raw_messages(true)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        device_index = Some(da.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    // Notifications before subscribing should not reach the client. Device
+    // events are handled in order, so a reading from an endpoint we are
+    // subscribed to tells us the earlier notification has been dropped.
+    server
+      .parse_message(messages::RawSubscribeCmd::new(device_index, Endpoint::Tx).into())
+      .await
+      .unwrap();
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Rx,
+      vec![0x0],
+    ));
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Tx,
+      vec![0xff],
+    ));
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::RawReading(reading) => {
+          assert_eq!(reading.endpoint(), Endpoint::Tx);
+          break;
+        }
+        _ => panic!("Returned message was not a RawReading message: {:?}", msg),
+      }
+    }
+    server
+      .parse_message(messages::RawUnsubscribeCmd::new(device_index, Endpoint::Tx).into())
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::RawSubscribeCmd::new(device_index, Endpoint::Rx).into())
+      .await
+      .unwrap();
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Rx,
+      vec![0x1, 0x2],
+    ));
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::RawReading(reading) => {
+          assert_eq!(reading.id(), 0);
+          assert_eq!(reading.device_index(), device_index);
+          assert_eq!(reading.endpoint(), Endpoint::Rx);
+          assert_eq!(*reading.data(), vec![0x1, 0x2]);
+          break;
+        }
+        _ => panic!("Returned message was not a RawReading message: {:?}", msg),
+      }
+    }
+    server
+      .parse_message(messages::RawUnsubscribeCmd::new(device_index, Endpoint::Rx).into())
+      .await
+      .unwrap();
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Rx,
+      vec![0x3],
+    ));
+    // Use a device removal as a sentinel, if we get it without a reading
+    // first, the unsubscribe worked.
+    device.disconnect().await.unwrap();
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceRemoved(dr) => {
+          assert_eq!(dr.device_index(), device_index);
+          return;
+        }
+        _ => panic!("Received message after unsubscribe: {:?}", msg),
+      }
+    }
+  });
+}
+
+#[test]
+fn test_server_device_reconnect_restores_state() {
+  async_manager::block_on(async {
+    let mut policy = DeviceReconnectPolicy::default();
+    policy.initial_delay(Duration::from_millis(50));
+    let server = ButtplugServerBuilder::default()
+      .reconnect_policy(policy)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper
+      .add_ble_device_with_address("Massage Demo", "reconnect-test")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        device_index = Some(da.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    server
+      .parse_message(
+        messages::VibrateCmd::new(device_index, vec![messages::VibrateSubcommand::new(0, 0.5)])
+          .into(),
+      )
+      .await
+      .unwrap();
+    // Queue up the same device to be found again by the reconnection scan.
+    let reconnected_device = helper
+      .add_ble_device_with_address("Massage Demo", "reconnect-test")
+      .await;
+    device.disconnect().await.unwrap();
+    let mut reconnecting = false;
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceReconnecting(dr) => {
+          assert_eq!(dr.device_index(), device_index);
+          reconnecting = true;
+        }
+        ButtplugServerMessage::DeviceReconnected(dr) => {
+          assert!(reconnecting);
+          assert_eq!(dr.device_index(), device_index);
+          break;
+        }
+        _ => panic!("Unexpected message during reconnection: {:?}", msg),
+      }
+    }
+    // State is restored in the background, so give it a moment.
+    Delay::new(Duration::from_millis(100)).await;
+    let command_receiver = reconnected_device
+      .get_endpoint_receiver(&Endpoint::Tx)
+      .unwrap();
+    check_test_recv_value(
+      &command_receiver,
+      DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+    );
+  });
+}
+
+#[test]
+fn test_server_device_reconnect_gives_up() {
+  async_manager::block_on(async {
+    let mut policy = DeviceReconnectPolicy::default();
+    policy
+      .max_attempts(1)
+      .initial_delay(Duration::from_millis(50));
+    let server = ButtplugServerBuilder::default()
+      .reconnect_policy(policy)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper
+      .add_ble_device_with_address("Massage Demo", "reconnect-test")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        device_index = Some(da.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    // A different device turning up during the reconnection scan shouldn't be
+    // mistaken for the one we lost.
+    helper
+      .add_ble_device_with_address("Massage Demo", "some-other-device")
+      .await;
+    device.disconnect().await.unwrap();
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceReconnecting(dr) => {
+          assert_eq!(dr.device_index(), device_index);
+        }
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_ne!(da.device_index(), device_index);
+        }
+        ButtplugServerMessage::DeviceRemoved(dr) => {
+          assert_eq!(dr.device_index(), device_index);
+          break;
+        }
+        _ => panic!("Unexpected message during reconnection: {:?}", msg),
+      }
+    }
+    assert!(server
+      .parse_message(
+        messages::VibrateCmd::new(device_index, vec![messages::VibrateSubcommand::new(0, 0.5)])
+          .into(),
+      )
+      .await
+      .is_err());
+  });
+}
+
+#[test]
+fn test_server_device_reconnect_scan_shares_comm_manager() {
+  async_manager::block_on(async {
+    let mut policy = DeviceReconnectPolicy::default();
+    policy
+      .max_attempts(1)
+      .initial_delay(Duration::from_millis(100));
+    let server = ButtplugServerBuilder::default()
+      .reconnect_policy(policy)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default().keep_scanning();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper
+      .add_ble_device_with_address("Massage Demo", "reconnect-test")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(_) = msg {
+        break;
+      }
+    }
+    server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .unwrap();
+    device.disconnect().await.unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceReconnecting(_) = msg {
+        break;
+      }
+    }
+    // The reconnection scan has the comm manager running, which shouldn't keep
+    // the client from scanning with it.
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceRemoved(_) = msg {
+        break;
+      }
+    }
+    // Giving up on the device shouldn't stop the client's scan.
+    assert!(server.device_manager().comm_managers()[0].scanning);
+    server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::ScanningFinished(_) = msg {
+        break;
+      }
+    }
+    assert!(!server.device_manager().comm_managers()[0].scanning);
+  });
+}
+
+#[test]
+fn test_server_device_reconnect_events_need_spec_v3() {
+  async_manager::block_on(async {
+    let mut policy = DeviceReconnectPolicy::default();
+    policy
+      .max_attempts(1)
+      .initial_delay(Duration::from_millis(50));
+    let server = ButtplugServerBuilder::default()
+      .reconnect_policy(policy)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper
+      .add_ble_device_with_address("Massage Demo", "reconnect-test")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        device_index = Some(da.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    helper
+      .add_ble_device_with_address("Massage Demo", "some-other-device")
+      .await;
+    device.disconnect().await.unwrap();
+    // v2 clients only see the device go away once reconnecting gives up.
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_ne!(da.device_index(), device_index);
+        }
+        ButtplugServerMessage::DeviceRemoved(dr) => {
+          assert_eq!(dr.device_index(), device_index);
+          break;
+        }
+        _ => panic!("Unexpected message during reconnection: {:?}", msg),
+      }
+    }
+  });
+}
+
+#[test]
+fn test_server_device_inactivity_watchdog() {
+  async_manager::block_on(async {
+    let server = ButtplugServerBuilder::default()
+      .device_inactivity_timeout(200)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        device_index = Some(da.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    let command_receiver = device.get_endpoint_receiver(&Endpoint::Tx).unwrap();
+    // Keep the device busy for longer than the timeout, it shouldn't be
+    // stopped as long as commands keep coming in.
+    for speed in [0.5, 0.75, 0.5, 0.75].iter() {
+      server
+        .parse_message(
+          messages::VibrateCmd::new(
+            device_index,
+            vec![messages::VibrateSubcommand::new(0, *speed)],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      Delay::new(Duration::from_millis(50)).await;
+    }
+    for step in [64, 96, 64, 96].iter() {
+      check_test_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, *step], false)),
+      );
+    }
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceStopped(ds) => {
+          assert_eq!(ds.device_index(), device_index);
+          assert_eq!(ds.reason(), messages::DeviceStopReason::InactivityTimeout);
+          break;
+        }
+        _ => panic!("Unexpected message while waiting for watchdog: {:?}", msg),
+      }
+    }
+    check_test_recv_value(
+      &command_receiver,
+      DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false)),
+    );
+    // A stopped device has nothing for the watchdog to do.
+    server
+      .parse_message(messages::StopDeviceCmd::new(device_index).into())
+      .await
+      .unwrap();
+    Delay::new(Duration::from_millis(400)).await;
+    assert!(check_test_recv_empty(&command_receiver));
+  });
+}
+
+#[test]
+fn test_server_device_filter_rules() {
+  async_manager::block_on(async {
+    let user_config = format!(
+      r#"{{
+        "version": {},
+        "protocols": {{}},
+        "device-filters": [
+          {{ "action": "allow", "bus": "btle" }},
+          {{ "action": "deny", "protocol": "aneros" }}
+        ]
+      }}"#,
+      get_internal_config_version()
+    );
+    let server = ButtplugServerBuilder::default()
+      .user_device_configuration_json(Some(user_config))
+      .finish()
+      .unwrap();
+    let mut deny_aneros = DeviceFilterRule::new(DeviceFilterAction::Deny);
+    deny_aneros.protocol("aneros");
+    assert!(server
+      .device_manager()
+      .device_filter_rules()
+      .contains(&deny_aneros));
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    helper.add_ble_device("Onyx+").await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        assert!(!da.device_name().contains("Aneros"));
+        break;
+      }
+    }
+    // Give the denied device time to show up if it was going to.
+    Delay::new(Duration::from_millis(100)).await;
+    match server
+      .parse_message(messages::RequestDeviceList::default().into())
+      .await
+      .unwrap()
+    {
+      ButtplugServerMessage::DeviceList(list) => assert_eq!(list.devices().len(), 1),
+      msg => panic!("Expected DeviceList, got {:?}", msg),
+    }
+    assert!(server
+      .device_manager()
+      .remove_device_filter_rule(&deny_aneros));
+    assert!(!server
+      .device_manager()
+      .device_filter_rules()
+      .contains(&deny_aneros));
+  });
+}
+
+#[test]
+fn test_server_device_index_persistence() {
+  async_manager::block_on(async {
+    let store: Arc<dyn DeviceStore> = Arc::new(InMemoryDeviceStore::default());
+    let start_server = |store: Arc<dyn DeviceStore>| {
+      ButtplugServerBuilder::default()
+        .device_store(store)
+        .finish()
+        .unwrap()
+    };
+    let server = start_server(store.clone());
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper
+      .add_ble_device_with_address("Massage Demo", "first-device")
+      .await;
+    helper
+      .add_ble_device_with_address("Massage Demo", "second-device")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut added = 0;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(_) = msg {
+        added += 1;
+        if added == 2 {
+          break;
+        }
+      }
+    }
+    let second_index = server.device_manager().stored_devices()["second-device"].index;
+    server
+      .device_manager()
+      .set_device_display_name(second_index, Some("Alice's Vivi"))
+      .unwrap();
+    assert!(server
+      .device_manager()
+      .set_device_display_name(100, Some("Nobody"))
+      .is_err());
+    drop(server);
+
+    // After a restart, the second device should get the same index back, even
+    // though it's the only device found this time.
+    let server = start_server(store);
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper
+      .add_ble_device_with_address("Massage Demo", "second-device")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        assert_eq!(da.device_index(), second_index);
+        assert_eq!(da.device_display_name(), &Some("Alice's Vivi".to_owned()));
+        break;
+      }
+    }
+    match server
+      .parse_message(messages::RequestDeviceList::default().into())
+      .await
+      .unwrap()
+    {
+      ButtplugServerMessage::DeviceList(list) => {
+        assert_eq!(list.devices()[0].device_index, second_index);
+        assert_eq!(
+          list.devices()[0].device_display_name,
+          Some("Alice's Vivi".to_owned())
+        );
+      }
+      msg => panic!("Expected DeviceList, got {:?}", msg),
+    }
+  });
+}
+
+#[test]
+fn test_server_device_manager_metrics() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper
+      .add_ble_device_with_address("Massage Demo", "metrics-device")
+      .await;
+    helper
+      .add_ble_device_with_address("Not A Toy", "unknown-device")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(_) = msg {
+        break;
+      }
+    }
+    server
+      .parse_message(
+        messages::VibrateCmd::new(0, vec![messages::VibrateSubcommand::new(0, 0.5)]).into(),
+      )
+      .await
+      .unwrap();
+    check_test_recv_value(
+      &device.get_endpoint_receiver(&Endpoint::Tx).unwrap(),
+      DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+    );
+    // Give the unmatched device a chance to make it through the event loop.
+    Delay::new(Duration::from_millis(100)).await;
+
+    let metrics = server.device_manager().metrics();
+    let device_metrics = &metrics.devices[&0];
+    assert_eq!(device_metrics.address, "metrics-device");
+    assert_eq!(device_metrics.metrics.commands_received, 1);
+    assert_eq!(
+      device_metrics.metrics.bytes_written.get(&Endpoint::Tx),
+      Some(&2)
+    );
+    assert!(device_metrics.metrics.last_command_time.is_some());
+    assert!(device_metrics.metrics.average_write_latency.is_some());
+    let mgr_metrics = &metrics.comm_managers["TestDeviceCommunicationManager"];
+    assert_eq!(mgr_metrics.scans_started, 1);
+    assert_eq!(mgr_metrics.devices_found, 2);
+    assert_eq!(
+      mgr_metrics.unmatched_devices.get("unknown-device"),
+      Some(&"Not A Toy".to_owned())
+    );
+
+    let prometheus = metrics.to_prometheus();
+    assert!(prometheus.contains(&format!(
+      "buttplug_device_bytes_written_total{{index=\"0\",name=\"{}\",address=\"metrics-device\",endpoint=\"tx\"}} 2",
+      device_metrics.name
+    )));
+    assert!(prometheus
+      .contains("buttplug_comm_manager_scans_started_total{comm_manager=\"TestDeviceCommunicationManager\"} 1"));
+  });
+}
+
+#[test]
+fn test_server_remove_comm_manager() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper
+      .add_ble_device_with_address("Massage Demo", "removable-device")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(_) = msg {
+        break;
+      }
+    }
+    let comm_managers = server.device_manager().comm_managers();
+    assert_eq!(comm_managers.len(), 1);
+    assert_eq!(comm_managers[0].name, "TestDeviceCommunicationManager");
+    assert_eq!(comm_managers[0].device_count, 1);
+
+    assert!(server
+      .device_manager()
+      .remove_comm_manager("NotARealCommManager")
+      .await
+      .is_err());
+    server
+      .device_manager()
+      .remove_comm_manager("TestDeviceCommunicationManager")
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::DeviceRemoved(removed) => {
+          assert_eq!(removed.device_index(), 0);
+          break;
+        }
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        msg => panic!("Expected DeviceRemoved, got {:?}", msg),
+      }
+    }
+    assert!(server.device_manager().comm_managers().is_empty());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_err());
+
+    // Re-adding the same kind of comm manager works, and the device comes back
+    // under its old index.
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper
+      .add_ble_device_with_address("Massage Demo", "removable-device")
+      .await;
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::DeviceAdded(added) => {
+          assert_eq!(added.device_index(), 0);
+          break;
+        }
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        msg => panic!("Expected DeviceAdded, got {:?}", msg),
+      }
+    }
+    // The disconnect from the old comm manager shouldn't take the new device
+    // down with it.
+    Delay::new(Duration::from_millis(100)).await;
+    assert_eq!(server.device_manager().comm_managers()[0].device_count, 1);
+  });
+}
+
+#[test]
+fn test_server_device_initialize_timeout() {
+  async_manager::block_on(async {
+    let mut policy = DeviceConnectionPolicy::default();
+    policy.initialize_timeout(Some(Duration::from_millis(50)));
+    let server = ButtplugServerBuilder::default()
+      .connection_policy(policy)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    // Lovense devices wait for an answer to their device type query during
+    // initialization, which the test device never sends.
+    helper
+      .add_ble_device_with_address("LVS-Test", "silent-lovense")
+      .await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::DeviceConnectionFailed(failed) => {
+          assert_eq!(failed.device_name(), "LVS-Test");
+          assert_eq!(failed.device_address(), "silent-lovense");
+          assert_eq!(failed.stage(), DeviceConnectionStage::Initialize);
+          break;
+        }
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        msg => panic!("Expected DeviceConnectionFailed, got {:?}", msg),
+      }
+    }
+  });
+}
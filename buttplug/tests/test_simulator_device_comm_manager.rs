@@ -0,0 +1,319 @@
+#![cfg(feature = "simulator-manager")]
+
+use buttplug::{
+  core::messages::{
+    self, ButtplugDeviceMessageType, ButtplugServerMessage, VibrateSubcommand,
+    BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+  },
+  device::Endpoint,
+  server::{
+    comm_managers::simulator::SimulatorDeviceCommunicationManagerBuilder, BatteryMonitorPolicy,
+    ButtplugServer, ButtplugServerBuilder,
+  },
+  util::{async_manager, device_configuration::get_internal_config_version},
+};
+use futures::{pin_mut, StreamExt};
+use std::time::{Duration, Instant};
+
+const LOVENSE_EDGE_CONFIG: &str = r#"
+{
+  "devices": [
+    {
+      "name": "LVS-Edge",
+      "address": "simulated-edge",
+      "replies": [
+        { "endpoint": "tx", "request": "DeviceType;", "reply_endpoint": "rx", "reply": "P:37:0082059AD3BD;" },
+        { "endpoint": "tx", "request": "Battery;", "reply_endpoint": "rx", "reply": "85;" }
+      ],
+      "actuators": [
+        { "name": "Vibrate1", "endpoint": "tx", "prefix": "Vibrate1:" },
+        { "name": "Vibrate2", "endpoint": "tx", "prefix": "Vibrate2:" }
+      ]
+    }
+  ]
+}
+"#;
+
+#[test]
+fn test_simulated_lovense_edge() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = SimulatorDeviceCommunicationManagerBuilder::default()
+      .config_json(LOVENSE_EDGE_CONFIG)
+      .unwrap();
+    let edge = builder.devices()[0].clone();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(added) = msg {
+        // The name comes from the simulated DeviceType reply.
+        assert_eq!(added.device_name(), "Lovense Edge");
+        device_index = Some(added.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    assert!(edge.connected());
+    assert!(edge.subscribed(Endpoint::Rx));
+
+    server
+      .parse_message(
+        messages::VibrateCmd::new(
+          device_index,
+          vec![
+            VibrateSubcommand::new(0, 0.5),
+            VibrateSubcommand::new(1, 0.25),
+          ],
+        )
+        .into(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(edge.actuator_state("Vibrate1"), Some(10));
+    assert_eq!(edge.actuator_state("Vibrate2"), Some(5));
+    assert_eq!(edge.last_write(Endpoint::Tx), Some(b"Vibrate2:5;".to_vec()));
+
+    let reply = server
+      .parse_message(messages::BatteryLevelCmd::new(device_index).into())
+      .await
+      .unwrap();
+    if let ButtplugServerMessage::BatteryLevelReading(reading) = reply {
+      assert!((reading.battery_level() - 0.85).abs() < f64::EPSILON);
+    } else {
+      panic!("Expected BatteryLevelReading, got {:?}", reply);
+    }
+    // No signal strength configured, so none reported.
+    assert!(server
+      .parse_message(messages::RSSILevelCmd::new(device_index).into())
+      .await
+      .is_err());
+
+    edge.disconnect();
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::DeviceRemoved(removed) => {
+          assert_eq!(removed.device_index(), device_index);
+          break;
+        }
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        msg => panic!("Expected DeviceRemoved, got {:?}", msg),
+      }
+    }
+    assert!(!edge.connected());
+    // State sticks around after disconnecting.
+    assert_eq!(edge.actuator_state("Vibrate1"), Some(10));
+  });
+}
+
+#[test]
+fn test_min_write_interval_uses_protocol_identifier() {
+  async_manager::block_on(async {
+    // Lovense devices are configured by the device type they report, not
+    // their bluetooth name, so the interval has to be found under "P".
+    let user_config = format!(
+      r#"{{
+        "version": {},
+        "protocols": {{
+          "lovense": {{
+            "configurations": [
+              {{
+                "identifier": ["P"],
+                "name": {{ "en-us": "Lovense Edge" }},
+                "messages": {{ "VibrateCmd": {{ "FeatureCount": 2, "StepCount": [20, 20] }} }},
+                "min-write-interval": 250
+              }}
+            ]
+          }}
+        }}
+      }}"#,
+      get_internal_config_version()
+    );
+    let server = ButtplugServerBuilder::default()
+      .user_device_configuration_json(Some(user_config))
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = SimulatorDeviceCommunicationManagerBuilder::default()
+      .config_json(LOVENSE_EDGE_CONFIG)
+      .unwrap();
+    let edge = builder.devices()[0].clone();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(added) = msg {
+        device_index = Some(added.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+
+    let start = Instant::now();
+    for speed in [0.5, 1.0] {
+      server
+        .parse_message(
+          messages::VibrateCmd::new(device_index, vec![VibrateSubcommand::new(0, speed)]).into(),
+        )
+        .await
+        .unwrap();
+    }
+    // The second command has to wait out the interval before it's written.
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(edge.actuator_state("Vibrate1"), Some(20));
+  });
+}
+
+const ANEROS_CONFIG: &str = r#"
+{
+  "devices": [
+    {
+      "name": "Massage Demo",
+      "address": "simulated-aneros",
+      "rssi_level": -60
+    }
+  ]
+}
+"#;
+
+#[test]
+fn test_simulated_rssi_level() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = SimulatorDeviceCommunicationManagerBuilder::default()
+      .config_json(ANEROS_CONFIG)
+      .unwrap();
+    let aneros = builder.devices()[0].clone();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(added) = msg {
+        // Advertised because the connection reports signal strength, even
+        // though the protocol doesn't know about it.
+        assert!(added
+          .device_messages()
+          .contains_key(&ButtplugDeviceMessageType::RSSILevelCmd));
+        device_index = Some(added.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+
+    for expected in [-60, -85] {
+      aneros.set_rssi_level(expected);
+      let reply = server
+        .parse_message(messages::RSSILevelCmd::new(device_index).into())
+        .await
+        .unwrap();
+      if let ButtplugServerMessage::RSSILevelReading(reading) = reply {
+        assert_eq!(reading.rssi_level(), expected);
+      } else {
+        panic!("Expected RSSILevelReading, got {:?}", reply);
+      }
+    }
+  });
+}
+
+#[test]
+fn test_battery_monitor() {
+  async_manager::block_on(async {
+    let mut policy = BatteryMonitorPolicy::default();
+    policy
+      .poll_interval(Duration::from_secs(60))
+      .low_battery_thresholds(&[0.5, 0.9]);
+    let server = ButtplugServerBuilder::default()
+      .battery_monitor_policy(policy)
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = SimulatorDeviceCommunicationManagerBuilder::default()
+      .config_json(LOVENSE_EDGE_CONFIG)
+      .unwrap();
+    let edge = builder.devices()[0].clone();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    let mut device_index = None;
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::DeviceAdded(added) => device_index = Some(added.device_index()),
+        ButtplugServerMessage::BatteryLevelLow(low) => {
+          assert_eq!(Some(low.device_index()), device_index);
+          assert!((low.battery_level() - 0.85).abs() < f64::EPSILON);
+          assert!((low.threshold() - 0.9).abs() < f64::EPSILON);
+          break;
+        }
+        _ => continue,
+      }
+    }
+    let device_index = device_index.unwrap();
+
+    server
+      .parse_message(
+        messages::VibrateCmd::new(device_index, vec![VibrateSubcommand::new(0, 0.5)]).into(),
+      )
+      .await
+      .unwrap();
+    // The monitor just read the battery, so this is answered without asking
+    // the device again.
+    let reply = server
+      .parse_message(messages::BatteryLevelCmd::new(device_index).into())
+      .await
+      .unwrap();
+    if let ButtplugServerMessage::BatteryLevelReading(reading) = reply {
+      assert!((reading.battery_level() - 0.85).abs() < f64::EPSILON);
+    } else {
+      panic!("Expected BatteryLevelReading, got {:?}", reply);
+    }
+    assert_eq!(
+      edge.last_write(Endpoint::Tx),
+      Some(b"Vibrate1:10;".to_vec())
+    );
+  });
+}
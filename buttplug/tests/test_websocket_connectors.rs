@@ -3,16 +3,21 @@
 #[cfg(feature = "websockets")]
 mod websocket_connector_tests {
   use buttplug::{
-    client::ButtplugClient,
+    client::{ButtplugClient, ButtplugClientDevice, ButtplugClientEvent, VibrateCommand},
     connector::{
       ButtplugRemoteClientConnector, ButtplugRemoteServerConnector,
       ButtplugWebsocketClientTransport, ButtplugWebsocketServerTransport,
       ButtplugWebsocketServerTransportBuilder,
     },
     core::messages::serializer::{ButtplugClientJSONSerializer, ButtplugServerJSONSerializer},
-    server::ButtplugRemoteServer,
+    server::{
+      comm_managers::test::TestDeviceCommunicationManagerBuilder,
+      multi_client_server::ButtplugMultiClientServerEvent, ButtplugMultiClientServer,
+      ButtplugRemoteServer,
+    },
     util::async_manager,
   };
+  use futures::{pin_mut, Stream, StreamExt};
   use futures_timer::Delay;
   use std::sync::Arc;
   use std::time::Duration;
@@ -87,6 +92,91 @@ mod websocket_connector_tests {
       server.disconnect().await.unwrap();
     });
   }
+
+  async fn connect_ws_client(port: u16) -> ButtplugClient {
+    let connector = ButtplugRemoteClientConnector::<
+      ButtplugWebsocketClientTransport,
+      ButtplugClientJSONSerializer,
+    >::new(ButtplugWebsocketClientTransport::new_insecure_connector(
+      &format!("ws://127.0.0.1:{}", port),
+    ));
+    let client = ButtplugClient::new("Test Client");
+    client.connect(connector).await.unwrap();
+    client
+  }
+
+  async fn wait_for_device(
+    event_stream: &mut (impl Stream<Item = ButtplugClientEvent> + Unpin),
+  ) -> Arc<ButtplugClientDevice> {
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        return da;
+      }
+    }
+    panic!("Client event stream closed before device was added.");
+  }
+
+  #[test]
+  fn test_multi_client_ws_server_device_leases() {
+    async_manager::block_on(async move {
+      let server = Arc::new(ButtplugMultiClientServer::default());
+      let server_events = server.event_stream();
+      pin_mut!(server_events);
+      let builder = TestDeviceCommunicationManagerBuilder::default();
+      let helper = builder.helper();
+      server.device_manager().add_comm_manager(builder).unwrap();
+      let _ = helper.add_ble_device("Massage Demo").await;
+      let listener = ButtplugWebsocketServerTransportBuilder::default()
+        .port(12350)
+        .listen()
+        .await
+        .unwrap();
+      let server_clone = server.clone();
+      async_manager::spawn(async move {
+        while let Ok(transport) = listener.accept().await {
+          let connector = ButtplugRemoteServerConnector::<
+            ButtplugWebsocketServerTransport,
+            ButtplugServerJSONSerializer,
+          >::new(transport);
+          let fut = server_clone.start(connector);
+          async_manager::spawn(async move {
+            let _ = fut.await;
+          })
+          .unwrap();
+        }
+      })
+      .unwrap();
+
+      let client1 = connect_ws_client(12350).await;
+      let mut client1_events = client1.event_stream();
+      let client2 = connect_ws_client(12350).await;
+      let mut client2_events = client2.event_stream();
+      client1.start_scanning().await.unwrap();
+      let device1 = wait_for_device(&mut client1_events).await;
+      let device2 = wait_for_device(&mut client2_events).await;
+      assert_eq!(device1.index(), device2.index());
+
+      // Exclusive lease locks the second client out.
+      device1.request_lease(true).await.unwrap();
+      assert!(device1.vibrate(VibrateCommand::Speed(0.5)).await.is_ok());
+      assert!(device2.vibrate(VibrateCommand::Speed(0.5)).await.is_err());
+      assert!(device2.request_lease(false).await.is_err());
+
+      // Disconnecting releases the lease, so the second client can take over.
+      client1.disconnect().await.unwrap();
+      while let Some(event) = server_events.next().await {
+        if let ButtplugMultiClientServerEvent::ClientDisconnected(_) = event {
+          break;
+        }
+      }
+      assert!(device2.vibrate(VibrateCommand::Speed(0.5)).await.is_ok());
+      device2.request_lease(true).await.unwrap();
+      device2.release_lease().await.unwrap();
+      assert!(device2.release_lease().await.is_err());
+      client2.disconnect().await.unwrap();
+      server.disconnect().await.unwrap();
+    });
+  }
 }
 
 // TODO Test disconnection event from server side
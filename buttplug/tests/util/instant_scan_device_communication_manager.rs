@@ -0,0 +1,54 @@
+use buttplug::{
+  core::ButtplugResultFuture,
+  server::comm_managers::{
+    DeviceCommunicationEvent, DeviceCommunicationManager, DeviceCommunicationManagerBuilder,
+  },
+};
+use tokio::sync::mpsc::Sender;
+
+/// Comm manager that finishes scanning as soon as it starts, without finding
+/// anything.
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct InstantScanDeviceCommunicationManagerBuilder {
+  sender: Option<tokio::sync::mpsc::Sender<DeviceCommunicationEvent>>,
+}
+
+impl DeviceCommunicationManagerBuilder for InstantScanDeviceCommunicationManagerBuilder {
+  fn event_sender(mut self, sender: Sender<DeviceCommunicationEvent>) -> Self {
+    self.sender = Some(sender);
+    self
+  }
+
+  fn finish(mut self) -> Box<dyn DeviceCommunicationManager> {
+    Box::new(InstantScanDeviceCommunicationManager {
+      sender: self.sender.take().unwrap(),
+    })
+  }
+}
+
+#[allow(dead_code)]
+pub struct InstantScanDeviceCommunicationManager {
+  sender: Sender<DeviceCommunicationEvent>,
+}
+
+impl DeviceCommunicationManager for InstantScanDeviceCommunicationManager {
+  fn name(&self) -> &'static str {
+    "InstantScanDeviceCommunicationManager"
+  }
+
+  fn start_scanning(&self) -> ButtplugResultFuture {
+    let sender = self.sender.clone();
+    Box::pin(async move {
+      sender
+        .send(DeviceCommunicationEvent::ScanningFinished)
+        .await
+        .unwrap();
+      Ok(())
+    })
+  }
+
+  fn stop_scanning(&self) -> ButtplugResultFuture {
+    Box::pin(async move { Ok(()) })
+  }
+}
@@ -1,5 +1,8 @@
 mod delay_device_communication_manager;
 pub use delay_device_communication_manager::DelayDeviceCommunicationManagerBuilder;
+mod instant_scan_device_communication_manager;
+#[allow(unused_imports)]
+pub use instant_scan_device_communication_manager::InstantScanDeviceCommunicationManagerBuilder;
 mod channel_transport;
 pub use channel_transport::*;
 